@@ -20,6 +20,18 @@ impl<P: PwmPin> PwmHeater<P>
 		self.pin.set_duty_cycle(percentage)
 	}
 
+	/// Returns the `percentage` of current given to the heater.
+	pub fn get_heat_percentage(&self) -> Percentage
+	{
+		self.pin.get_duty_cycle()
+	}
+
+	/// Shifts the phase of the PWM signal of the heater (check [`PwmPin::set_phase`]).
+	pub fn set_phase(&mut self, phase: Percentage) -> Result<(), <P as PwmPin>::Error>
+	{
+		self.pin.set_phase(phase)
+	}
+
 	/// Returns a reference to the `pin` you provided to [`Self::new`].
 	pub fn get_pin_ref(&self) -> &P
 	{
@@ -0,0 +1,194 @@
+use core::fmt::Debug;
+
+use embedded_hal::spi::SpiDevice;
+
+use super::temperature_sensor::{SensorFault, TemperatureSensor};
+use crate::utils::physical_quantities::temperature::Temperature;
+
+const OPEN_CIRCUIT: u32 = 1 << 0;
+const SHORT_TO_GND: u32 = 1 << 1;
+const SHORT_TO_VCC: u32 = 1 << 2;
+
+/// A K-type thermocouple-to-digital converter, which also measures the temperature of its cold junction (the chip
+/// itself) and can read temperatures below `0°C`.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::max31855::*, hardware::mock::MockSpi};
+/// #
+/// // 100.25°C on the thermocouple and 25.5°C on the cold junction
+/// let spi = MockSpi::new(vec![0x0644_1980_u32.to_be_bytes().to_vec()]);
+/// let mut max31855 = MAX31855::new(spi);
+///
+/// let reading = max31855.read().unwrap();
+/// assert!((reading.thermocouple.as_celsius() - 100.25).abs() < 0.01);
+/// assert!((reading.cold_junction.as_celsius() - 25.5).abs() < 0.01);
+/// ```
+pub struct MAX31855<Spi: SpiDevice<u8>>
+{
+	spi: Spi,
+}
+
+impl<Spi: SpiDevice<u8>> MAX31855<Spi>
+{
+	pub const SENSITIVITY: Temperature = Temperature::from_kelvin(0.25);
+	pub const COLD_JUNCTION_SENSITIVITY: Temperature = Temperature::from_kelvin(0.0625);
+
+	pub fn new(spi: Spi) -> Self
+	{
+		Self { spi }
+	}
+
+	/// Returns the temperatures of the thermocouple and of the cold junction.
+	///
+	/// If the thermocouple is faulty (open, or shorted to `GND` or `VCC`) the temperatures aren't returned, because the
+	/// temperature of the thermocouple is wrong.
+	pub fn read(&mut self) -> Result<MAX31855Reading, ReadError<Spi>>
+	{
+		let mut read_bytes = [0_u8; 4];
+		self.spi.transfer(&mut read_bytes, &[0_u8; 4]).map_err(ReadError::Spi)?;
+
+		let read_value = u32::from_be_bytes(read_bytes);
+
+		// The fault bit (D16) is set together with the bit of the fault
+		if read_value & OPEN_CIRCUIT != 0
+		{
+			return Err(ReadError::OpenThermocouple);
+		}
+		if read_value & SHORT_TO_GND != 0
+		{
+			return Err(ReadError::ShortedToGround);
+		}
+		if read_value & SHORT_TO_VCC != 0
+		{
+			return Err(ReadError::ShortedToVcc);
+		}
+
+		// Both temperatures are signed, so they are shifted as `i32` to extend their sign
+		let thermocouple = (read_value as i32) >> 18;
+		let cold_junction = ((read_value << 16) as i32) >> 20;
+
+		Ok(MAX31855Reading {
+			thermocouple: Temperature::from_celsius(Self::SENSITIVITY.as_kelvin() * thermocouple as f32),
+			cold_junction: Temperature::from_celsius(
+				Self::COLD_JUNCTION_SENSITIVITY.as_kelvin() * cold_junction as f32,
+			),
+		})
+	}
+
+	/// Returns the temperature of the thermocouple.
+	pub fn read_temperature(&mut self) -> Result<Temperature, ReadError<Spi>>
+	{
+		self.read().map(|reading| reading.thermocouple)
+	}
+}
+
+impl<Spi: SpiDevice<u8>> TemperatureSensor for MAX31855<Spi>
+{
+	type Context = ();
+	type Error = ReadError<Spi>;
+
+	fn read_temperature(&mut self, _: &mut ()) -> Result<Temperature, Self::Error>
+	{
+		MAX31855::read_temperature(self)
+	}
+
+	fn get_sensor_fault(error: &Self::Error) -> Option<SensorFault>
+	{
+		match error
+		{
+			ReadError::Spi(_) => None,
+			ReadError::OpenThermocouple => Some(SensorFault::OpenCircuit),
+			ReadError::ShortedToGround | ReadError::ShortedToVcc => Some(SensorFault::ShortCircuit),
+		}
+	}
+}
+
+/// The temperatures read by a [`MAX31855`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MAX31855Reading
+{
+	/// The temperature of the hot junction of the thermocouple.
+	pub thermocouple: Temperature,
+	/// The temperature of the chip, where the thermocouple is connected.
+	pub cold_junction: Temperature,
+}
+
+pub enum ReadError<Spi: SpiDevice<u8>>
+{
+	Spi(Spi::Error),
+	OpenThermocouple,
+	/// The thermocouple is shorted to `GND`.
+	ShortedToGround,
+	/// The thermocouple is shorted to `VCC`.
+	ShortedToVcc,
+}
+
+impl<Spi: SpiDevice<u8>> Debug for ReadError<Spi>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Spi(arg0) => f.debug_tuple("Spi").field(arg0).finish(),
+			Self::OpenThermocouple => write!(f, "OpenThermocouple"),
+			Self::ShortedToGround => write!(f, "ShortedToGround"),
+			Self::ShortedToVcc => write!(f, "ShortedToVcc"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::hardware::mock::MockSpi;
+
+	const FAULT: u32 = 1 << 16;
+
+	fn read(value: u32) -> Result<MAX31855Reading, ReadError<MockSpi>>
+	{
+		MAX31855::new(MockSpi::new(vec![value.to_be_bytes().to_vec()])).read()
+	}
+
+	#[test]
+	fn negative_temperatures()
+	{
+		// -250°C on the thermocouple and -0.0625°C on the cold junction
+		let reading = read(0xF060_FFF0).unwrap();
+
+		assert_eq!(reading.thermocouple.as_celsius(), -250.);
+		assert!((reading.cold_junction.as_celsius() + 0.0625).abs() < 0.001);
+	}
+
+	#[test]
+	fn faults()
+	{
+		let faults = [
+			(0b001, SensorFault::OpenCircuit),
+			(0b010, SensorFault::ShortCircuit),
+			(0b100, SensorFault::ShortCircuit),
+		];
+
+		for (fault_bits, sensor_fault) in faults
+		{
+			let error = read(0x0644_1980 | FAULT | fault_bits).unwrap_err();
+			assert_eq!(MAX31855::<MockSpi>::get_sensor_fault(&error), Some(sensor_fault));
+		}
+
+		assert!(matches!(
+			read(0x0644_1980 | FAULT | 0b110),
+			Err(ReadError::ShortedToGround)
+		));
+	}
+
+	#[test]
+	fn missing_spi_data_is_an_error()
+	{
+		for read_operations in [vec![], vec![vec![0x06, 0x44]]]
+		{
+			let result = MAX31855::new(MockSpi::new(read_operations)).read();
+			assert!(matches!(result, Err(ReadError::Spi(_))));
+		}
+	}
+}
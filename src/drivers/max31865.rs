@@ -0,0 +1,446 @@
+use core::{fmt::Debug, ops::RangeInclusive};
+
+use embedded_hal::spi::{Operation, SpiDevice};
+use enumset::EnumSet;
+
+use super::temperature_sensor::{SensorFault, TemperatureSensor};
+use crate::utils::physical_quantities::temperature::Temperature;
+
+const WRITE: u8 = 1 << 7;
+const CONFIGURATION_REGISTER: u8 = 0x00;
+const RTD_REGISTER: u8 = 0x01;
+const HIGH_FAULT_THRESHOLD_REGISTER: u8 = 0x03;
+const FAULT_STATUS_REGISTER: u8 = 0x07;
+
+const CONFIGURATION_BIAS: u8 = 1 << 7;
+const CONFIGURATION_AUTOMATIC_CONVERSION: u8 = 1 << 6;
+const CONFIGURATION_THREE_WIRE: u8 = 1 << 4;
+const CONFIGURATION_FAULT_STATUS_CLEAR: u8 = 1 << 1;
+const CONFIGURATION_FILTER_50HZ: u8 = 1 << 0;
+
+/// The RTD register and the fault threshold registers are 15 bits ratios of the reference resistance.
+const ADC_RESOLUTION: f32 = (1 << 15) as f32;
+
+/// The coefficients of the [`Callendar–Van Dusen equation`] defined by IEC 60751 for platinum RTDs.
+///
+/// [`Callendar–Van Dusen equation`]: https://en.wikipedia.org/wiki/Callendar%E2%80%93Van_Dusen_equation
+const A: f32 = 3.9083e-3;
+const B: f32 = -5.775e-7;
+const C: f32 = -4.183e-12;
+
+/// A RTD-to-digital converter for PT100 and PT1000 platinum resistance thermometers.
+///
+/// It converts continuously, so each [`MAX31865::read_temperature`] returns the last conversion (a new one is ready
+/// every `~20ms`).
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::max31865::*, hardware::mock::MockSpi};
+/// #
+/// // The resistance of the PT100 is 138.51Ω, so 100°C
+/// let spi = MockSpi::new(vec![(((138.51 / 430. * 32768.) as u16) << 1).to_be_bytes().to_vec()]);
+/// let mut max31865 = MAX31865::new(
+///     spi,
+///     MAX31865Config {
+///         rtd: Rtd::Pt100,
+///         wiring: RtdWiring::ThreeWire,
+///         reference_resistance: 430.,
+///         filter: FilterFrequency::Hz50,
+///     },
+/// )
+/// .unwrap();
+///
+/// let temperature = max31865.read_temperature().unwrap();
+/// assert!((temperature.as_celsius() - 100.).abs() < 0.1);
+/// ```
+pub struct MAX31865<Spi: SpiDevice<u8>>
+{
+	spi: Spi,
+	config: MAX31865Config,
+}
+
+impl<Spi: SpiDevice<u8>> MAX31865<Spi>
+{
+	/// Returns a [`MAX31865`] that communicates with the chip through the provided `spi`, after configuring it with the
+	/// provided `config` and clearing its faults.
+	pub fn new(spi: Spi, config: MAX31865Config) -> Result<Self, Spi::Error>
+	{
+		let mut max31865 = Self { spi, config };
+		max31865.clear_faults()?;
+
+		Ok(max31865)
+	}
+
+	/// Returns the [`MAX31865Config`] used by this chip.
+	pub fn get_config(&self) -> MAX31865Config
+	{
+		self.config
+	}
+
+	/// Returns the resistance of the RTD, in `Ω`.
+	///
+	/// If the chip has detected a fault, it's returned in [`ReadError::Fault`] and cleared.
+	pub fn read_resistance(&mut self) -> Result<f32, ReadError<Spi>>
+	{
+		let mut read_bytes = [0_u8; 2];
+		self.read_registers(RTD_REGISTER, &mut read_bytes)
+			.map_err(ReadError::Spi)?;
+
+		let read_value = u16::from_be_bytes(read_bytes);
+
+		// The lowest bit is set when there is a fault
+		if read_value & 1 != 0
+		{
+			let mut fault_status = 0;
+			self.read_registers(FAULT_STATUS_REGISTER, core::slice::from_mut(&mut fault_status))
+				.map_err(ReadError::Spi)?;
+			self.clear_faults().map_err(ReadError::Spi)?;
+
+			return Err(ReadError::Fault(RtdFault::from_status(fault_status)));
+		}
+
+		Ok((read_value >> 1) as f32 / ADC_RESOLUTION * self.config.reference_resistance)
+	}
+
+	/// Returns the temperature of the RTD.
+	///
+	/// If the chip has detected a fault, it's returned in [`ReadError::Fault`] and cleared.
+	pub fn read_temperature(&mut self) -> Result<Temperature, ReadError<Spi>>
+	{
+		self.read_resistance()
+			.map(|resistance| self.config.rtd.get_temperature(resistance))
+	}
+
+	/// Sets the fault threshold registers of the chip, so that a temperature outside of `range` is reported as a
+	/// [`RtdFault::AboveHighThreshold`] or [`RtdFault::BelowLowThreshold`].
+	///
+	/// By default the thresholds are the whole range that the chip can measure.
+	pub fn set_fault_thresholds(&mut self, range: RangeInclusive<Temperature>) -> Result<(), Spi::Error>
+	{
+		let to_register_value = |temperature: Temperature| {
+			let ratio = self.config.rtd.get_resistance(temperature) / self.config.reference_resistance;
+			let code = (ratio * ADC_RESOLUTION).clamp(0., ADC_RESOLUTION - 1.) as u16;
+			(code << 1).to_be_bytes()
+		};
+		let [high_msb, high_lsb] = to_register_value(*range.end());
+		let [low_msb, low_lsb] = to_register_value(*range.start());
+
+		self.spi.write(&[
+			WRITE | HIGH_FAULT_THRESHOLD_REGISTER,
+			high_msb,
+			high_lsb,
+			low_msb,
+			low_lsb,
+		])
+	}
+
+	fn clear_faults(&mut self) -> Result<(), Spi::Error>
+	{
+		let configuration = self.config.get_configuration_register();
+
+		self.spi.write(&[
+			WRITE | CONFIGURATION_REGISTER,
+			configuration | CONFIGURATION_FAULT_STATUS_CLEAR,
+		])
+	}
+
+	fn read_registers(&mut self, address: u8, values: &mut [u8]) -> Result<(), Spi::Error>
+	{
+		self.spi
+			.transaction(&mut [Operation::Write(&[address]), Operation::Read(values)])
+	}
+}
+
+impl<Spi: SpiDevice<u8>> TemperatureSensor for MAX31865<Spi>
+{
+	type Context = ();
+	type Error = ReadError<Spi>;
+
+	fn read_temperature(&mut self, _: &mut ()) -> Result<Temperature, Self::Error>
+	{
+		MAX31865::read_temperature(self)
+	}
+
+	fn get_sensor_fault(error: &Self::Error) -> Option<SensorFault>
+	{
+		match error
+		{
+			ReadError::Spi(_) => None,
+			ReadError::Fault(faults)
+				if !faults.is_disjoint(RtdFault::BelowLowThreshold | RtdFault::OverOrUndervoltage) =>
+			{
+				Some(SensorFault::ShortCircuit)
+			},
+			ReadError::Fault(_) => Some(SensorFault::OpenCircuit),
+		}
+	}
+}
+
+/// How a [`MAX31865`] is configured.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MAX31865Config
+{
+	pub rtd: Rtd,
+	pub wiring: RtdWiring,
+	/// The resistance of the reference resistor of the board, in `Ω` (usually `430Ω` for a PT100 and `4300Ω` for a
+	/// PT1000).
+	pub reference_resistance: f32,
+	/// The frequency of the mains, which the chip filters out of the measurements.
+	pub filter: FilterFrequency,
+}
+
+impl MAX31865Config
+{
+	fn get_configuration_register(&self) -> u8
+	{
+		let mut configuration = CONFIGURATION_BIAS | CONFIGURATION_AUTOMATIC_CONVERSION;
+
+		if self.wiring == RtdWiring::ThreeWire
+		{
+			configuration |= CONFIGURATION_THREE_WIRE;
+		}
+		if self.filter == FilterFrequency::Hz50
+		{
+			configuration |= CONFIGURATION_FILTER_50HZ;
+		}
+
+		configuration
+	}
+}
+
+/// A platinum resistance thermometer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rtd
+{
+	/// `100Ω` at `0°C`.
+	Pt100,
+	/// `1000Ω` at `0°C`.
+	Pt1000,
+}
+
+impl Rtd
+{
+	/// Returns the resistance of the RTD at `0°C`, in `Ω`.
+	pub fn get_nominal_resistance(&self) -> f32
+	{
+		match self
+		{
+			Self::Pt100 => 100.,
+			Self::Pt1000 => 1000.,
+		}
+	}
+
+	/// Returns the resistance of the RTD at the provided `temperature`, in `Ω`, with the Callendar–Van Dusen
+	/// equation.
+	pub fn get_resistance(&self, temperature: Temperature) -> f32
+	{
+		self.get_nominal_resistance() * get_resistance_ratio(temperature.as_celsius())
+	}
+
+	/// Returns the [`Temperature`] of the RTD whose resistance is `resistance` Ω, by inverting the Callendar–Van Dusen
+	/// equation.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::drivers::max31865::Rtd;
+	/// #
+	/// assert!((Rtd::Pt100.get_temperature(18.52).as_celsius() + 200.).abs() < 0.01);
+	/// assert!((Rtd::Pt1000.get_temperature(3904.81).as_celsius() - 850.).abs() < 0.01);
+	/// ```
+	pub fn get_temperature(&self, resistance: f32) -> Temperature
+	{
+		let resistance_ratio = resistance / self.get_nominal_resistance();
+
+		// Newton's method starting from the linear approximation, which converges in a few iterations in the whole
+		// range of the equation (`-200°C` to `850°C`) without needing a square root
+		let mut degrees = (resistance_ratio - 1.) / A;
+		for _ in 0..4
+		{
+			let derivative = if degrees < 0.
+			{
+				A + 2. * B * degrees + C * (4. * degrees - 300.) * degrees * degrees
+			}
+			else
+			{
+				A + 2. * B * degrees
+			};
+			degrees -= (get_resistance_ratio(degrees) - resistance_ratio) / derivative;
+		}
+
+		Temperature::from_celsius(degrees)
+	}
+}
+
+/// Returns the ratio between the resistance of a platinum RTD at `degrees` °C and its resistance at `0°C`.
+fn get_resistance_ratio(degrees: f32) -> f32
+{
+	let ratio = 1. + A * degrees + B * degrees * degrees;
+
+	if degrees < 0.
+	{
+		ratio + C * (degrees - 100.) * degrees * degrees * degrees
+	}
+	else
+	{
+		ratio
+	}
+}
+
+/// How a [`Rtd`] is wired to a [`MAX31865`]. The wires added to the two of the RTD compensate the resistance of the
+/// wires themselves.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtdWiring
+{
+	TwoWire,
+	ThreeWire,
+	FourWire,
+}
+
+/// The frequency of the mains.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterFrequency
+{
+	Hz50,
+	Hz60,
+}
+
+/// A fault detected by a [`MAX31865`], as reported by its fault status register.
+#[derive(enumset::EnumSetType, Debug, Hash)]
+pub enum RtdFault
+{
+	/// The resistance is above the high fault threshold, usually because the RTD is disconnected.
+	AboveHighThreshold,
+	/// The resistance is below the low fault threshold, usually because the RTD is shorted.
+	BelowLowThreshold,
+	/// The voltage of `REFIN-` is above `0.85 × VBIAS`.
+	ReferenceInputTooHigh,
+	/// The voltage of `REFIN-` is below `0.85 × VBIAS`, because `FORCE-` is open.
+	ReferenceInputTooLow,
+	/// The voltage of `RTDIN-` is below `0.85 × VBIAS`, because `FORCE-` is open.
+	RtdInputTooLow,
+	/// An input is above `VDD` or below `GND`.
+	OverOrUndervoltage,
+}
+
+impl RtdFault
+{
+	fn from_status(fault_status: u8) -> EnumSet<Self>
+	{
+		[
+			(1 << 7, Self::AboveHighThreshold),
+			(1 << 6, Self::BelowLowThreshold),
+			(1 << 5, Self::ReferenceInputTooHigh),
+			(1 << 4, Self::ReferenceInputTooLow),
+			(1 << 3, Self::RtdInputTooLow),
+			(1 << 2, Self::OverOrUndervoltage),
+		]
+		.into_iter()
+		.filter(|(bit, _)| fault_status & bit != 0)
+		.map(|(_, fault)| fault)
+		.collect()
+	}
+}
+
+pub enum ReadError<Spi: SpiDevice<u8>>
+{
+	Spi(Spi::Error),
+	/// The chip has detected the faults in the set.
+	Fault(EnumSet<RtdFault>),
+}
+
+impl<Spi: SpiDevice<u8>> Debug for ReadError<Spi>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Spi(arg0) => f.debug_tuple("Spi").field(arg0).finish(),
+			Self::Fault(arg0) => f.debug_tuple("Fault").field(arg0).finish(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::hardware::mock::MockSpi;
+
+	const CONFIG: MAX31865Config = MAX31865Config {
+		rtd: Rtd::Pt1000,
+		wiring: RtdWiring::FourWire,
+		reference_resistance: 4300.,
+		filter: FilterFrequency::Hz60,
+	};
+
+	fn get_write_operations(max31865: MAX31865<MockSpi>) -> Vec<Vec<u8>>
+	{
+		match max31865.spi
+		{
+			MockSpi::Ok { write_operations, .. } => write_operations,
+			MockSpi::Err(_) => unreachable!(),
+		}
+	}
+
+	#[test]
+	fn callendar_van_dusen_round_trip()
+	{
+		for degrees in [-200., -100., -10., 0., 10., 100., 300., 850.]
+		{
+			let temperature = Temperature::from_celsius(degrees);
+			let resistance = Rtd::Pt100.get_resistance(temperature);
+
+			let read_degrees = Rtd::Pt100.get_temperature(resistance).as_celsius();
+			assert!((read_degrees - degrees).abs() < 0.01, "{degrees}°C: {read_degrees}");
+		}
+	}
+
+	#[test]
+	fn configuration_and_fault_thresholds()
+	{
+		let mut max31865 = MAX31865::new(MockSpi::new(Vec::new()), CONFIG).unwrap();
+		max31865
+			.set_fault_thresholds(Temperature::from_celsius(0.)..=Temperature::from_celsius(100.))
+			.unwrap();
+
+		// 1385.06Ω and 1000Ω are 10554 and 7620 with a reference of 4300Ω, shifted left by one bit
+		assert_eq!(
+			get_write_operations(max31865),
+			[vec![0x80, 0b1100_0010], vec![0x83, 0x52, 0x74, 0x3B, 0x88],]
+		);
+
+		let three_wire_config = MAX31865Config {
+			wiring: RtdWiring::ThreeWire,
+			filter: FilterFrequency::Hz50,
+			..CONFIG
+		};
+		let max31865 = MAX31865::new(MockSpi::new(Vec::new()), three_wire_config).unwrap();
+		assert_eq!(get_write_operations(max31865), [vec![0x80, 0b1101_0011]]);
+	}
+
+	#[test]
+	fn faults_are_read_and_cleared()
+	{
+		// The fault bit of the RTD register is set, and the fault status is "below low threshold"
+		let spi = MockSpi::new(vec![vec![0x00, 0x01], vec![1 << 6]]);
+		let mut max31865 = MAX31865::new(spi, CONFIG).unwrap();
+
+		let error = max31865.read_temperature().unwrap_err();
+		assert!(matches!(error, ReadError::Fault(faults) if faults == RtdFault::BelowLowThreshold));
+		assert_eq!(
+			MAX31865::<MockSpi>::get_sensor_fault(&error),
+			Some(SensorFault::ShortCircuit)
+		);
+
+		// The faults are cleared by writing the configuration again
+		assert_eq!(
+			get_write_operations(max31865),
+			[
+				vec![0x80, 0b1100_0010],
+				vec![RTD_REGISTER],
+				vec![FAULT_STATUS_REGISTER],
+				vec![0x80, 0b1100_0010],
+			]
+		);
+	}
+}
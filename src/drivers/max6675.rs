@@ -2,6 +2,7 @@ use core::fmt::Debug;
 
 use embedded_hal::spi::SpiDevice;
 
+use super::temperature_sensor::{SensorFault, TemperatureSensor};
 use crate::utils::physical_quantities::temperature::Temperature;
 
 pub struct MAX6675<Spi: SpiDevice<u8>>
@@ -35,6 +36,26 @@ impl<Spi: SpiDevice<u8>> MAX6675<Spi>
 	}
 }
 
+impl<Spi: SpiDevice<u8>> TemperatureSensor for MAX6675<Spi>
+{
+	type Context = ();
+	type Error = ReadError<Spi>;
+
+	fn read_temperature(&mut self, _: &mut ()) -> Result<Temperature, Self::Error>
+	{
+		MAX6675::read_temperature(self)
+	}
+
+	fn get_sensor_fault(error: &Self::Error) -> Option<SensorFault>
+	{
+		match error
+		{
+			ReadError::Spi(_) => None,
+			ReadError::OpenThermocouple => Some(SensorFault::OpenCircuit),
+		}
+	}
+}
+
 pub enum ReadError<Spi: SpiDevice<u8>>
 {
 	Spi(Spi::Error),
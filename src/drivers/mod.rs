@@ -4,8 +4,12 @@ pub mod heater;
 pub mod joystick;
 pub mod l298n;
 pub mod led;
+pub mod max31855;
+pub mod max31865;
 pub mod max6675;
 pub mod potentiometer;
 pub mod servo_motor;
 pub mod spi_flash_memory;
+pub mod spi_nor_flash;
+pub mod temperature_sensor;
 pub mod thermistor;
@@ -2,7 +2,7 @@
 
 use core::{fmt::Debug, marker::PhantomData};
 
-use super::chip::FlashMemoryChip;
+use super::chip::{FlashMemoryChip, FlashMemoryChipExt};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 /// Identifies the byte location within a page to be accessed in a flash memory.
@@ -25,14 +25,23 @@ impl ColumnAddress
 }
 
 /// Identifies the page, block and LUN to be accessed in a flash memory.
-pub struct RowAddress<Chip: FlashMemoryChip>([u8; 3], PhantomData<Chip>);
+///
+/// In the chips made of multiple LUNs (dies), the bytes sent over SPI only identify the page in its LUN, so the
+/// LUN must be selected before (check [`SpiFlashMemory::select_die`]).
+///
+/// [`SpiFlashMemory::select_die`]: super::SpiFlashMemory::select_die
+pub struct RowAddress<Chip: FlashMemoryChip>([u8; 3], u8, PhantomData<Chip>);
 impl<Chip: FlashMemoryChip> RowAddress<Chip>
 {
-	/// Returns a [`RowAddress`] from the address of a byte in the memory.
+	/// Returns a [`RowAddress`] from the index of a page in the memory.
 	pub const fn from_page_index(page_index: u32) -> Self
 	{
-		let bytes = page_index.to_be_bytes();
-		Self([bytes[1], bytes[2], bytes[3]], PhantomData)
+		let bytes = (page_index % Chip::PAGES_PER_LUN).to_be_bytes();
+		Self(
+			[bytes[1], bytes[2], bytes[3]],
+			(page_index / Chip::PAGES_PER_LUN) as u8,
+			PhantomData,
+		)
 	}
 
 	/// Returns a [`RowAddress`] from the address of a byte in the memory.
@@ -44,7 +53,7 @@ impl<Chip: FlashMemoryChip> RowAddress<Chip>
 		Self::from_page_index(address / Chip::PAGE_SIZE)
 	}
 
-	/// Returns the row address as bytes that can be sent over SPI.
+	/// Returns the row address as bytes that can be sent over SPI, which identify the page in its LUN.
 	pub const fn as_bytes(&self) -> &[u8; 3]
 	{
 		&self.0
@@ -53,9 +62,16 @@ impl<Chip: FlashMemoryChip> RowAddress<Chip>
 	/// Returns the index of the page identified by this address.
 	pub fn get_page_index(&self) -> u32
 	{
-		u32::from_be_bytes(core::array::from_fn(|i| {
+		let page_index_in_lun = u32::from_be_bytes(core::array::from_fn(|i| {
 			self.0.get(i.overflowing_sub(1).0).copied().unwrap_or(0)
-		}))
+		}));
+		self.1 as u32 * Chip::PAGES_PER_LUN + page_index_in_lun
+	}
+
+	/// Returns the index of the LUN (die) that contains the page identified by this address.
+	pub const fn get_lun_index(&self) -> u8
+	{
+		self.1
 	}
 
 	/// Returns the index of the plane of the page identified by this address.
@@ -86,7 +102,7 @@ impl<Chip: FlashMemoryChip> PartialEq for RowAddress<Chip>
 {
 	fn eq(&self, other: &Self) -> bool
 	{
-		self.0 == other.0
+		self.0 == other.0 && self.1 == other.1
 	}
 }
 impl<Chip: FlashMemoryChip> Eq for RowAddress<Chip> {}
@@ -95,14 +111,17 @@ impl<Chip: FlashMemoryChip> Debug for RowAddress<Chip>
 {
 	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
 	{
-		f.debug_tuple("RowAddress").field(&self.0).finish()
+		f.debug_tuple("RowAddress").field(&self.0).field(&self.1).finish()
 	}
 }
 
 #[cfg(test)]
 mod tests
 {
-	use super::{super::MT29F2G01ABAGDWB, *};
+	use super::{
+		super::{MT29F2G01ABAGDWB, MT29F4G01ADAGDWB},
+		*,
+	};
 
 	#[test]
 	fn row_address_instantation()
@@ -116,4 +135,17 @@ mod tests
 			);
 		}
 	}
+
+	#[test]
+	fn row_address_of_multiple_luns()
+	{
+		type Chip = MT29F4G01ADAGDWB;
+
+		let row_address = RowAddress::<Chip>::from_page_index(Chip::PAGES_PER_LUN + 65);
+		assert_eq!(row_address.get_lun_index(), 1);
+		assert_eq!(row_address.as_bytes(), &[0, 0, 65]);
+		assert_eq!(row_address.get_page_index(), Chip::PAGES_PER_LUN + 65);
+		assert_eq!(row_address.get_plane_index(), 1);
+		assert_ne!(row_address, RowAddress::from_page_index(65));
+	}
 }
@@ -0,0 +1,840 @@
+//! A [`bad block`] manager that hides the bad blocks of a [`FlashMemory`], replacing them with some good blocks
+//! reserved for that purpose.
+//!
+//! ## Layout
+//! The blocks of the flash memory are split in 3 areas:
+//! - the usable blocks, which are the only ones that can be accessed through the [`BadBlockManager`] (starting
+//!   from the block at index 0).
+//! - the replacement pool, which contains the blocks used to replace the bad usable blocks.
+//! - the last [`BadBlockManager::TABLE_BLOCKS`] blocks, 2 good ones of which are used to store the bad block table.
+//!
+//! ## Bad block table
+//! The first time the flash memory is [`mounted`], all the blocks are scanned to find the bad block marks written by
+//! the manufacturer, and the result is saved in the bad block table. The following times the table is just read.
+//!
+//! When a program or erase operation fails because the block is worn out (check [`FlashMemory::is_block_failure`]),
+//! the block is marked as bad, the pages already written in it are copied in a replacement block, the table is
+//! updated and the operation is retried in the replacement block.
+//!
+//! [`bad block`]: <https://en.wikipedia.org/wiki/Flash_memory#Memory_wear>
+//! [`mounted`]: BadBlockManager::mount
+
+use core::{fmt::Debug, ops::RangeInclusive};
+
+use super::{EccStatus, FlashMemory, FlashMemoryChip, FlashMemoryChipExt, RowAddress};
+use crate::utils::algorithms::crc::crc32;
+
+const TABLE_MAGIC: u32 = u32::from_le_bytes(*b"BBT1");
+const TABLE_HEADER_SIZE: usize = 12;
+const TABLE_ENTRY_SIZE: usize = 4;
+const NO_REPLACEMENT: u16 = u16::MAX;
+
+/// Manages the bad blocks of a [`FlashMemory`], which can contain at most `MAX_BAD_BLOCKS` bad blocks.
+///
+/// It implements [`FlashMemory`] itself, so [`program`], [`read`] and [`erase_blocks`] can be routed through it (and
+/// a [`flash translation layer`] can be built on top of it), and they will never access a bad block.
+///
+/// Check [`module's documentation`](self) for more info.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::spi_flash_memory::{*, bad_block_manager::*}, hardware::mock::MockFlashMemory};
+/// #
+/// let mut page_buffer = [0; (MT29F2G01ABAGDWB::PAGE_SIZE + MT29F2G01ABAGDWB::PAGE_ECC_SIZE) as usize];
+/// let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::with_factory_bad_blocks(&[3]);
+/// let mut bad_block_manager = BadBlockManager::<_, 40>::new(flash_memory, 20, &mut page_buffer);
+/// bad_block_manager.mount().unwrap();
+///
+/// // The block at index 3 is bad, but it has been replaced
+/// assert!(!bad_block_manager.contains_bad_block_mark(3).unwrap());
+/// bad_block_manager.program(b"Hello", MT29F2G01ABAGDWB::get_address_of_block_index(3)).unwrap();
+/// assert_eq!(bad_block_manager.get_bad_blocks().collect::<Vec<_>>(), [3]);
+/// ```
+///
+/// [`program`]: FlashMemory::program
+/// [`read`]: FlashMemory::read
+/// [`erase_blocks`]: FlashMemory::erase_blocks
+/// [`flash translation layer`]: super::ftl::FlashTranslationLayer
+pub struct BadBlockManager<'a, F: FlashMemory, const MAX_BAD_BLOCKS: usize>
+{
+	flash: F,
+	page_buffer: &'a mut [u8],
+	replacement_blocks_count: u16,
+	is_mounted: bool,
+
+	bad_blocks: [BadBlock; MAX_BAD_BLOCKS],
+	bad_blocks_count: usize,
+
+	table_blocks: [u16; 2],
+	table_block_in_use: usize,
+	table_next_page: u32,
+	table_version: u32,
+}
+
+impl<'a, F: FlashMemory, const MAX_BAD_BLOCKS: usize> BadBlockManager<'a, F, MAX_BAD_BLOCKS>
+{
+	/// How many blocks at the end of the flash memory are reserved for the bad block table.
+	pub const TABLE_BLOCKS: u16 = 4;
+
+	const TOTAL_BLOCKS: u16 = F::Chip::BLOCKS_COUNT as u16;
+
+	/// Returns a [`BadBlockManager`] that reserves `replacement_blocks_count` blocks to replace the bad ones.
+	/// The `page_buffer` is used to copy the pages of a block that goes bad.
+	///
+	/// # Warning
+	/// Before using it, you need to [`mount`] it.
+	///
+	/// # Panics
+	/// Panics if `page_buffer.len() < Chip::PAGE_SIZE + Chip::PAGE_ECC_SIZE`, if the reserved blocks are more than
+	/// the blocks in the flash memory, or if a bad block table with `MAX_BAD_BLOCKS` entries can't fit in a page.
+	///
+	/// [`mount`]: Self::mount
+	pub fn new(flash: F, replacement_blocks_count: u16, page_buffer: &'a mut [u8]) -> Self
+	{
+		assert!(page_buffer.len() as u32 >= F::Chip::PAGE_SIZE + F::Chip::PAGE_ECC_SIZE);
+		assert!(replacement_blocks_count + Self::TABLE_BLOCKS < Self::TOTAL_BLOCKS);
+		assert!(TABLE_HEADER_SIZE + MAX_BAD_BLOCKS * TABLE_ENTRY_SIZE + 4 <= F::Chip::PAGE_SIZE as usize);
+
+		Self {
+			flash,
+			page_buffer,
+			replacement_blocks_count,
+			is_mounted: false,
+			bad_blocks: [BadBlock {
+				block: 0,
+				replacement: NO_REPLACEMENT,
+			}; MAX_BAD_BLOCKS],
+			bad_blocks_count: 0,
+			table_blocks: [0; 2],
+			table_block_in_use: 0,
+			table_next_page: 0,
+			table_version: 0,
+		}
+	}
+
+	/// Reads the bad block table from the flash memory. If there isn't one (for example the first time the flash memory
+	/// is used), all the blocks are scanned to find the bad block marks and a new table is saved.
+	///
+	/// # Warning
+	/// The bad block marks written by the manufacturer are lost when a block is erased, so make sure this is
+	/// called before erasing any block of a new flash memory.
+	pub fn mount(&mut self) -> Result<(), Error<F>>
+	{
+		self.is_mounted = false;
+		self.bad_blocks_count = 0;
+		self.table_version = 0;
+
+		let mut good_table_blocks = 0;
+		for block in (Self::TOTAL_BLOCKS - Self::TABLE_BLOCKS)..Self::TOTAL_BLOCKS
+		{
+			if good_table_blocks < self.table_blocks.len()
+				&& !self.flash.contains_bad_block_mark(block).map_err(Error::Flash)?
+			{
+				self.table_blocks[good_table_blocks] = block;
+				good_table_blocks += 1;
+			}
+		}
+		if good_table_blocks < self.table_blocks.len()
+		{
+			return Err(Error::NotEnoughBlocks);
+		}
+
+		if !self.load_table()?
+		{
+			self.scan_bad_block_marks()?;
+
+			// Makes the next write erase the first table block
+			self.table_block_in_use = 1;
+			self.table_next_page = F::Chip::PAGES_PER_BLOCK;
+			self.write_table()?;
+		}
+
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Returns how many blocks can be accessed through this manager (the blocks with index from 0 to the returned
+	/// value excluded).
+	pub fn get_usable_blocks_count(&self) -> u16
+	{
+		Self::TOTAL_BLOCKS - Self::TABLE_BLOCKS - self.replacement_blocks_count
+	}
+
+	/// Returns an iterator over the indices of all the bad blocks (excluding the ones reserved for the bad block
+	/// table).
+	pub fn get_bad_blocks(&self) -> impl Iterator<Item = u16> + '_
+	{
+		self.bad_blocks[..self.bad_blocks_count]
+			.iter()
+			.map(|bad_block| bad_block.block)
+	}
+
+	/// Marks the block at the provided `block_index` as bad and replaces it with a block of the replacement pool,
+	/// copying all its pages.
+	///
+	/// It's useful when you know a block is going bad even if no operation failed (for example if the ECC can't
+	/// correct a read anymore).
+	pub fn mark_bad(&mut self, block_index: u16) -> Result<(), Error<F>>
+	{
+		self.check_mounted()?;
+
+		self.replace_block(block_index, F::Chip::PAGES_PER_BLOCK)
+	}
+
+	/// Returns a mutable reference to the underlying flash memory.
+	///
+	/// # Warning
+	/// The indices of the blocks in the underlying flash memory aren't remapped.
+	pub fn get_flash_mut(&mut self) -> &mut F
+	{
+		&mut self.flash
+	}
+
+	/// Consumes this struct and returns the underlying flash memory.
+	pub fn release(self) -> F
+	{
+		self.flash
+	}
+
+	fn check_mounted(&self) -> Result<(), Error<F>>
+	{
+		match self.is_mounted
+		{
+			true => Ok(()),
+			false => Err(Error::NotMounted),
+		}
+	}
+
+	fn find_bad_block(&self, block_index: u16) -> Option<usize>
+	{
+		self.bad_blocks[..self.bad_blocks_count]
+			.iter()
+			.position(|bad_block| bad_block.block == block_index)
+	}
+
+	fn add_bad_block(&mut self, bad_block: BadBlock) -> Result<(), Error<F>>
+	{
+		if self.bad_blocks_count == MAX_BAD_BLOCKS
+		{
+			return Err(Error::TooManyBadBlocks);
+		}
+		self.bad_blocks[self.bad_blocks_count] = bad_block;
+		self.bad_blocks_count += 1;
+
+		Ok(())
+	}
+
+	/// Returns the index of the block in the underlying flash memory that replaces the usable block at the provided
+	/// `block_index`.
+	fn get_physical_block(&self, block_index: u16) -> Result<u16, Error<F>>
+	{
+		self.check_mounted()?;
+		if block_index >= self.get_usable_blocks_count()
+		{
+			return Err(Error::OutOfRange);
+		}
+
+		match self.find_bad_block(block_index)
+		{
+			None => Ok(block_index),
+			Some(i) => match self.bad_blocks[i].replacement
+			{
+				NO_REPLACEMENT => Err(Error::NoReplacementBlock),
+				replacement => Ok(replacement),
+			},
+		}
+	}
+
+	fn get_physical_address(&self, address: u32) -> Result<u32, Error<F>>
+	{
+		let block_index = F::Chip::get_block_index_of_address(address);
+		let physical_block = self.get_physical_block(block_index)?;
+
+		Ok(F::Chip::get_address_of_block_index(physical_block) + address % F::Chip::BLOCK_SIZE)
+	}
+
+	fn get_physical_row_address(&self, row_address: RowAddress<F::Chip>) -> Result<RowAddress<F::Chip>, Error<F>>
+	{
+		let page_index = row_address.get_page_index();
+		let physical_block = self.get_physical_block((page_index / F::Chip::PAGES_PER_BLOCK) as u16)?;
+
+		Ok(RowAddress::from_page_index(
+			physical_block as u32 * F::Chip::PAGES_PER_BLOCK + page_index % F::Chip::PAGES_PER_BLOCK,
+		))
+	}
+
+	/// Executes the provided `operation` on the block of the underlying flash memory that replaces the usable block at
+	/// the provided `block_index`. If it fails because the block is worn out, the block is replaced (copying its first
+	/// `pages_to_keep` pages) and the `operation` is retried.
+	fn execute_on_block(
+		&mut self, block_index: u16, pages_to_keep: u32, mut operation: impl FnMut(&mut F, u16) -> Result<(), F::Error>,
+	) -> Result<(), Error<F>>
+	{
+		loop
+		{
+			let physical_block = self.get_physical_block(block_index)?;
+			match operation(&mut self.flash, physical_block)
+			{
+				Ok(()) => return Ok(()),
+				Err(error) if F::is_block_failure(&error) => self.replace_block(block_index, pages_to_keep)?,
+				Err(error) => return Err(Error::Flash(error)),
+			}
+		}
+	}
+
+	/// Replaces the usable block at the provided `block_index` with a block of the replacement pool, copying its first
+	/// `pages_to_keep` pages.
+	fn replace_block(&mut self, block_index: u16, pages_to_keep: u32) -> Result<(), Error<F>>
+	{
+		let old_block = self.get_physical_block(block_index)?;
+		self.write_bad_block_mark(old_block);
+		if old_block != block_index
+		{
+			// The old block was itself a replacement
+			self.add_bad_block(BadBlock {
+				block: old_block,
+				replacement: NO_REPLACEMENT,
+			})?;
+		}
+
+		let replacement = loop
+		{
+			let Some(new_block) = self.find_free_replacement_block()
+			else
+			{
+				break NO_REPLACEMENT;
+			};
+
+			match self.copy_block(old_block, new_block, pages_to_keep)
+			{
+				Ok(()) => break new_block,
+				Err(Error::Flash(error)) if F::is_block_failure(&error) =>
+				{
+					self.write_bad_block_mark(new_block);
+					self.add_bad_block(BadBlock {
+						block: new_block,
+						replacement: NO_REPLACEMENT,
+					})?;
+				},
+				Err(error) => return Err(error),
+			}
+		};
+
+		match self.find_bad_block(block_index)
+		{
+			Some(i) => self.bad_blocks[i].replacement = replacement,
+			None => self.add_bad_block(BadBlock {
+				block: block_index,
+				replacement,
+			})?,
+		}
+		self.write_table()?;
+
+		match replacement
+		{
+			NO_REPLACEMENT => Err(Error::NoReplacementBlock),
+			_ => Ok(()),
+		}
+	}
+
+	/// Returns the first block of the replacement pool that is neither bad nor already used.
+	fn find_free_replacement_block(&self) -> Option<u16>
+	{
+		let usable_blocks_count = self.get_usable_blocks_count();
+		(usable_blocks_count..(usable_blocks_count + self.replacement_blocks_count)).find(|block| {
+			!self.bad_blocks[..self.bad_blocks_count]
+				.iter()
+				.any(|bad_block| bad_block.block == *block || bad_block.replacement == *block)
+		})
+	}
+
+	/// Erases the `to_block` and copies the first `pages_to_copy` pages (both the data and the ECC area) of the
+	/// `from_block` in it.
+	fn copy_block(&mut self, from_block: u16, to_block: u16, pages_to_copy: u32) -> Result<(), Error<F>>
+	{
+		self.flash.erase_blocks(to_block..=to_block).map_err(Error::Flash)?;
+
+		let (data, ecc_data) = self.page_buffer[..(F::Chip::PAGE_SIZE + F::Chip::PAGE_ECC_SIZE) as usize]
+			.split_at_mut(F::Chip::PAGE_SIZE as usize);
+		for page in 0..pages_to_copy
+		{
+			let from_page = from_block as u32 * F::Chip::PAGES_PER_BLOCK + page;
+			let data_ecc_status = self
+				.flash
+				.read(from_page * F::Chip::PAGE_SIZE, data)
+				.map_err(Error::Flash)?;
+			let ecc_data_ecc_status = self
+				.flash
+				.read_ecc(RowAddress::from_page_index(from_page), ecc_data)
+				.map_err(Error::Flash)?;
+			if data_ecc_status.max(ecc_data_ecc_status) == EccStatus::Uncorrectable
+			{
+				return Err(Error::Uncorrectable);
+			}
+
+			if data.iter().chain(ecc_data.iter()).any(|byte| *byte != 0xFF)
+			{
+				let to_page = to_block as u32 * F::Chip::PAGES_PER_BLOCK + page;
+				self.flash
+					.program_page(RowAddress::from_page_index(to_page), data, ecc_data)
+					.map_err(Error::Flash)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Writes a bad block mark in the block at the provided `block_index` of the underlying flash memory, so that the
+	/// block is found even if the bad block table is lost.
+	fn write_bad_block_mark(&mut self, block_index: u16)
+	{
+		let row_address = RowAddress::from_page_index(block_index as u32 * F::Chip::PAGES_PER_BLOCK);
+
+		// The block is bad, so this could fail, but the bad block table is enough to know that it's bad
+		let _ = self.flash.program_page(row_address, &[], &[0x00]);
+	}
+
+	/// Adds all the blocks (except the ones reserved for the table) that contain a bad block mark to the bad block
+	/// table, and replaces the bad usable blocks.
+	fn scan_bad_block_marks(&mut self) -> Result<(), Error<F>>
+	{
+		for block in 0..(Self::TOTAL_BLOCKS - Self::TABLE_BLOCKS)
+		{
+			if self.flash.contains_bad_block_mark(block).map_err(Error::Flash)?
+			{
+				self.add_bad_block(BadBlock {
+					block,
+					replacement: NO_REPLACEMENT,
+				})?;
+			}
+		}
+
+		for i in 0..self.bad_blocks_count
+		{
+			if self.bad_blocks[i].block < self.get_usable_blocks_count()
+			{
+				if let Some(replacement) = self.find_free_replacement_block()
+				{
+					self.bad_blocks[i].replacement = replacement;
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Loads the newest valid bad block table in the flash memory.
+	///
+	/// Returns `Ok(true)` if there was one, otherwise `Ok(false)`.
+	fn load_table(&mut self) -> Result<bool, Error<F>>
+	{
+		let mut newest_table: Option<(u32, usize, u32)> = None;
+		let mut table_blocks_end = [0; 2];
+		for (i, table_block_end) in table_blocks_end.iter_mut().enumerate()
+		{
+			for page in 0..F::Chip::PAGES_PER_BLOCK
+			{
+				match self.read_table_page(self.table_blocks[i], page)?
+				{
+					TablePage::Erased => break,
+					TablePage::Valid { version } if newest_table.is_none_or(|(newest, _, _)| version > newest) =>
+					{
+						newest_table = Some((version, i, page))
+					},
+					_ => (),
+				}
+				*table_block_end = page + 1;
+			}
+		}
+
+		let Some((version, table_block_in_use, page)) = newest_table
+		else
+		{
+			return Ok(false);
+		};
+
+		self.read_table_page(self.table_blocks[table_block_in_use], page)?;
+		let count = u32::from_le_bytes(self.page_buffer[8..12].try_into().unwrap()) as usize;
+		for i in 0..count
+		{
+			let entry = &self.page_buffer[(TABLE_HEADER_SIZE + i * TABLE_ENTRY_SIZE)..];
+			self.bad_blocks[i] = BadBlock {
+				block: u16::from_le_bytes([entry[0], entry[1]]),
+				replacement: u16::from_le_bytes([entry[2], entry[3]]),
+			};
+		}
+		self.bad_blocks_count = count;
+		self.table_version = version;
+		self.table_block_in_use = table_block_in_use;
+		self.table_next_page = table_blocks_end[table_block_in_use];
+
+		Ok(true)
+	}
+
+	/// Reads the `page` of the `table_block` in the page buffer, and checks if it contains a valid bad block table.
+	fn read_table_page(&mut self, table_block: u16, page: u32) -> Result<TablePage, Error<F>>
+	{
+		let buffer = &mut self.page_buffer[..F::Chip::PAGE_SIZE as usize];
+		self.flash
+			.read(
+				F::Chip::get_address_of_block_index(table_block) + page * F::Chip::PAGE_SIZE,
+				buffer,
+			)
+			.map_err(Error::Flash)?;
+
+		let word = |offset: usize| u32::from_le_bytes(buffer[offset..(offset + 4)].try_into().unwrap());
+		if buffer.iter().all(|byte| *byte == 0xFF)
+		{
+			return Ok(TablePage::Erased);
+		}
+
+		let count = word(8) as usize;
+		if word(0) != TABLE_MAGIC || count > MAX_BAD_BLOCKS
+		{
+			return Ok(TablePage::Invalid);
+		}
+
+		let crc_offset = TABLE_HEADER_SIZE + count * TABLE_ENTRY_SIZE;
+		match crc32(&buffer[..crc_offset]) == word(crc_offset)
+		{
+			true => Ok(TablePage::Valid { version: word(4) }),
+			false => Ok(TablePage::Invalid),
+		}
+	}
+
+	/// Writes the bad block table in the next free page of the table blocks.
+	fn write_table(&mut self) -> Result<(), Error<F>>
+	{
+		if self.table_next_page >= F::Chip::PAGES_PER_BLOCK
+		{
+			// The older table block is erased, so the newest table is never lost
+			self.table_block_in_use = 1 - self.table_block_in_use;
+			let table_block = self.table_blocks[self.table_block_in_use];
+			self.flash
+				.erase_blocks(table_block..=table_block)
+				.map_err(Error::Flash)?;
+			self.table_next_page = 0;
+		}
+
+		self.table_version += 1;
+
+		let buffer = &mut self.page_buffer[..F::Chip::PAGE_SIZE as usize];
+		buffer.fill(0xFF);
+		buffer[0..4].copy_from_slice(&TABLE_MAGIC.to_le_bytes());
+		buffer[4..8].copy_from_slice(&self.table_version.to_le_bytes());
+		buffer[8..12].copy_from_slice(&(self.bad_blocks_count as u32).to_le_bytes());
+		for (i, bad_block) in self.bad_blocks[..self.bad_blocks_count].iter().enumerate()
+		{
+			let entry = &mut buffer[(TABLE_HEADER_SIZE + i * TABLE_ENTRY_SIZE)..];
+			entry[0..2].copy_from_slice(&bad_block.block.to_le_bytes());
+			entry[2..4].copy_from_slice(&bad_block.replacement.to_le_bytes());
+		}
+		let crc_offset = TABLE_HEADER_SIZE + self.bad_blocks_count * TABLE_ENTRY_SIZE;
+		let crc = crc32(&buffer[..crc_offset]);
+		buffer[crc_offset..(crc_offset + 4)].copy_from_slice(&crc.to_le_bytes());
+
+		let row_address = RowAddress::from_page_index(
+			self.table_blocks[self.table_block_in_use] as u32 * F::Chip::PAGES_PER_BLOCK + self.table_next_page,
+		);
+		self.table_next_page += 1;
+		self.flash.program_page(row_address, buffer, &[]).map_err(Error::Flash)
+	}
+}
+
+impl<'a, F: FlashMemory, const MAX_BAD_BLOCKS: usize> FlashMemory for BadBlockManager<'a, F, MAX_BAD_BLOCKS>
+{
+	type Chip = F::Chip;
+	type Error = Error<F>;
+
+	/// Check [`FlashMemory::program`].
+	///
+	/// # Note
+	/// If the block goes bad while programming a page, the data programmed before in that page (with a partial
+	/// program) is lost. The other pages of the block are copied in the replacement block.
+	fn program(&mut self, data: &[u8], address: u32) -> Result<(), Self::Error>
+	{
+		let mut written_bytes = 0;
+		while written_bytes < data.len()
+		{
+			let current_address = address + written_bytes as u32;
+			let bytes_to_write =
+				((F::Chip::PAGE_SIZE - current_address % F::Chip::PAGE_SIZE) as usize).min(data.len() - written_bytes);
+			let chunk = &data[written_bytes..(written_bytes + bytes_to_write)];
+			let block_offset = current_address % F::Chip::BLOCK_SIZE;
+
+			self.execute_on_block(
+				F::Chip::get_block_index_of_address(current_address),
+				block_offset / F::Chip::PAGE_SIZE,
+				|flash, physical_block| {
+					flash.program(
+						chunk,
+						F::Chip::get_address_of_block_index(physical_block) + block_offset,
+					)
+				},
+			)?;
+
+			written_bytes += bytes_to_write;
+		}
+
+		Ok(())
+	}
+
+	fn program_page(
+		&mut self, row_address: RowAddress<Self::Chip>, data: &[u8], ecc_data: &[u8],
+	) -> Result<(), Self::Error>
+	{
+		let page_index = row_address.get_page_index();
+		let page_in_block = page_index % F::Chip::PAGES_PER_BLOCK;
+
+		self.execute_on_block(
+			(page_index / F::Chip::PAGES_PER_BLOCK) as u16,
+			page_in_block,
+			|flash, physical_block| {
+				let row_address =
+					RowAddress::from_page_index(physical_block as u32 * F::Chip::PAGES_PER_BLOCK + page_in_block);
+				flash.program_page(row_address, data, ecc_data)
+			},
+		)
+	}
+
+	fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		let mut ecc_status = EccStatus::NoErrors;
+		let mut read_bytes = 0;
+		while read_bytes < data.len()
+		{
+			let current_address = address + read_bytes as u32;
+			let bytes_to_read =
+				((F::Chip::BLOCK_SIZE - current_address % F::Chip::BLOCK_SIZE) as usize).min(data.len() - read_bytes);
+
+			let physical_address = self.get_physical_address(current_address)?;
+			let chunk_ecc_status = self
+				.flash
+				.read(physical_address, &mut data[read_bytes..(read_bytes + bytes_to_read)])
+				.map_err(Error::Flash)?;
+			ecc_status = ecc_status.max(chunk_ecc_status);
+
+			read_bytes += bytes_to_read;
+		}
+
+		Ok(ecc_status)
+	}
+
+	fn read_ecc(&mut self, row_address: RowAddress<Self::Chip>, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		let physical_row_address = self.get_physical_row_address(row_address)?;
+		self.flash.read_ecc(physical_row_address, data).map_err(Error::Flash)
+	}
+
+	fn erase_blocks(&mut self, block_indices_to_erase: RangeInclusive<u16>) -> Result<(), Self::Error>
+	{
+		for block_index in block_indices_to_erase
+		{
+			self.execute_on_block(block_index, 0, |flash, physical_block| {
+				flash.erase_blocks(physical_block..=physical_block)
+			})?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns `Ok(true)` only if the block at the provided `block_index` is bad and there wasn't any block left in the
+	/// replacement pool to replace it.
+	fn contains_bad_block_mark(&mut self, block_index: u16) -> Result<bool, Self::Error>
+	{
+		match self.get_physical_block(block_index)
+		{
+			Ok(_) => Ok(false),
+			Err(Error::NoReplacementBlock) => Ok(true),
+			Err(error) => Err(error),
+		}
+	}
+}
+
+/// An error returned from a [`BadBlockManager`].
+pub enum Error<F: FlashMemory>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(F::Error),
+	/// You need to [`mount`](BadBlockManager::mount) the bad block manager before using it.
+	NotMounted,
+	/// The accessed block isn't one of the usable blocks.
+	OutOfRange,
+	/// There aren't enough good blocks to store the bad block table.
+	NotEnoughBlocks,
+	/// The accessed block is bad, and there wasn't any block left in the replacement pool to replace it.
+	NoReplacementBlock,
+	/// There are more than `MAX_BAD_BLOCKS` bad blocks.
+	TooManyBadBlocks,
+	/// A page of the block that was being replaced contains more flipped bits than the ECC can correct, so it
+	/// couldn't be copied in the replacement block.
+	Uncorrectable,
+}
+
+impl<F: FlashMemory> Debug for Error<F>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Flash(arg0) => f.debug_tuple("Flash").field(arg0).finish(),
+			Self::NotMounted => write!(f, "NotMounted"),
+			Self::OutOfRange => write!(f, "OutOfRange"),
+			Self::NotEnoughBlocks => write!(f, "NotEnoughBlocks"),
+			Self::NoReplacementBlock => write!(f, "NoReplacementBlock"),
+			Self::TooManyBadBlocks => write!(f, "TooManyBadBlocks"),
+			Self::Uncorrectable => write!(f, "Uncorrectable"),
+		}
+	}
+}
+
+/// An entry of the bad block table.
+///
+/// If `block` is one of the usable blocks, `replacement` is the block of the replacement pool that replaces it (or
+/// [`NO_REPLACEMENT`] if the pool was empty). Otherwise `block` is a bad block of the replacement pool, and
+/// `replacement` is always [`NO_REPLACEMENT`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct BadBlock
+{
+	block: u16,
+	replacement: u16,
+}
+
+enum TablePage
+{
+	Erased,
+	Valid
+	{
+		version: u32,
+	},
+	Invalid,
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::{super::MT29F2G01ABAGDWB, *};
+	use crate::hardware::mock::MockFlashMemory;
+
+	const PAGE_SIZE: u32 = MT29F2G01ABAGDWB::PAGE_SIZE;
+	const BUFFER_SIZE: usize = (MT29F2G01ABAGDWB::PAGE_SIZE + MT29F2G01ABAGDWB::PAGE_ECC_SIZE) as usize;
+
+	fn address_of_page(block_index: u16, page_in_block: u32) -> u32
+	{
+		MT29F2G01ABAGDWB::get_address_of_block_index(block_index) + page_in_block * PAGE_SIZE
+	}
+
+	#[test]
+	fn factory_bad_blocks_are_replaced()
+	{
+		let mut page_buffer = [0; BUFFER_SIZE];
+		let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::with_factory_bad_blocks(&[0, 7, 2030, 2047]);
+		let mut bad_block_manager = BadBlockManager::<_, 16>::new(flash_memory, 8, &mut page_buffer);
+		assert!(matches!(bad_block_manager.read(0, &mut [0]), Err(Error::NotMounted)));
+		bad_block_manager.mount().unwrap();
+
+		assert_eq!(bad_block_manager.get_usable_blocks_count(), 2036);
+		assert_eq!(bad_block_manager.get_bad_blocks().collect::<Vec<_>>(), [0, 7, 2030]);
+		assert!(!bad_block_manager.contains_bad_block_mark(0).unwrap());
+		assert!(matches!(
+			bad_block_manager.contains_bad_block_mark(2036),
+			Err(Error::OutOfRange)
+		));
+
+		bad_block_manager.erase_blocks(0..=7).unwrap();
+		bad_block_manager.program(b"Hello", address_of_page(7, 1)).unwrap();
+
+		let mut flash_memory = bad_block_manager.release();
+		assert_eq!(flash_memory.get_erase_count(0), 0);
+		assert_eq!(flash_memory.get_erase_count(7), 0);
+		let mut data = [0; 5];
+		flash_memory.read(address_of_page(2037, 1), &mut data).unwrap();
+		assert_eq!(&data, b"Hello");
+	}
+
+	#[test]
+	fn runtime_bad_blocks_are_replaced_and_remembered()
+	{
+		let mut page_buffer = [0; BUFFER_SIZE];
+		let mut bad_block_manager =
+			BadBlockManager::<_, 16>::new(MockFlashMemory::<MT29F2G01ABAGDWB>::new(), 8, &mut page_buffer);
+		bad_block_manager.mount().unwrap();
+
+		bad_block_manager.program(b"Page 0", address_of_page(10, 0)).unwrap();
+		bad_block_manager
+			.program_page(RowAddress::from_page_index(10 * 64 + 1), b"Page 1", &[0xFF, 1, 2])
+			.unwrap();
+
+		bad_block_manager.get_flash_mut().wear_out_block(10);
+		bad_block_manager.program(b"Page 2", address_of_page(10, 2)).unwrap();
+		assert_eq!(bad_block_manager.get_bad_blocks().collect::<Vec<_>>(), [10]);
+
+		// The replacement block wears out too
+		bad_block_manager.get_flash_mut().wear_out_block(2036);
+		bad_block_manager.erase_blocks(11..=11).unwrap();
+		bad_block_manager.program(b"Page 3", address_of_page(10, 3)).unwrap();
+		assert_eq!(bad_block_manager.get_bad_blocks().collect::<Vec<_>>(), [10, 2036]);
+
+		// The bad block table is read again from the flash memory
+		let flash_memory = bad_block_manager.release();
+		let mut bad_block_manager = BadBlockManager::<_, 16>::new(flash_memory, 8, &mut page_buffer);
+		bad_block_manager.mount().unwrap();
+		assert_eq!(bad_block_manager.get_bad_blocks().collect::<Vec<_>>(), [10, 2036]);
+
+		for (page, expected_data) in [b"Page 0", b"Page 1", b"Page 2", b"Page 3"].iter().enumerate()
+		{
+			let mut data = [0; 6];
+			bad_block_manager
+				.read(address_of_page(10, page as u32), &mut data)
+				.unwrap();
+			assert_eq!(&&data, expected_data);
+		}
+		let mut ecc_data = [0; 3];
+		bad_block_manager
+			.read_ecc(RowAddress::from_page_index(10 * 64 + 1), &mut ecc_data)
+			.unwrap();
+		assert_eq!(ecc_data, [0xFF, 1, 2]);
+	}
+
+	#[test]
+	fn uncorrectable_pages_arent_copied()
+	{
+		let mut page_buffer = [0; BUFFER_SIZE];
+		let mut bad_block_manager =
+			BadBlockManager::<_, 16>::new(MockFlashMemory::<MT29F2G01ABAGDWB>::new(), 8, &mut page_buffer);
+		bad_block_manager.mount().unwrap();
+
+		bad_block_manager.program(b"Page 0", address_of_page(10, 0)).unwrap();
+		bad_block_manager
+			.get_flash_mut()
+			.set_ecc_status(10 * 64, EccStatus::Uncorrectable);
+
+		// Replacing the worn out block needs to copy the corrupted page
+		bad_block_manager.get_flash_mut().wear_out_block(10);
+		assert!(matches!(
+			bad_block_manager.program(b"Page 1", address_of_page(10, 1)),
+			Err(Error::Uncorrectable)
+		));
+	}
+
+	#[test]
+	fn replacement_pool_runs_out()
+	{
+		let mut page_buffer = [0; BUFFER_SIZE];
+		let mut bad_block_manager =
+			BadBlockManager::<_, 16>::new(MockFlashMemory::<MT29F2G01ABAGDWB>::new(), 1, &mut page_buffer);
+		bad_block_manager.mount().unwrap();
+
+		bad_block_manager.get_flash_mut().wear_out_block(1);
+		bad_block_manager.get_flash_mut().wear_out_block(2);
+		bad_block_manager.erase_blocks(1..=1).unwrap();
+		assert!(matches!(
+			bad_block_manager.erase_blocks(2..=2),
+			Err(Error::NoReplacementBlock)
+		));
+
+		assert!(!bad_block_manager.contains_bad_block_mark(1).unwrap());
+		assert!(bad_block_manager.contains_bad_block_mark(2).unwrap());
+	}
+}
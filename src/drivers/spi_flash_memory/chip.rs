@@ -1,7 +1,16 @@
-use embedded_hal::spi::{ErrorType, Mode, SpiDevice, MODE_0};
+use embedded_hal::{
+	delay::DelayNs,
+	spi::{Mode, SpiDevice, MODE_0},
+};
 
-use super::{address::RowAddress, FeatureRegister, SpiFlashMemory};
-use crate::utils::physical_quantities::frequency::*;
+use super::{
+	address::RowAddress, BlockLockRegister, ConfigurationRegister, EccStatus, FeatureRegisterValue, SpiFlashMemory,
+	SpiFlashMemoryError,
+};
+use crate::{
+	peripherals::{spi::BusWidth, time::system_time::SystemTime},
+	utils::physical_quantities::frequency::*,
+};
 
 /// A type that represents a [`flash memory chip`](https://en.wikipedia.org/wiki/Flash_memory).
 pub trait FlashMemoryChip
@@ -18,28 +27,56 @@ pub trait FlashMemoryChip
 	const PAGE_SIZE: u32;
 	/// Size of the ECC area of a page.
 	const PAGE_ECC_SIZE: u32;
+	/// Max number of times a page can be programmed before it needs to be erased again (the `NOP` in the datasheets).
+	///
+	/// The page is split in this many parts of the same size, each one programmed at most once.
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 1;
 
 	/// Preferred [`SPI mode`](https://en.wikipedia.org/wiki/Serial_Peripheral_Interface#Mode_numbers).
 	const SPI_MODE: Mode;
 	/// Max supported frequency of the SPI clock.
 	const MAX_CLOCK_FREQUENCY: Frequency;
+	/// Widest bus supported by the `READ FROM CACHE` commands (`x2` and `x4`).
+	const MAX_READ_BUS_WIDTH: BusWidth = BusWidth::Quad;
+	/// If `true`, the `QE` bit of the [`ConfigurationRegister`] must be set before the quad SPI commands can be used.
+	const REQUIRES_QUAD_ENABLE: bool = false;
+	/// If `true`, the chip supports the `READ PAGE CACHE RANDOM` and `READ PAGE CACHE LAST` commands, which read the
+	/// next page from the memory while the previous one is sent to the microcontroller.
+	const SUPPORTS_CACHE_READ: bool = false;
+	/// How the LUN (die) that receives the commands is selected, when [`LUNS_PER_DEVICE`] is more than 1.
+	///
+	/// [`LUNS_PER_DEVICE`]: FlashMemoryChip::LUNS_PER_DEVICE
+	const DIE_SELECTION: DieSelection = DieSelection::FeatureRegister;
 
 	const MANUFACTURER_ID: u8;
 	const DEVICE_ID: u8;
+	/// Bytes of the device ID sent by the chip after [`DEVICE_ID`](FlashMemoryChip::DEVICE_ID), for the chips
+	/// whose device ID is longer than 1 byte (at most 3 bytes).
+	const EXTENDED_DEVICE_ID: &'static [u8] = &[];
 
-	fn initialize<Spi: SpiDevice<u8>>(
-		spi_flash_memory: &mut SpiFlashMemory<Self, Spi>,
-	) -> Result<(), <Spi as ErrorType>::Error>
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
 	where Self: Sized;
 
 	/// Check if the block at the provided `block_index` in the provided `spi_flash_memory` contains a bad block mark.
 	///
 	/// Returns `Err(...)` if there is a problem in reading from the flash memory and it has been impossible to check if
 	/// the mark is there. Otherwise returns `Ok(contains_bad_block_mark)`.
-	fn contains_bad_block_mark<Spi: SpiDevice<u8>>(
-		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi>,
-	) -> Result<bool, <Spi as ErrorType>::Error>
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
 	where Self: Sized;
+
+	/// Returns the result of the on-die ECC encoded in the provided `status` (the value of the
+	/// [`FeatureRegister::Status`](super::FeatureRegister::Status) register after a page has been read).
+	///
+	/// The default implementation uses [`EccStatus::from_2_bits_status`], override it if the chip has a different
+	/// encoding.
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		EccStatus::from_2_bits_status(status)
+	}
 }
 
 /// Extra functionality provided automatically to every type that implements [`FlashMemoryChip`].
@@ -52,7 +89,16 @@ pub trait FlashMemoryChipExt: FlashMemoryChip
 	/// Size of the data area of a LUN.
 	const LUN_SIZE: u32 = Self::PLANES_PER_LUN * Self::PLANE_SIZE;
 	/// Size of the data area of the chip.
+	///
+	/// # Note
+	/// It doesn't fit in a `u32` for the chips of 4GiB or more, use [`Self::CAPACITY`] for those.
 	const MEMORY_SIZE: u32 = Self::LUNS_PER_DEVICE * Self::LUN_SIZE;
+	/// Size of the data area of the chip, as a `u64`.
+	const CAPACITY: u64 = Self::BLOCKS_COUNT as u64 * Self::BLOCK_SIZE as u64;
+	/// Number of pages contained in a LUN of the chip.
+	const PAGES_PER_LUN: u32 = Self::PLANES_PER_LUN * Self::BLOCKS_PER_PLANE * Self::PAGES_PER_BLOCK;
+	/// Number of blocks contained in the chip.
+	const BLOCKS_COUNT: u32 = Self::LUNS_PER_DEVICE * Self::PLANES_PER_LUN * Self::BLOCKS_PER_PLANE;
 
 	/// Returns the memory address of the first byte of the first page of the block at the
 	/// provided `block_index`.
@@ -69,6 +115,16 @@ pub trait FlashMemoryChipExt: FlashMemoryChip
 }
 impl<Chip: FlashMemoryChip> FlashMemoryChipExt for Chip {}
 
+/// How the LUN (die) that receives the commands is selected in the chips made of multiple LUNs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DieSelection
+{
+	/// The `DS0` bit of the [`DieSelectRegister`](super::DieSelectRegister), used by the Micron chips.
+	FeatureRegister,
+	/// The `SOFTWARE DIE SELECT` command (`C2h`) followed by the index of the die, used by the Winbond chips.
+	Command,
+}
+
 /// 2Gbit 3.3V NAND SPI flash memory chip ([datasheet]).
 ///
 /// [datasheet]: <https://datasheet.lcsc.com/lcsc/1912111437_Micron-Tech-MT29F2G01ABAGDWB-IT-G_C410863.pdf>
@@ -82,34 +138,471 @@ impl FlashMemoryChip for MT29F2G01ABAGDWB
 	const PAGES_PER_BLOCK: u32 = 64;
 	const PAGE_SIZE: u32 = 2048;
 	const PAGE_ECC_SIZE: u32 = 128;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
 
 	const SPI_MODE: Mode = MODE_0;
 	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(133_000_000);
+	const SUPPORTS_CACHE_READ: bool = true;
 
 	const MANUFACTURER_ID: u8 = 0x2C;
 	const DEVICE_ID: u8 = 0x24;
 
-	fn initialize<Spi: SpiDevice<u8>>(
-		spi_flash_memory: &mut SpiFlashMemory<Self, Spi>,
-	) -> Result<(), <Spi as ErrorType>::Error>
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		initialize_micron(spi_flash_memory)
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		// The bytes of a good block are erased (`0xFF`), while a bad block mark is `0x00`
+		contains_bad_block_mark_in_pages(block_index, 1, spi_flash_memory)
+	}
+
+	/// Check page 33 of the datasheet.
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		decode_micron_ecc_status(status)
+	}
+}
+
+/// 4Gbit 3.3V NAND SPI flash memory chip by Micron, made of 2 [`MT29F2G01ABAGDWB`] dies.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MT29F4G01ADAGDWB;
+impl FlashMemoryChip for MT29F4G01ADAGDWB
+{
+	const LUNS_PER_DEVICE: u32 = 2;
+	const PLANES_PER_LUN: u32 = 2;
+	const BLOCKS_PER_PLANE: u32 = 1024;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 128;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(133_000_000);
+	const SUPPORTS_CACHE_READ: bool = true;
+
+	const MANUFACTURER_ID: u8 = 0x2C;
+	const DEVICE_ID: u8 = 0x36;
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		initialize_micron(spi_flash_memory)
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		contains_bad_block_mark_in_pages(block_index, 1, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		decode_micron_ecc_status(status)
+	}
+}
+
+/// 1Gbit 3.3V NAND SPI flash memory chip by Winbond.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct W25N01GV;
+impl FlashMemoryChip for W25N01GV
+{
+	const LUNS_PER_DEVICE: u32 = 1;
+	const PLANES_PER_LUN: u32 = 1;
+	const BLOCKS_PER_PLANE: u32 = 1024;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 64;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(104_000_000);
+
+	const MANUFACTURER_ID: u8 = 0xEF;
+	const DEVICE_ID: u8 = 0xAA;
+	const EXTENDED_DEVICE_ID: &'static [u8] = &[0x21];
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		initialize_winbond(spi_flash_memory)
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		contains_bad_block_mark_in_pages(block_index, 1, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		// The ECC corrects 1 bit for each 528 bytes sector
+		decode_winbond_ecc_status(status, 1)
+	}
+}
+
+/// 2Gbit 3.3V NAND SPI flash memory chip by Winbond.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct W25N02KV;
+impl FlashMemoryChip for W25N02KV
+{
+	const LUNS_PER_DEVICE: u32 = 1;
+	const PLANES_PER_LUN: u32 = 1;
+	const BLOCKS_PER_PLANE: u32 = 2048;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 128;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(104_000_000);
+
+	const MANUFACTURER_ID: u8 = 0xEF;
+	const DEVICE_ID: u8 = 0xAA;
+	const EXTENDED_DEVICE_ID: &'static [u8] = &[0x22];
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		initialize_winbond(spi_flash_memory)
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		contains_bad_block_mark_in_pages(block_index, 1, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		decode_winbond_ecc_status(status, 8)
+	}
+}
+
+/// 2Gbit 3.3V NAND SPI flash memory chip by Winbond, made of 2 [`W25N01GV`] dies.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct W25M02GV;
+impl FlashMemoryChip for W25M02GV
+{
+	const LUNS_PER_DEVICE: u32 = 2;
+	const PLANES_PER_LUN: u32 = 1;
+	const BLOCKS_PER_PLANE: u32 = 1024;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 64;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(104_000_000);
+	const DIE_SELECTION: DieSelection = DieSelection::Command;
+
+	const MANUFACTURER_ID: u8 = 0xEF;
+	const DEVICE_ID: u8 = 0xAB;
+	const EXTENDED_DEVICE_ID: &'static [u8] = &[0x21];
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		initialize_winbond(spi_flash_memory)
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		contains_bad_block_mark_in_pages(block_index, 2, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		decode_winbond_ecc_status(status, 1)
+	}
+}
+
+/// 1Gbit 3.3V NAND SPI flash memory chip by GigaDevice.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GD5F1GQ4UB;
+impl FlashMemoryChip for GD5F1GQ4UB
+{
+	const LUNS_PER_DEVICE: u32 = 1;
+	const PLANES_PER_LUN: u32 = 1;
+	const BLOCKS_PER_PLANE: u32 = 1024;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 128;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(120_000_000);
+	const REQUIRES_QUAD_ENABLE: bool = true;
+
+	const MANUFACTURER_ID: u8 = 0xC8;
+	const DEVICE_ID: u8 = 0xD1;
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		spi_flash_memory.reset()?;
+		// All the blocks are protected after the power up, and the `BRWD` bit (bit 7) must be cleared too to be able to
+		// change the protection bits again later
+		spi_flash_memory.set_register(BlockLockRegister::from_bits(0x00))?;
+		// Make sure the on-die ECC is enabled (`ECC_EN` bit), since the bad block management relies on it
+		spi_flash_memory.set_ecc_enabled(true)?;
+
+		Ok(())
+	}
+
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
+	where Self: Sized
+	{
+		// The mark can be in the first or in the second page of the block
+		contains_bad_block_mark_in_pages(block_index, 2, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		match (status >> 4) & 0b11
+		{
+			0b00 => EccStatus::NoErrors,
+			0b01 => EccStatus::Corrected {
+				max_corrected_bits: Some(7),
+				should_refresh: false,
+			},
+			// 8 bits is the max the ECC can correct
+			0b11 => EccStatus::Corrected {
+				max_corrected_bits: Some(8),
+				should_refresh: true,
+			},
+			_ => EccStatus::Uncorrectable,
+		}
+	}
+}
+
+/// 1Gbit 3.3V NAND SPI flash memory chip by Macronix.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MX35LF1GE4AB;
+impl FlashMemoryChip for MX35LF1GE4AB
+{
+	const LUNS_PER_DEVICE: u32 = 1;
+	const PLANES_PER_LUN: u32 = 1;
+	const BLOCKS_PER_PLANE: u32 = 1024;
+	const PAGES_PER_BLOCK: u32 = 64;
+	const PAGE_SIZE: u32 = 2048;
+	const PAGE_ECC_SIZE: u32 = 64;
+	const PARTIAL_PROGRAMS_PER_PAGE: u32 = 4;
+
+	const SPI_MODE: Mode = MODE_0;
+	const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(104_000_000);
+	const REQUIRES_QUAD_ENABLE: bool = true;
+
+	const MANUFACTURER_ID: u8 = 0xC2;
+	const DEVICE_ID: u8 = 0x12;
+
+	fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
 	where Self: Sized
 	{
 		spi_flash_memory.reset()?;
-		// This is required to remove the block protection which is enabled by default (check page 38 of the datasheet).
-		spi_flash_memory.set_features(FeatureRegister::BlockLock, 0x00)?;
+		// The `BP0`, `BP1` and `BP2` bits are set after the power up, and the `SP` bit (bit 0, solid protection) must
+		// stay cleared or the protection can't be removed until the next power cycle
+		spi_flash_memory.set_register(BlockLockRegister::from_bits(0x00))?;
 
 		Ok(())
 	}
 
-	fn contains_bad_block_mark<Spi: SpiDevice<u8>>(
-		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi>,
-	) -> Result<bool, <Spi as ErrorType>::Error>
+	fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+		block_index: u16, spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+	) -> Result<bool, SpiFlashMemoryError<Spi>>
 	where Self: Sized
+	{
+		// The mark can be in the first or in the second page of the block
+		contains_bad_block_mark_in_pages(block_index, 2, spi_flash_memory)
+	}
+
+	fn decode_ecc_status(status: u8) -> EccStatus
+	{
+		match (status >> 4) & 0b11
+		{
+			0b00 => EccStatus::NoErrors,
+			0b01 => EccStatus::Corrected {
+				max_corrected_bits: Some(4),
+				should_refresh: false,
+			},
+			// 10 means uncorrectable, 11 is reserved
+			_ => EccStatus::Uncorrectable,
+		}
+	}
+}
+
+/// The initialization shared by the Micron chips.
+fn initialize_micron<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+	spi_flash_memory: &mut SpiFlashMemory<Chip, Spi, T, D>,
+) -> Result<(), SpiFlashMemoryError<Spi>>
+{
+	spi_flash_memory.reset()?;
+	// This is required to remove the block protection which is enabled by default (check page 38 of the datasheet of
+	// the `MT29F2G01ABAGDWB`), and each die has its own protection
+	for_each_lun(spi_flash_memory, |spi_flash_memory| {
+		spi_flash_memory.set_register(BlockLockRegister::from_bits(0x00))
+	})
+}
+
+/// The initialization shared by the Winbond chips.
+fn initialize_winbond<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+	spi_flash_memory: &mut SpiFlashMemory<Chip, Spi, T, D>,
+) -> Result<(), SpiFlashMemoryError<Spi>>
+{
+	spi_flash_memory.reset()?;
+	for_each_lun(spi_flash_memory, |spi_flash_memory| {
+		// All the blocks are protected after the power up (`BP0`-`BP3` and `TB` bits of the protection register)
+		spi_flash_memory.set_register(BlockLockRegister::from_bits(0x00))?;
+		// Some variants start in continuous read mode, where the column address of the reads is ignored, so the buffer
+		// read mode (`BUF` bit) is selected together with the on-die ECC (`ECC-E` bit)
+		let mut configuration: ConfigurationRegister = spi_flash_memory.get_register()?;
+		configuration.ecc_enabled = true;
+		configuration.buffer_mode = true;
+		spi_flash_memory.set_register(configuration)
+	})
+}
+
+/// Calls the provided `callback` with each LUN (die) of the chip selected, since each LUN has its own feature
+/// registers.
+fn for_each_lun<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+	spi_flash_memory: &mut SpiFlashMemory<Chip, Spi, T, D>,
+	mut callback: impl FnMut(&mut SpiFlashMemory<Chip, Spi, T, D>) -> Result<(), SpiFlashMemoryError<Spi>>,
+) -> Result<(), SpiFlashMemoryError<Spi>>
+{
+	if Chip::LUNS_PER_DEVICE == 1
+	{
+		return callback(spi_flash_memory);
+	}
+
+	for lun_index in 0..Chip::LUNS_PER_DEVICE
+	{
+		spi_flash_memory.select_die(lun_index as u8)?;
+		callback(spi_flash_memory)?;
+	}
+
+	spi_flash_memory.select_die(0)
+}
+
+/// The ECC status bits of the Micron chips (check page 33 of the datasheet of the `MT29F2G01ABAGDWB`).
+fn decode_micron_ecc_status(status: u8) -> EccStatus
+{
+	match (status >> 4) & 0b111
+	{
+		0b000 => EccStatus::NoErrors,
+		0b001 => EccStatus::Corrected {
+			max_corrected_bits: Some(3),
+			should_refresh: false,
+		},
+		0b011 => EccStatus::Corrected {
+			max_corrected_bits: Some(6),
+			should_refresh: true,
+		},
+		0b101 => EccStatus::Corrected {
+			max_corrected_bits: Some(8),
+			should_refresh: true,
+		},
+		// 010 means uncorrectable, the other values are reserved
+		_ => EccStatus::Uncorrectable,
+	}
+}
+
+/// The ECC status bits of the Winbond chips: `01` means that up to `max_corrected_bits` have been corrected, while
+/// both `10` and `11` mean that the data is uncorrectable (`11` is only used by the continuous read mode).
+fn decode_winbond_ecc_status(status: u8, max_corrected_bits: u8) -> EccStatus
+{
+	match (status >> 4) & 0b11
+	{
+		0b00 => EccStatus::NoErrors,
+		0b01 => EccStatus::Corrected {
+			max_corrected_bits: Some(max_corrected_bits),
+			should_refresh: false,
+		},
+		_ => EccStatus::Uncorrectable,
+	}
+}
+
+/// Returns `Ok(true)` if the first byte of the ECC area of one of the first `pages_to_check` pages of the block at the
+/// provided `block_index` isn't erased (`0xFF`), which is how most of the chips mark the factory bad blocks.
+fn contains_bad_block_mark_in_pages<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+	block_index: u16, pages_to_check: u32, spi_flash_memory: &mut SpiFlashMemory<Chip, Spi, T, D>,
+) -> Result<bool, SpiFlashMemoryError<Spi>>
+{
+	let first_page_index = Chip::get_address_of_block_index(block_index) / Chip::PAGE_SIZE;
+	for page_index in first_page_index..(first_page_index + pages_to_check)
 	{
 		let mut data = 0;
-		let row_address = RowAddress::from_memory_address(block_index as u32 * Self::BLOCK_SIZE);
-		spi_flash_memory.read_ecc(row_address, core::slice::from_mut(&mut data))?;
+		spi_flash_memory.read_ecc(
+			RowAddress::from_page_index(page_index),
+			core::slice::from_mut(&mut data),
+		)?;
+		if data != 0xFF
+		{
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use alloc::{vec, vec::Vec};
+
+	use super::*;
+	use crate::hardware::mock::MockSpi;
 
-		Ok(data != 0)
+	extern crate alloc;
+
+	/// Returns if the block is marked as bad when the first byte of its ECC area is `mark`.
+	fn contains_bad_block_mark(mark: u8) -> bool
+	{
+		let spi = MockSpi::Ok {
+			// First the status register (no operation in progress), then the mark
+			read_operations: vec![vec![0x00], vec![mark]],
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		};
+		let mut spi_flash_memory = SpiFlashMemory::new(spi, MT29F2G01ABAGDWB);
+
+		MT29F2G01ABAGDWB::contains_bad_block_mark(7, &mut spi_flash_memory).unwrap()
+	}
+
+	#[test]
+	fn mt29f_bad_block_mark()
+	{
+		// The factory marks bad blocks with `0x00`, which the old `data != 0` check read as a good block
+		assert!(contains_bad_block_mark(0x00));
+		assert!(!contains_bad_block_mark(0xFF));
 	}
 }
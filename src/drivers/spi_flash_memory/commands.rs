@@ -4,6 +4,7 @@ use super::{
 	address::{ColumnAddress, RowAddress},
 	chip::FlashMemoryChip,
 };
+use crate::peripherals::spi::BusWidth;
 
 #[derive(Debug)]
 /// A command to send to a flash memory over SPI.
@@ -20,7 +21,13 @@ pub enum Command<'a, Chip: FlashMemoryChip>
 	{
 		column_address: ColumnAddress,
 		output: &'a mut [u8],
+		bus_width: BusWidth,
 	},
+	ReadPageCacheRandom
+	{
+		row_address: RowAddress<Chip>,
+	},
+	ReadPageCacheLast,
 	BlockErase
 	{
 		row_address: RowAddress<Chip>,
@@ -42,7 +49,7 @@ pub enum Command<'a, Chip: FlashMemoryChip>
 	ReadId
 	{
 		manufacturer_id: &'a mut u8,
-		device_id: &'a mut u8,
+		device_id: &'a mut [u8],
 	},
 	GetFeatures
 	{
@@ -54,6 +61,10 @@ pub enum Command<'a, Chip: FlashMemoryChip>
 		features_address: u8,
 		features_value: u8,
 	},
+	SoftwareDieSelect
+	{
+		die_index: u8,
+	},
 }
 
 impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
@@ -62,20 +73,32 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 	///
 	/// Returns `Ok(())` if the command has been sent succesfully, otherwise returns `Err(...)`.
 	pub fn execute<Spi: SpiDevice<u8>>(self, spi_device: &mut Spi) -> Result<(), <Spi as ErrorType>::Error>
+	{
+		self.execute_with(|operations| spi_device.transaction(operations))
+	}
+
+	/// Send this command as the `operations` of a single transaction executed by the provided `transaction`.
+	///
+	/// Returns `Ok(())` if the command has been sent succesfully, otherwise returns `Err(...)`.
+	pub fn execute_with<E>(self, transaction: impl FnOnce(&mut [Operation<'_, u8>]) -> Result<(), E>) -> Result<(), E>
 	{
 		let op_code = [self.op_code()];
 		let op_code_operation = Operation::Write(&op_code);
 
 		match self
 		{
-			Command::PageRead { row_address } =>
+			Command::PageRead { row_address } | Command::ReadPageCacheRandom { row_address } =>
 			{
-				spi_device.transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
+				transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
 				// Here I can read the status register
 			},
-			Command::ReadFromCache { column_address, output } =>
+			Command::ReadFromCache {
+				column_address,
+				output,
+				bus_width: _,
+			} =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(column_address.as_bytes()),
 					Operation::Write(&[0]), // Dummy byte
@@ -84,11 +107,11 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 			},
 			Command::BlockErase { row_address } =>
 			{
-				spi_device.transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
+				transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
 			},
 			Command::ProgramLoad { column_address, input } =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(column_address.as_bytes()),
 					Operation::Write(input),
@@ -96,7 +119,7 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 			},
 			Command::ProgramLoadRandomData { column_address, input } =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(column_address.as_bytes()),
 					Operation::Write(input),
@@ -104,22 +127,22 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 			},
 			Command::ProgramExecute { row_address } =>
 			{
-				spi_device.transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
+				transaction(&mut [op_code_operation, Operation::Write(row_address.as_bytes())])?;
 			},
-			Command::Reset | Command::WriteEnable | Command::WriteDisable =>
+			Command::Reset | Command::WriteEnable | Command::WriteDisable | Command::ReadPageCacheLast =>
 			{
-				spi_device.transaction(&mut [op_code_operation])?;
+				transaction(&mut [op_code_operation])?;
 			},
 			Command::ReadId {
 				manufacturer_id,
 				device_id,
 			} =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(&[0]), // Dummy byte
 					Operation::Read(core::slice::from_mut(manufacturer_id)),
-					Operation::Read(core::slice::from_mut(device_id)),
+					Operation::Read(device_id),
 				])?;
 			},
 			Command::GetFeatures {
@@ -127,7 +150,7 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 				features_value: feature_value,
 			} =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(&[feature_address]),
 					Operation::Read(core::slice::from_mut(feature_value)),
@@ -138,12 +161,16 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 				features_value: feature_value,
 			} =>
 			{
-				spi_device.transaction(&mut [
+				transaction(&mut [
 					op_code_operation,
 					Operation::Write(&[feature_address]),
 					Operation::Write(&[feature_value]),
 				])?;
 			},
+			Command::SoftwareDieSelect { die_index } =>
+			{
+				transaction(&mut [op_code_operation, Operation::Write(&[die_index])])?;
+			},
 		}
 
 		Ok(())
@@ -160,7 +187,15 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 			Command::ReadFromCache {
 				column_address: _,
 				output: _,
-			} => 0x03,
+				bus_width,
+			} => match bus_width
+			{
+				BusWidth::Single => 0x03,
+				BusWidth::Dual => 0x3B,
+				BusWidth::Quad => 0x6B,
+			},
+			Command::ReadPageCacheRandom { row_address: _ } => 0x30,
+			Command::ReadPageCacheLast => 0x3F,
 			Command::BlockErase { row_address: _ } => 0xD8,
 			Command::ProgramLoad {
 				column_address: _,
@@ -183,6 +218,7 @@ impl<'a, Chip: FlashMemoryChip> Command<'a, Chip>
 				features_address: _,
 				features_value: _,
 			} => 0x1F,
+			Command::SoftwareDieSelect { die_index: _ } => 0xC2,
 		}
 	}
 }
@@ -0,0 +1,71 @@
+/// Result of the on-die [`ECC`] of a flash memory chip after a page has been read.
+///
+/// The variants are ordered from the best to the worst result, so the worst result of multiple reads is the
+/// [`max`](Ord::max) of them.
+///
+/// [`ECC`]: <https://en.wikipedia.org/wiki/Error_correction_code>
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum EccStatus
+{
+	/// No bit was wrong.
+	NoErrors,
+	/// Some bits were flipped, but the ECC was able to correct them, so the read data is right.
+	Corrected
+	{
+		/// The max number of bits that could have been corrected in a single ECC sector, if the chip reports it.
+		max_corrected_bits: Option<u8>,
+		/// The number of corrected bits is near the limit the ECC can correct, so the page should be rewritten
+		/// somewhere else (and its block erased) before it becomes uncorrectable.
+		should_refresh: bool,
+	},
+	/// There were more flipped bits than the ECC can correct, so the read data is corrupted.
+	Uncorrectable,
+}
+
+impl EccStatus
+{
+	/// Returns `true` if the read data is right (even if some bits have been corrected).
+	pub fn is_data_valid(&self) -> bool
+	{
+		*self != Self::Uncorrectable
+	}
+
+	/// Returns `true` if the page should be rewritten somewhere else, because it's corrupted or it's going to be soon.
+	pub fn should_refresh(&self) -> bool
+	{
+		match self
+		{
+			Self::NoErrors => false,
+			Self::Corrected { should_refresh, .. } => *should_refresh,
+			Self::Uncorrectable => true,
+		}
+	}
+
+	/// Returns the [`EccStatus`] decoded from the 2 `ECC status` bits (bits 4 and 5) of the status register that most
+	/// SPI NAND flash memory chips have:
+	/// - `00`: no errors.
+	/// - `01`: some bits have been corrected.
+	/// - `10`: uncorrectable.
+	/// - `11`: some bits have been corrected, and their number is near the limit.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::drivers::spi_flash_memory::EccStatus;
+	/// #
+	/// assert_eq!(EccStatus::from_2_bits_status(0b0000_0000), EccStatus::NoErrors);
+	/// assert_eq!(EccStatus::from_2_bits_status(0b0010_0001), EccStatus::Uncorrectable);
+	/// assert!(EccStatus::from_2_bits_status(0b0011_0000).should_refresh());
+	/// ```
+	pub fn from_2_bits_status(status: u8) -> Self
+	{
+		match (status >> 4) & 0b11
+		{
+			0b00 => Self::NoErrors,
+			0b10 => Self::Uncorrectable,
+			bits => Self::Corrected {
+				max_corrected_bits: None,
+				should_refresh: bits == 0b11,
+			},
+		}
+	}
+}
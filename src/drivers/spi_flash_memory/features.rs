@@ -1,3 +1,48 @@
+//! The feature registers configure the chip (block protection, on-die ECC, OTP access, ...) and report the status of
+//! its operations.
+//!
+//! Each register has a typed value ([`BlockLockRegister`], [`ConfigurationRegister`], [`StatusRegister`] and
+//! [`DieSelectRegister`]) that can be read and written with [`SpiFlashMemory::get_register`] and
+//! [`SpiFlashMemory::set_register`], so that there's no need to know the position of the bits. The bits whose meaning
+//! changes too much between the chips aren't decoded, but they are kept as they are when a value is written back.
+//!
+//! ## High level operations
+//! The most common changes to the registers are available directly in [`SpiFlashMemory`]:
+//! - [`lock_blocks`](SpiFlashMemory::lock_blocks) and [`unlock_all_blocks`](SpiFlashMemory::unlock_all_blocks).
+//! - [`set_ecc_enabled`](SpiFlashMemory::set_ecc_enabled).
+//! - [`enter_otp_mode`](SpiFlashMemory::enter_otp_mode), [`read_otp_page`](SpiFlashMemory::read_otp_page),
+//!   [`program_otp_page`](SpiFlashMemory::program_otp_page) and [`lock_otp_area`](SpiFlashMemory::lock_otp_area).
+//! - [`select_die`](SpiFlashMemory::select_die).
+//!
+//! # Examples
+//! ```
+//! # use a13c_embedded::{drivers::spi_flash_memory::*, hardware::mock::MockSpiNandFlash};
+//! #
+//! # let spi = MockSpiNandFlash::<MT29F2G01ABAGDWB>::new();
+//! let mut spi_flash_memory = SpiFlashMemory::new(spi, MT29F2G01ABAGDWB);
+//! MT29F2G01ABAGDWB::initialize(&mut spi_flash_memory).unwrap();
+//!
+//! // Protect the upper quarter of the memory (where the firmware could be stored) from accidental changes
+//! spi_flash_memory.lock_blocks(LockedBlocks::Upper(BlockFraction::Quarter)).unwrap();
+//! assert_eq!(
+//!     spi_flash_memory.get_locked_blocks().unwrap().get_block_indices::<MT29F2G01ABAGDWB>(),
+//!     1536..2048
+//! );
+//!
+//! let configuration: ConfigurationRegister = spi_flash_memory.get_register().unwrap();
+//! assert!(configuration.ecc_enabled);
+//! ```
+
+use core::ops::Range;
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
+use super::{
+	commands::Command, ColumnAddress, DieSelection, EccStatus, FlashMemoryChip, RowAddress, SpiFlashMemory,
+	SpiFlashMemoryError,
+};
+use crate::peripherals::time::system_time::SystemTime;
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 /// The register of a feature in the [`MT29F2G01ABAGDWB`] chip.
 ///
@@ -24,3 +69,525 @@ impl FeatureRegister
 		}
 	}
 }
+
+/// The typed value of a [`FeatureRegister`].
+pub trait FeatureRegisterValue: Sized
+{
+	/// The register that stores this value.
+	const REGISTER: FeatureRegister;
+
+	/// Returns the value decoded from the provided `bits` read from the register.
+	fn from_bits(bits: u8) -> Self;
+
+	/// Returns the bits to write in the register.
+	fn to_bits(&self) -> u8;
+}
+
+/// The value of the [`FeatureRegister::BlockLock`] register, which protects the blocks from program and erase
+/// operations.
+///
+/// Most chips lock all the blocks after the power up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BlockLockRegister
+{
+	/// The `BP0`-`BP3` bits (bits 3-6), which select how many blocks are protected. Check [`LockedBlocks`].
+	pub protection_bits: u8,
+	/// The `TB` bit (bit 2), which selects if the protection starts from the bottom of the memory instead of the top.
+	pub is_bottom_protected: bool,
+	/// The `BRWD` (or `SRP0`) bit (bit 7), which makes this register read-only while the `WP#` pin is low.
+	pub is_write_disabled: bool,
+	/// Bits 0 and 1, whose meaning changes between the chips.
+	other_bits: u8,
+}
+
+impl FeatureRegisterValue for BlockLockRegister
+{
+	const REGISTER: FeatureRegister = FeatureRegister::BlockLock;
+
+	fn from_bits(bits: u8) -> Self
+	{
+		Self {
+			protection_bits: (bits >> 3) & 0b1111,
+			is_bottom_protected: bits & 0b0000_0100 != 0,
+			is_write_disabled: bits & 0b1000_0000 != 0,
+			other_bits: bits & 0b0000_0011,
+		}
+	}
+
+	fn to_bits(&self) -> u8
+	{
+		((self.protection_bits & 0b1111) << 3)
+			| ((self.is_bottom_protected as u8) << 2)
+			| ((self.is_write_disabled as u8) << 7)
+			| self.other_bits
+	}
+}
+
+/// The value of the [`FeatureRegister::Configuration`] register.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ConfigurationRegister
+{
+	/// The `CFG2` (or `OTP-L`) bit (bit 7), which together with [`Self::otp_access`] permanently protects the OTP area
+	/// at the next program operation.
+	pub otp_protect: bool,
+	/// The `CFG1` (or `OTP-E`) bit (bit 6), which makes the reads and programs access the OTP area instead of the
+	/// memory.
+	pub otp_access: bool,
+	/// The `LOT_EN` (or `SR1-L`) bit (bit 5), which keeps the [`BlockLockRegister`] as it is until the next power
+	/// cycle.
+	pub lock_tight: bool,
+	/// The `ECC_EN` (or `ECC-E`) bit (bit 4), which enables the on-die ECC.
+	pub ecc_enabled: bool,
+	/// The `BUF` bit (bit 3) of the Winbond chips, which selects the buffer read mode instead of the continuous one.
+	pub buffer_mode: bool,
+	/// The `QE` bit (bit 0) of the GigaDevice and Macronix chips, which enables the quad SPI commands.
+	pub quad_enabled: bool,
+	/// Bits 1 and 2, whose meaning changes between the chips.
+	other_bits: u8,
+}
+
+impl FeatureRegisterValue for ConfigurationRegister
+{
+	const REGISTER: FeatureRegister = FeatureRegister::Configuration;
+
+	fn from_bits(bits: u8) -> Self
+	{
+		Self {
+			otp_protect: bits & 0b1000_0000 != 0,
+			otp_access: bits & 0b0100_0000 != 0,
+			lock_tight: bits & 0b0010_0000 != 0,
+			ecc_enabled: bits & 0b0001_0000 != 0,
+			buffer_mode: bits & 0b0000_1000 != 0,
+			quad_enabled: bits & 0b0000_0001 != 0,
+			other_bits: bits & 0b0000_0110,
+		}
+	}
+
+	fn to_bits(&self) -> u8
+	{
+		((self.otp_protect as u8) << 7)
+			| ((self.otp_access as u8) << 6)
+			| ((self.lock_tight as u8) << 5)
+			| ((self.ecc_enabled as u8) << 4)
+			| ((self.buffer_mode as u8) << 3)
+			| self.quad_enabled as u8
+			| self.other_bits
+	}
+}
+
+/// The value of the [`FeatureRegister::Status`] register, which is read-only.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct StatusRegister
+{
+	/// The `OIP` bit (bit 0): the chip is busy and it ignores most commands.
+	pub operation_in_progress: bool,
+	/// The `WEL` bit (bit 1): the next program or erase operation is allowed.
+	pub write_enabled: bool,
+	/// The `E_FAIL` bit (bit 2): the last erase operation failed.
+	pub erase_failed: bool,
+	/// The `P_FAIL` bit (bit 3): the last program operation failed.
+	pub program_failed: bool,
+	/// The `ECCS` bits (bits 4-6), which encode the result of the on-die ECC of the last read page. Check
+	/// [`Self::get_ecc_status`] to decode them.
+	pub ecc_status_bits: u8,
+	/// The `CRBSY` bit (bit 7): a cache read operation is in progress.
+	pub cache_read_busy: bool,
+}
+
+impl StatusRegister
+{
+	/// Returns the result of the on-die ECC of the last read page, decoded with [`FlashMemoryChip::decode_ecc_status`].
+	pub fn get_ecc_status<Chip: FlashMemoryChip>(&self) -> EccStatus
+	{
+		Chip::decode_ecc_status(self.to_bits())
+	}
+}
+
+impl FeatureRegisterValue for StatusRegister
+{
+	const REGISTER: FeatureRegister = FeatureRegister::Status;
+
+	fn from_bits(bits: u8) -> Self
+	{
+		Self {
+			operation_in_progress: bits & 0b0000_0001 != 0,
+			write_enabled: bits & 0b0000_0010 != 0,
+			erase_failed: bits & 0b0000_0100 != 0,
+			program_failed: bits & 0b0000_1000 != 0,
+			ecc_status_bits: (bits >> 4) & 0b111,
+			cache_read_busy: bits & 0b1000_0000 != 0,
+		}
+	}
+
+	fn to_bits(&self) -> u8
+	{
+		self.operation_in_progress as u8
+			| ((self.write_enabled as u8) << 1)
+			| ((self.erase_failed as u8) << 2)
+			| ((self.program_failed as u8) << 3)
+			| ((self.ecc_status_bits & 0b111) << 4)
+			| ((self.cache_read_busy as u8) << 7)
+	}
+}
+
+/// The value of the [`FeatureRegister::DieSelect`] register of the chips made of multiple dies, which selects the die
+/// that receives the following commands.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DieSelectRegister
+{
+	/// The `DS0` bit (bit 6), the index of the selected die.
+	pub die_index: u8,
+	/// All the bits except bit 6, which are reserved.
+	other_bits: u8,
+}
+
+impl FeatureRegisterValue for DieSelectRegister
+{
+	const REGISTER: FeatureRegister = FeatureRegister::DieSelect;
+
+	fn from_bits(bits: u8) -> Self
+	{
+		Self {
+			die_index: (bits >> 6) & 1,
+			other_bits: bits & !0b0100_0000,
+		}
+	}
+
+	fn to_bits(&self) -> u8
+	{
+		((self.die_index & 1) << 6) | self.other_bits
+	}
+}
+
+/// A fraction of the blocks of the memory, used by [`LockedBlocks`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum BlockFraction
+{
+	SixtyFourth,
+	ThirtySecond,
+	Sixteenth,
+	Eighth,
+	Quarter,
+	Half,
+}
+
+impl BlockFraction
+{
+	/// Returns the denominator of the fraction.
+	pub fn get_denominator(&self) -> u32
+	{
+		1 << (7 - self.get_protection_bits())
+	}
+
+	fn get_protection_bits(&self) -> u8
+	{
+		match self
+		{
+			Self::SixtyFourth => 1,
+			Self::ThirtySecond => 2,
+			Self::Sixteenth => 3,
+			Self::Eighth => 4,
+			Self::Quarter => 5,
+			Self::Half => 6,
+		}
+	}
+}
+
+/// The blocks protected by the [`BlockLockRegister`], which can't be programmed or erased.
+///
+/// In the chips made of multiple LUNs, each LUN has its own register which protects only its blocks.
+///
+/// The protection bits are encoded like in the [`MT29F2G01ABAGDWB`], check the datasheet of the other chips because
+/// the same bits could protect a different fraction of the memory.
+///
+/// [`MT29F2G01ABAGDWB`]: super::MT29F2G01ABAGDWB
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum LockedBlocks
+{
+	None,
+	/// The blocks at the end of the memory.
+	Upper(BlockFraction),
+	/// The blocks at the start of the memory.
+	Lower(BlockFraction),
+	All,
+}
+
+impl LockedBlocks
+{
+	/// Returns the [`LockedBlocks`] encoded in the provided `register`.
+	///
+	/// The reserved combinations of bits protect all the blocks.
+	pub fn from_register(register: &BlockLockRegister) -> Self
+	{
+		let fraction = match register.protection_bits
+		{
+			0 => return Self::None,
+			1 => BlockFraction::SixtyFourth,
+			2 => BlockFraction::ThirtySecond,
+			3 => BlockFraction::Sixteenth,
+			4 => BlockFraction::Eighth,
+			5 => BlockFraction::Quarter,
+			6 => BlockFraction::Half,
+			_ => return Self::All,
+		};
+
+		match register.is_bottom_protected
+		{
+			false => Self::Upper(fraction),
+			true => Self::Lower(fraction),
+		}
+	}
+
+	/// Writes these locked blocks in the provided `register`, without changing its other bits.
+	pub fn write_to_register(&self, register: &mut BlockLockRegister)
+	{
+		(register.protection_bits, register.is_bottom_protected) = match self
+		{
+			Self::None => (0, false),
+			Self::Upper(fraction) => (fraction.get_protection_bits(), false),
+			Self::Lower(fraction) => (fraction.get_protection_bits(), true),
+			Self::All => (0b0111, false),
+		};
+	}
+
+	/// Returns the indices of the blocks of a LUN of the `Chip` that are locked, relative to the first block of the LUN.
+	pub fn get_block_indices<Chip: FlashMemoryChip>(&self) -> Range<u16>
+	{
+		let blocks_count = (Chip::PLANES_PER_LUN * Chip::BLOCKS_PER_PLANE) as u16;
+		match self
+		{
+			Self::None => 0..0,
+			Self::Upper(fraction) => (blocks_count - blocks_count / fraction.get_denominator() as u16)..blocks_count,
+			Self::Lower(fraction) => 0..(blocks_count / fraction.get_denominator() as u16),
+			Self::All => 0..blocks_count,
+		}
+	}
+}
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> SpiFlashMemory<Chip, Spi, T, D>
+{
+	/// Returns the typed value of the register `R`.
+	pub fn get_register<R: FeatureRegisterValue>(&mut self) -> Result<R, SpiFlashMemoryError<Spi>>
+	{
+		Ok(R::from_bits(self.get_features(R::REGISTER)?))
+	}
+
+	/// Writes the provided typed `value` in its register.
+	///
+	/// # Note
+	/// Writing the [`StatusRegister`] has no effect, since it's read-only.
+	pub fn set_register<R: FeatureRegisterValue>(&mut self, value: R) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		self.set_features(R::REGISTER, value.to_bits())
+	}
+
+	/// Returns the blocks of the selected LUN that are currently protected from program and erase operations.
+	pub fn get_locked_blocks(&mut self) -> Result<LockedBlocks, SpiFlashMemoryError<Spi>>
+	{
+		Ok(LockedBlocks::from_register(&self.get_register()?))
+	}
+
+	/// Protects the provided `locked_blocks` of the selected LUN from program and erase operations, and removes the
+	/// protection from all its other blocks.
+	///
+	/// # Note
+	/// The change is ignored by the chip if [`ConfigurationRegister::lock_tight`] is set, or if
+	/// [`BlockLockRegister::is_write_disabled`] is set and the `WP#` pin is low.
+	pub fn lock_blocks(&mut self, locked_blocks: LockedBlocks) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let mut block_lock: BlockLockRegister = self.get_register()?;
+		locked_blocks.write_to_register(&mut block_lock);
+		self.set_register(block_lock)
+	}
+
+	/// Removes the protection from all the blocks of the selected LUN. Check [`Self::lock_blocks`].
+	pub fn unlock_all_blocks(&mut self) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		self.lock_blocks(LockedBlocks::None)
+	}
+
+	/// Returns `true` if the on-die ECC is enabled.
+	pub fn is_ecc_enabled(&mut self) -> Result<bool, SpiFlashMemoryError<Spi>>
+	{
+		Ok(self.get_register::<ConfigurationRegister>()?.ecc_enabled)
+	}
+
+	/// Enables or disables the on-die ECC.
+	///
+	/// # Warning
+	/// Without the on-die ECC the bit flips aren't corrected (and the [`EccStatus`] of the reads is always
+	/// [`EccStatus::NoErrors`]), so the data must be protected in another way.
+	pub fn set_ecc_enabled(&mut self, is_enabled: bool) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let mut configuration: ConfigurationRegister = self.get_register()?;
+		configuration.ecc_enabled = is_enabled;
+		self.set_register(configuration)
+	}
+
+	/// Makes the following reads and programs access the OTP area instead of the memory, until
+	/// [`Self::exit_otp_mode`] is called.
+	///
+	/// The OTP area is made of a few pages that can be programmed only once, and then they can be permanently
+	/// protected with [`Self::lock_otp_area`]. Check the datasheet of the chip to know which pages are available,
+	/// because the first ones usually contain the unique ID and the [`OnfiParameterPage`](super::OnfiParameterPage)
+	/// (for example, pages `0x02`-`0x1F` are free in the [`MT29F2G01ABAGDWB`](super::MT29F2G01ABAGDWB)).
+	pub fn enter_otp_mode(&mut self) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let mut configuration: ConfigurationRegister = self.get_register()?;
+		configuration.otp_access = true;
+		self.set_register(configuration)
+	}
+
+	/// Restores the normal access to the memory after [`Self::enter_otp_mode`].
+	pub fn exit_otp_mode(&mut self) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let mut configuration: ConfigurationRegister = self.get_register()?;
+		configuration.otp_access = false;
+		configuration.otp_protect = false;
+		self.set_register(configuration)
+	}
+
+	/// Reads [`data.len()`] bytes from the page of the OTP area at the provided `page_index`, starting from the
+	/// provided `column`.
+	///
+	/// The normal access to the memory is restored before returning, even if the read fails. Check
+	/// [`Self::enter_otp_mode`] for more info about the OTP area.
+	pub fn read_otp_page(
+		&mut self, page_index: u32, column: u16, data: &mut [u8],
+	) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
+	{
+		self.with_otp_mode(|spi_flash_memory| {
+			spi_flash_memory.read_internal(
+				RowAddress::from_page_index(page_index),
+				ColumnAddress::new(column, 0),
+				data,
+			)
+		})
+	}
+
+	/// Programs the provided `data` in the page of the OTP area at the provided `page_index`, starting from the
+	/// provided `column`.
+	///
+	/// The normal access to the memory is restored before returning, even if the program fails. Check
+	/// [`Self::enter_otp_mode`] for more info about the OTP area.
+	///
+	/// Returns `Err(SpiFlashMemoryError::ProgramFailed)` if the page isn't available or the OTP area is locked.
+	///
+	/// # Warning
+	/// A page of the OTP area can't be erased, so it can be programmed only once.
+	pub fn program_otp_page(
+		&mut self, page_index: u32, column: u16, data: &[u8],
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		self.with_otp_mode(|spi_flash_memory| {
+			let row_address = RowAddress::from_page_index(page_index);
+			spi_flash_memory.select_lun_of(row_address)?;
+			spi_flash_memory.execute(Command::<Chip>::WriteEnable)?;
+			spi_flash_memory.execute(Command::ProgramLoad::<Chip> {
+				column_address: ColumnAddress::new(column, 0),
+				input: data,
+			})?;
+			spi_flash_memory.execute(Command::ProgramExecute::<Chip> { row_address })?;
+
+			Self::check_program_status(spi_flash_memory.wait_for_operation_to_finish()?)
+		})
+	}
+
+	/// Permanently protects the OTP area from program operations.
+	///
+	/// # Warning
+	/// This can't be undone, not even with a power cycle.
+	pub fn lock_otp_area(&mut self) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let configuration: ConfigurationRegister = self.get_register()?;
+		self.set_register(ConfigurationRegister {
+			otp_access: true,
+			otp_protect: true,
+			..configuration
+		})?;
+
+		// The protection is applied by a program operation, whose row address is ignored
+		let result = self
+			.select_lun_of(RowAddress::from_page_index(0))
+			.and_then(|_| self.execute(Command::<Chip>::WriteEnable))
+			.and_then(|_| {
+				self.execute(Command::ProgramExecute::<Chip> {
+					row_address: RowAddress::from_page_index(0),
+				})
+			})
+			.and_then(|_| self.wait_for_operation_to_finish())
+			.and_then(Self::check_program_status);
+
+		self.set_register(configuration)?;
+		result
+	}
+
+	/// Returns the index of the die that receives the commands, in the chips made of multiple dies.
+	///
+	/// Returns `Ok(None)` if it's unknown, because no die has been selected since the last reset of a chip that
+	/// [`selects the dies with a command`](DieSelection::Command).
+	pub fn get_selected_die(&mut self) -> Result<Option<u8>, SpiFlashMemoryError<Spi>>
+	{
+		match Chip::DIE_SELECTION
+		{
+			DieSelection::FeatureRegister => Ok(Some(self.get_register::<DieSelectRegister>()?.die_index)),
+			DieSelection::Command => Ok(self.selected_lun),
+		}
+	}
+
+	/// Makes all the following commands go to the die at the provided `die_index`, in the chips made of multiple dies.
+	///
+	/// There's usually no need to call this, since the operations on the memory select the die that contains the
+	/// accessed pages automatically (check [`struct's documentation`](Self#multiple-luns)).
+	///
+	/// # Note
+	/// Each die has its own block lock, configuration and status registers, so for example the blocks must be unlocked
+	/// in each die.
+	///
+	/// Returns `Err(SpiFlashMemoryError::DieOutOfRange)` if the chip doesn't have a die at `die_index`.
+	pub fn select_die(&mut self, die_index: u8) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		if die_index as u32 >= Chip::LUNS_PER_DEVICE
+		{
+			return Err(SpiFlashMemoryError::DieOutOfRange);
+		}
+
+		match Chip::DIE_SELECTION
+		{
+			DieSelection::FeatureRegister =>
+			{
+				// The register has a single bit for the index of the die
+				if die_index >= 2
+				{
+					return Err(SpiFlashMemoryError::DieOutOfRange);
+				}
+
+				let mut die_select: DieSelectRegister = self.get_register()?;
+				die_select.die_index = die_index;
+				self.set_register(die_select)?;
+			},
+			DieSelection::Command => self.execute(Command::SoftwareDieSelect::<Chip> { die_index })?,
+		}
+		self.selected_lun = Some(die_index);
+
+		Ok(())
+	}
+
+	/// Calls the provided `callback` while the OTP area is accessible, restoring the normal access to the memory even if
+	/// the `callback` fails.
+	pub(super) fn with_otp_mode<R>(
+		&mut self, callback: impl FnOnce(&mut Self) -> Result<R, SpiFlashMemoryError<Spi>>,
+	) -> Result<R, SpiFlashMemoryError<Spi>>
+	{
+		let configuration: ConfigurationRegister = self.get_register()?;
+		self.set_register(ConfigurationRegister {
+			otp_access: true,
+			..configuration
+		})?;
+
+		let result = callback(self);
+
+		self.set_register(configuration)?;
+		result
+	}
+}
@@ -0,0 +1,102 @@
+use core::{fmt::Debug, ops::RangeInclusive};
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
+use super::{address::RowAddress, chip::FlashMemoryChip, EccStatus, SpiFlashMemory, SpiFlashMemoryError};
+use crate::peripherals::time::system_time::SystemTime;
+
+/// A NAND flash memory made of the blocks and pages described by [`Self::Chip`].
+///
+/// It's implemented by [`SpiFlashMemory`], and it allows the code built on top of a flash memory (like the
+/// [`flash translation layer`]) to also work with other implementations, like an in-memory one for tests.
+///
+/// Check [`SpiFlashMemory`] to know what each method does.
+///
+/// [`flash translation layer`]: super::ftl::FlashTranslationLayer
+pub trait FlashMemory
+{
+	/// The chip that describes the layout of this flash memory.
+	type Chip: FlashMemoryChip;
+	/// Error type that can be returned by the operations on the flash memory.
+	type Error: Debug;
+
+	/// Check [`SpiFlashMemory::program`].
+	fn program(&mut self, data: &[u8], address: u32) -> Result<(), Self::Error>;
+
+	/// Check [`SpiFlashMemory::program_page`].
+	fn program_page(
+		&mut self, row_address: RowAddress<Self::Chip>, data: &[u8], ecc_data: &[u8],
+	) -> Result<(), Self::Error>;
+
+	/// Check [`SpiFlashMemory::read`].
+	fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, Self::Error>;
+
+	/// Check [`SpiFlashMemory::read_ecc`].
+	fn read_ecc(&mut self, row_address: RowAddress<Self::Chip>, data: &mut [u8]) -> Result<EccStatus, Self::Error>;
+
+	/// Check [`SpiFlashMemory::erase_blocks`].
+	fn erase_blocks(&mut self, block_indices_to_erase: RangeInclusive<u16>) -> Result<(), Self::Error>;
+
+	/// Check [`FlashMemoryChip::contains_bad_block_mark`].
+	fn contains_bad_block_mark(&mut self, block_index: u16) -> Result<bool, Self::Error>;
+
+	/// Returns `true` if the provided `error` means that a program or erase operation failed because the block is
+	/// worn out (so it should be marked as bad), or `false` if it's caused by something else (like the communication
+	/// with the flash memory).
+	///
+	/// It's used by the [`bad block manager`] to replace the blocks that go bad at runtime.
+	///
+	/// [`bad block manager`]: super::bad_block_manager::BadBlockManager
+	fn is_block_failure(error: &Self::Error) -> bool
+	{
+		let _ = error;
+		false
+	}
+}
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> FlashMemory
+	for SpiFlashMemory<Chip, Spi, T, D>
+{
+	type Chip = Chip;
+	type Error = SpiFlashMemoryError<Spi>;
+
+	fn program(&mut self, data: &[u8], address: u32) -> Result<(), Self::Error>
+	{
+		SpiFlashMemory::program(self, data, address)
+	}
+
+	fn program_page(
+		&mut self, row_address: RowAddress<Self::Chip>, data: &[u8], ecc_data: &[u8],
+	) -> Result<(), Self::Error>
+	{
+		SpiFlashMemory::program_page(self, row_address, data, ecc_data)
+	}
+
+	fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		SpiFlashMemory::read(self, address, data)
+	}
+
+	fn read_ecc(&mut self, row_address: RowAddress<Self::Chip>, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		SpiFlashMemory::read_ecc(self, row_address, data)
+	}
+
+	fn erase_blocks(&mut self, block_indices_to_erase: RangeInclusive<u16>) -> Result<(), Self::Error>
+	{
+		SpiFlashMemory::erase_blocks(self, block_indices_to_erase)
+	}
+
+	fn contains_bad_block_mark(&mut self, block_index: u16) -> Result<bool, Self::Error>
+	{
+		Chip::contains_bad_block_mark(block_index, self)
+	}
+
+	fn is_block_failure(error: &Self::Error) -> bool
+	{
+		matches!(
+			error,
+			SpiFlashMemoryError::ProgramFailed | SpiFlashMemoryError::EraseFailed
+		)
+	}
+}
@@ -0,0 +1,1324 @@
+//! A [`flash translation layer`] (FTL) that hides the NAND semantics of a [`FlashMemory`] behind a linear logical
+//! address space that can be read and written at any address, like a RAM.
+//!
+//! ## How it works
+//! The logical address space is split in logical pages of `Chip::PAGE_SIZE` bytes. Every time a logical page is
+//! written, it's programmed in the next free physical page of the currently open block, and the old physical page
+//! becomes stale. Each physical page contains in its ECC area a small tag with the index of the logical page it
+//! contains, which means that the mapping table can always be rebuilt from the flash memory itself (so it survives a
+//! power loss).
+//!
+//! To make the mount faster, the whole mapping table (and the erase count of each block) can be [`saved`] in some
+//! reserved blocks. At mount only the pages programmed after the last saved mapping table need to be scanned.
+//!
+//! When there are only a few free blocks left, the garbage collector moves the valid pages of the block with the
+//! fewest valid pages and erases it.
+//!
+//! The wear leveling is both:
+//! - dynamic: the free block with the lowest erase count is always the next one to be written.
+//! - static: when the difference between the erase counts of the most and least worn blocks gets bigger than
+//!   [`WearLevelingConfig::max_erase_count_difference`], the data in the least worn block (that is data that never
+//!   changes) is moved somewhere else, so that the block can be reused.
+//!
+//! ## Memory usage
+//! The mapping table is kept in RAM, and it uses `4 * LOGICAL_PAGES` bytes, plus `16 * BLOCKS` bytes for the state of
+//! the blocks. If it's too much for your microcontroller, you can create multiple smaller FTLs each one managing
+//! a different range of blocks.
+//!
+//! [`flash translation layer`]: <https://en.wikipedia.org/wiki/Flash_memory_controller#Flash_Translation_Layer_(FTL)_and_Mapping>
+//! [`saved`]: FlashTranslationLayer::flush
+
+use core::fmt::Debug;
+
+use super::{EccStatus, FlashMemory, FlashMemoryChip, FlashMemoryChipExt, RowAddress};
+use crate::utils::{algorithms::crc::Crc32, math::NumberExt};
+
+/// Offset in the ECC area of a page where the tag is stored (the first bytes are used for the bad block mark).
+const TAG_OFFSET: usize = 4;
+/// Size of the tag stored in the ECC area of each page.
+const TAG_SIZE: usize = 16;
+
+/// Value of a mapping table entry of a logical page that has never been written.
+const UNMAPPED: u32 = u32::MAX;
+/// Value of [`PageTag::logical_page`] for the pages that contain a saved mapping table.
+const CHECKPOINT_PAGE: u32 = u32::MAX - 1;
+const CHECKPOINT_MAGIC: u32 = u32::from_le_bytes(*b"FTL1");
+const CHECKPOINT_HEADER_WORDS: usize = 7;
+const NO_OPEN_BLOCK: u32 = u32::MAX;
+
+/// Number of free blocks under which the garbage collector starts freeing blocks.
+const RESERVED_FREE_BLOCKS: usize = 2;
+
+/// A flash translation layer that manages the `BLOCKS` blocks of a [`FlashMemory`] starting from a certain block,
+/// exposing `LOGICAL_PAGES * Chip::PAGE_SIZE` bytes of logical address space.
+///
+/// Check [`module's documentation`](self) for more info.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::spi_flash_memory::{*, ftl::*}, hardware::mock::MockFlashMemory};
+/// #
+/// let mut page_buffer = [0; MT29F2G01ABAGDWB::PAGE_SIZE as usize];
+/// let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::new();
+/// let mut ftl = FlashTranslationLayer::<_, 128, 8>::new(flash_memory, 0, &mut page_buffer, WearLevelingConfig::default());
+/// ftl.format().unwrap();
+///
+/// ftl.write(10_000, b"Hello").unwrap();
+/// ftl.write(10_000, b"Hi").unwrap();
+///
+/// let mut data = [0; 5];
+/// ftl.read(10_000, &mut data).unwrap();
+/// assert_eq!(&data, b"Hillo");
+/// ```
+pub struct FlashTranslationLayer<'a, F: FlashMemory, const LOGICAL_PAGES: usize, const BLOCKS: usize>
+{
+	flash: F,
+	first_block: u16,
+	page_buffer: &'a mut [u8],
+	config: WearLevelingConfig,
+	is_mounted: bool,
+
+	/// Index of the physical page (relative to the first managed block) of each logical page.
+	map: [u32; LOGICAL_PAGES],
+	blocks: [BlockInfo; BLOCKS],
+	open_block: Option<usize>,
+	next_free_page: u32,
+	next_sequence: u32,
+
+	checkpoint_blocks_per_copy: usize,
+	checkpoint_copy: usize,
+	checkpoint_next_page: u32,
+}
+
+impl<'a, F: FlashMemory, const LOGICAL_PAGES: usize, const BLOCKS: usize>
+	FlashTranslationLayer<'a, F, LOGICAL_PAGES, BLOCKS>
+{
+	/// Size in bytes of the logical address space.
+	pub const CAPACITY: u32 = LOGICAL_PAGES as u32 * F::Chip::PAGE_SIZE;
+
+	const PAGES_PER_BLOCK: u32 = F::Chip::PAGES_PER_BLOCK;
+	const CHECKPOINT_WORDS: usize = CHECKPOINT_HEADER_WORDS + LOGICAL_PAGES + BLOCKS + 1;
+
+	/// Returns a [`FlashTranslationLayer`] that manages the `BLOCKS` blocks of the provided `flash` starting from
+	/// the one at `first_block`. The `page_buffer` is used to move pages and to write less than a page.
+	///
+	/// # Warning
+	/// Before reading or writing, you need to either [`format`] or [`mount`] it.
+	///
+	/// # Panics
+	/// Panics if the managed blocks don't exist in the flash memory or if `page_buffer.len() < Chip::PAGE_SIZE`.
+	///
+	/// [`format`]: Self::format
+	/// [`mount`]: Self::mount
+	pub fn new(flash: F, first_block: u16, page_buffer: &'a mut [u8], config: WearLevelingConfig) -> Self
+	{
+		assert!((first_block as u32 + BLOCKS as u32) <= F::Chip::BLOCKS_COUNT);
+		assert!(page_buffer.len() as u32 >= F::Chip::PAGE_SIZE);
+
+		let checkpoint_pages = (Self::CHECKPOINT_WORDS as u32 * 4).ceil_div(F::Chip::PAGE_SIZE);
+
+		Self {
+			flash,
+			first_block,
+			page_buffer,
+			config,
+			is_mounted: false,
+			map: [UNMAPPED; LOGICAL_PAGES],
+			blocks: [BlockInfo::FREE; BLOCKS],
+			open_block: None,
+			next_free_page: 0,
+			next_sequence: 1,
+			checkpoint_blocks_per_copy: checkpoint_pages.ceil_div(Self::PAGES_PER_BLOCK) as usize,
+			checkpoint_copy: 0,
+			checkpoint_next_page: 0,
+		}
+	}
+
+	/// Erases all the managed blocks (except the ones with a bad block mark), losing all the data written before.
+	pub fn format(&mut self) -> Result<(), Error<F>>
+	{
+		self.is_mounted = false;
+		self.classify_blocks()?;
+
+		for block in 0..BLOCKS
+		{
+			if self.blocks[block].state != BlockState::Bad
+			{
+				self.erase_block(block)?;
+			}
+		}
+
+		self.checkpoint_copy = 0;
+		self.checkpoint_next_page = 0;
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Rebuilds the mapping table from what has been written in the flash memory (even if there has been a power loss
+	/// while writing).
+	pub fn mount(&mut self) -> Result<(), Error<F>>
+	{
+		self.is_mounted = false;
+		self.classify_blocks()?;
+
+		for block in 0..BLOCKS
+		{
+			if self.blocks[block].state == BlockState::Free
+			{
+				if let ReadTag::Valid(tag) | ReadTag::Corrupted(tag) = self.read_tag(block, 0)?
+				{
+					self.blocks[block] = BlockInfo {
+						state: BlockState::Full,
+						erase_count: tag.erase_count,
+						sequence: tag.sequence,
+						valid_pages: 0,
+					};
+				}
+			}
+		}
+
+		let checkpoint = self.load_latest_checkpoint()?;
+		self.replay(checkpoint)?;
+
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Reads `data.len()` bytes of the logical address space starting from the provided `address`.
+	///
+	/// The bytes that have never been written are read as `0xFF`.
+	///
+	/// Returns `Ok(ecc_status)` if all the bytes have been read, where `ecc_status` is the worst [`EccStatus`] of
+	/// the read pages, otherwise returns `Err(...)`.
+	pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, Error<F>>
+	{
+		self.check_access(address, data.len())?;
+
+		let mut ecc_status = EccStatus::NoErrors;
+		let mut read_bytes = 0;
+		while read_bytes < data.len()
+		{
+			let current_address = address + read_bytes as u32;
+			let logical_page = (current_address / F::Chip::PAGE_SIZE) as usize;
+			let column = current_address % F::Chip::PAGE_SIZE;
+			let bytes_to_read = ((F::Chip::PAGE_SIZE - column) as usize).min(data.len() - read_bytes);
+			let chunk = &mut data[read_bytes..(read_bytes + bytes_to_read)];
+
+			match self.map[logical_page]
+			{
+				UNMAPPED => chunk.fill(0xFF),
+				physical_page =>
+				{
+					let page_ecc_status = self
+						.flash
+						.read(self.get_address_of_physical_page(physical_page) + column, chunk)
+						.map_err(Error::Flash)?;
+					ecc_status = ecc_status.max(page_ecc_status);
+				},
+			}
+
+			read_bytes += bytes_to_read;
+		}
+
+		Ok(ecc_status)
+	}
+
+	/// Writes the provided `data` in the logical address space starting from the provided `address`.
+	///
+	/// Unlike [`FlashMemory::program`], you can overwrite data you previously wrote without erasing it.
+	///
+	/// # Note
+	/// Writing less than a whole logical page requires reading the old content of the page, so align the writes
+	/// to `Chip::PAGE_SIZE` if you can.
+	pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error<F>>
+	{
+		self.check_access(address, data.len())?;
+
+		let mut written_bytes = 0;
+		while written_bytes < data.len()
+		{
+			let current_address = address + written_bytes as u32;
+			let logical_page = (current_address / F::Chip::PAGE_SIZE) as usize;
+			let column = (current_address % F::Chip::PAGE_SIZE) as usize;
+			let bytes_to_write = (F::Chip::PAGE_SIZE as usize - column).min(data.len() - written_bytes);
+			let chunk = &data[written_bytes..(written_bytes + bytes_to_write)];
+
+			// This could move some pages, so it must be done before the page buffer is filled
+			self.prepare_open_block(true)?;
+
+			if bytes_to_write == F::Chip::PAGE_SIZE as usize
+			{
+				self.program_logical_page(logical_page, chunk)?;
+			}
+			else
+			{
+				let page_buffer = core::mem::take(&mut self.page_buffer);
+				let page = &mut page_buffer[..F::Chip::PAGE_SIZE as usize];
+
+				let result = self.read_logical_page_to_copy(logical_page, page).and_then(|_| {
+					page[column..(column + bytes_to_write)].copy_from_slice(chunk);
+					self.program_logical_page(logical_page, page)
+				});
+				self.page_buffer = page_buffer;
+				result?;
+			}
+
+			written_bytes += bytes_to_write;
+		}
+
+		Ok(())
+	}
+
+	/// Saves the mapping table and the erase counts of the blocks in the flash memory, so that the next [`mount`]
+	/// will be faster.
+	///
+	/// Calling this function is not required to not lose data, but the more pages are written after the last call,
+	/// the slower the next [`mount`] will be.
+	///
+	/// # Warning
+	/// If the mapping table is bigger than the space left in the reserved blocks, they need to be erased, so don't
+	/// call this function after every write.
+	///
+	/// [`mount`]: Self::mount
+	pub fn flush(&mut self) -> Result<(), Error<F>>
+	{
+		self.check_mounted()?;
+
+		let checkpoint_pages = (Self::CHECKPOINT_WORDS as u32 * 4).ceil_div(F::Chip::PAGE_SIZE);
+		if self.checkpoint_next_page == 0 || self.checkpoint_next_page + checkpoint_pages > self.checkpoint_copy_size()
+		{
+			// The previous copy is kept until the new one has been fully written
+			if self.checkpoint_next_page != 0
+			{
+				self.checkpoint_copy = 1 - self.checkpoint_copy;
+			}
+			for block in 0..self.checkpoint_blocks_per_copy
+			{
+				let block = self.get_checkpoint_block(self.checkpoint_copy * self.checkpoint_blocks_per_copy + block);
+				self.erase_block(block)?;
+			}
+			self.checkpoint_next_page = 0;
+		}
+
+		let sequence = self.next_sequence;
+		self.next_sequence += 1;
+
+		let words_per_page = F::Chip::PAGE_SIZE as usize / 4;
+		let mut crc = Crc32::new();
+		let page_buffer = core::mem::take(&mut self.page_buffer);
+		let mut result = Ok(());
+		for page in 0..checkpoint_pages
+		{
+			let first_word = page as usize * words_per_page;
+			for (i, bytes) in page_buffer[..F::Chip::PAGE_SIZE as usize]
+				.chunks_exact_mut(4)
+				.enumerate()
+			{
+				let word_index = first_word + i;
+				let word = match word_index
+				{
+					_ if word_index == Self::CHECKPOINT_WORDS - 1 => crc.finish(),
+					_ if word_index >= Self::CHECKPOINT_WORDS => u32::MAX,
+					_ => self.get_checkpoint_word(word_index, sequence),
+				};
+				bytes.copy_from_slice(&word.to_le_bytes());
+				if word_index < Self::CHECKPOINT_WORDS - 1
+				{
+					crc.update(bytes);
+				}
+			}
+
+			let (block, page_in_block) = self.get_checkpoint_page(self.checkpoint_copy, self.checkpoint_next_page);
+			let tag = PageTag {
+				logical_page: CHECKPOINT_PAGE,
+				sequence,
+				erase_count: self.blocks[block].erase_count,
+			};
+			result = self.program_physical_page(block, page_in_block, &page_buffer[..F::Chip::PAGE_SIZE as usize], tag);
+			if result.is_err()
+			{
+				break;
+			}
+			self.checkpoint_next_page += 1;
+		}
+		self.page_buffer = page_buffer;
+
+		result
+	}
+
+	/// Returns some statistics about the wear of the managed blocks.
+	pub fn get_wear_statistics(&self) -> WearStatistics
+	{
+		let mut statistics = WearStatistics {
+			min_erase_count: u32::MAX,
+			max_erase_count: 0,
+			free_blocks: 0,
+			bad_blocks: 0,
+		};
+		for block in self.blocks.iter()
+		{
+			match block.state
+			{
+				BlockState::Bad => statistics.bad_blocks += 1,
+				BlockState::Checkpoint => (),
+				state =>
+				{
+					statistics.free_blocks += (state == BlockState::Free) as usize;
+					statistics.min_erase_count = statistics.min_erase_count.min(block.erase_count);
+					statistics.max_erase_count = statistics.max_erase_count.max(block.erase_count);
+				},
+			}
+		}
+
+		statistics
+	}
+
+	/// Returns a mutable reference to the underlying flash memory.
+	///
+	/// # Warning
+	/// Writing in the managed blocks without passing through this struct will corrupt its data.
+	pub fn get_flash_mut(&mut self) -> &mut F
+	{
+		&mut self.flash
+	}
+
+	/// Consumes this struct and returns the underlying flash memory.
+	pub fn release(self) -> F
+	{
+		self.flash
+	}
+
+	fn check_mounted(&self) -> Result<(), Error<F>>
+	{
+		match self.is_mounted
+		{
+			true => Ok(()),
+			false => Err(Error::NotMounted),
+		}
+	}
+
+	fn check_access(&self, address: u32, length: usize) -> Result<(), Error<F>>
+	{
+		self.check_mounted()?;
+
+		match address.checked_add(length as u32)
+		{
+			Some(end) if end <= Self::CAPACITY => Ok(()),
+			_ => Err(Error::OutOfRange),
+		}
+	}
+
+	/// Resets the state of all the blocks, marking them as free (or bad) and reserving the ones for the checkpoints.
+	fn classify_blocks(&mut self) -> Result<(), Error<F>>
+	{
+		self.map = [UNMAPPED; LOGICAL_PAGES];
+		self.open_block = None;
+		self.next_free_page = 0;
+		self.next_sequence = 1;
+
+		let mut checkpoint_blocks_left = 2 * self.checkpoint_blocks_per_copy;
+		let mut data_blocks = 0;
+		for block in 0..BLOCKS
+		{
+			let is_bad = self
+				.flash
+				.contains_bad_block_mark(self.first_block + block as u16)
+				.map_err(Error::Flash)?;
+
+			self.blocks[block] = BlockInfo::FREE;
+			if is_bad
+			{
+				self.blocks[block].state = BlockState::Bad;
+			}
+			else if checkpoint_blocks_left > 0
+			{
+				self.blocks[block].state = BlockState::Checkpoint;
+				checkpoint_blocks_left -= 1;
+			}
+			else
+			{
+				data_blocks += 1;
+			}
+		}
+
+		if checkpoint_blocks_left > 0
+			|| data_blocks < RESERVED_FREE_BLOCKS + 1
+			|| LOGICAL_PAGES as u32 > (data_blocks - RESERVED_FREE_BLOCKS - 1) as u32 * Self::PAGES_PER_BLOCK
+		{
+			return Err(Error::NotEnoughBlocks);
+		}
+
+		Ok(())
+	}
+
+	fn get_address_of_physical_page(&self, physical_page: u32) -> u32
+	{
+		F::Chip::get_address_of_block_index(self.first_block) + physical_page * F::Chip::PAGE_SIZE
+	}
+
+	fn get_row_address(&self, block: usize, page_in_block: u32) -> RowAddress<F::Chip>
+	{
+		RowAddress::from_memory_address(
+			self.get_address_of_physical_page(block as u32 * Self::PAGES_PER_BLOCK + page_in_block),
+		)
+	}
+
+	fn read_tag(&mut self, block: usize, page_in_block: u32) -> Result<ReadTag, Error<F>>
+	{
+		let mut bytes = [0; TAG_OFFSET + TAG_SIZE];
+		self.flash
+			.read_ecc(self.get_row_address(block, page_in_block), &mut bytes)
+			.map_err(Error::Flash)?;
+
+		Ok(PageTag::from_bytes(&bytes[TAG_OFFSET..]))
+	}
+
+	fn program_physical_page(
+		&mut self, block: usize, page_in_block: u32, data: &[u8], tag: PageTag,
+	) -> Result<(), Error<F>>
+	{
+		let mut ecc_data = [0xFF; TAG_OFFSET + TAG_SIZE];
+		ecc_data[TAG_OFFSET..].copy_from_slice(&tag.to_bytes());
+
+		self.flash
+			.program_page(self.get_row_address(block, page_in_block), data, &ecc_data)
+			.map_err(Error::Flash)
+	}
+
+	fn erase_block(&mut self, block: usize) -> Result<(), Error<F>>
+	{
+		let block_index = self.first_block + block as u16;
+		self.flash
+			.erase_blocks(block_index..=block_index)
+			.map_err(Error::Flash)?;
+
+		let block = &mut self.blocks[block];
+		block.erase_count += 1;
+		block.sequence = 0;
+		block.valid_pages = 0;
+		if block.state != BlockState::Checkpoint
+		{
+			block.state = BlockState::Free;
+		}
+
+		Ok(())
+	}
+
+	fn read_logical_page(&mut self, logical_page: usize, page: &mut [u8]) -> Result<EccStatus, Error<F>>
+	{
+		match self.map[logical_page]
+		{
+			UNMAPPED =>
+			{
+				page.fill(0xFF);
+				Ok(EccStatus::NoErrors)
+			},
+			physical_page => self
+				.flash
+				.read(self.get_address_of_physical_page(physical_page), page)
+				.map_err(Error::Flash),
+		}
+	}
+
+	/// Like [`Self::read_logical_page`], but returns [`Error::Uncorrectable`] if the read data is corrupted, so that it
+	/// isn't programmed again as if it was right.
+	fn read_logical_page_to_copy(&mut self, logical_page: usize, page: &mut [u8]) -> Result<(), Error<F>>
+	{
+		match self.read_logical_page(logical_page, page)?
+		{
+			EccStatus::Uncorrectable => Err(Error::Uncorrectable),
+			_ => Ok(()),
+		}
+	}
+
+	/// Programs the `data` of the `logical_page` in the next free page of the open block.
+	///
+	/// [`Self::prepare_open_block`] must have been called before this.
+	fn program_logical_page(&mut self, logical_page: usize, data: &[u8]) -> Result<(), Error<F>>
+	{
+		let block = self.open_block.expect("There must be an open block");
+		let page_in_block = self.next_free_page;
+		let tag = PageTag {
+			logical_page: logical_page as u32,
+			sequence: self.blocks[block].sequence,
+			erase_count: self.blocks[block].erase_count,
+		};
+
+		// Even if the program fails, the page can't be used anymore
+		self.next_free_page += 1;
+		if self.next_free_page == Self::PAGES_PER_BLOCK
+		{
+			self.blocks[block].state = BlockState::Full;
+			self.open_block = None;
+		}
+
+		self.program_physical_page(block, page_in_block, data, tag)?;
+
+		let old_physical_page = self.map[logical_page];
+		if old_physical_page != UNMAPPED
+		{
+			self.blocks[(old_physical_page / Self::PAGES_PER_BLOCK) as usize].valid_pages -= 1;
+		}
+		self.map[logical_page] = block as u32 * Self::PAGES_PER_BLOCK + page_in_block;
+		self.blocks[block].valid_pages += 1;
+
+		Ok(())
+	}
+
+	/// Makes sure there's an open block with at least a free page, opening the free block with the lowest erase
+	/// count if needed.
+	///
+	/// If `can_collect_garbage` is `true` and a new block needs to be opened, the garbage collector and the static
+	/// wear leveling are run before.
+	fn prepare_open_block(&mut self, can_collect_garbage: bool) -> Result<(), Error<F>>
+	{
+		if self.open_block.is_some()
+		{
+			return Ok(());
+		}
+
+		if can_collect_garbage
+		{
+			self.collect_garbage()?;
+			self.level_static_wear()?;
+
+			// The pages moved by the garbage collector could have been written in a block that is still open
+			if self.open_block.is_some()
+			{
+				return Ok(());
+			}
+		}
+
+		let block = (0..BLOCKS)
+			.filter(|block| self.blocks[*block].state == BlockState::Free)
+			.min_by_key(|block| self.blocks[*block].erase_count)
+			.ok_or(Error::Full)?;
+
+		self.blocks[block].state = BlockState::Open;
+		self.blocks[block].sequence = self.next_sequence;
+		self.next_sequence += 1;
+		self.open_block = Some(block);
+		self.next_free_page = 0;
+
+		Ok(())
+	}
+
+	fn collect_garbage(&mut self) -> Result<(), Error<F>>
+	{
+		while self.get_free_blocks_count() <= RESERVED_FREE_BLOCKS
+		{
+			let victim = (0..BLOCKS)
+				.filter(|block| self.blocks[*block].state == BlockState::Full)
+				.min_by_key(|block| self.blocks[*block].valid_pages);
+
+			match victim
+			{
+				Some(victim) if self.blocks[victim].valid_pages < Self::PAGES_PER_BLOCK =>
+				{
+					self.relocate_block(victim)?
+				},
+				_ => break,
+			}
+		}
+
+		Ok(())
+	}
+
+	fn level_static_wear(&mut self) -> Result<(), Error<F>>
+	{
+		let max_erase_count = self.get_wear_statistics().max_erase_count;
+		let coldest_block = (0..BLOCKS)
+			.filter(|block| self.blocks[*block].state == BlockState::Full)
+			.min_by_key(|block| self.blocks[*block].erase_count);
+
+		if let Some(coldest_block) = coldest_block
+		{
+			if max_erase_count - self.blocks[coldest_block].erase_count > self.config.max_erase_count_difference
+			{
+				self.relocate_block(coldest_block)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Moves all the valid pages of the provided `block` in the open block, and then erases it.
+	fn relocate_block(&mut self, block: usize) -> Result<(), Error<F>>
+	{
+		for page_in_block in 0..Self::PAGES_PER_BLOCK
+		{
+			if self.blocks[block].valid_pages == 0
+			{
+				break;
+			}
+
+			let physical_page = block as u32 * Self::PAGES_PER_BLOCK + page_in_block;
+			if let ReadTag::Valid(tag) = self.read_tag(block, page_in_block)?
+			{
+				let logical_page = tag.logical_page as usize;
+				if logical_page < LOGICAL_PAGES && self.map[logical_page] == physical_page
+				{
+					self.prepare_open_block(false)?;
+
+					let page_buffer = core::mem::take(&mut self.page_buffer);
+					let page = &mut page_buffer[..F::Chip::PAGE_SIZE as usize];
+					let result = self
+						.read_logical_page_to_copy(logical_page, page)
+						.and_then(|_| self.program_logical_page(logical_page, page));
+					self.page_buffer = page_buffer;
+					result?;
+				}
+			}
+		}
+
+		self.erase_block(block)
+	}
+
+	fn get_free_blocks_count(&self) -> usize
+	{
+		self.blocks
+			.iter()
+			.filter(|block| block.state == BlockState::Free)
+			.count()
+	}
+
+	fn checkpoint_copy_size(&self) -> u32
+	{
+		self.checkpoint_blocks_per_copy as u32 * Self::PAGES_PER_BLOCK
+	}
+
+	/// Returns the index of the `n`th block reserved for the checkpoints.
+	fn get_checkpoint_block(&self, n: usize) -> usize
+	{
+		(0..BLOCKS)
+			.filter(|block| self.blocks[*block].state == BlockState::Checkpoint)
+			.nth(n)
+			.expect("The checkpoint blocks are reserved when mounting")
+	}
+
+	/// Returns the block and the page in that block of the `page`th page of the provided checkpoint `copy`.
+	fn get_checkpoint_page(&self, copy: usize, page: u32) -> (usize, u32)
+	{
+		let block =
+			self.get_checkpoint_block(copy * self.checkpoint_blocks_per_copy + (page / Self::PAGES_PER_BLOCK) as usize);
+		(block, page % Self::PAGES_PER_BLOCK)
+	}
+
+	fn get_checkpoint_word(&self, word_index: usize, sequence: u32) -> u32
+	{
+		match word_index
+		{
+			0 => CHECKPOINT_MAGIC,
+			1 => sequence,
+			2 => LOGICAL_PAGES as u32,
+			3 => BLOCKS as u32,
+			4 => self.open_block.map(|block| block as u32).unwrap_or(NO_OPEN_BLOCK),
+			5 => self.open_block.map(|block| self.blocks[block].sequence).unwrap_or(0),
+			6 => self.next_free_page,
+			_ if word_index < CHECKPOINT_HEADER_WORDS + LOGICAL_PAGES => self.map[word_index - CHECKPOINT_HEADER_WORDS],
+			_ => self.blocks[word_index - CHECKPOINT_HEADER_WORDS - LOGICAL_PAGES].erase_count,
+		}
+	}
+
+	/// Loads the latest valid checkpoint in the flash memory (if there's one), and prepares the checkpoint blocks
+	/// for the next [`Self::flush`].
+	fn load_latest_checkpoint(&mut self) -> Result<Option<Checkpoint>, Error<F>>
+	{
+		// Find the first page of the latest checkpoint and where each copy ends
+		let mut latest_start: Option<(u32, usize, u32)> = None;
+		let mut copies_end = [0; 2];
+		for (copy, copy_end) in copies_end.iter_mut().enumerate()
+		{
+			let mut previous_sequence = None;
+			for page in 0..self.checkpoint_copy_size()
+			{
+				let (block, page_in_block) = self.get_checkpoint_page(copy, page);
+				match self.read_tag(block, page_in_block)?
+				{
+					ReadTag::Erased => break,
+					ReadTag::Valid(tag) if tag.logical_page == CHECKPOINT_PAGE =>
+					{
+						self.blocks[block].erase_count = self.blocks[block].erase_count.max(tag.erase_count);
+						if previous_sequence != Some(tag.sequence)
+							&& latest_start.is_none_or(|(sequence, _, _)| tag.sequence > sequence)
+						{
+							latest_start = Some((tag.sequence, copy, page));
+						}
+						previous_sequence = Some(tag.sequence);
+					},
+					_ => previous_sequence = None,
+				}
+				*copy_end = page + 1;
+			}
+		}
+
+		// If there isn't a valid checkpoint, the next flush will erase the first copy
+		self.checkpoint_copy = 1;
+		self.checkpoint_next_page = self.checkpoint_copy_size();
+
+		let Some((_, copy, first_page)) = latest_start
+		else
+		{
+			return Ok(None);
+		};
+
+		let checkpoint_pages = (Self::CHECKPOINT_WORDS as u32 * 4).ceil_div(F::Chip::PAGE_SIZE);
+		if first_page + checkpoint_pages > self.checkpoint_copy_size()
+		{
+			return Ok(None);
+		}
+
+		let words_per_page = F::Chip::PAGE_SIZE as usize / 4;
+		let mut crc = Crc32::new();
+		let mut header = [0; CHECKPOINT_HEADER_WORDS];
+		let mut erase_counts_are_valid = false;
+		let page_buffer = core::mem::take(&mut self.page_buffer);
+		let mut result = Ok(());
+		'pages: for page in 0..checkpoint_pages
+		{
+			let (block, page_in_block) = self.get_checkpoint_page(copy, first_page + page);
+			let address = self.get_address_of_physical_page(block as u32 * Self::PAGES_PER_BLOCK + page_in_block);
+			result = self
+				.flash
+				.read(address, &mut page_buffer[..F::Chip::PAGE_SIZE as usize])
+				.map(|_| ())
+				.map_err(Error::Flash);
+			if result.is_err()
+			{
+				break;
+			}
+
+			for (i, bytes) in page_buffer[..F::Chip::PAGE_SIZE as usize].chunks_exact(4).enumerate()
+			{
+				let word_index = page as usize * words_per_page + i;
+				let word = u32::from_le_bytes(bytes.try_into().unwrap());
+				if word_index == Self::CHECKPOINT_WORDS - 1
+				{
+					erase_counts_are_valid = word == crc.finish();
+					break 'pages;
+				}
+				crc.update(bytes);
+
+				if word_index < CHECKPOINT_HEADER_WORDS
+				{
+					header[word_index] = word;
+					if word_index == CHECKPOINT_HEADER_WORDS - 1
+						&& (header[0] != CHECKPOINT_MAGIC
+							|| header[2] != LOGICAL_PAGES as u32
+							|| header[3] != BLOCKS as u32)
+					{
+						break 'pages;
+					}
+				}
+				else if word_index < CHECKPOINT_HEADER_WORDS + LOGICAL_PAGES
+				{
+					self.map[word_index - CHECKPOINT_HEADER_WORDS] = word;
+				}
+			}
+		}
+		self.page_buffer = page_buffer;
+		result?;
+
+		if !erase_counts_are_valid
+		{
+			self.map = [UNMAPPED; LOGICAL_PAGES];
+			return Ok(None);
+		}
+
+		// The erase counts are read again, now that it's known that they are valid
+		let erase_counts_start = CHECKPOINT_HEADER_WORDS + LOGICAL_PAGES;
+		for block in 0..BLOCKS
+		{
+			let word_index = erase_counts_start + block;
+			let page = (word_index / words_per_page) as u32;
+			let (checkpoint_block, page_in_block) = self.get_checkpoint_page(copy, first_page + page);
+			let address = self
+				.get_address_of_physical_page(checkpoint_block as u32 * Self::PAGES_PER_BLOCK + page_in_block)
+				+ (word_index % words_per_page) as u32 * 4;
+
+			let mut bytes = [0; 4];
+			self.flash.read(address, &mut bytes).map_err(Error::Flash)?;
+			let erase_count = u32::from_le_bytes(bytes);
+			self.blocks[block].erase_count = self.blocks[block].erase_count.max(erase_count);
+		}
+
+		self.checkpoint_copy = copy;
+		self.checkpoint_next_page = copies_end[copy];
+
+		Ok(Some(Checkpoint {
+			sequence: header[1],
+			open_block: header[4],
+			open_block_sequence: header[5],
+			next_free_page: header[6],
+		}))
+	}
+
+	/// Updates the mapping table with the pages written after the provided `checkpoint` (or with all the pages if
+	/// there isn't a checkpoint), and then restores the state of the blocks.
+	fn replay(&mut self, checkpoint: Option<Checkpoint>) -> Result<(), Error<F>>
+	{
+		let mut blocks_by_sequence = [0_u16; BLOCKS];
+		let mut used_blocks_count = 0;
+		for block in 0..BLOCKS
+		{
+			if self.blocks[block].state == BlockState::Full
+			{
+				blocks_by_sequence[used_blocks_count] = block as u16;
+				used_blocks_count += 1;
+			}
+		}
+		let blocks_by_sequence = &mut blocks_by_sequence[..used_blocks_count];
+		blocks_by_sequence.sort_unstable_by_key(|block| self.blocks[*block as usize].sequence);
+
+		let checkpoint_sequence = checkpoint.map_or(0, |checkpoint| checkpoint.sequence);
+		for block in blocks_by_sequence.iter().map(|block| *block as usize)
+		{
+			let sequence = self.blocks[block].sequence;
+			let first_page = match checkpoint
+			{
+				_ if sequence >= checkpoint_sequence => 0,
+				Some(checkpoint)
+					if checkpoint.open_block == block as u32 && checkpoint.open_block_sequence == sequence =>
+				{
+					checkpoint.next_free_page
+				},
+				_ => continue,
+			};
+
+			for page_in_block in first_page..Self::PAGES_PER_BLOCK
+			{
+				match self.read_tag(block, page_in_block)?
+				{
+					ReadTag::Erased => break,
+					ReadTag::Valid(tag) if (tag.logical_page as usize) < LOGICAL_PAGES =>
+					{
+						self.map[tag.logical_page as usize] = block as u32 * Self::PAGES_PER_BLOCK + page_in_block;
+					},
+					_ => (),
+				}
+			}
+		}
+
+		// Only the last written block can have some free pages
+		if let Some(last_block) = blocks_by_sequence.last().map(|block| *block as usize)
+		{
+			let mut next_free_page = Self::PAGES_PER_BLOCK;
+			while next_free_page > 0 && self.read_tag(last_block, next_free_page - 1)? == ReadTag::Erased
+			{
+				next_free_page -= 1;
+			}
+
+			if next_free_page < Self::PAGES_PER_BLOCK
+			{
+				self.blocks[last_block].state = BlockState::Open;
+				self.open_block = Some(last_block);
+				self.next_free_page = next_free_page;
+			}
+		}
+
+		for block in self.blocks.iter_mut()
+		{
+			block.valid_pages = 0;
+			self.next_sequence = self.next_sequence.max(block.sequence + 1);
+		}
+		self.next_sequence = self.next_sequence.max(checkpoint_sequence + 1);
+		for physical_page in self.map.iter().filter(|physical_page| **physical_page != UNMAPPED)
+		{
+			self.blocks[(*physical_page / Self::PAGES_PER_BLOCK) as usize].valid_pages += 1;
+		}
+
+		Ok(())
+	}
+}
+
+/// Configuration of the wear leveling of a [`FlashTranslationLayer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WearLevelingConfig
+{
+	/// The maximum difference between the erase counts of the most and least worn blocks before the data in the least
+	/// worn block is moved, to make that block available for new writes.
+	///
+	/// Lower values keep the wear more even, but move data more often.
+	pub max_erase_count_difference: u32,
+}
+
+impl Default for WearLevelingConfig
+{
+	fn default() -> Self
+	{
+		Self {
+			max_erase_count_difference: 16,
+		}
+	}
+}
+
+/// Some statistics about the wear of the blocks managed by a [`FlashTranslationLayer`].
+///
+/// Check [`FlashTranslationLayer::get_wear_statistics`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WearStatistics
+{
+	/// The lowest erase count of the blocks used to store data.
+	pub min_erase_count: u32,
+	/// The highest erase count of the blocks used to store data.
+	pub max_erase_count: u32,
+	/// How many blocks are erased and ready to be written.
+	pub free_blocks: usize,
+	/// How many blocks contain a bad block mark.
+	pub bad_blocks: usize,
+}
+
+/// An error returned from a [`FlashTranslationLayer`].
+pub enum Error<F: FlashMemory>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(F::Error),
+	/// You need to [`format`] or [`mount`] the flash translation layer before using it.
+	///
+	/// [`format`]: FlashTranslationLayer::format
+	/// [`mount`]: FlashTranslationLayer::mount
+	NotMounted,
+	/// The accessed addresses are outside the logical address space.
+	OutOfRange,
+	/// There aren't enough good blocks to store `LOGICAL_PAGES` pages, the checkpoints and the blocks reserved for
+	/// the garbage collector.
+	NotEnoughBlocks,
+	/// There isn't any free block to write to.
+	Full,
+	/// A page that had to be copied (to write only part of it, or to move it out of a block that is going to be
+	/// erased) contains more flipped bits than the ECC can correct.
+	Uncorrectable,
+}
+
+impl<F: FlashMemory> Debug for Error<F>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Flash(arg0) => f.debug_tuple("Flash").field(arg0).finish(),
+			Self::NotMounted => write!(f, "NotMounted"),
+			Self::OutOfRange => write!(f, "OutOfRange"),
+			Self::NotEnoughBlocks => write!(f, "NotEnoughBlocks"),
+			Self::Full => write!(f, "Full"),
+			Self::Uncorrectable => write!(f, "Uncorrectable"),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BlockState
+{
+	Free,
+	Open,
+	Full,
+	Bad,
+	Checkpoint,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BlockInfo
+{
+	state: BlockState,
+	erase_count: u32,
+	/// When the block has been opened (compared to the other blocks).
+	sequence: u32,
+	valid_pages: u32,
+}
+
+impl BlockInfo
+{
+	const FREE: Self = Self {
+		state: BlockState::Free,
+		erase_count: 0,
+		sequence: 0,
+		valid_pages: 0,
+	};
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Checkpoint
+{
+	sequence: u32,
+	open_block: u32,
+	open_block_sequence: u32,
+	next_free_page: u32,
+}
+
+/// The data stored in the ECC area of each page written by the [`FlashTranslationLayer`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct PageTag
+{
+	logical_page: u32,
+	sequence: u32,
+	erase_count: u32,
+}
+
+impl PageTag
+{
+	fn to_bytes(self) -> [u8; TAG_SIZE]
+	{
+		let mut bytes = [0; TAG_SIZE];
+		bytes[0..4].copy_from_slice(&self.logical_page.to_le_bytes());
+		bytes[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+		bytes[8..12].copy_from_slice(&self.erase_count.to_le_bytes());
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..12]);
+		bytes[12..16].copy_from_slice(&crc.finish().to_le_bytes());
+
+		bytes
+	}
+
+	fn from_bytes(bytes: &[u8]) -> ReadTag
+	{
+		if bytes.iter().all(|byte| *byte == 0xFF)
+		{
+			return ReadTag::Erased;
+		}
+
+		let word = |i: usize| u32::from_le_bytes(bytes[(i * 4)..(i * 4 + 4)].try_into().unwrap());
+		let tag = Self {
+			logical_page: word(0),
+			sequence: word(1),
+			erase_count: word(2),
+		};
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..12]);
+		match crc.finish() == word(3)
+		{
+			true => ReadTag::Valid(tag),
+			false => ReadTag::Corrupted(tag),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ReadTag
+{
+	Erased,
+	Valid(PageTag),
+	/// The page has been programmed, but the tag isn't valid (probably because of a power loss while programming).
+	Corrupted(PageTag),
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::{super::MT29F2G01ABAGDWB, *};
+	use crate::hardware::mock::MockFlashMemory;
+
+	const PAGE_SIZE: usize = MT29F2G01ABAGDWB::PAGE_SIZE as usize;
+	type Ftl<'a> = FlashTranslationLayer<'a, MockFlashMemory<MT29F2G01ABAGDWB>, 192, 8>;
+
+	fn page_content(logical_page: usize, version: usize) -> [u8; PAGE_SIZE]
+	{
+		core::array::from_fn(|i| (i + logical_page * 7 + version * 13) as u8)
+	}
+
+	#[test]
+	fn unaligned_writes()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ftl = Ftl::new(
+			MockFlashMemory::new(),
+			3,
+			&mut page_buffer,
+			WearLevelingConfig::default(),
+		);
+		assert!(matches!(ftl.write(0, &[0]), Err(Error::NotMounted)));
+		ftl.format().unwrap();
+
+		let data: [u8; 5000] = core::array::from_fn(|i| (i % 251) as u8);
+		ftl.write(1000, &data).unwrap();
+		ftl.write(3000, &[0xAB; 10]).unwrap();
+
+		let mut read_data = [0; 5002];
+		ftl.read(999, &mut read_data).unwrap();
+		assert_eq!(read_data[0], 0xFF);
+		assert_eq!(read_data[1..2001], data[..2000]);
+		assert_eq!(read_data[2001..2011], [0xAB; 10]);
+		assert_eq!(read_data[2011..5001], data[2010..]);
+		assert_eq!(read_data[5001], 0xFF);
+
+		assert!(matches!(ftl.write(Ftl::CAPACITY - 1, &[0, 0]), Err(Error::OutOfRange)));
+	}
+
+	#[test]
+	fn ecc_status_is_reported()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ftl = Ftl::new(
+			MockFlashMemory::new(),
+			0,
+			&mut page_buffer,
+			WearLevelingConfig::default(),
+		);
+		ftl.format().unwrap();
+		ftl.write(0, &page_content(0, 0)).unwrap();
+
+		let corrected = EccStatus::Corrected {
+			max_corrected_bits: Some(3),
+			should_refresh: false,
+		};
+		for page_index in 0..(8 * MT29F2G01ABAGDWB::PAGES_PER_BLOCK)
+		{
+			ftl.get_flash_mut().set_ecc_status(page_index, corrected);
+		}
+
+		let mut data = [0; PAGE_SIZE * 2];
+		assert_eq!(ftl.read(0, &mut data).unwrap(), corrected);
+		assert_eq!(ftl.read(PAGE_SIZE as u32, &mut data).unwrap(), EccStatus::NoErrors);
+	}
+
+	#[test]
+	fn uncorrectable_pages_arent_copied()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ftl = Ftl::new(
+			MockFlashMemory::new(),
+			0,
+			&mut page_buffer,
+			WearLevelingConfig::default(),
+		);
+		ftl.format().unwrap();
+		ftl.write(0, &page_content(0, 0)).unwrap();
+
+		let physical_page = ftl.map[0];
+		let page_index = ftl.get_address_of_physical_page(physical_page) / PAGE_SIZE as u32;
+		ftl.get_flash_mut().set_ecc_status(page_index, EccStatus::Uncorrectable);
+
+		// Writing part of the page would program the corrupted bytes around the new ones
+		assert!(matches!(ftl.write(10, &[1, 2, 3]), Err(Error::Uncorrectable)));
+		assert_eq!(ftl.map[0], physical_page);
+
+		// Relocating its block would move the corrupted page as if it was right
+		let block = (physical_page / Ftl::PAGES_PER_BLOCK) as usize;
+		assert!(matches!(ftl.relocate_block(block), Err(Error::Uncorrectable)));
+		assert_eq!(ftl.map[0], physical_page);
+
+		// Overwriting the whole page doesn't need its old content
+		ftl.write(0, &page_content(0, 1)).unwrap();
+		let mut data = [0; PAGE_SIZE];
+		assert_eq!(ftl.read(0, &mut data).unwrap(), EccStatus::NoErrors);
+		assert_eq!(data, page_content(0, 1));
+	}
+
+	#[test]
+	fn garbage_collection_and_wear_leveling()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let config = WearLevelingConfig {
+			max_erase_count_difference: 4,
+		};
+		let mut ftl = Ftl::new(MockFlashMemory::new(), 0, &mut page_buffer, config);
+		ftl.format().unwrap();
+
+		// Cold data that is never rewritten
+		for logical_page in 0..150
+		{
+			ftl.write((logical_page * PAGE_SIZE) as u32, &page_content(logical_page, 0))
+				.unwrap();
+		}
+		// Hot data that is continuously rewritten
+		for version in 1..1500
+		{
+			let logical_page = 150 + version % 40;
+			ftl.write((logical_page * PAGE_SIZE) as u32, &page_content(logical_page, version))
+				.unwrap();
+		}
+
+		let statistics = ftl.get_wear_statistics();
+		assert!(statistics.max_erase_count - statistics.min_erase_count <= config.max_erase_count_difference + 1);
+
+		let mut data = [0; PAGE_SIZE];
+		for logical_page in 0..150
+		{
+			ftl.read((logical_page * PAGE_SIZE) as u32, &mut data).unwrap();
+			assert_eq!(data, page_content(logical_page, 0));
+		}
+		for version in 1460..1500
+		{
+			let logical_page = 150 + version % 40;
+			ftl.read((logical_page * PAGE_SIZE) as u32, &mut data).unwrap();
+			assert_eq!(data, page_content(logical_page, version));
+		}
+	}
+
+	#[test]
+	fn mount_after_power_loss()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ftl = Ftl::new(
+			MockFlashMemory::new(),
+			0,
+			&mut page_buffer,
+			WearLevelingConfig::default(),
+		);
+		ftl.format().unwrap();
+
+		for version in 0..300
+		{
+			let logical_page = version % 100;
+			ftl.write((logical_page * PAGE_SIZE) as u32, &page_content(logical_page, version))
+				.unwrap();
+			if version == 150
+			{
+				ftl.flush().unwrap();
+			}
+		}
+
+		// Without calling flush, like if the power was lost
+		let flash_memory = ftl.release();
+		let mut ftl = Ftl::new(flash_memory, 0, &mut page_buffer, WearLevelingConfig::default());
+		ftl.mount().unwrap();
+
+		let mut data = [0; PAGE_SIZE];
+		for version in 200..300
+		{
+			let logical_page = version % 100;
+			ftl.read((logical_page * PAGE_SIZE) as u32, &mut data).unwrap();
+			assert_eq!(data, page_content(logical_page, version));
+		}
+
+		// Mounting again after the flush must give the same result
+		ftl.write(0, &page_content(0, 1000)).unwrap();
+		ftl.flush().unwrap();
+		let flash_memory = ftl.release();
+		let mut ftl = Ftl::new(flash_memory, 0, &mut page_buffer, WearLevelingConfig::default());
+		ftl.mount().unwrap();
+
+		ftl.read(0, &mut data).unwrap();
+		assert_eq!(data, page_content(0, 1000));
+		ftl.read(PAGE_SIZE as u32, &mut data).unwrap();
+		assert_eq!(data, page_content(1, 201));
+	}
+
+	#[test]
+	fn factory_bad_blocks_are_skipped()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::with_factory_bad_blocks(&[1, 5]);
+		let mut ftl = FlashTranslationLayer::<_, 64, 8>::new(flash_memory, 0, &mut page_buffer, Default::default());
+		ftl.format().unwrap();
+
+		for version in 0..500
+		{
+			let logical_page = version % 64;
+			ftl.write((logical_page * PAGE_SIZE) as u32, &page_content(logical_page, version))
+				.unwrap();
+		}
+
+		assert_eq!(ftl.get_wear_statistics().bad_blocks, 2);
+		assert_eq!(ftl.get_flash_mut().get_erase_count(1), 0);
+		assert_eq!(ftl.get_flash_mut().get_erase_count(5), 0);
+
+		let mut page_buffer = [0; PAGE_SIZE];
+		let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::with_factory_bad_blocks(&[1, 5]);
+		let mut ftl = FlashTranslationLayer::<_, 128, 8>::new(flash_memory, 0, &mut page_buffer, Default::default());
+		assert!(matches!(ftl.format(), Err(Error::NotEnoughBlocks)));
+	}
+}
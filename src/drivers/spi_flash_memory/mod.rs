@@ -1,23 +1,48 @@
 use core::{
 	fmt::Debug,
 	ops::{Range, RangeInclusive},
+	time::Duration,
 };
 
-use embedded_hal::spi::{ErrorType, SpiDevice};
+use embedded_hal::{
+	delay::DelayNs,
+	spi::{ErrorType, Operation, SpiDevice},
+};
 
-use self::{
-	address::{ColumnAddress, RowAddress},
-	commands::Command,
+use self::commands::Command;
+use crate::{
+	peripherals::{
+		spi::{BusWidth, MultiIoSpiDevice},
+		time::system_time::SystemTime,
+	},
+	utils::math::NumberExt,
 };
-use crate::utils::math::NumberExt;
 
 mod address;
+pub mod bad_block_manager;
 mod chip;
 mod commands;
+mod ecc;
 mod features;
+mod flash_memory;
+pub mod ftl;
+mod onfi;
+#[cfg(feature = "storage")]
+mod storage;
+mod wait;
 
+pub use address::*;
 pub use chip::*;
+pub use ecc::*;
 pub use features::*;
+pub use flash_memory::*;
+pub use onfi::*;
+#[cfg(feature = "storage")]
+pub use storage::*;
+pub use wait::*;
+
+/// Index of the page of the OTP area that contains the ONFI parameter page.
+const PARAMETER_PAGE_INDEX: u32 = 0x01;
 
 /// A flash memory connected to the microcontroller through a SPI interface.
 ///
@@ -25,18 +50,138 @@ pub use features::*;
 /// It's really important to understand how a [flash memory works] before you use any of the method in this struct,
 /// or you might have unexpected results and/or wear out the memory.
 ///
+/// ## Waits
+/// Most operations need to wait for the chip to finish, by polling its status register. By default the status register
+/// is polled continuously and forever, but you can set a [`timeout`] and a [`delay between polls`].
+///
+///
+/// ## Fast reads
+/// The reads of multiple pages use the cache read commands when the chip [`supports them`], so that the chip reads
+/// the next page while the previous one is sent to the microcontroller. If the SPI device can receive data on more
+/// than one line (check [`MultiIoSpiDevice`]), the data can also be read using the `x2` and `x4` commands after
+/// calling [`enable_multi_io_reads`].
+///
+/// ## Multiple LUNs
+/// In the chips made of multiple LUNs (dies), the LUN that contains the accessed pages is selected automatically
+/// before each operation, so the memory can be used like a single one. The feature registers are the only exception,
+/// since each LUN has its own: they are read and written in the LUN selected by the last operation or by
+/// [`select_die`].
+///
+/// The addresses of the bytes are `u32`, so they can only address the first 4GiB of the memory: the pages after that
+/// can still be accessed by their [`RowAddress`].
+///
 /// [flash memory works]: <https://flashdba.com/2014/06/20/understanding-flash-blocks-pages-and-program-erases/>
-pub struct SpiFlashMemory<Chip: FlashMemoryChip, Spi: SpiDevice<u8>>
+/// [`select_die`]: Self::select_die
+/// [`timeout`]: Self::with_timeout
+/// [`delay between polls`]: Self::with_poll_delay
+/// [`supports them`]: FlashMemoryChip::SUPPORTS_CACHE_READ
+/// [`enable_multi_io_reads`]: Self::enable_multi_io_reads
+pub struct SpiFlashMemory<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime = NoTimeout, D: DelayNs = NoDelay>
 {
 	spi: Spi,
 	chip: Chip,
+	system_time: T,
+	timeout: Duration,
+	delay: D,
+	poll_interval: Duration,
+	multi_io_reads: Option<MultiIoReads<Spi>>,
+	/// The LUN that receives the commands, or `None` if it's unknown (after a reset).
+	selected_lun: Option<u8>,
 }
 
 impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 {
 	pub fn new(spi: Spi, chip: Chip) -> Self
 	{
-		Self { spi, chip }
+		Self {
+			spi,
+			chip,
+			system_time: NoTimeout,
+			timeout: Duration::MAX,
+			delay: NoDelay,
+			poll_interval: Duration::ZERO,
+			multi_io_reads: None,
+			selected_lun: None,
+		}
+	}
+}
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> SpiFlashMemory<Chip, Spi, T, D>
+{
+	/// Makes every wait for an operation of the chip fail with [`SpiFlashMemoryError::Timeout`] if it takes more than
+	/// the provided `timeout`, measured using the provided `system_time`.
+	///
+	/// The timeout should be longer than the slowest operation of the chip (usually the block erase, check
+	/// the `tBERS` in the datasheet).
+	pub fn with_timeout<NewT: SystemTime>(
+		self, system_time: NewT, timeout: Duration,
+	) -> SpiFlashMemory<Chip, Spi, NewT, D>
+	{
+		SpiFlashMemory {
+			spi: self.spi,
+			chip: self.chip,
+			system_time,
+			timeout,
+			delay: self.delay,
+			poll_interval: self.poll_interval,
+			multi_io_reads: self.multi_io_reads,
+			selected_lun: self.selected_lun,
+		}
+	}
+
+	/// Returns the same [`SpiFlashMemory`] with a different `chip`, usually the one found by [`Self::detect_chip`].
+	///
+	/// # Note
+	/// The multi I/O reads are disabled, since the new chip could support a different bus width.
+	pub fn with_chip<NewChip: FlashMemoryChip>(self, chip: NewChip) -> SpiFlashMemory<NewChip, Spi, T, D>
+	{
+		SpiFlashMemory {
+			spi: self.spi,
+			chip,
+			system_time: self.system_time,
+			timeout: self.timeout,
+			delay: self.delay,
+			poll_interval: self.poll_interval,
+			multi_io_reads: None,
+			selected_lun: None,
+		}
+	}
+
+	/// Returns the same [`SpiFlashMemory`] with the provided `chip`, if it's the `detected_chip` found by
+	/// [`Self::detect_chip`].
+	///
+	/// The geometry of a chip is described at compile time by its [`FlashMemoryChip`], so a chip detected only through
+	/// its parameter page ([`DetectedChip::Onfi`]) can be used only with a `NewChip` of the same geometry.
+	///
+	/// Returns this unchanged [`SpiFlashMemory`] as the error if `NewChip` isn't the `detected_chip` (check
+	/// [`DetectedChip::matches`]).
+	pub fn with_detected_chip<NewChip: FlashMemoryChip>(
+		self, chip: NewChip, detected_chip: DetectedChip,
+	) -> Result<SpiFlashMemory<NewChip, Spi, T, D>, Self>
+	{
+		match detected_chip.matches::<NewChip>()
+		{
+			true => Ok(self.with_chip(chip)),
+			false => Err(self),
+		}
+	}
+
+	/// Makes every wait for an operation of the chip wait `poll_interval` using the provided `delay` between two reads
+	/// of the status register, instead of reading it continuously.
+	pub fn with_poll_delay<NewD: DelayNs>(
+		self, delay: NewD, poll_interval: Duration,
+	) -> SpiFlashMemory<Chip, Spi, T, NewD>
+	{
+		SpiFlashMemory {
+			spi: self.spi,
+			chip: self.chip,
+			system_time: self.system_time,
+			timeout: self.timeout,
+			delay,
+			poll_interval,
+			multi_io_reads: self.multi_io_reads,
+			selected_lun: self.selected_lun,
+		}
 	}
 
 	/// Program the provided `data` in the flash memory starting from the provided `address`,
@@ -51,86 +196,133 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 	/// You can't update some data in the memory you programmed before without erasing it first.
 	///
 	/// Check [`struct's documentation`](Self#warning).
-	pub fn program(&mut self, data: &[u8], address: u32) -> Result<(), <Spi as ErrorType>::Error>
+	pub fn program(&mut self, data: &[u8], address: u32) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
 		if data.is_empty()
 		{
 			return Ok(());
 		}
 
-		Self::cycle_pages(address, data.len() as u32, |parameters| {
-			Command::<Chip>::WriteEnable.execute(&mut self.spi)?;
+		self.write_disable_after(|this| {
+			Self::cycle_pages(address, data.len() as u32, |parameters| {
+				this.select_lun_of(parameters.row_address)?;
+				this.execute(Command::<Chip>::WriteEnable)?;
 
-			if parameters.column_address == ColumnAddress::new(0, 0)
-				|| parameters.column_address == ColumnAddress::new(0, 1)
-			{
-				Command::ProgramLoad::<Chip> {
-					column_address: parameters.column_address,
-					input: &data[parameters.data_range],
-				}
-				.execute(&mut self.spi)?;
-			}
-			else
-			{
-				Command::ProgramLoadRandomData::<Chip> {
+				// `ProgramLoad` resets the whole cache register, so the bytes of the page outside of `data_range` (that
+				// could contain the page read before) aren't programmed
+				this.execute(Command::ProgramLoad::<Chip> {
 					column_address: parameters.column_address,
 					input: &data[parameters.data_range],
-				}
-				.execute(&mut self.spi)?;
-			}
+				})?;
 
-			self.wait_for_operation_to_finish()?;
+				this.wait_for_operation_to_finish()?;
 
-			Command::ProgramExecute::<Chip> {
-				row_address: parameters.row_address,
-			}
-			.execute(&mut self.spi)?;
+				this.execute(Command::ProgramExecute::<Chip> {
+					row_address: parameters.row_address,
+				})?;
 
-			self.wait_for_operation_to_finish()?;
+				Self::check_program_status(this.wait_for_operation_to_finish()?)
+			})
+		})
+	}
 
-			Ok(())
-		})?;
+	/// Program the provided `data` in the data area and the provided `ecc_data` in the ECC area of the page identified
+	/// by the provided `row_address`, using a single program operation.
+	///
+	/// Returns `Ok(())` if all the bytes have been written successfully, otherwise returns `Err(...)`.
+	///
+	/// # Note
+	/// Since both areas are programmed at the same time, a power loss can't leave the page with only one of
+	/// them written.
+	///
+	/// # Panics
+	/// Panics if `data.len() > Chip::PAGE_SIZE` or `ecc_data.len() > Chip::PAGE_ECC_SIZE`.
+	///
+	/// # Warning
+	/// Check [`struct's documentation`](Self#warning).
+	pub fn program_page(
+		&mut self, row_address: RowAddress<Chip>, data: &[u8], ecc_data: &[u8],
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		assert!(data.len() as u32 <= Chip::PAGE_SIZE);
+		assert!(ecc_data.len() as u32 <= Chip::PAGE_ECC_SIZE);
 
-		Command::<Chip>::WriteDisable.execute(&mut self.spi)?;
+		let plane_index = row_address.get_plane_index();
 
-		Ok(())
+		self.write_disable_after(|this| {
+			this.select_lun_of(row_address)?;
+			this.execute(Command::<Chip>::WriteEnable)?;
+
+			this.execute(Command::ProgramLoad::<Chip> {
+				column_address: ColumnAddress::new(0, plane_index),
+				input: data,
+			})?;
+
+			this.execute(Command::ProgramLoadRandomData::<Chip> {
+				column_address: ColumnAddress::new(Chip::PAGE_SIZE as u16, plane_index),
+				input: ecc_data,
+			})?;
+
+			this.execute(Command::ProgramExecute::<Chip> { row_address })?;
+
+			Self::check_program_status(this.wait_for_operation_to_finish()?)
+		})
 	}
 
 	/// Reads [`data.len()`] bytes from the data areas of the pages starting from the specified `address`.
 	///
-	/// Returns `Ok(())` if all the bytes have been read successfully, otherwise returns `Err(...)`.
+	/// Returns `Ok(ecc_status)` if all the bytes have been read, where `ecc_status` is the worst [`EccStatus`] of
+	/// the read pages, otherwise returns `Err(...)`.
+	///
+	/// # Warning
+	/// If the returned status is [`EccStatus::Uncorrectable`], some of the read bytes are corrupted.
+	///
+	/// Returns `Err(SpiFlashMemoryError::OutOfBounds)` if the bytes go beyond the end of the memory.
 	///
 	/// # Note
 	/// The read will only affect the data area of a page, not the ECC one.
-	pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), <Spi as ErrorType>::Error>
+	pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
 	{
-		if data.is_empty()
+		let end_address = u32::try_from(data.len())
+			.ok()
+			.and_then(|length| address.checked_add(length))
+			.filter(|end_address| *end_address <= Chip::MEMORY_SIZE)
+			.ok_or(SpiFlashMemoryError::OutOfBounds)?;
+		let start_page_index = address / Chip::PAGE_SIZE;
+		let end_page_index = end_address.saturating_sub(1) / Chip::PAGE_SIZE;
+		if Chip::SUPPORTS_CACHE_READ && end_page_index > start_page_index
 		{
-			return Ok(());
+			return self.cache_read(address, data);
 		}
 
+		let mut ecc_status = EccStatus::NoErrors;
 		Self::cycle_pages(address, data.len() as u32, |parameters| {
-			self.read_internal(
+			let page_ecc_status = self.read_internal(
 				parameters.row_address,
 				parameters.column_address,
 				&mut data[parameters.data_range],
-			)
+			)?;
+			ecc_status = ecc_status.max(page_ecc_status);
+
+			Ok(())
 		})?;
 
-		Ok(())
+		Ok(ecc_status)
 	}
 
 	/// Reads [`data.len()`] bytes from the ECC area of the page identified by the provided `row_address`.
 	///
-	/// Returns `Ok(())` if all the bytes have been read successfully, otherwise returns `Err(...)`.
+	/// Returns `Ok(ecc_status)` if all the bytes have been read, where `ecc_status` is the [`EccStatus`] of the
+	/// page, otherwise returns `Err(...)`.
 	///
 	/// # Note
 	/// The read will only affect the ECC area of a page, not the data one.
 	///
 	/// # Panics
 	/// Panics if `data.len() > Chip::PAGE_ECC_SIZE`.
-	pub fn read_ecc(&mut self, row_address: RowAddress<Chip>, data: &mut [u8])
-		-> Result<(), <Spi as ErrorType>::Error>
+	pub fn read_ecc(
+		&mut self, row_address: RowAddress<Chip>, data: &mut [u8],
+	) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
 	{
 		assert!(data.len() as u32 <= Chip::PAGE_ECC_SIZE);
 
@@ -147,46 +339,96 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 	/// It's an internal move because bytes aren't copied in the microcontroller and then programmed
 	/// at the right location, but everything is done in the flash memory chip.
 	///
+	/// # Panics
+	/// Panics if a page would be moved to a different LUN, since each LUN has its own cache register.
+	///
 	/// # Warning
 	/// Check [`struct's documentation`](Self#warning).
 	pub fn internal_data_move(
 		&mut self, from: RangeInclusive<u32>, to_start_address: u32,
-	) -> Result<(), <Spi as ErrorType>::Error>
+	) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
-		Command::<Chip>::WriteEnable.execute(&mut self.spi)?;
+		self.write_disable_after(|this| {
+			Self::cycle_pages(*from.start(), from.end() - from.start(), |parameters| {
+				let row_address =
+					RowAddress::from_memory_address(to_start_address + parameters.data_range.start as u32);
+				assert_eq!(row_address.get_lun_index(), parameters.row_address.get_lun_index());
 
-		Self::cycle_pages(*from.start(), from.end() - from.start(), |parameters| {
-			Command::PageRead::<Chip> {
-				row_address: parameters.row_address,
-			}
-			.execute(&mut self.spi)?;
+				this.select_lun_of(parameters.row_address)?;
+				this.execute(Command::PageRead::<Chip> {
+					row_address: parameters.row_address,
+				})?;
+				this.wait_for_operation_to_finish()?;
 
-			Command::ProgramLoadRandomData::<Chip> {
-				column_address: parameters.column_address,
-				input: &[],
-			}
-			.execute(&mut self.spi)?;
+				this.execute(Command::<Chip>::WriteEnable)?;
+				this.execute(Command::ProgramLoadRandomData::<Chip> {
+					column_address: parameters.column_address,
+					input: &[],
+				})?;
 
-			let row_address = RowAddress::from_memory_address(to_start_address + parameters.data_range.start as u32);
-			Command::ProgramExecute::<Chip> { row_address }.execute(&mut self.spi)?;
+				this.execute(Command::ProgramExecute::<Chip> { row_address })?;
 
-			self.wait_for_operation_to_finish()?;
+				Self::check_program_status(this.wait_for_operation_to_finish()?)
+			})
+		})
+	}
 
-			Ok(())
-		})?;
+	/// Makes the reads receive the data on the widest bus supported by both the SPI device and the chip, setting the
+	/// `QE` bit of the [`ConfigurationRegister`] if the chip [`requires it`](FlashMemoryChip::REQUIRES_QUAD_ENABLE).
+	///
+	/// Returns `Ok(bus_width)` with the bus width that will be used by the reads, otherwise returns `Err(...)`.
+	///
+	/// # Warning
+	/// The `WP#` and `HOLD#` pins of the chip become data lines in quad mode, so they can't be used anymore.
+	pub fn enable_multi_io_reads(&mut self) -> Result<BusWidth, SpiFlashMemoryError<Spi>>
+	where Spi: MultiIoSpiDevice
+	{
+		let bus_width = self.spi.get_max_read_bus_width().min(Chip::MAX_READ_BUS_WIDTH);
+		if bus_width == BusWidth::Quad && Chip::REQUIRES_QUAD_ENABLE
+		{
+			let mut configuration: ConfigurationRegister = self.get_register()?;
+			configuration.quad_enabled = true;
+			self.set_register(configuration)?;
+		}
 
-		Command::<Chip>::WriteDisable.execute(&mut self.spi)?;
+		self.multi_io_reads = match bus_width
+		{
+			BusWidth::Single => None,
+			_ => Some(MultiIoReads {
+				bus_width,
+				transaction: Spi::transaction_with_read_bus_width,
+			}),
+		};
 
-		Ok(())
+		Ok(bus_width)
+	}
+
+	/// Makes the reads receive the data on a single line again, after [`Self::enable_multi_io_reads`].
+	pub fn disable_multi_io_reads(&mut self)
+	{
+		self.multi_io_reads = None;
+	}
+
+	/// Returns the bus width used by the reads to receive the data.
+	pub fn get_read_bus_width(&self) -> BusWidth
+	{
+		self.multi_io_reads
+			.as_ref()
+			.map_or(BusWidth::Single, |multi_io_reads| multi_io_reads.bus_width)
 	}
 
 	/// Puts the flash memory in a known condition.
 	///
 	/// # Warning
 	/// Calling this function can block the microcontroller for some milliseconds.
-	pub fn reset(&mut self) -> Result<(), <Spi as ErrorType>::Error>
+	pub fn reset(&mut self) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
-		Command::Reset::<Chip>.execute(&mut self.spi)
+		self.execute(Command::Reset::<Chip>)?;
+		// The chip ignores all the other commands until the reset is finished
+		self.wait_for_operation_to_finish()?;
+		self.selected_lun = None;
+
+		Ok(())
 	}
 
 	/// Erases all the blocks in the provided `block_indices_to_erase` range by resetting all the bits
@@ -197,21 +439,25 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 	/// # Warning
 	/// Check [`struct's documentation`](Self#warning).
 	pub fn erase_blocks(&mut self, block_indices_to_erase: RangeInclusive<u16>)
-		-> Result<(), <Spi as ErrorType>::Error>
+		-> Result<(), SpiFlashMemoryError<Spi>>
 	{
-		for block_index in block_indices_to_erase
-		{
-			Command::WriteEnable::<Chip>.execute(&mut self.spi)?;
-
-			let row_address = RowAddress::from_memory_address(Chip::get_address_of_block_index(block_index));
-			Command::BlockErase::<Chip> { row_address }.execute(&mut self.spi)?;
+		self.write_disable_after(|this| {
+			for block_index in block_indices_to_erase
+			{
+				let row_address = RowAddress::from_memory_address(Chip::get_address_of_block_index(block_index));
+				this.select_lun_of(row_address)?;
+				this.execute(Command::WriteEnable::<Chip>)?;
 
-			self.wait_for_operation_to_finish()?;
-		}
+				this.execute(Command::BlockErase::<Chip> { row_address })?;
 
-		Command::WriteDisable::<Chip>.execute(&mut self.spi)?;
+				if this.wait_for_operation_to_finish()?.erase_failed
+				{
+					return Err(SpiFlashMemoryError::EraseFailed);
+				}
+			}
 
-		Ok(())
+			Ok(())
+		})
 	}
 
 	/// Validate that the IDs (`ManufacturerID` and `DeviceID`) of the connected flash memory chip are
@@ -222,15 +468,17 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 	pub fn validate_id(&mut self) -> Result<(), ValidateIdError<Spi>>
 	{
 		let mut manufacturer_id = 0;
-		let mut device_id = 0;
+		let mut device_id = [0; 3];
+		let device_id = &mut device_id[..(1 + Chip::EXTENDED_DEVICE_ID.len())];
 		Command::ReadId::<Chip> {
 			manufacturer_id: &mut manufacturer_id,
-			device_id: &mut device_id,
+			device_id,
 		}
 		.execute(&mut self.spi)
 		.map_err(ValidateIdError::CoudlntReadSpi)?;
 
-		match (manufacturer_id == Chip::MANUFACTURER_ID, device_id == Chip::DEVICE_ID)
+		let is_device_id_right = device_id[0] == Chip::DEVICE_ID && device_id[1..] == *Chip::EXTENDED_DEVICE_ID;
+		match (manufacturer_id == Chip::MANUFACTURER_ID, is_device_id_right)
 		{
 			(true, true) => Ok(()),
 			(true, false) => Err(ValidateIdError::DeviceIdDoesntMatch),
@@ -239,33 +487,104 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 		}
 	}
 
+	/// Detects the chip connected to the SPI bus, using its IDs or its [`OnfiParameterPage`] if the chip isn't
+	/// supported by this driver.
+	///
+	/// The `Chip` of this [`SpiFlashMemory`] doesn't need to be the connected one, so after the detection the right
+	/// chip can be set with [`Self::with_chip`]. This allows the same firmware to support different chips.
+	///
+	/// Returns `Ok(None)` if the chip isn't supported and it doesn't have a valid parameter page, otherwise returns
+	/// `Ok(Some(detected_chip))`.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{drivers::spi_flash_memory::*, hardware::mock::MockSpiNandFlash};
+	/// #
+	/// # let spi = MockSpiNandFlash::<W25N01GV>::new();
+	/// let mut spi_flash_memory = SpiFlashMemory::new(spi, MT29F2G01ABAGDWB);
+	/// match spi_flash_memory.detect_chip().unwrap()
+	/// {
+	///     Some(DetectedChip::W25N01GV) =>
+	///     {
+	///         let mut spi_flash_memory = spi_flash_memory.with_chip(W25N01GV);
+	///         W25N01GV::initialize(&mut spi_flash_memory).unwrap();
+	///         // ...
+	///     },
+	///     _ => panic!("Unsupported flash memory chip"),
+	/// }
+	/// ```
+	pub fn detect_chip(&mut self) -> Result<Option<DetectedChip>, SpiFlashMemoryError<Spi>>
+	{
+		let mut manufacturer_id = 0;
+		let mut device_id = [0; 3];
+		self.execute(Command::ReadId::<Chip> {
+			manufacturer_id: &mut manufacturer_id,
+			device_id: &mut device_id,
+		})?;
+
+		if let Some(detected_chip) = DetectedChip::from_id(manufacturer_id, &device_id)
+		{
+			return Ok(Some(detected_chip));
+		}
+
+		Ok(self.read_onfi_parameter_page()?.map(DetectedChip::Onfi))
+	}
+
+	/// Reads the [`OnfiParameterPage`] from the OTP area of the chip.
+	///
+	/// Returns `Ok(None)` if none of the copies of the parameter page is valid (or the chip doesn't have it), otherwise
+	/// returns `Ok(Some(parameter_page))`.
+	pub fn read_onfi_parameter_page(&mut self) -> Result<Option<OnfiParameterPage>, SpiFlashMemoryError<Spi>>
+	{
+		self.with_otp_mode(|spi_flash_memory| {
+			let mut bytes = [0; PARAMETER_PAGE_SIZE];
+			for copy_index in 0..PARAMETER_PAGE_COPIES
+			{
+				let column_address = ColumnAddress::new((copy_index * PARAMETER_PAGE_SIZE) as u16, 0);
+				spi_flash_memory.read_internal(
+					RowAddress::from_page_index(PARAMETER_PAGE_INDEX),
+					column_address,
+					&mut bytes,
+				)?;
+
+				let parameter_page = OnfiParameterPage::parse(&bytes);
+				if parameter_page.is_some()
+				{
+					return Ok(parameter_page);
+				}
+			}
+
+			Ok(None)
+		})
+	}
+
 	/// Returns the value stored in the provided `features` register of the flash memory chip.
 	///
-	/// Check the datasheet of the chip to understand what each feature does.
-	pub fn get_features(&mut self, features: FeatureRegister) -> Result<u8, <Spi as ErrorType>::Error>
+	/// Check the datasheet of the chip to understand what each feature does, or use [`Self::get_register`] to get
+	/// the value already decoded.
+	pub fn get_features(&mut self, features: FeatureRegister) -> Result<u8, SpiFlashMemoryError<Spi>>
 	{
 		let mut value = 0;
-		Command::GetFeatures::<Chip> {
+		self.execute(Command::GetFeatures::<Chip> {
 			features_address: features.address(),
 			features_value: &mut value,
-		}
-		.execute(&mut self.spi)?;
+		})?;
 
 		Ok(value)
 	}
 
 	/// Sets the value stored in the provided `features` register of the flash memory chip to `features_value`.
 	///
-	/// Check the datasheet of the chip to understand what each feature does.
+	/// Check the datasheet of the chip to understand what each feature does, or use [`Self::set_register`] to write
+	/// a typed value.
 	pub fn set_features(
 		&mut self, features: FeatureRegister, features_value: u8,
-	) -> Result<(), <Spi as ErrorType>::Error>
+	) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
-		Command::SetFeatures::<Chip> {
+		self.execute(Command::SetFeatures::<Chip> {
 			features_address: features.address(),
 			features_value,
-		}
-		.execute(&mut self.spi)?;
+		})?;
 
 		Ok(())
 	}
@@ -276,38 +595,179 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 		&mut self.chip
 	}
 
-	fn is_operation_in_progress(&mut self) -> Result<bool, <Spi as ErrorType>::Error>
+	/// Returns a mutable reference to the underlying SPI device.
+	pub fn get_spi_mut(&mut self) -> &mut Spi
 	{
-		let status = self.get_features(FeatureRegister::Status)?;
-		let is_in_progress = (status & 0b0000_0001) == 1;
-		Ok(is_in_progress)
+		&mut self.spi
 	}
 
-	fn wait_for_operation_to_finish(&mut self) -> Result<(), <Spi as ErrorType>::Error>
+	fn execute(&mut self, command: Command<'_, Chip>) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
-		while self.is_operation_in_progress()?
-		{}
-		Ok(())
+		command.execute(&mut self.spi).map_err(SpiFlashMemoryError::Spi)
+	}
+
+	/// Returns the value of the [`FeatureRegister::Status`] register after the operation finished.
+	fn wait_for_operation_to_finish(&mut self) -> Result<StatusRegister, SpiFlashMemoryError<Spi>>
+	{
+		let start_time = self.system_time.now();
+		loop
+		{
+			let status: StatusRegister = self.get_register()?;
+			if !status.operation_in_progress
+			{
+				return Ok(status);
+			}
+
+			if self.system_time.now().saturating_sub(start_time) > self.timeout
+			{
+				return Err(SpiFlashMemoryError::Timeout);
+			}
+			if !self.poll_interval.is_zero()
+			{
+				delay_for(&mut self.delay, self.poll_interval);
+			}
+		}
+	}
+
+	/// Executes the provided `operation` and then sends `WriteDisable`, even if the operation failed, so that the chip
+	/// doesn't keep accepting program and erase commands.
+	///
+	/// The error of the `operation` is returned before the one of `WriteDisable`.
+	fn write_disable_after(
+		&mut self, operation: impl FnOnce(&mut Self) -> Result<(), SpiFlashMemoryError<Spi>>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let result = operation(self);
+		let write_disable_result = self.execute(Command::<Chip>::WriteDisable);
+
+		result.and(write_disable_result)
+	}
+
+	fn check_program_status(status: StatusRegister) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		match status.program_failed
+		{
+			false => Ok(()),
+			true => Err(SpiFlashMemoryError::ProgramFailed),
+		}
 	}
 
 	fn read_internal(
 		&mut self, row_address: RowAddress<Chip>, column_address: ColumnAddress, output: &mut [u8],
-	) -> Result<(), <Spi as ErrorType>::Error>
+	) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
 	{
-		Command::PageRead::<Chip> { row_address }.execute(&mut self.spi)?;
+		self.select_lun_of(row_address)?;
+		self.execute(Command::PageRead::<Chip> { row_address })?;
 
-		self.wait_for_operation_to_finish()?;
+		let status = self.wait_for_operation_to_finish()?;
 
-		Command::ReadFromCache::<Chip> { column_address, output }.execute(&mut self.spi)?;
+		self.read_from_cache(column_address, output)?;
 
-		Ok(())
+		Ok(status.get_ecc_status::<Chip>())
+	}
+
+	/// Reads the pages like [`Self::read`], but each page is read from the memory while the previous one is read from
+	/// the cache register.
+	fn cache_read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
+	{
+		let mut ecc_status = EccStatus::NoErrors;
+		// The page loaded in the data register of the chip, which is moved in the cache register by the next command
+		let mut loaded_page: Option<(u8, ColumnAddress, Range<usize>)> = None;
+		Self::cycle_pages(address, data.len() as u32, |parameters| {
+			let row_address = parameters.row_address;
+			match loaded_page.take()
+			{
+				Some((lun_index, column_address, data_range)) if lun_index == row_address.get_lun_index() =>
+				{
+					self.execute(Command::ReadPageCacheRandom::<Chip> { row_address })?;
+					ecc_status = ecc_status.max(self.wait_for_operation_to_finish()?.get_ecc_status::<Chip>());
+					self.read_from_cache(column_address, &mut data[data_range])?;
+				},
+				previous_loaded_page =>
+				{
+					// The cache read can't continue in another LUN, so it's finished and started again
+					if let Some((_, column_address, data_range)) = previous_loaded_page
+					{
+						ecc_status = ecc_status.max(self.finish_cache_read(column_address, &mut data[data_range])?);
+					}
+
+					self.select_lun_of(row_address)?;
+					self.execute(Command::PageRead::<Chip> { row_address })?;
+					self.wait_for_operation_to_finish()?;
+				},
+			}
+			loaded_page = Some((
+				row_address.get_lun_index(),
+				parameters.column_address,
+				parameters.data_range,
+			));
+
+			Ok(())
+		})?;
+
+		if let Some((_, column_address, data_range)) = loaded_page
+		{
+			ecc_status = ecc_status.max(self.finish_cache_read(column_address, &mut data[data_range])?);
+		}
+
+		Ok(ecc_status)
+	}
+
+	/// Moves the last page loaded by a cache read in the cache register, and reads it.
+	fn finish_cache_read(
+		&mut self, column_address: ColumnAddress, output: &mut [u8],
+	) -> Result<EccStatus, SpiFlashMemoryError<Spi>>
+	{
+		self.execute(Command::<Chip>::ReadPageCacheLast)?;
+		let status = self.wait_for_operation_to_finish()?;
+		self.read_from_cache(column_address, output)?;
+
+		Ok(status.get_ecc_status::<Chip>())
+	}
+
+	/// Selects the LUN that contains the page identified by the provided `row_address`, if it isn't already selected.
+	fn select_lun_of(&mut self, row_address: RowAddress<Chip>) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		let lun_index = row_address.get_lun_index();
+		if Chip::LUNS_PER_DEVICE == 1 || self.selected_lun == Some(lun_index)
+		{
+			return Ok(());
+		}
+
+		self.select_die(lun_index)
+	}
+
+	/// Reads the cache register using the widest bus available.
+	fn read_from_cache(
+		&mut self, column_address: ColumnAddress, output: &mut [u8],
+	) -> Result<(), SpiFlashMemoryError<Spi>>
+	{
+		match &self.multi_io_reads
+		{
+			Some(MultiIoReads { bus_width, transaction }) =>
+			{
+				let (bus_width, transaction) = (*bus_width, *transaction);
+				Command::ReadFromCache::<Chip> {
+					column_address,
+					output,
+					bus_width,
+				}
+				.execute_with(|operations| transaction(&mut self.spi, operations, bus_width))
+				.map_err(SpiFlashMemoryError::Spi)
+			},
+			None => self.execute(Command::ReadFromCache::<Chip> {
+				column_address,
+				output,
+				bus_width: BusWidth::Single,
+			}),
+		}
 	}
 
 	/// Check the test module below for some examples.
 	fn cycle_pages(
 		address: u32, data_length: u32,
-		mut callback: impl FnMut(CyclePageParameters<Chip>) -> Result<(), <Spi as ErrorType>::Error>,
-	) -> Result<(), <Spi as ErrorType>::Error>
+		mut callback: impl FnMut(CyclePageParameters<Chip>) -> Result<(), SpiFlashMemoryError<Spi>>,
+	) -> Result<(), SpiFlashMemoryError<Spi>>
 	{
 		if data_length == 0
 		{
@@ -344,6 +804,17 @@ impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>> SpiFlashMemory<Chip, Spi>
 	}
 }
 
+/// The bus width of the reads, and the function that executes the transactions whose reads use more than one line
+/// (taken from the [`MultiIoSpiDevice`] implementation of the SPI device).
+struct MultiIoReads<Spi: SpiDevice<u8>>
+{
+	bus_width: BusWidth,
+	transaction: MultiIoTransaction<Spi>,
+}
+
+type MultiIoTransaction<Spi> =
+	fn(&mut Spi, &mut [Operation<'_, u8>], BusWidth) -> Result<(), <Spi as ErrorType>::Error>;
+
 struct CyclePageParameters<Chip: FlashMemoryChip>
 {
 	data_range: Range<usize>,
@@ -351,6 +822,41 @@ struct CyclePageParameters<Chip: FlashMemoryChip>
 	column_address: ColumnAddress,
 }
 
+/// An error returned from the operations of a [`SpiFlashMemory`].
+pub enum SpiFlashMemoryError<Spi: SpiDevice<u8>>
+{
+	/// It has been impossible to communicate via SPI.
+	Spi(<Spi as ErrorType>::Error),
+	/// The chip reported that a program operation failed, usually because the block is worn out and should be marked as
+	/// bad.
+	ProgramFailed,
+	/// The chip reported that an erase operation failed, usually because the block is worn out and should be marked as
+	/// bad.
+	EraseFailed,
+	/// The chip was still busy after the [`timeout`](SpiFlashMemory::with_timeout).
+	Timeout,
+	/// The chip doesn't have the die that should have been [`selected`](SpiFlashMemory::select_die).
+	DieOutOfRange,
+	/// The bytes to read go beyond the end of the memory.
+	OutOfBounds,
+}
+
+impl<Spi: SpiDevice<u8>> Debug for SpiFlashMemoryError<Spi>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Spi(arg0) => f.debug_tuple("Spi").field(arg0).finish(),
+			Self::ProgramFailed => write!(f, "ProgramFailed"),
+			Self::EraseFailed => write!(f, "EraseFailed"),
+			Self::Timeout => write!(f, "Timeout"),
+			Self::DieOutOfRange => write!(f, "DieOutOfRange"),
+			Self::OutOfBounds => write!(f, "OutOfBounds"),
+		}
+	}
+}
+
 /// An error returned from [`SpiFlashMemory::validate_id`].
 pub enum ValidateIdError<Spi: SpiDevice<u8>>
 {
@@ -380,3 +886,557 @@ impl<Spi: SpiDevice<u8>> Debug for ValidateIdError<Spi>
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests
+{
+	use alloc::{vec, vec::Vec};
+	use core::cell::Cell;
+
+	use super::*;
+	use crate::hardware::mock::{MockSpi, MockSpiNandFlash};
+
+	extern crate alloc;
+
+	type Chip = MT29F2G01ABAGDWB;
+
+	fn new_flash_memory(mock: MockSpiNandFlash<Chip>) -> SpiFlashMemory<Chip, MockSpiNandFlash<Chip>>
+	{
+		let mut spi_flash_memory = SpiFlashMemory::new(mock, MT29F2G01ABAGDWB);
+		spi_flash_memory.validate_id().unwrap();
+		Chip::initialize(&mut spi_flash_memory).unwrap();
+
+		spi_flash_memory
+	}
+
+	/// Returns a flash memory whose status register says that an operation is in progress the first 4 times it's
+	/// read, and that it's finished the 5th time.
+	fn new_slow_flash_memory() -> SpiFlashMemory<Chip, MockSpi>
+	{
+		let spi = MockSpi::Ok {
+			read_operations: vec![vec![0x01], vec![0x01], vec![0x01], vec![0x01], vec![0x00]],
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		};
+
+		SpiFlashMemory::new(spi, MT29F2G01ABAGDWB)
+	}
+
+	/// A [`SystemTime`] that advances by 1ms each time it's read.
+	struct SteppingTime(Cell<Duration>);
+	impl SystemTime for SteppingTime
+	{
+		fn now(&self) -> Duration
+		{
+			let now = self.0.get();
+			self.0.set(now + Duration::from_millis(1));
+			now
+		}
+	}
+
+	/// A [`DelayNs`] that only sums the time it has been asked to wait.
+	#[derive(Default)]
+	struct CountingDelay(Duration);
+	impl DelayNs for CountingDelay
+	{
+		fn delay_ns(&mut self, ns: u32)
+		{
+			self.0 += Duration::from_nanos(ns as u64);
+		}
+	}
+
+	#[test]
+	fn wait_gives_up_after_timeout()
+	{
+		let mut spi_flash_memory =
+			new_slow_flash_memory().with_timeout(SteppingTime(Cell::new(Duration::ZERO)), Duration::from_millis(10));
+		spi_flash_memory.erase_blocks(0..=0).unwrap();
+
+		let mut spi_flash_memory =
+			new_slow_flash_memory().with_timeout(SteppingTime(Cell::new(Duration::ZERO)), Duration::from_millis(2));
+		assert!(matches!(
+			spi_flash_memory.erase_blocks(0..=0),
+			Err(SpiFlashMemoryError::Timeout)
+		));
+	}
+
+	#[test]
+	fn poll_delay_is_waited_between_status_reads()
+	{
+		// Longer than the `~4.29s` that fit in the nanoseconds of `DelayNs::delay_ns`
+		let poll_interval = Duration::from_secs(5);
+		let mut spi_flash_memory = new_slow_flash_memory().with_poll_delay(CountingDelay::default(), poll_interval);
+
+		spi_flash_memory.erase_blocks(0..=0).unwrap();
+
+		// The delay is waited after each of the first 4 reads of the status register
+		assert_eq!(spi_flash_memory.delay.0, poll_interval * 4);
+	}
+
+	#[test]
+	fn reset_waits_for_the_chip()
+	{
+		let mut spi_flash_memory = new_slow_flash_memory();
+
+		spi_flash_memory.reset().unwrap();
+
+		// All the reads of the status register, until it said that the reset was finished, have been done
+		let MockSpi::Ok { read_operations, .. } = &spi_flash_memory.spi
+		else
+		{
+			unreachable!()
+		};
+		assert!(read_operations.is_empty());
+	}
+
+	#[test]
+	fn erase_failure_is_detected()
+	{
+		let spi = MockSpi::Ok {
+			read_operations: vec![vec![StatusRegister {
+				erase_failed: true,
+				..Default::default()
+			}
+			.to_bits()]],
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		};
+		let mut spi_flash_memory = SpiFlashMemory::new(spi, MT29F2G01ABAGDWB);
+
+		assert!(matches!(
+			spi_flash_memory.erase_blocks(0..=0),
+			Err(SpiFlashMemoryError::EraseFailed)
+		));
+
+		// The chip doesn't keep accepting program and erase commands after the failure
+		let MockSpi::Ok { write_operations, .. } = &spi_flash_memory.spi
+		else
+		{
+			unreachable!()
+		};
+		assert_eq!(write_operations.last().unwrap(), &[0x04]);
+	}
+
+	#[test]
+	fn program_and_read_across_pages()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+
+		// The data starts in the middle of a page of the first plane and ends in a page of the second one
+		let address = Chip::BLOCK_SIZE - 100;
+		let data: [u8; 300] = core::array::from_fn(|i| i as u8);
+		spi_flash_memory.program(&data, address).unwrap();
+
+		let mut read_data = [0; 300];
+		assert_eq!(
+			spi_flash_memory.read(address, &mut read_data).unwrap(),
+			EccStatus::NoErrors
+		);
+		assert_eq!(read_data, data);
+
+		// The bytes around the programmed ones are still erased
+		let mut byte = 0;
+		spi_flash_memory
+			.read(address - 1, core::slice::from_mut(&mut byte))
+			.unwrap();
+		assert_eq!(byte, 0xFF);
+		spi_flash_memory
+			.read(address + 300, core::slice::from_mut(&mut byte))
+			.unwrap();
+		assert_eq!(byte, 0xFF);
+	}
+
+	#[test]
+	fn program_only_clears_bits_and_erase_sets_them()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+
+		spi_flash_memory.program(&[0b1010_1010], 0).unwrap();
+		spi_flash_memory.program(&[0b1100_1100], 0).unwrap();
+		let mut byte = 0;
+		spi_flash_memory.read(0, core::slice::from_mut(&mut byte)).unwrap();
+		assert_eq!(byte, 0b1000_1000);
+
+		spi_flash_memory.erase_blocks(0..=0).unwrap();
+		spi_flash_memory.read(0, core::slice::from_mut(&mut byte)).unwrap();
+		assert_eq!(byte, 0xFF);
+	}
+
+	#[test]
+	fn ecc_status_of_bit_flips()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		let data = [0x5A; 16];
+		spi_flash_memory.program(&data, 0).unwrap();
+
+		let mut read_data = [0; 16];
+		spi_flash_memory.get_spi_mut().inject_bit_flips(0, 2);
+		assert_eq!(
+			spi_flash_memory.read(0, &mut read_data).unwrap(),
+			EccStatus::Corrected {
+				max_corrected_bits: Some(3),
+				should_refresh: false
+			}
+		);
+		assert_eq!(read_data, data);
+
+		spi_flash_memory.get_spi_mut().inject_bit_flips(0, 5);
+		assert!(spi_flash_memory.read(0, &mut read_data).unwrap().should_refresh());
+		assert_eq!(read_data, data);
+
+		spi_flash_memory.get_spi_mut().inject_bit_flips(0, 5);
+		assert_eq!(
+			spi_flash_memory.read(0, &mut read_data).unwrap(),
+			EccStatus::Uncorrectable
+		);
+		assert_ne!(read_data, data);
+	}
+
+	#[test]
+	fn worn_out_and_locked_blocks_fail()
+	{
+		let mut mock = MockSpiNandFlash::new();
+		mock.wear_out_block(1);
+		let mut spi_flash_memory = new_flash_memory(mock);
+
+		assert!(matches!(
+			spi_flash_memory.program(&[0], Chip::BLOCK_SIZE),
+			Err(SpiFlashMemoryError::ProgramFailed)
+		));
+		assert!(matches!(
+			spi_flash_memory.erase_blocks(1..=1),
+			Err(SpiFlashMemoryError::EraseFailed)
+		));
+
+		// Without `initialize` all the blocks are locked
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<Chip>::new(), MT29F2G01ABAGDWB);
+		assert!(matches!(
+			spi_flash_memory.program(&[0], 0),
+			Err(SpiFlashMemoryError::ProgramFailed)
+		));
+	}
+
+	#[test]
+	fn factory_bad_blocks_are_marked()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::with_factory_bad_blocks(&[3]));
+
+		assert!(!Chip::contains_bad_block_mark(2, &mut spi_flash_memory).unwrap());
+		assert!(Chip::contains_bad_block_mark(3, &mut spi_flash_memory).unwrap());
+		assert!(matches!(
+			spi_flash_memory.erase_blocks(3..=3),
+			Err(SpiFlashMemoryError::EraseFailed)
+		));
+	}
+
+	#[test]
+	fn multi_page_reads_use_cache_read()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		let address = Chip::PAGE_SIZE / 2;
+		let data: [u8; 5000] = core::array::from_fn(|i| (i * 7) as u8);
+		spi_flash_memory.program(&data, address).unwrap();
+		spi_flash_memory.get_spi_mut().inject_bit_flips(2, 2);
+
+		let mut read_data = [0; 5000];
+		assert_eq!(
+			spi_flash_memory.read(address, &mut read_data).unwrap(),
+			EccStatus::Corrected {
+				max_corrected_bits: Some(3),
+				should_refresh: false
+			}
+		);
+		assert_eq!(read_data, data);
+
+		// The 3 pages are loaded by a `PAGE READ`, 2 `READ PAGE CACHE RANDOM` and a `READ PAGE CACHE LAST`
+		let mock = spi_flash_memory.get_spi_mut();
+		assert_eq!(mock.get_commands_count(0x13), 1);
+		assert_eq!(mock.get_commands_count(0x30), 2);
+		assert_eq!(mock.get_commands_count(0x3F), 1);
+		assert_eq!(mock.get_commands_count(0x03), 3);
+	}
+
+	#[test]
+	fn reads_beyond_the_end_of_the_memory_are_rejected()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		let mut data = [0; 20];
+		assert!(matches!(
+			spi_flash_memory.read(Chip::MEMORY_SIZE - 10, &mut data),
+			Err(SpiFlashMemoryError::OutOfBounds)
+		));
+		// The end address doesn't wrap around to the start of the memory
+		assert!(matches!(
+			spi_flash_memory.read(u32::MAX - 10, &mut data),
+			Err(SpiFlashMemoryError::OutOfBounds)
+		));
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x13), 0);
+
+		spi_flash_memory.read(Chip::MEMORY_SIZE - 20, &mut data).unwrap();
+	}
+
+	#[test]
+	fn multi_io_reads()
+	{
+		let mut mock = MockSpiNandFlash::new();
+		mock.set_max_read_bus_width(BusWidth::Dual);
+		let mut spi_flash_memory = new_flash_memory(mock);
+		let data: [u8; 100] = core::array::from_fn(|i| i as u8);
+		spi_flash_memory.program(&data, 10).unwrap();
+
+		assert_eq!(spi_flash_memory.get_read_bus_width(), BusWidth::Single);
+		assert_eq!(spi_flash_memory.enable_multi_io_reads().unwrap(), BusWidth::Dual);
+		assert_eq!(spi_flash_memory.get_read_bus_width(), BusWidth::Dual);
+
+		let mut read_data = [0; 100];
+		spi_flash_memory.read(10, &mut read_data).unwrap();
+		assert_eq!(read_data, data);
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x3B), 1);
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x03), 0);
+
+		spi_flash_memory.get_spi_mut().set_max_read_bus_width(BusWidth::Quad);
+		assert_eq!(spi_flash_memory.enable_multi_io_reads().unwrap(), BusWidth::Quad);
+		spi_flash_memory.read(10, &mut read_data).unwrap();
+		assert_eq!(read_data, data);
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x6B), 1);
+
+		spi_flash_memory.disable_multi_io_reads();
+		spi_flash_memory.read(10, &mut read_data).unwrap();
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x03), 1);
+	}
+
+	#[test]
+	fn feature_registers_keep_unknown_bits()
+	{
+		for bits in 0..=u8::MAX
+		{
+			assert_eq!(BlockLockRegister::from_bits(bits).to_bits(), bits);
+			assert_eq!(ConfigurationRegister::from_bits(bits).to_bits(), bits);
+			assert_eq!(StatusRegister::from_bits(bits).to_bits(), bits);
+			assert_eq!(DieSelectRegister::from_bits(bits).to_bits(), bits);
+		}
+
+		let block_lock = BlockLockRegister::from_bits(0b1011_0110);
+		assert_eq!(block_lock.protection_bits, 0b0110);
+		assert!(block_lock.is_bottom_protected);
+		assert!(block_lock.is_write_disabled);
+		assert_eq!(
+			LockedBlocks::from_register(&block_lock),
+			LockedBlocks::Lower(BlockFraction::Half)
+		);
+	}
+
+	#[test]
+	fn lock_and_unlock_block_ranges()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		let last_block_address = Chip::MEMORY_SIZE - Chip::BLOCK_SIZE;
+
+		spi_flash_memory
+			.lock_blocks(LockedBlocks::Upper(BlockFraction::SixtyFourth))
+			.unwrap();
+		assert_eq!(
+			spi_flash_memory
+				.get_locked_blocks()
+				.unwrap()
+				.get_block_indices::<Chip>(),
+			2016..2048
+		);
+		spi_flash_memory.program(&[0], 0).unwrap();
+		assert!(matches!(
+			spi_flash_memory.program(&[0], last_block_address),
+			Err(SpiFlashMemoryError::ProgramFailed)
+		));
+
+		spi_flash_memory
+			.lock_blocks(LockedBlocks::Lower(BlockFraction::Half))
+			.unwrap();
+		assert!(matches!(
+			spi_flash_memory.erase_blocks(0..=0),
+			Err(SpiFlashMemoryError::EraseFailed)
+		));
+		spi_flash_memory.program(&[0], last_block_address).unwrap();
+
+		spi_flash_memory.unlock_all_blocks().unwrap();
+		assert_eq!(spi_flash_memory.get_locked_blocks().unwrap(), LockedBlocks::None);
+		spi_flash_memory.erase_blocks(0..=0).unwrap();
+	}
+
+	#[test]
+	fn disabled_ecc_doesnt_correct_bit_flips()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		let data = [0x5A; 16];
+		spi_flash_memory.program(&data, 0).unwrap();
+		spi_flash_memory.get_spi_mut().inject_bit_flips(0, 1);
+
+		assert!(spi_flash_memory.is_ecc_enabled().unwrap());
+		spi_flash_memory.set_ecc_enabled(false).unwrap();
+		assert!(!spi_flash_memory.is_ecc_enabled().unwrap());
+
+		let mut read_data = [0; 16];
+		assert_eq!(spi_flash_memory.read(0, &mut read_data).unwrap(), EccStatus::NoErrors);
+		assert_ne!(read_data, data);
+
+		spi_flash_memory.set_ecc_enabled(true).unwrap();
+		assert!(spi_flash_memory.read(0, &mut read_data).unwrap().is_data_valid());
+		assert_eq!(read_data, data);
+	}
+
+	#[test]
+	fn otp_pages_are_programmed_once_and_locked()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+
+		spi_flash_memory.program_otp_page(2, 10, b"SN-0042").unwrap();
+		let mut serial_number = [0; 7];
+		spi_flash_memory.read_otp_page(2, 10, &mut serial_number).unwrap();
+		assert_eq!(&serial_number, b"SN-0042");
+
+		// The OTP area doesn't overlap with the memory, which is accessible again after the OTP operations
+		assert!(
+			!spi_flash_memory
+				.get_register::<ConfigurationRegister>()
+				.unwrap()
+				.otp_access
+		);
+		spi_flash_memory
+			.read(2 * Chip::PAGE_SIZE + 10, &mut serial_number)
+			.unwrap();
+		assert_eq!(serial_number, [0xFF; 7]);
+		assert!(spi_flash_memory.read_onfi_parameter_page().unwrap().is_some());
+
+		// The parameter page can't be changed
+		assert!(matches!(
+			spi_flash_memory.program_otp_page(1, 0, &[0]),
+			Err(SpiFlashMemoryError::ProgramFailed)
+		));
+
+		spi_flash_memory.lock_otp_area().unwrap();
+		assert!(spi_flash_memory.get_spi_mut().is_otp_locked());
+		assert!(matches!(
+			spi_flash_memory.program_otp_page(3, 0, &[0]),
+			Err(SpiFlashMemoryError::ProgramFailed)
+		));
+		let configuration: ConfigurationRegister = spi_flash_memory.get_register().unwrap();
+		assert!(!configuration.otp_access && !configuration.otp_protect);
+
+		// The OTP mode can also be entered manually, to use the normal reads
+		spi_flash_memory.enter_otp_mode().unwrap();
+		spi_flash_memory
+			.read(2 * Chip::PAGE_SIZE + 10, &mut serial_number)
+			.unwrap();
+		assert_eq!(&serial_number, b"SN-0042");
+		spi_flash_memory.exit_otp_mode().unwrap();
+	}
+
+	#[test]
+	fn select_die()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new());
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), Some(0));
+
+		// The chip has a single die
+		assert!(matches!(
+			spi_flash_memory.select_die(1),
+			Err(SpiFlashMemoryError::DieOutOfRange)
+		));
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), Some(0));
+
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<MT29F4G01ADAGDWB>::new(), MT29F4G01ADAGDWB);
+		spi_flash_memory.select_die(1).unwrap();
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), Some(1));
+		assert_eq!(
+			spi_flash_memory.get_features(FeatureRegister::DieSelect).unwrap(),
+			0b0100_0000
+		);
+		assert!(matches!(
+			spi_flash_memory.select_die(2),
+			Err(SpiFlashMemoryError::DieOutOfRange)
+		));
+	}
+
+	#[test]
+	fn program_read_and_erase_across_luns()
+	{
+		type MultiLunChip = MT29F4G01ADAGDWB;
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<MultiLunChip>::new(), MT29F4G01ADAGDWB);
+		spi_flash_memory.validate_id().unwrap();
+		MultiLunChip::initialize(&mut spi_flash_memory).unwrap();
+
+		// The data starts in the last page of the first LUN and ends in the first page of the second one
+		let address = MultiLunChip::PAGES_PER_LUN * MultiLunChip::PAGE_SIZE - 100;
+		let data: [u8; 300] = core::array::from_fn(|i| i as u8);
+		spi_flash_memory.program(&data, address).unwrap();
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), Some(1));
+
+		// The cache read is started again in the second LUN
+		let mut read_data = [0; 300];
+		assert_eq!(
+			spi_flash_memory.read(address, &mut read_data).unwrap(),
+			EccStatus::NoErrors
+		);
+		assert_eq!(read_data, data);
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x13), 2);
+		assert_eq!(spi_flash_memory.get_spi_mut().get_commands_count(0x3F), 2);
+
+		// Reading only the first LUN selects it again
+		let mut byte = 0;
+		spi_flash_memory
+			.read(address, core::slice::from_mut(&mut byte))
+			.unwrap();
+		assert_eq!(byte, data[0]);
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), Some(0));
+
+		let first_block_of_second_lun = (MultiLunChip::BLOCKS_COUNT / 2) as u16;
+		spi_flash_memory
+			.erase_blocks(first_block_of_second_lun..=first_block_of_second_lun)
+			.unwrap();
+		spi_flash_memory.read(address, &mut read_data).unwrap();
+		assert_eq!(read_data[..100], data[..100]);
+		assert!(read_data[100..].iter().all(|&byte| byte == 0xFF));
+	}
+
+	#[test]
+	fn software_die_select()
+	{
+		type MultiLunChip = W25M02GV;
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<MultiLunChip>::new(), W25M02GV);
+		spi_flash_memory.validate_id().unwrap();
+		MultiLunChip::initialize(&mut spi_flash_memory).unwrap();
+
+		let address = MultiLunChip::PAGES_PER_LUN * MultiLunChip::PAGE_SIZE;
+		let commands_count = spi_flash_memory.get_spi_mut().get_commands_count(0xC2);
+		spi_flash_memory.program(&[0x42], address).unwrap();
+		spi_flash_memory.program(&[0x24], 0).unwrap();
+		assert_eq!(
+			spi_flash_memory.get_spi_mut().get_commands_count(0xC2),
+			commands_count + 2
+		);
+
+		let mut bytes = [0; 2];
+		spi_flash_memory.read(address, &mut bytes[..1]).unwrap();
+		spi_flash_memory.read(0, &mut bytes[1..]).unwrap();
+		assert_eq!(bytes, [0x42, 0x24]);
+
+		// After a reset the selected die is unknown
+		spi_flash_memory.reset().unwrap();
+		assert_eq!(spi_flash_memory.get_selected_die().unwrap(), None);
+	}
+
+	#[test]
+	fn stuck_chip_times_out()
+	{
+		let mut spi_flash_memory = new_flash_memory(MockSpiNandFlash::new())
+			.with_timeout(SteppingTime(Cell::new(Duration::ZERO)), Duration::from_millis(10));
+
+		let mut byte = 0;
+		spi_flash_memory.read(0, core::slice::from_mut(&mut byte)).unwrap();
+
+		spi_flash_memory.get_spi_mut().set_stuck_busy(true);
+		assert!(matches!(
+			spi_flash_memory.read(0, core::slice::from_mut(&mut byte)),
+			Err(SpiFlashMemoryError::Timeout)
+		));
+	}
+}
@@ -0,0 +1,432 @@
+//! The [`ONFI`] parameter page describes the geometry and the features of a flash memory chip, and it's available
+//! (in the OTP area) in most SPI NAND flash memory chips, so it can be used to detect the connected chip at runtime.
+//!
+//! [`ONFI`]: <https://www.onfi.org/specifications>
+
+use super::{
+	FlashMemoryChip, FlashMemoryChipExt, GD5F1GQ4UB, MT29F2G01ABAGDWB, MT29F4G01ADAGDWB, MX35LF1GE4AB, W25M02GV,
+	W25N01GV, W25N02KV,
+};
+
+/// Size of a copy of the parameter page.
+pub const PARAMETER_PAGE_SIZE: usize = 256;
+/// The parameter page is stored this many times in a row, so that it can still be read if a copy is corrupted.
+pub const PARAMETER_PAGE_COPIES: usize = 3;
+
+const SIGNATURE: &[u8; 4] = b"ONFI";
+const CRC16_POLYNOMIAL: u16 = 0x8005;
+const CRC16_INITIAL_VALUE: u16 = 0x4F4E;
+
+/// The fields of an [`ONFI`] parameter page used by this driver.
+///
+/// [`ONFI`]: <https://www.onfi.org/specifications>
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OnfiParameterPage
+{
+	/// Name of the manufacturer, padded with spaces.
+	pub manufacturer: [u8; 12],
+	/// Name of the model, padded with spaces.
+	pub model: [u8; 20],
+	/// The JEDEC manufacturer ID, the same returned by the `READ ID` command.
+	pub jedec_manufacturer_id: u8,
+	/// Size of the data area of a page.
+	pub page_size: u32,
+	/// Size of the ECC area of a page.
+	pub page_ecc_size: u32,
+	/// Number of pages contained in a block.
+	pub pages_per_block: u32,
+	/// Number of blocks contained in a LUN.
+	pub blocks_per_lun: u32,
+	/// Number of LUNs contained in the chip.
+	pub luns_per_device: u32,
+	/// Number of planes contained in a LUN.
+	pub planes_per_lun: u32,
+	/// Max number of times a page can be programmed before it needs to be erased again.
+	pub partial_programs_per_page: u32,
+	/// Max number of bad blocks that a LUN can contain during its life.
+	pub max_bad_blocks_per_lun: u16,
+	/// Number of bits the on-die ECC can correct.
+	pub ecc_correctable_bits: u8,
+}
+
+impl OnfiParameterPage
+{
+	/// Returns the [`OnfiParameterPage`] contained in the provided `bytes` (a single copy of the parameter page).
+	///
+	/// Returns `None` if the bytes don't start with the `ONFI` signature or their CRC is wrong.
+	pub fn parse(bytes: &[u8; PARAMETER_PAGE_SIZE]) -> Option<Self>
+	{
+		let crc = u16::from_le_bytes([bytes[254], bytes[255]]);
+		if bytes[0..4] != *SIGNATURE || crc16(&bytes[..254]) != crc
+		{
+			return None;
+		}
+
+		let read_u16 = |index: usize| u16::from_le_bytes([bytes[index], bytes[index + 1]]);
+		let read_u32 =
+			|index: usize| u32::from_le_bytes([bytes[index], bytes[index + 1], bytes[index + 2], bytes[index + 3]]);
+		Some(Self {
+			manufacturer: bytes[32..44].try_into().unwrap(),
+			model: bytes[44..64].try_into().unwrap(),
+			jedec_manufacturer_id: bytes[64],
+			page_size: read_u32(80),
+			page_ecc_size: read_u16(84) as u32,
+			pages_per_block: read_u32(92),
+			blocks_per_lun: read_u32(96),
+			luns_per_device: bytes[100] as u32,
+			planes_per_lun: 1 << (bytes[113] & 0x0F),
+			partial_programs_per_page: bytes[110] as u32,
+			max_bad_blocks_per_lun: read_u16(103),
+			ecc_correctable_bits: bytes[112],
+		})
+	}
+
+	/// Returns the bytes of a copy of the parameter page that contains this [`OnfiParameterPage`] (and the right CRC).
+	///
+	/// All the fields not contained in [`OnfiParameterPage`] are set to 0.
+	pub fn to_bytes(&self) -> [u8; PARAMETER_PAGE_SIZE]
+	{
+		let mut bytes = [0; PARAMETER_PAGE_SIZE];
+		bytes[0..4].copy_from_slice(SIGNATURE);
+		bytes[32..44].copy_from_slice(&self.manufacturer);
+		bytes[44..64].copy_from_slice(&self.model);
+		bytes[64] = self.jedec_manufacturer_id;
+		bytes[80..84].copy_from_slice(&self.page_size.to_le_bytes());
+		bytes[84..86].copy_from_slice(&(self.page_ecc_size as u16).to_le_bytes());
+		bytes[92..96].copy_from_slice(&self.pages_per_block.to_le_bytes());
+		bytes[96..100].copy_from_slice(&self.blocks_per_lun.to_le_bytes());
+		bytes[100] = self.luns_per_device as u8;
+		bytes[103..105].copy_from_slice(&self.max_bad_blocks_per_lun.to_le_bytes());
+		bytes[110] = self.partial_programs_per_page as u8;
+		bytes[112] = self.ecc_correctable_bits;
+		bytes[113] = self.planes_per_lun.trailing_zeros() as u8;
+
+		let crc = crc16(&bytes[..254]);
+		bytes[254..256].copy_from_slice(&crc.to_le_bytes());
+
+		bytes
+	}
+
+	/// Returns the name of the manufacturer, without the padding.
+	pub fn get_manufacturer(&self) -> &str
+	{
+		trim_padding(&self.manufacturer)
+	}
+
+	/// Returns the name of the model, without the padding.
+	pub fn get_model(&self) -> &str
+	{
+		trim_padding(&self.model)
+	}
+
+	/// Returns the size of the data area of the chip.
+	///
+	/// It's a [`u64`] because some chips are bigger than 4GB.
+	pub fn get_memory_size(&self) -> u64
+	{
+		self.page_size as u64 * self.pages_per_block as u64 * self.blocks_per_lun as u64 * self.luns_per_device as u64
+	}
+
+	/// Returns `true` if the geometry described by this parameter page is the same as the one of the `Chip`.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::drivers::spi_flash_memory::*;
+	/// #
+	/// let parameter_page = OnfiParameterPage::from_chip::<W25N01GV>(*b"WINBOND     ", *b"W25N01GV            ");
+	/// assert!(parameter_page.matches::<W25N01GV>());
+	/// assert!(!parameter_page.matches::<W25N02KV>());
+	/// ```
+	pub fn matches<Chip: FlashMemoryChip>(&self) -> bool
+	{
+		self.page_size == Chip::PAGE_SIZE
+			&& self.page_ecc_size == Chip::PAGE_ECC_SIZE
+			&& self.pages_per_block == Chip::PAGES_PER_BLOCK
+			&& self.blocks_per_lun == Chip::PLANES_PER_LUN * Chip::BLOCKS_PER_PLANE
+			&& self.luns_per_device == Chip::LUNS_PER_DEVICE
+			&& self.get_memory_size() == Chip::CAPACITY
+	}
+
+	/// Returns the [`OnfiParameterPage`] that describes the geometry of the `Chip`, with the provided `manufacturer`
+	/// and `model` names (already padded with spaces).
+	pub fn from_chip<Chip: FlashMemoryChip>(manufacturer: [u8; 12], model: [u8; 20]) -> Self
+	{
+		Self {
+			manufacturer,
+			model,
+			jedec_manufacturer_id: Chip::MANUFACTURER_ID,
+			page_size: Chip::PAGE_SIZE,
+			page_ecc_size: Chip::PAGE_ECC_SIZE,
+			pages_per_block: Chip::PAGES_PER_BLOCK,
+			blocks_per_lun: Chip::PLANES_PER_LUN * Chip::BLOCKS_PER_PLANE,
+			luns_per_device: Chip::LUNS_PER_DEVICE,
+			planes_per_lun: Chip::PLANES_PER_LUN,
+			partial_programs_per_page: Chip::PARTIAL_PROGRAMS_PER_PAGE,
+			max_bad_blocks_per_lun: (Chip::PLANES_PER_LUN * Chip::BLOCKS_PER_PLANE / 50) as u16,
+			ecc_correctable_bits: 8,
+		}
+	}
+}
+
+/// A chip detected at runtime by [`SpiFlashMemory::detect_chip`].
+///
+/// [`SpiFlashMemory::detect_chip`]: super::SpiFlashMemory::detect_chip
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DetectedChip
+{
+	MT29F2G01ABAGDWB,
+	MT29F4G01ADAGDWB,
+	W25N01GV,
+	W25N02KV,
+	W25M02GV,
+	GD5F1GQ4UB,
+	MX35LF1GE4AB,
+	/// A chip that isn't supported by this driver, but that has an [`OnfiParameterPage`] describing its geometry.
+	///
+	/// The driver needs the geometry at compile time, so this chip can only be used through a [`FlashMemoryChip`] with
+	/// the same geometry (check [`SpiFlashMemory::with_detected_chip`]).
+	///
+	/// [`SpiFlashMemory::with_detected_chip`]: super::SpiFlashMemory::with_detected_chip
+	Onfi(OnfiParameterPage),
+}
+
+impl DetectedChip
+{
+	/// Returns the supported chip with the provided IDs (the bytes returned by the `READ ID` command), if any.
+	pub fn from_id(manufacturer_id: u8, device_id: &[u8]) -> Option<Self>
+	{
+		fn has_id<Chip: FlashMemoryChip>(manufacturer_id: u8, device_id: &[u8]) -> bool
+		{
+			manufacturer_id == Chip::MANUFACTURER_ID
+				&& device_id.first() == Some(&Chip::DEVICE_ID)
+				&& device_id[1..].starts_with(Chip::EXTENDED_DEVICE_ID)
+		}
+
+		if has_id::<MT29F2G01ABAGDWB>(manufacturer_id, device_id)
+		{
+			Some(Self::MT29F2G01ABAGDWB)
+		}
+		else if has_id::<MT29F4G01ADAGDWB>(manufacturer_id, device_id)
+		{
+			Some(Self::MT29F4G01ADAGDWB)
+		}
+		else if has_id::<W25N01GV>(manufacturer_id, device_id)
+		{
+			Some(Self::W25N01GV)
+		}
+		else if has_id::<W25N02KV>(manufacturer_id, device_id)
+		{
+			Some(Self::W25N02KV)
+		}
+		else if has_id::<W25M02GV>(manufacturer_id, device_id)
+		{
+			Some(Self::W25M02GV)
+		}
+		else if has_id::<GD5F1GQ4UB>(manufacturer_id, device_id)
+		{
+			Some(Self::GD5F1GQ4UB)
+		}
+		else if has_id::<MX35LF1GE4AB>(manufacturer_id, device_id)
+		{
+			Some(Self::MX35LF1GE4AB)
+		}
+		else
+		{
+			None
+		}
+	}
+
+	/// Returns `true` if this is the `Chip`: a supported chip must have the same IDs, while a chip detected through its
+	/// [`OnfiParameterPage`] must have the same geometry.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::drivers::spi_flash_memory::*;
+	/// #
+	/// assert!(DetectedChip::W25N01GV.matches::<W25N01GV>());
+	/// assert!(!DetectedChip::W25N01GV.matches::<GD5F1GQ4UB>());
+	///
+	/// let parameter_page = OnfiParameterPage::from_chip::<W25N01GV>(*b"UNKNOWN     ", *b"UNKNOWN             ");
+	/// assert!(DetectedChip::Onfi(parameter_page).matches::<W25N01GV>());
+	/// ```
+	pub fn matches<Chip: FlashMemoryChip>(&self) -> bool
+	{
+		match self
+		{
+			Self::Onfi(parameter_page) => parameter_page.matches::<Chip>(),
+			_ =>
+			{
+				let mut device_id = [0; 4];
+				device_id[0] = Chip::DEVICE_ID;
+				device_id[1..=Chip::EXTENDED_DEVICE_ID.len()].copy_from_slice(Chip::EXTENDED_DEVICE_ID);
+
+				Self::from_id(Chip::MANUFACTURER_ID, &device_id) == Some(*self)
+			},
+		}
+	}
+}
+
+/// `CRC-16` with the polynomial and initial value defined by the ONFI specification.
+fn crc16(data: &[u8]) -> u16
+{
+	let mut crc = CRC16_INITIAL_VALUE;
+	for byte in data
+	{
+		crc ^= (*byte as u16) << 8;
+		for _ in 0..8
+		{
+			crc = match crc & 0x8000
+			{
+				0 => crc << 1,
+				_ => (crc << 1) ^ CRC16_POLYNOMIAL,
+			};
+		}
+	}
+
+	crc
+}
+
+fn trim_padding(bytes: &[u8]) -> &str
+{
+	core::str::from_utf8(bytes).unwrap_or("").trim_end_matches([' ', '\0'])
+}
+
+#[cfg(test)]
+mod tests
+{
+	use embedded_hal::{
+		delay::DelayNs,
+		spi::{Mode, SpiDevice, MODE_0},
+	};
+
+	use super::{
+		super::{BlockLockRegister, SpiFlashMemory, SpiFlashMemoryError},
+		*,
+	};
+	use crate::{
+		hardware::mock::MockSpiNandFlash, peripherals::time::system_time::SystemTime,
+		utils::physical_quantities::frequency::Frequency,
+	};
+
+	/// A chip with 2 dies that isn't supported by the driver, so it can only be detected by its parameter page.
+	#[derive(Clone, PartialEq, Eq, Debug)]
+	struct UnknownChip;
+	impl FlashMemoryChip for UnknownChip
+	{
+		const LUNS_PER_DEVICE: u32 = 2;
+		const PLANES_PER_LUN: u32 = 2;
+		const BLOCKS_PER_PLANE: u32 = 1024;
+		const PAGES_PER_BLOCK: u32 = 64;
+		const PAGE_SIZE: u32 = 2048;
+		const PAGE_ECC_SIZE: u32 = 128;
+
+		const SPI_MODE: Mode = MODE_0;
+		const MAX_CLOCK_FREQUENCY: Frequency = Frequency::from_hertz(50_000_000);
+
+		const MANUFACTURER_ID: u8 = 0xAA;
+		const DEVICE_ID: u8 = 0x55;
+
+		fn initialize<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+			spi_flash_memory: &mut SpiFlashMemory<Self, Spi, T, D>,
+		) -> Result<(), SpiFlashMemoryError<Spi>>
+		{
+			for die_index in 0..Self::LUNS_PER_DEVICE as u8
+			{
+				spi_flash_memory.select_die(die_index)?;
+				spi_flash_memory.set_register(BlockLockRegister::default())?;
+			}
+
+			Ok(())
+		}
+
+		fn contains_bad_block_mark<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs>(
+			_: u16, _: &mut SpiFlashMemory<Self, Spi, T, D>,
+		) -> Result<bool, SpiFlashMemoryError<Spi>>
+		{
+			Ok(false)
+		}
+	}
+
+	#[test]
+	fn parse_parameter_page()
+	{
+		let parameter_page =
+			OnfiParameterPage::from_chip::<MT29F2G01ABAGDWB>(*b"MICRON      ", *b"MT29F2G01ABAGDWB    ");
+		let mut bytes = parameter_page.to_bytes();
+
+		let parsed_parameter_page = OnfiParameterPage::parse(&bytes).unwrap();
+		assert_eq!(parsed_parameter_page, parameter_page);
+		assert_eq!(parsed_parameter_page.get_manufacturer(), "MICRON");
+		assert_eq!(parsed_parameter_page.get_model(), "MT29F2G01ABAGDWB");
+		assert_eq!(parsed_parameter_page.planes_per_lun, 2);
+		assert!(parsed_parameter_page.matches::<MT29F2G01ABAGDWB>());
+
+		bytes[80] ^= 1;
+		assert_eq!(OnfiParameterPage::parse(&bytes), None);
+	}
+
+	#[test]
+	fn detect_chips()
+	{
+		fn detect<Chip: FlashMemoryChip>() -> Option<DetectedChip>
+		{
+			let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<Chip>::new(), MT29F2G01ABAGDWB);
+			spi_flash_memory.detect_chip().unwrap()
+		}
+
+		assert_eq!(detect::<MT29F2G01ABAGDWB>(), Some(DetectedChip::MT29F2G01ABAGDWB));
+		assert_eq!(detect::<MT29F4G01ADAGDWB>(), Some(DetectedChip::MT29F4G01ADAGDWB));
+		assert_eq!(detect::<W25M02GV>(), Some(DetectedChip::W25M02GV));
+		assert_eq!(detect::<W25N01GV>(), Some(DetectedChip::W25N01GV));
+		assert_eq!(detect::<W25N02KV>(), Some(DetectedChip::W25N02KV));
+		assert_eq!(detect::<GD5F1GQ4UB>(), Some(DetectedChip::GD5F1GQ4UB));
+		assert_eq!(detect::<MX35LF1GE4AB>(), Some(DetectedChip::MX35LF1GE4AB));
+	}
+
+	#[test]
+	fn read_parameter_page_from_otp_area()
+	{
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<W25N02KV>::new(), W25N02KV);
+		W25N02KV::initialize(&mut spi_flash_memory).unwrap();
+		spi_flash_memory.program(&[0x12], 0).unwrap();
+
+		let parameter_page = spi_flash_memory.read_onfi_parameter_page().unwrap().unwrap();
+		assert!(parameter_page.matches::<W25N02KV>());
+		assert_eq!(parameter_page.get_memory_size(), W25N02KV::MEMORY_SIZE as u64);
+
+		// The normal access to the memory has been restored
+		let mut byte = 0;
+		spi_flash_memory.read(0, core::slice::from_mut(&mut byte)).unwrap();
+		assert_eq!(byte, 0x12);
+
+		spi_flash_memory.get_spi_mut().set_parameter_page(None);
+		assert_eq!(spi_flash_memory.read_onfi_parameter_page().unwrap(), None);
+	}
+
+	#[test]
+	fn onfi_chip_is_used_with_a_chip_of_the_same_geometry()
+	{
+		let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<UnknownChip>::new(), MT29F2G01ABAGDWB);
+		let detected_chip = spi_flash_memory.detect_chip().unwrap().unwrap();
+		assert!(matches!(detected_chip, DetectedChip::Onfi(parameter_page) if parameter_page.luns_per_device == 2));
+
+		let spi_flash_memory = match spi_flash_memory.with_detected_chip(MT29F2G01ABAGDWB, detected_chip)
+		{
+			Ok(_) => panic!("The single die chip doesn't have the same geometry"),
+			Err(spi_flash_memory) => spi_flash_memory,
+		};
+		let Ok(mut spi_flash_memory) = spi_flash_memory.with_detected_chip(UnknownChip, detected_chip)
+		else
+		{
+			panic!("The chip has the same geometry");
+		};
+		UnknownChip::initialize(&mut spi_flash_memory).unwrap();
+
+		// The second die is used
+		let address = UnknownChip::LUN_SIZE + 10;
+		spi_flash_memory.program(b"Die 1", address).unwrap();
+		let mut data = [0; 5];
+		spi_flash_memory.read(address, &mut data).unwrap();
+		assert_eq!(&data, b"Die 1");
+	}
+}
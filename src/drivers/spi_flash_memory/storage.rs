@@ -0,0 +1,198 @@
+//! Implementations of the [`embedded_storage`] traits for [`SpiFlashMemory`], so that it can be used by all the crates
+//! based on them.
+//!
+//! ## NAND semantics
+//! The [`NorFlash`] traits assume that every byte can be written once after an erase, but a NAND page can only be
+//! programmed `Chip::PARTIAL_PROGRAMS_PER_PAGE` times before being erased again. This is enforced by making
+//! [`NorFlash::WRITE_SIZE`] equal to `Chip::PAGE_SIZE / Chip::PARTIAL_PROGRAMS_PER_PAGE`: since every write must be
+//! aligned to it and [`MultiwriteNorFlash`] isn't implemented (so the same bytes can't be written twice without an
+//! erase), a page is split in `Chip::PARTIAL_PROGRAMS_PER_PAGE` parts each programmed at most once.
+//!
+//! [`MultiwriteNorFlash`]: embedded_storage::nor_flash::MultiwriteNorFlash
+
+use core::fmt::Debug;
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+use embedded_storage::nor_flash::{
+	check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use super::{EccStatus, FlashMemoryChip, FlashMemoryChipExt, SpiFlashMemory, SpiFlashMemoryError};
+use crate::peripherals::time::system_time::SystemTime;
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> ErrorType for SpiFlashMemory<Chip, Spi, T, D>
+{
+	type Error = StorageError<SpiFlashMemoryError<Spi>>;
+}
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> ReadNorFlash
+	for SpiFlashMemory<Chip, Spi, T, D>
+{
+	const READ_SIZE: usize = 1;
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>
+	{
+		check_read(self, offset, bytes.len())?;
+
+		match SpiFlashMemory::read(self, offset, bytes).map_err(StorageError::Flash)?
+		{
+			EccStatus::Uncorrectable => Err(StorageError::Uncorrectable),
+			_ => Ok(()),
+		}
+	}
+
+	fn capacity(&self) -> usize
+	{
+		Chip::MEMORY_SIZE as usize
+	}
+}
+
+impl<Chip: FlashMemoryChip, Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs> NorFlash for SpiFlashMemory<Chip, Spi, T, D>
+{
+	const WRITE_SIZE: usize = (Chip::PAGE_SIZE / Chip::PARTIAL_PROGRAMS_PER_PAGE) as usize;
+	const ERASE_SIZE: usize = Chip::BLOCK_SIZE as usize;
+
+	fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error>
+	{
+		check_erase(self, from, to)?;
+
+		if from == to
+		{
+			return Ok(());
+		}
+		let first_block = Chip::get_block_index_of_address(from);
+		let last_block = Chip::get_block_index_of_address(to - 1);
+		self.erase_blocks(first_block..=last_block).map_err(StorageError::Flash)
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>
+	{
+		check_write(self, offset, bytes.len())?;
+
+		self.program(bytes, offset).map_err(StorageError::Flash)
+	}
+}
+
+/// Error returned by the [`embedded_storage`] implementations of [`SpiFlashMemory`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageError<E>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(E),
+	/// The read data is corrupted, because the on-die ECC couldn't correct it (check [`EccStatus::Uncorrectable`]).
+	Uncorrectable,
+	/// The arguments are not aligned to [`NorFlash::WRITE_SIZE`] or [`NorFlash::ERASE_SIZE`].
+	NotAligned,
+	/// The arguments are out of the bounds of the flash memory.
+	OutOfBounds,
+}
+
+impl<E: Debug> NorFlashError for StorageError<E>
+{
+	fn kind(&self) -> NorFlashErrorKind
+	{
+		match self
+		{
+			Self::Flash(_) | Self::Uncorrectable => NorFlashErrorKind::Other,
+			Self::NotAligned => NorFlashErrorKind::NotAligned,
+			Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+		}
+	}
+}
+
+impl<E> From<NorFlashErrorKind> for StorageError<E>
+{
+	fn from(kind: NorFlashErrorKind) -> Self
+	{
+		match kind
+		{
+			NorFlashErrorKind::NotAligned => Self::NotAligned,
+			_ => Self::OutOfBounds,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use alloc::{vec, vec::Vec};
+
+	use super::*;
+	use crate::{drivers::spi_flash_memory::MT29F2G01ABAGDWB, hardware::mock::MockSpi};
+
+	extern crate alloc;
+
+	type Chip = MT29F2G01ABAGDWB;
+	type Flash = SpiFlashMemory<Chip, MockSpi>;
+
+	const WRITE_SIZE: usize = <Flash as NorFlash>::WRITE_SIZE;
+
+	/// Returns a flash memory whose status register always says that no operation is in progress.
+	fn new_flash_memory() -> Flash
+	{
+		let spi = MockSpi::Ok {
+			read_operations: vec![vec![0x00]; 16],
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		};
+
+		SpiFlashMemory::new(spi, MT29F2G01ABAGDWB)
+	}
+
+	fn get_write_operations(spi_flash_memory: &Flash) -> &Vec<Vec<u8>>
+	{
+		match &spi_flash_memory.spi
+		{
+			MockSpi::Ok { write_operations, .. } => write_operations,
+			MockSpi::Err(_) => unreachable!(),
+		}
+	}
+
+	#[test]
+	fn partial_page_write_loads_only_its_bytes()
+	{
+		let mut spi_flash_memory = new_flash_memory();
+
+		// Only the second part of the second page is written
+		let data = [0x5A; WRITE_SIZE];
+		NorFlash::write(&mut spi_flash_memory, Chip::PAGE_SIZE + WRITE_SIZE as u32, &data).unwrap();
+
+		// `ProgramLoad` (`0x02`) clears the cache register before loading the data, while `ProgramLoadRandomData`
+		// (`0x84`) would program the rest of the page with the bytes left in it by the last read
+		let write_operations = get_write_operations(&spi_flash_memory);
+		let program_load_index = write_operations
+			.iter()
+			.position(|operation| operation == &[0x02])
+			.unwrap();
+		assert_eq!(
+			write_operations[program_load_index + 1],
+			(WRITE_SIZE as u16).to_be_bytes()
+		);
+		assert_eq!(write_operations[program_load_index + 2], data);
+		assert!(!write_operations.iter().any(|operation| operation == &[0x84]));
+	}
+
+	#[test]
+	fn arguments_are_checked()
+	{
+		let mut spi_flash_memory = new_flash_memory();
+
+		assert!(matches!(
+			NorFlash::write(&mut spi_flash_memory, 1, &[0; WRITE_SIZE]),
+			Err(StorageError::NotAligned)
+		));
+		assert!(matches!(
+			NorFlash::write(&mut spi_flash_memory, 0, &[0; WRITE_SIZE - 1]),
+			Err(StorageError::NotAligned)
+		));
+		assert!(matches!(
+			NorFlash::erase(&mut spi_flash_memory, 0, Chip::BLOCK_SIZE / 2),
+			Err(StorageError::NotAligned)
+		));
+		assert!(matches!(
+			NorFlash::erase(&mut spi_flash_memory, 0, Chip::MEMORY_SIZE + Chip::BLOCK_SIZE),
+			Err(StorageError::OutOfBounds)
+		));
+		assert!(get_write_operations(&spi_flash_memory).is_empty());
+	}
+}
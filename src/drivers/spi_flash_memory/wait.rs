@@ -0,0 +1,43 @@
+use core::time::Duration;
+
+use embedded_hal::delay::DelayNs;
+
+use crate::peripherals::time::system_time::SystemTime;
+
+/// A [`SystemTime`] that never advances, used by a [`SpiFlashMemory`] whose waits don't have a timeout.
+///
+/// [`SpiFlashMemory`]: super::SpiFlashMemory
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NoTimeout;
+
+impl SystemTime for NoTimeout
+{
+	fn now(&self) -> Duration
+	{
+		Duration::ZERO
+	}
+}
+
+/// A [`DelayNs`] that doesn't wait, used by a [`SpiFlashMemory`] that polls the status register of the chip
+/// continuously.
+///
+/// [`SpiFlashMemory`]: super::SpiFlashMemory
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay
+{
+	fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Waits the provided `duration` with `delay`.
+///
+/// [`DelayNs::delay_ns`] can't wait more than `~4.29s`, so the longer durations are waited in microseconds.
+pub(crate) fn delay_for(delay: &mut impl DelayNs, duration: Duration)
+{
+	match u32::try_from(duration.as_nanos())
+	{
+		Ok(nanoseconds) => delay.delay_ns(nanoseconds),
+		Err(_) => delay.delay_us(u32::try_from(duration.as_micros()).unwrap_or(u32::MAX)),
+	}
+}
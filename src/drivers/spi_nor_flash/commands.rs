@@ -0,0 +1,180 @@
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+use super::StatusRegister;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// The address of a byte in a NOR flash memory, sent with 3 or 4 bytes depending on the size of the memory.
+pub struct Address
+{
+	bytes: [u8; 4],
+	is_four_bytes: bool,
+}
+
+impl Address
+{
+	/// Returns the [`Address`] of the byte at `address`, that will be sent with 4 bytes if `is_four_bytes` is `true`.
+	pub const fn new(address: u32, is_four_bytes: bool) -> Self
+	{
+		Self {
+			bytes: address.to_be_bytes(),
+			is_four_bytes,
+		}
+	}
+
+	/// Returns the address as bytes that can be sent over SPI.
+	pub fn as_bytes(&self) -> &[u8]
+	{
+		match self.is_four_bytes
+		{
+			true => &self.bytes,
+			false => &self.bytes[1..],
+		}
+	}
+}
+
+#[derive(Debug)]
+/// A command to send to a NOR flash memory over SPI.
+pub enum Command<'a>
+{
+	WriteEnable,
+	ReadStatusRegister
+	{
+		register: StatusRegister,
+		value: &'a mut u8,
+	},
+	WriteStatusRegister
+	{
+		register: StatusRegister,
+		value: u8,
+	},
+	ReadData
+	{
+		address: Address,
+		output: &'a mut [u8],
+	},
+	PageProgram
+	{
+		address: Address,
+		input: &'a [u8],
+	},
+	Erase
+	{
+		op_code: u8,
+		address: Address,
+	},
+	ChipErase,
+	PowerDown,
+	ReleasePowerDown,
+	ReadJedecId
+	{
+		output: &'a mut [u8; 3],
+	},
+	ReadSfdp
+	{
+		address: u32,
+		output: &'a mut [u8],
+	},
+	EnterFourByteAddressMode,
+	EnableReset,
+	Reset,
+}
+
+impl<'a> Command<'a>
+{
+	/// Send this command to the `spi_device` flash memory.
+	///
+	/// Returns `Ok(())` if the command has been sent succesfully, otherwise returns `Err(...)`.
+	pub fn execute<Spi: SpiDevice<u8>>(self, spi_device: &mut Spi) -> Result<(), <Spi as ErrorType>::Error>
+	{
+		let op_code = [self.op_code()];
+		let op_code_operation = Operation::Write(&op_code);
+
+		match self
+		{
+			Command::ReadStatusRegister { register: _, value } =>
+			{
+				spi_device.transaction(&mut [op_code_operation, Operation::Read(core::slice::from_mut(value))])?;
+			},
+			Command::WriteStatusRegister { register: _, value } =>
+			{
+				spi_device.transaction(&mut [op_code_operation, Operation::Write(&[value])])?;
+			},
+			Command::ReadData { address, output } =>
+			{
+				spi_device.transaction(&mut [
+					op_code_operation,
+					Operation::Write(address.as_bytes()),
+					Operation::Read(output),
+				])?;
+			},
+			Command::PageProgram { address, input } =>
+			{
+				spi_device.transaction(&mut [
+					op_code_operation,
+					Operation::Write(address.as_bytes()),
+					Operation::Write(input),
+				])?;
+			},
+			Command::Erase { op_code: _, address } =>
+			{
+				spi_device.transaction(&mut [op_code_operation, Operation::Write(address.as_bytes())])?;
+			},
+			Command::ReadJedecId { output } =>
+			{
+				spi_device.transaction(&mut [op_code_operation, Operation::Read(output)])?;
+			},
+			Command::ReadSfdp { address, output } =>
+			{
+				spi_device.transaction(&mut [
+					op_code_operation,
+					Operation::Write(&address.to_be_bytes()[1..]),
+					Operation::Write(&[0]), // Dummy byte
+					Operation::Read(output),
+				])?;
+			},
+			Command::WriteEnable
+			| Command::ChipErase
+			| Command::PowerDown
+			| Command::ReleasePowerDown
+			| Command::EnterFourByteAddressMode
+			| Command::EnableReset
+			| Command::Reset =>
+			{
+				spi_device.transaction(&mut [op_code_operation])?;
+			},
+		}
+
+		Ok(())
+	}
+
+	fn op_code(&self) -> u8
+	{
+		match self
+		{
+			Command::WriteEnable => 0x06,
+			Command::ReadStatusRegister { register, value: _ } => match register
+			{
+				StatusRegister::One => 0x05,
+				StatusRegister::Two => 0x35,
+				StatusRegister::Three => 0x15,
+			},
+			Command::WriteStatusRegister { register, value: _ } => match register
+			{
+				StatusRegister::One => 0x01,
+				StatusRegister::Two => 0x31,
+				StatusRegister::Three => 0x11,
+			},
+			Command::ReadData { address: _, output: _ } => 0x03,
+			Command::PageProgram { address: _, input: _ } => 0x02,
+			Command::Erase { op_code, address: _ } => *op_code,
+			Command::ChipErase => 0xC7,
+			Command::PowerDown => 0xB9,
+			Command::ReleasePowerDown => 0xAB,
+			Command::ReadJedecId { output: _ } => 0x9F,
+			Command::ReadSfdp { address: _, output: _ } => 0x5A,
+			Command::EnterFourByteAddressMode => 0xB7,
+			Command::EnableReset => 0x66,
+			Command::Reset => 0x99,
+		}
+	}
+}
@@ -0,0 +1,839 @@
+use core::{fmt::Debug, time::Duration};
+
+use embedded_hal::{
+	delay::DelayNs,
+	spi::{ErrorType, SpiDevice},
+};
+
+use self::commands::{Address, Command};
+use crate::{
+	drivers::spi_flash_memory::{delay_for, NoDelay, NoTimeout},
+	peripherals::time::system_time::SystemTime,
+};
+
+mod commands;
+mod sfdp;
+#[cfg(feature = "storage")]
+mod storage;
+
+pub use sfdp::*;
+
+const STATUS_BUSY: u8 = 0b0000_0001;
+const STATUS_BLOCK_PROTECTION_BITS: u8 = 0b0001_1100;
+const STATUS_TOP_BOTTOM_PROTECTION: u8 = 0b0010_0000;
+const STATUS_SECTOR_PROTECTION: u8 = 0b0100_0000;
+/// Time the chip needs to enter or exit the deep power-down mode (`tDP` and `tRES1` in the datasheets).
+const POWER_DOWN_TRANSITION_TIME: Duration = Duration::from_micros(3);
+/// Time the chip needs to be reset (`tRST` in the datasheets).
+const RESET_TIME: Duration = Duration::from_micros(30);
+/// The 4KB sector erase is supported by almost all the NOR flash memory chips.
+const DEFAULT_ERASE_SIZE: u32 = 4 * 1024;
+
+/// A NOR flash memory (like the `W25Q` family) connected to the microcontroller through a SPI interface.
+///
+/// Differently from a NAND flash memory, every byte can be read and programmed individually (a program can only
+/// change bits from 1 to 0), while the erase (that sets the bits back to 1) works on sectors of at least 4KB.
+///
+/// The geometry and the timings of the chip are described by its [`NorFlashParameters`], which can be read from the
+/// chip itself with [`Self::from_sfdp`].
+///
+/// ## Waits
+/// Most operations need to wait for the chip to finish, by polling its status register. By default the status register
+/// is polled continuously and forever, but you can set a [`timeout`] and a [`delay between polls`].
+///
+/// ## Erase size
+/// `ERASE_SIZE` is the smallest number of bytes erased by the `embedded_storage` traits, so it must be one of the erase
+/// types of the chip. It's 4KB by default, but you can choose [`another erase type`] of the [`NorFlashParameters`].
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::spi_nor_flash::*, hardware::mock::MockSpiNorFlash};
+/// #
+/// # let spi = MockSpiNorFlash::new(16 * 1024 * 1024);
+/// let mut spi_nor_flash = SpiNorFlash::from_sfdp(spi).unwrap();
+/// assert_eq!(spi_nor_flash.get_parameters().memory_size, 16 * 1024 * 1024);
+///
+/// spi_nor_flash.erase(0, EraseSize::Sector4K).unwrap();
+/// spi_nor_flash.program(250, b"Across pages").unwrap();
+///
+/// let mut data = [0; 12];
+/// spi_nor_flash.read(250, &mut data).unwrap();
+/// assert_eq!(&data, b"Across pages");
+/// ```
+///
+/// [`timeout`]: Self::with_timeout
+/// [`delay between polls`]: Self::with_poll_delay
+/// [`another erase type`]: Self::with_erase_size
+pub struct SpiNorFlash<
+	Spi: SpiDevice<u8>,
+	T: SystemTime = NoTimeout,
+	D: DelayNs = NoDelay,
+	const ERASE_SIZE: u32 = DEFAULT_ERASE_SIZE,
+> {
+	spi: Spi,
+	parameters: NorFlashParameters,
+	is_powered_down: bool,
+	system_time: T,
+	timeout: Duration,
+	delay: D,
+	poll_interval: Duration,
+}
+
+impl<Spi: SpiDevice<u8>> SpiNorFlash<Spi>
+{
+	/// Returns a [`SpiNorFlash`] that communicates with the chip through the provided `spi`, whose geometry and
+	/// timings are the provided `parameters`.
+	pub fn new(spi: Spi, parameters: NorFlashParameters) -> Self
+	{
+		Self {
+			spi,
+			parameters,
+			is_powered_down: false,
+			system_time: NoTimeout,
+			timeout: Duration::MAX,
+			delay: NoDelay,
+			poll_interval: Duration::ZERO,
+		}
+	}
+
+	/// Returns a [`SpiNorFlash`] whose [`NorFlashParameters`] are read from the SFDP tables of the chip, and that has
+	/// already been [`initialized`](Self::initialize).
+	pub fn from_sfdp(spi: Spi) -> Result<Self, SpiNorFlashError<Spi>>
+	{
+		let mut spi_nor_flash = Self::new(spi, NorFlashParameters::w25q(0));
+		spi_nor_flash.release_power_down()?;
+		spi_nor_flash.parameters = spi_nor_flash.read_sfdp_parameters()?;
+		spi_nor_flash.initialize()?;
+
+		Ok(spi_nor_flash)
+	}
+}
+
+impl<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs, const ERASE_SIZE: u32> SpiNorFlash<Spi, T, D, ERASE_SIZE>
+{
+	/// Makes every wait for an operation of the chip fail with [`SpiNorFlashError::Timeout`] if it takes more than
+	/// the provided `timeout`, measured using the provided `system_time`.
+	///
+	/// The erase operations use the max time in the [`NorFlashParameters`] if it's longer than `timeout`.
+	pub fn with_timeout<NewT: SystemTime>(
+		self, system_time: NewT, timeout: Duration,
+	) -> SpiNorFlash<Spi, NewT, D, ERASE_SIZE>
+	{
+		SpiNorFlash {
+			spi: self.spi,
+			parameters: self.parameters,
+			is_powered_down: self.is_powered_down,
+			system_time,
+			timeout,
+			delay: self.delay,
+			poll_interval: self.poll_interval,
+		}
+	}
+
+	/// Makes every wait for an operation of the chip wait `poll_interval` using the provided `delay` between two reads
+	/// of the status register, instead of reading it continuously.
+	///
+	/// The `delay` is also used to wait for the chip to enter and exit the deep power-down mode.
+	pub fn with_poll_delay<NewD: DelayNs>(
+		self, delay: NewD, poll_interval: Duration,
+	) -> SpiNorFlash<Spi, T, NewD, ERASE_SIZE>
+	{
+		SpiNorFlash {
+			spi: self.spi,
+			parameters: self.parameters,
+			is_powered_down: self.is_powered_down,
+			system_time: self.system_time,
+			timeout: self.timeout,
+			delay,
+			poll_interval,
+		}
+	}
+
+	/// Makes the `embedded_storage` traits erase `NEW_ERASE_SIZE` bytes at a time.
+	///
+	/// # Panics
+	/// Panics if `NEW_ERASE_SIZE` isn't the size of one of the erase types in the [`NorFlashParameters`] (for example
+	/// the ones read from the SFDP tables of the chip), check it with [`NorFlashParameters::get_erase_type`].
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{drivers::spi_nor_flash::*, hardware::mock::MockSpiNorFlash};
+	/// #
+	/// # let spi = MockSpiNorFlash::new(16 * 1024 * 1024);
+	/// let spi_nor_flash = SpiNorFlash::from_sfdp(spi).unwrap();
+	///
+	/// assert!(spi_nor_flash.get_parameters().get_erase_type(16 * 1024).is_none());
+	/// assert!(spi_nor_flash.get_parameters().get_erase_type(64 * 1024).is_some());
+	/// let spi_nor_flash = spi_nor_flash.with_erase_size::<{ 64 * 1024 }>();
+	/// ```
+	pub fn with_erase_size<const NEW_ERASE_SIZE: u32>(self) -> SpiNorFlash<Spi, T, D, NEW_ERASE_SIZE>
+	{
+		assert!(self.parameters.get_erase_type(NEW_ERASE_SIZE).is_some());
+
+		SpiNorFlash {
+			spi: self.spi,
+			parameters: self.parameters,
+			is_powered_down: self.is_powered_down,
+			system_time: self.system_time,
+			timeout: self.timeout,
+			delay: self.delay,
+			poll_interval: self.poll_interval,
+		}
+	}
+
+	/// Puts the chip in a known condition and enables the 4 bytes addresses if the memory is bigger than 16MB.
+	pub fn initialize(&mut self) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.execute(Command::EnableReset)?;
+		self.execute(Command::Reset)?;
+		self.delay.delay_us(RESET_TIME.as_micros() as u32);
+
+		if self.parameters.needs_four_byte_addresses()
+		{
+			self.execute(Command::EnterFourByteAddressMode)?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the [`NorFlashParameters`] used by this driver.
+	pub fn get_parameters(&self) -> &NorFlashParameters
+	{
+		&self.parameters
+	}
+
+	/// Returns the [`JedecId`] of the chip.
+	pub fn read_jedec_id(&mut self) -> Result<JedecId, SpiNorFlashError<Spi>>
+	{
+		let mut output = [0; 3];
+		self.execute(Command::ReadJedecId { output: &mut output })?;
+
+		Ok(JedecId {
+			manufacturer_id: output[0],
+			memory_type: output[1],
+			capacity: output[2],
+		})
+	}
+
+	/// Reads the [`NorFlashParameters`] from the SFDP tables of the chip.
+	///
+	/// Returns `Err(SpiNorFlashError::InvalidSfdp)` if the chip doesn't support SFDP or the tables are corrupted.
+	pub fn read_sfdp_parameters(&mut self) -> Result<NorFlashParameters, SpiNorFlashError<Spi>>
+	{
+		let mut header = [0; SFDP_HEADER_SIZE];
+		self.read_sfdp(0, &mut header)?;
+
+		// Only the Basic Flash Parameter Table is needed, which is usually the first one
+		let mut parameter_headers = [0; 4 * PARAMETER_HEADER_SIZE];
+		let parameter_headers_count = NorFlashParameters::get_parameter_headers_count(&header).min(4);
+		let parameter_headers = &mut parameter_headers[..(parameter_headers_count * PARAMETER_HEADER_SIZE)];
+		self.read_sfdp(SFDP_HEADER_SIZE as u32, parameter_headers)?;
+
+		let (table_address, table_dwords) =
+			NorFlashParameters::find_basic_flash_parameter_table(&header, parameter_headers)
+				.ok_or(SpiNorFlashError::InvalidSfdp)?;
+		let table_dwords = table_dwords.min(BASIC_FLASH_PARAMETER_TABLE_DWORDS);
+
+		let mut table = [0; BASIC_FLASH_PARAMETER_TABLE_DWORDS * 4];
+		self.read_sfdp(table_address, &mut table[..(table_dwords * 4)])?;
+		let mut dwords = [0; BASIC_FLASH_PARAMETER_TABLE_DWORDS];
+		for (dword, bytes) in dwords.iter_mut().zip(table.chunks_exact(4))
+		{
+			*dword = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+
+		NorFlashParameters::parse_basic_flash_parameter_table(&dwords[..table_dwords])
+			.ok_or(SpiNorFlashError::InvalidSfdp)
+	}
+
+	/// Reads [`data.len()`] bytes starting from the provided `address`.
+	///
+	/// Returns `Ok(())` if all the bytes have been read, otherwise returns `Err(...)`.
+	pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.check_bounds(address, data.len())?;
+		self.check_powered_up()?;
+
+		let address = self.get_address(address);
+		self.execute(Command::ReadData { address, output: data })
+	}
+
+	/// Program the provided `data` starting from the provided `address`, splitting it in multiple program operations
+	/// when it crosses the boundary of a page.
+	///
+	/// Returns `Ok(())` if all the bytes have been written, otherwise returns `Err(...)`.
+	///
+	/// # Warning
+	/// A program can only change bits from 1 to 0, so the bytes should be [`erased`](Self::erase) first.
+	pub fn program(&mut self, address: u32, data: &[u8]) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.check_bounds(address, data.len())?;
+		self.check_powered_up()?;
+
+		let page_size = self.parameters.page_size;
+		let mut address = address;
+		let mut data = data;
+		while !data.is_empty()
+		{
+			// A page program that crosses the boundary of the page wraps around to its start
+			let bytes_to_page_end = page_size - address % page_size;
+			let (page_data, remaining_data) = data.split_at(data.len().min(bytes_to_page_end as usize));
+
+			self.execute(Command::WriteEnable)?;
+			self.execute(Command::PageProgram {
+				address: self.get_address(address),
+				input: page_data,
+			})?;
+			self.wait_for_operation_to_finish(self.parameters.page_program_max_time)?;
+
+			address += page_data.len() as u32;
+			data = remaining_data;
+		}
+
+		Ok(())
+	}
+
+	/// Erases the sector or the block of the provided `erase_size` that starts at the provided `address`, by setting
+	/// all its bits to 1.
+	///
+	/// Returns `Err(SpiNorFlashError::UnsupportedEraseSize)` if the chip doesn't support the `erase_size` and
+	/// `Err(SpiNorFlashError::NotAligned)` if `address` isn't aligned to it.
+	pub fn erase(&mut self, address: u32, erase_size: EraseSize) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		let erase_type = self
+			.parameters
+			.get_erase_type(erase_size.get_size())
+			.ok_or(SpiNorFlashError::UnsupportedEraseSize)?;
+
+		self.erase_with_type(address, erase_type)
+	}
+
+	/// Erases all the bytes in the range from `from` (included) to `to` (excluded), using the biggest erase types
+	/// allowed by the alignment of the range.
+	///
+	/// Returns `Err(SpiNorFlashError::NotAligned)` if `from` or `to` isn't aligned to the smallest erase type.
+	pub fn erase_range(&mut self, from: u32, to: u32) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		let smallest_erase_type = self
+			.parameters
+			.get_smallest_erase_type()
+			.ok_or(SpiNorFlashError::UnsupportedEraseSize)?;
+		if !from.is_multiple_of(smallest_erase_type.size) || !to.is_multiple_of(smallest_erase_type.size)
+		{
+			return Err(SpiNorFlashError::NotAligned);
+		}
+		self.check_bounds(from, to.saturating_sub(from) as usize)?;
+
+		let mut address = from;
+		while address < to
+		{
+			let erase_type = self
+				.parameters
+				.erase_types
+				.iter()
+				.rev()
+				.flatten()
+				.find(|erase_type| {
+					address.is_multiple_of(erase_type.size)
+						&& address.checked_add(erase_type.size).is_some_and(|end| end <= to)
+				})
+				.copied()
+				.unwrap_or(smallest_erase_type);
+
+			self.erase_with_type(address, erase_type)?;
+			address = match address.checked_add(erase_type.size)
+			{
+				Some(next_address) => next_address,
+				None => break,
+			};
+		}
+
+		Ok(())
+	}
+
+	/// Erases all the bytes of the chip.
+	///
+	/// # Warning
+	/// It can take up to [`NorFlashParameters::chip_erase_max_time`] (some minutes for the biggest chips).
+	pub fn erase_chip(&mut self) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.check_powered_up()?;
+
+		self.execute(Command::WriteEnable)?;
+		self.execute(Command::ChipErase)?;
+		self.wait_for_operation_to_finish(self.parameters.chip_erase_max_time)
+	}
+
+	/// Returns the [`BlockProtection`] set in the status register of the chip.
+	pub fn get_block_protection(&mut self) -> Result<BlockProtection, SpiNorFlashError<Spi>>
+	{
+		let status = self.read_status_register(StatusRegister::One)?;
+
+		Ok(BlockProtection {
+			protected_blocks: (status & STATUS_BLOCK_PROTECTION_BITS) >> 2,
+			is_from_bottom: status & STATUS_TOP_BOTTOM_PROTECTION != 0,
+			is_sector_granularity: status & STATUS_SECTOR_PROTECTION != 0,
+		})
+	}
+
+	/// Sets the provided [`BlockProtection`] in the status register of the chip, so that the protected area can't be
+	/// programmed or erased.
+	///
+	/// # Note
+	/// The write to the status register is volatile on some chips (the protection is restored after a power cycle).
+	pub fn set_block_protection(&mut self, block_protection: BlockProtection) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		let mut status = self.read_status_register(StatusRegister::One)?;
+		status &= !(STATUS_BLOCK_PROTECTION_BITS | STATUS_TOP_BOTTOM_PROTECTION | STATUS_SECTOR_PROTECTION);
+		status |= (block_protection.protected_blocks << 2) & STATUS_BLOCK_PROTECTION_BITS;
+		if block_protection.is_from_bottom
+		{
+			status |= STATUS_TOP_BOTTOM_PROTECTION;
+		}
+		if block_protection.is_sector_granularity
+		{
+			status |= STATUS_SECTOR_PROTECTION;
+		}
+
+		self.write_status_register(StatusRegister::One, status)
+	}
+
+	/// Returns the value of the provided status `register`.
+	///
+	/// Check the datasheet of the chip to understand what each bit does.
+	pub fn read_status_register(&mut self, register: StatusRegister) -> Result<u8, SpiNorFlashError<Spi>>
+	{
+		self.check_powered_up()?;
+
+		let mut value = 0;
+		self.execute(Command::ReadStatusRegister {
+			register,
+			value: &mut value,
+		})?;
+
+		Ok(value)
+	}
+
+	/// Sets the value of the provided status `register` to `value`.
+	///
+	/// Check the datasheet of the chip to understand what each bit does.
+	pub fn write_status_register(&mut self, register: StatusRegister, value: u8) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.check_powered_up()?;
+
+		self.execute(Command::WriteEnable)?;
+		self.execute(Command::WriteStatusRegister { register, value })?;
+		self.wait_for_operation_to_finish(Duration::ZERO)
+	}
+
+	/// Puts the chip in the deep power-down mode, where it consumes the least current and ignores all the commands
+	/// until [`Self::release_power_down`] is called.
+	///
+	/// The methods that would send a command to the chip return `Err(SpiNorFlashError::PoweredDown)` in the meantime.
+	pub fn power_down(&mut self) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.execute(Command::PowerDown)?;
+		self.delay.delay_us(POWER_DOWN_TRANSITION_TIME.as_micros() as u32);
+		self.is_powered_down = true;
+
+		Ok(())
+	}
+
+	/// Wakes up the chip from the deep power-down mode.
+	pub fn release_power_down(&mut self) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.execute(Command::ReleasePowerDown)?;
+		self.delay.delay_us(POWER_DOWN_TRANSITION_TIME.as_micros() as u32);
+		self.is_powered_down = false;
+
+		Ok(())
+	}
+
+	/// Returns `true` if the chip is in the deep power-down mode.
+	pub fn is_powered_down(&self) -> bool
+	{
+		self.is_powered_down
+	}
+
+	/// Returns a mutable reference to the underlying SPI device.
+	pub fn get_spi_mut(&mut self) -> &mut Spi
+	{
+		&mut self.spi
+	}
+
+	fn execute(&mut self, command: Command<'_>) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		command.execute(&mut self.spi).map_err(SpiNorFlashError::Spi)
+	}
+
+	fn erase_with_type(&mut self, address: u32, erase_type: EraseType) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		if !address.is_multiple_of(erase_type.size)
+		{
+			return Err(SpiNorFlashError::NotAligned);
+		}
+		self.check_bounds(address, erase_type.size as usize)?;
+		self.check_powered_up()?;
+
+		self.execute(Command::WriteEnable)?;
+		self.execute(Command::Erase {
+			op_code: erase_type.op_code,
+			address: self.get_address(address),
+		})?;
+		self.wait_for_operation_to_finish(erase_type.max_time)
+	}
+
+	fn read_sfdp(&mut self, address: u32, output: &mut [u8]) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		self.check_powered_up()?;
+		self.execute(Command::ReadSfdp { address, output })
+	}
+
+	fn get_address(&self, address: u32) -> Address
+	{
+		Address::new(address, self.parameters.needs_four_byte_addresses())
+	}
+
+	fn check_bounds(&self, address: u32, length: usize) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		match (address as u64 + length as u64) <= self.parameters.memory_size as u64
+		{
+			true => Ok(()),
+			false => Err(SpiNorFlashError::OutOfBounds),
+		}
+	}
+
+	fn check_powered_up(&self) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		match self.is_powered_down
+		{
+			true => Err(SpiNorFlashError::PoweredDown),
+			false => Ok(()),
+		}
+	}
+
+	/// Waits until the chip isn't busy anymore, for at most the longest between the provided `max_time` and the
+	/// timeout of this [`SpiNorFlash`].
+	fn wait_for_operation_to_finish(&mut self, max_time: Duration) -> Result<(), SpiNorFlashError<Spi>>
+	{
+		let timeout = self.timeout.max(max_time);
+		let start_time = self.system_time.now();
+		loop
+		{
+			if self.read_status_register(StatusRegister::One)? & STATUS_BUSY == 0
+			{
+				return Ok(());
+			}
+
+			if self.system_time.now().saturating_sub(start_time) > timeout
+			{
+				return Err(SpiNorFlashError::Timeout);
+			}
+			if !self.poll_interval.is_zero()
+			{
+				delay_for(&mut self.delay, self.poll_interval);
+			}
+		}
+	}
+}
+
+/// The ID of a chip, as defined by JEDEC.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JedecId
+{
+	pub manufacturer_id: u8,
+	pub memory_type: u8,
+	/// Usually the base 2 logarithm of the size of the memory in bytes.
+	pub capacity: u8,
+}
+
+/// The sizes of the erase operations supported by most NOR flash memory chips.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EraseSize
+{
+	Sector4K,
+	Block32K,
+	Block64K,
+}
+
+impl EraseSize
+{
+	/// Returns the number of bytes erased.
+	pub fn get_size(&self) -> u32
+	{
+		match self
+		{
+			Self::Sector4K => 4 * 1024,
+			Self::Block32K => 32 * 1024,
+			Self::Block64K => 64 * 1024,
+		}
+	}
+}
+
+/// A status register of a NOR flash memory chip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusRegister
+{
+	One,
+	Two,
+	Three,
+}
+
+/// The area of the memory protected from program and erase operations, set in the status register of the chip.
+///
+/// Check the datasheet of the chip to know which addresses each combination protects.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BlockProtection
+{
+	/// The value of the `BP` bits: 0 means that nothing is protected, while the max value (7 for most of the chips)
+	/// means that everything is protected.
+	pub protected_blocks: u8,
+	/// If `true` the protected area starts from the bottom of the memory (`TB` bit), otherwise from the top.
+	pub is_from_bottom: bool,
+	/// If `true` the protected area is measured in 4KB sectors instead of 64KB blocks (`SEC` bit).
+	pub is_sector_granularity: bool,
+}
+
+impl BlockProtection
+{
+	/// Nothing is protected.
+	pub const NONE: Self = Self {
+		protected_blocks: 0,
+		is_from_bottom: false,
+		is_sector_granularity: false,
+	};
+}
+
+/// An error returned from the operations of a [`SpiNorFlash`].
+pub enum SpiNorFlashError<Spi: SpiDevice<u8>>
+{
+	/// It has been impossible to communicate via SPI.
+	Spi(<Spi as ErrorType>::Error),
+	/// The chip was still busy after the [`timeout`](SpiNorFlash::with_timeout).
+	Timeout,
+	/// The chip doesn't support SFDP, or its tables are corrupted.
+	InvalidSfdp,
+	/// The chip doesn't support the requested [`EraseSize`].
+	UnsupportedEraseSize,
+	/// The address isn't aligned to the size of the erase.
+	NotAligned,
+	/// The address range is out of the bounds of the memory.
+	OutOfBounds,
+	/// The chip is in the deep power-down mode (check [`SpiNorFlash::power_down`]).
+	PoweredDown,
+}
+
+impl<Spi: SpiDevice<u8>> Debug for SpiNorFlashError<Spi>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Spi(arg0) => f.debug_tuple("Spi").field(arg0).finish(),
+			Self::Timeout => write!(f, "Timeout"),
+			Self::InvalidSfdp => write!(f, "InvalidSfdp"),
+			Self::UnsupportedEraseSize => write!(f, "UnsupportedEraseSize"),
+			Self::NotAligned => write!(f, "NotAligned"),
+			Self::OutOfBounds => write!(f, "OutOfBounds"),
+			Self::PoweredDown => write!(f, "PoweredDown"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use alloc::{vec, vec::Vec};
+	use core::cell::Cell;
+
+	use super::*;
+	use crate::hardware::mock::{MockSpi, MockSpiNorFlash, MockSpiNorTimings};
+
+	extern crate alloc;
+
+	const MEMORY_SIZE: u32 = 1024 * 1024;
+
+	/// A [`SystemTime`] that advances by 1ms each time it's read.
+	struct SteppingTime(Cell<Duration>);
+	impl SystemTime for SteppingTime
+	{
+		fn now(&self) -> Duration
+		{
+			let now = self.0.get();
+			self.0.set(now + Duration::from_millis(1));
+			now
+		}
+	}
+
+	/// A [`DelayNs`] that only sums the time it has been asked to wait.
+	#[derive(Default)]
+	struct CountingDelay(Duration);
+	impl DelayNs for CountingDelay
+	{
+		fn delay_ns(&mut self, ns: u32)
+		{
+			self.0 += Duration::from_nanos(ns as u64);
+		}
+	}
+
+	#[test]
+	fn discover_chip()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+
+		let jedec_id = spi_nor_flash.read_jedec_id().unwrap();
+		assert_eq!((jedec_id.manufacturer_id, jedec_id.capacity), (0xEF, 20));
+
+		let parameters = spi_nor_flash.get_parameters();
+		assert_eq!(parameters.memory_size, MEMORY_SIZE);
+		assert_eq!(parameters.page_size, 256);
+		assert!(parameters.get_erase_type(EraseSize::Block32K.get_size()).is_some());
+	}
+
+	#[test]
+	fn program_across_pages_and_erase()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+
+		let data: [u8; 600] = core::array::from_fn(|i| i as u8);
+		spi_nor_flash.program(4096 - 100, &data).unwrap();
+		let mut read_data = [0; 600];
+		spi_nor_flash.read(4096 - 100, &mut read_data).unwrap();
+		assert_eq!(read_data, data);
+
+		// Only the first sector is erased
+		spi_nor_flash.erase(0, EraseSize::Sector4K).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(4095), 0xFF);
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(4096), data[100]);
+
+		assert!(matches!(
+			spi_nor_flash.erase(4096, EraseSize::Block64K),
+			Err(SpiNorFlashError::NotAligned)
+		));
+		spi_nor_flash.erase_range(0, 128 * 1024).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(4096), 0xFF);
+
+		assert!(matches!(
+			spi_nor_flash.program(MEMORY_SIZE - 1, &[0, 0]),
+			Err(SpiNorFlashError::OutOfBounds)
+		));
+	}
+
+	#[test]
+	fn erase_range_at_the_end_of_the_address_space()
+	{
+		// The 32KB and 64KB blocks at the end of the range would end exactly at `2^32`
+		let end = u32::MAX - 4095;
+		let spi = MockSpi::Ok {
+			read_operations: vec![vec![0x00]; 8],
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		};
+		let mut spi_nor_flash = SpiNorFlash::new(spi, NorFlashParameters::w25q(end));
+
+		spi_nor_flash.erase_range(end - 60 * 1024, end).unwrap();
+
+		// A 32KB block, then 7 sectors of 4KB
+		let MockSpi::Ok { write_operations, .. } = spi_nor_flash.get_spi_mut()
+		else
+		{
+			unreachable!()
+		};
+		let erase_op_codes: Vec<u8> = write_operations
+			.iter()
+			.filter(|operation| operation == &&[0x52] || operation == &&[0x20])
+			.map(|operation| operation[0])
+			.collect();
+		assert_eq!(erase_op_codes, [[0x52].as_slice(), &[0x20; 7]].concat());
+	}
+
+	#[test]
+	fn block_protection()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+
+		// Protect the first 64KB
+		let block_protection = BlockProtection {
+			protected_blocks: 1,
+			is_from_bottom: true,
+			is_sector_granularity: false,
+		};
+		spi_nor_flash.set_block_protection(block_protection).unwrap();
+		assert_eq!(spi_nor_flash.get_block_protection().unwrap(), block_protection);
+
+		spi_nor_flash.program(0, &[0x00]).unwrap();
+		spi_nor_flash.program(64 * 1024, &[0x00]).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(0), 0xFF);
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(64 * 1024), 0x00);
+
+		spi_nor_flash.set_block_protection(BlockProtection::NONE).unwrap();
+		spi_nor_flash.program(0, &[0x00]).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(0), 0x00);
+	}
+
+	#[test]
+	fn deep_power_down()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+
+		spi_nor_flash.power_down().unwrap();
+		assert!(spi_nor_flash.get_spi_mut().is_powered_down());
+		assert!(matches!(
+			spi_nor_flash.read(0, &mut [0]),
+			Err(SpiNorFlashError::PoweredDown)
+		));
+
+		spi_nor_flash.release_power_down().unwrap();
+		assert!(!spi_nor_flash.get_spi_mut().is_powered_down());
+		spi_nor_flash.program(0, &[0x12]).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(0), 0x12);
+	}
+
+	#[test]
+	fn four_byte_addresses()
+	{
+		const BIG_MEMORY_SIZE: u32 = 32 * 1024 * 1024;
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(BIG_MEMORY_SIZE)).unwrap();
+		assert!(spi_nor_flash.get_parameters().needs_four_byte_addresses());
+
+		let address = BIG_MEMORY_SIZE - 10;
+		spi_nor_flash.program(address, b"last bytes").unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(address), b'l');
+
+		let mut data = [0; 10];
+		spi_nor_flash.read(address, &mut data).unwrap();
+		assert_eq!(&data, b"last bytes");
+	}
+
+	#[test]
+	fn stuck_chip_times_out()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE))
+			.unwrap()
+			.with_timeout(SteppingTime(Cell::new(Duration::ZERO)), Duration::from_millis(10));
+
+		spi_nor_flash.get_spi_mut().set_stuck_busy(true);
+		assert!(matches!(spi_nor_flash.program(0, &[0]), Err(SpiNorFlashError::Timeout)));
+	}
+
+	#[test]
+	fn poll_delay_is_waited_between_status_reads()
+	{
+		let mut mock = MockSpiNorFlash::new(MEMORY_SIZE);
+		mock.set_timings(MockSpiNorTimings {
+			page_program: Duration::from_millis(5),
+			status_read: Duration::from_millis(1),
+			..MockSpiNorTimings::default()
+		});
+		// Longer than the `~4.29s` that fit in the nanoseconds of `DelayNs::delay_ns`
+		let poll_interval = Duration::from_secs(5);
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(mock)
+			.unwrap()
+			.with_poll_delay(CountingDelay::default(), poll_interval);
+
+		spi_nor_flash.program(0, &[0]).unwrap();
+
+		// The program finishes at the 5th read of the status register
+		assert_eq!(spi_nor_flash.delay.0, poll_interval * 4);
+	}
+}
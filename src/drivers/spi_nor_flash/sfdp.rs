@@ -0,0 +1,281 @@
+//! The [`SFDP`] (Serial Flash Discoverable Parameters, JESD216) tables describe the geometry, the supported
+//! commands and the timings of a NOR flash memory chip, so that the same driver can work with chips of different
+//! sizes and manufacturers.
+//!
+//! [`SFDP`]: <https://www.jedec.org/standards-documents/docs/jesd216b>
+
+use core::time::Duration;
+
+/// Size of the SFDP header, which is followed by the parameter headers.
+pub const SFDP_HEADER_SIZE: usize = 8;
+/// Size of each parameter header.
+pub const PARAMETER_HEADER_SIZE: usize = 8;
+/// Number of DWORDs of the Basic Flash Parameter Table used by this driver.
+pub const BASIC_FLASH_PARAMETER_TABLE_DWORDS: usize = 11;
+
+const SIGNATURE: &[u8; 4] = b"SFDP";
+/// ID of the Basic Flash Parameter Table, the one every SFDP compliant chip has.
+const BASIC_FLASH_PARAMETER_TABLE_ID: u16 = 0xFF00;
+/// Chips bigger than this need 4 bytes addresses.
+const MAX_SIZE_WITH_3_BYTES_ADDRESSES: u32 = 1 << 24;
+
+/// A type of erase operation supported by a NOR flash memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EraseType
+{
+	/// Number of bytes erased by the operation, the address of the erase must be aligned to it.
+	pub size: u32,
+	/// Op code of the command that performs the erase.
+	pub op_code: u8,
+	/// Max time the erase can take.
+	pub max_time: Duration,
+}
+
+/// The geometry, the erase types and the timings of a NOR flash memory chip.
+///
+/// It can be read from the chip by [`SpiNorFlash::from_sfdp`] or defined manually for the chips that don't support
+/// SFDP.
+///
+/// [`SpiNorFlash::from_sfdp`]: super::SpiNorFlash::from_sfdp
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NorFlashParameters
+{
+	/// Size of the memory in bytes.
+	pub memory_size: u32,
+	/// Max number of bytes programmable with a single program operation, and the alignment of the pages.
+	pub page_size: u32,
+	/// The supported erase types, sorted from the smallest to the biggest one.
+	pub erase_types: [Option<EraseType>; 4],
+	/// Max time a page program can take.
+	pub page_program_max_time: Duration,
+	/// Max time a chip erase can take.
+	pub chip_erase_max_time: Duration,
+}
+
+impl NorFlashParameters
+{
+	/// Returns the [`NorFlashParameters`] of a chip like the `W25Q` family, with the provided `memory_size`: 256 bytes
+	/// pages and 4KB, 32KB and 64KB erase types (with their worst timings).
+	pub const fn w25q(memory_size: u32) -> Self
+	{
+		Self {
+			memory_size,
+			page_size: 256,
+			erase_types: [
+				Some(EraseType {
+					size: 4 * 1024,
+					op_code: 0x20,
+					max_time: Duration::from_millis(400),
+				}),
+				Some(EraseType {
+					size: 32 * 1024,
+					op_code: 0x52,
+					max_time: Duration::from_millis(1600),
+				}),
+				Some(EraseType {
+					size: 64 * 1024,
+					op_code: 0xD8,
+					max_time: Duration::from_millis(2000),
+				}),
+				None,
+			],
+			page_program_max_time: Duration::from_millis(3),
+			chip_erase_max_time: Duration::from_secs(200),
+		}
+	}
+
+	/// Returns `true` if the memory is too big to be addressed with 3 bytes.
+	pub fn needs_four_byte_addresses(&self) -> bool
+	{
+		self.memory_size > MAX_SIZE_WITH_3_BYTES_ADDRESSES
+	}
+
+	/// Returns the [`EraseType`] that erases exactly `size` bytes, if the chip supports it.
+	pub fn get_erase_type(&self, size: u32) -> Option<EraseType>
+	{
+		self.erase_types
+			.iter()
+			.flatten()
+			.find(|erase_type| erase_type.size == size)
+			.copied()
+	}
+
+	/// Returns the smallest [`EraseType`] supported by the chip.
+	pub fn get_smallest_erase_type(&self) -> Option<EraseType>
+	{
+		self.erase_types.iter().flatten().next().copied()
+	}
+
+	/// Returns the address and the number of DWORDs of the Basic Flash Parameter Table, found in the provided `header`
+	/// (the first [`SFDP_HEADER_SIZE`] bytes of the SFDP area) and `parameter_headers`.
+	///
+	/// Returns `None` if the signature of the header is wrong or the table isn't there.
+	pub fn find_basic_flash_parameter_table(
+		header: &[u8; SFDP_HEADER_SIZE], parameter_headers: &[u8],
+	) -> Option<(u32, usize)>
+	{
+		if header[0..4] != *SIGNATURE
+		{
+			return None;
+		}
+
+		parameter_headers
+			.chunks_exact(PARAMETER_HEADER_SIZE)
+			.take(Self::get_parameter_headers_count(header))
+			.find(|parameter_header| {
+				u16::from_be_bytes([parameter_header[7], parameter_header[0]]) == BASIC_FLASH_PARAMETER_TABLE_ID
+			})
+			.map(|parameter_header| {
+				let address = u32::from_le_bytes([parameter_header[4], parameter_header[5], parameter_header[6], 0]);
+				(address, parameter_header[3] as usize)
+			})
+	}
+
+	/// Returns the number of parameter headers that follow the provided `header`.
+	pub fn get_parameter_headers_count(header: &[u8; SFDP_HEADER_SIZE]) -> usize
+	{
+		header[6] as usize + 1
+	}
+
+	/// Returns the [`NorFlashParameters`] described by the provided `dwords` of the Basic Flash Parameter Table.
+	///
+	/// At least the first 9 DWORDs (the ones defined by the first version of the standard) are required, otherwise
+	/// `None` is returned. The timings are in the 10th and 11th DWORDs, if they are missing the ones of
+	/// [`NorFlashParameters::w25q`] are used.
+	pub fn parse_basic_flash_parameter_table(dwords: &[u32]) -> Option<Self>
+	{
+		if dwords.len() < 9
+		{
+			return None;
+		}
+
+		let density = dwords[1];
+		let memory_size_in_bits = match density & (1 << 31)
+		{
+			0 => density as u64 + 1,
+			_ => 1_u64.checked_shl(density & !(1 << 31))?,
+		};
+		let memory_size = u32::try_from(memory_size_in_bits / 8).ok()?;
+
+		let mut parameters = Self::w25q(memory_size);
+		let timings = dwords.get(9).copied();
+		let max_time_multiplier = timings.map(|timings| 2 * ((timings & 0b1111) + 1));
+		for (i, erase_type) in parameters.erase_types.iter_mut().enumerate()
+		{
+			let bytes = dwords[7 + i / 2].to_le_bytes();
+			let (size_exponent, op_code) = (bytes[(i % 2) * 2], bytes[(i % 2) * 2 + 1]);
+			let default_max_time = erase_type.map(|erase_type| erase_type.max_time);
+
+			*erase_type = match size_exponent
+			{
+				0 => None,
+				_ => Some(EraseType {
+					size: 1_u32.checked_shl(size_exponent as u32)?,
+					op_code,
+					max_time: match (timings, max_time_multiplier)
+					{
+						(Some(timings), Some(multiplier)) =>
+						{
+							let time = timings >> (4 + 7 * i);
+							let unit = match (time >> 5) & 0b11
+							{
+								0b00 => Duration::from_millis(1),
+								0b01 => Duration::from_millis(16),
+								0b10 => Duration::from_millis(128),
+								_ => Duration::from_secs(1),
+							};
+							unit * ((time & 0b1_1111) + 1) * multiplier
+						},
+						_ => default_max_time.unwrap_or(Duration::from_secs(2)),
+					},
+				}),
+			};
+		}
+		parameters
+			.erase_types
+			.sort_by_key(|erase_type| erase_type.map_or(u32::MAX, |erase_type| erase_type.size));
+
+		if let Some(program_timings) = dwords.get(10).copied()
+		{
+			let multiplier = 2 * ((program_timings & 0b1111) + 1);
+			parameters.page_size = 1 << ((program_timings >> 4) & 0b1111);
+
+			let program_unit = match (program_timings >> 13) & 1
+			{
+				0 => Duration::from_micros(8),
+				_ => Duration::from_micros(64),
+			};
+			parameters.page_program_max_time = program_unit * (((program_timings >> 8) & 0b1_1111) + 1) * multiplier;
+
+			let chip_erase_unit = match (program_timings >> 29) & 0b11
+			{
+				0b00 => Duration::from_millis(16),
+				0b01 => Duration::from_millis(256),
+				0b10 => Duration::from_secs(4),
+				_ => Duration::from_secs(64),
+			};
+			parameters.chip_erase_max_time = chip_erase_unit * (((program_timings >> 24) & 0b1_1111) + 1) * multiplier;
+		}
+
+		Some(parameters)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// Basic Flash Parameter Table of a 16MB chip, with the timings of the `W25Q128JV`.
+	const BASIC_FLASH_PARAMETER_TABLE: [u32; 11] = [
+		0xFFF1_20E5,
+		0x07FF_FFFF,
+		0x6B08_EB44,
+		0xBB42_3B08,
+		0xFFFF_FFFE,
+		0xFF00_FFFF,
+		0xEB40_FFFF,
+		0x520F_200C,
+		0x0000_D810,
+		0x0090_9222,
+		0x3C66_8281,
+	];
+
+	#[test]
+	fn parse_basic_flash_parameter_table()
+	{
+		let parameters = NorFlashParameters::parse_basic_flash_parameter_table(&BASIC_FLASH_PARAMETER_TABLE).unwrap();
+
+		assert_eq!(parameters.memory_size, 16 * 1024 * 1024);
+		assert_eq!(parameters.page_size, 256);
+		assert!(!parameters.needs_four_byte_addresses());
+
+		let erase_sizes = parameters
+			.erase_types
+			.map(|erase_type| erase_type.map(|erase_type| erase_type.size));
+		assert_eq!(erase_sizes, [Some(4 * 1024), Some(32 * 1024), Some(64 * 1024), None]);
+		assert_eq!(parameters.get_erase_type(32 * 1024).unwrap().op_code, 0x52);
+		assert!(parameters.get_erase_type(4 * 1024).unwrap().max_time > Duration::from_millis(45));
+
+		assert!(NorFlashParameters::parse_basic_flash_parameter_table(&BASIC_FLASH_PARAMETER_TABLE[..8]).is_none());
+	}
+
+	#[test]
+	fn find_basic_flash_parameter_table()
+	{
+		let header = *b"SFDP\x06\x01\x01\xFF";
+		let parameter_headers = [
+			0x84, 0x00, 0x01, 0x02, 0x80, 0x00, 0x00, 0xFF, // A different table
+			0x00, 0x06, 0x01, 0x10, 0x30, 0x00, 0x00, 0xFF,
+		];
+
+		assert_eq!(
+			NorFlashParameters::find_basic_flash_parameter_table(&header, &parameter_headers),
+			Some((0x30, 16))
+		);
+		assert_eq!(
+			NorFlashParameters::find_basic_flash_parameter_table(b"SFDX\x06\x01\x01\xFF", &parameter_headers),
+			None
+		);
+	}
+}
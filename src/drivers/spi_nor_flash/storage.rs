@@ -0,0 +1,142 @@
+//! Implementations of the [`embedded_storage`] traits for [`SpiNorFlash`], so that it can be used by all the crates
+//! based on them.
+//!
+//! Differently from the NAND flash memories, the bits of a NOR flash memory can be cleared by multiple writes without
+//! an erase, so [`MultiwriteNorFlash`] is implemented too.
+//!
+//! [`NorFlash::ERASE_SIZE`] is the `ERASE_SIZE` of the [`SpiNorFlash`], which is always one of the erase types of the
+//! chip.
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+use embedded_storage::nor_flash::{
+	check_erase, check_read, check_write, ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind,
+	ReadNorFlash,
+};
+
+use super::{SpiNorFlash, SpiNorFlashError};
+use crate::peripherals::time::system_time::SystemTime;
+
+impl<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs, const ERASE_SIZE: u32> ErrorType
+	for SpiNorFlash<Spi, T, D, ERASE_SIZE>
+{
+	type Error = SpiNorFlashError<Spi>;
+}
+
+impl<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs, const ERASE_SIZE: u32> ReadNorFlash
+	for SpiNorFlash<Spi, T, D, ERASE_SIZE>
+{
+	const READ_SIZE: usize = 1;
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>
+	{
+		check_read(self, offset, bytes.len())?;
+
+		SpiNorFlash::read(self, offset, bytes)
+	}
+
+	fn capacity(&self) -> usize
+	{
+		self.get_parameters().memory_size as usize
+	}
+}
+
+impl<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs, const ERASE_SIZE: u32> NorFlash
+	for SpiNorFlash<Spi, T, D, ERASE_SIZE>
+{
+	const WRITE_SIZE: usize = 1;
+	const ERASE_SIZE: usize = ERASE_SIZE as usize;
+
+	fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error>
+	{
+		check_erase(self, from, to)?;
+
+		self.erase_range(from, to)
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>
+	{
+		check_write(self, offset, bytes.len())?;
+
+		self.program(offset, bytes)
+	}
+}
+
+impl<Spi: SpiDevice<u8>, T: SystemTime, D: DelayNs, const ERASE_SIZE: u32> MultiwriteNorFlash
+	for SpiNorFlash<Spi, T, D, ERASE_SIZE>
+{
+}
+
+impl<Spi: SpiDevice<u8>> NorFlashError for SpiNorFlashError<Spi>
+{
+	fn kind(&self) -> NorFlashErrorKind
+	{
+		match self
+		{
+			Self::NotAligned => NorFlashErrorKind::NotAligned,
+			Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+			_ => NorFlashErrorKind::Other,
+		}
+	}
+}
+
+impl<Spi: SpiDevice<u8>> From<NorFlashErrorKind> for SpiNorFlashError<Spi>
+{
+	fn from(kind: NorFlashErrorKind) -> Self
+	{
+		match kind
+		{
+			NorFlashErrorKind::NotAligned => Self::NotAligned,
+			_ => Self::OutOfBounds,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::hardware::mock::MockSpiNorFlash;
+
+	const MEMORY_SIZE: u32 = 1024 * 1024;
+
+	#[test]
+	fn erase_size_is_an_erase_type_of_the_chip()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE))
+			.unwrap()
+			.with_erase_size::<{ 32 * 1024 }>();
+
+		NorFlash::write(&mut spi_nor_flash, 4096, &[0x12; 4]).unwrap();
+		// The 4KB sector erase isn't used anymore
+		assert!(matches!(
+			NorFlash::erase(&mut spi_nor_flash, 0, 4096),
+			Err(SpiNorFlashError::NotAligned)
+		));
+		NorFlash::erase(&mut spi_nor_flash, 0, 32 * 1024).unwrap();
+		assert_eq!(spi_nor_flash.get_spi_mut().get_byte(4096), 0xFF);
+	}
+
+	#[test]
+	#[should_panic]
+	fn erase_size_not_supported_by_the_chip()
+	{
+		let spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+		let _ = spi_nor_flash.with_erase_size::<{ 16 * 1024 }>();
+	}
+
+	#[test]
+	fn error_kinds()
+	{
+		let mut spi_nor_flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(MEMORY_SIZE)).unwrap();
+
+		let error = NorFlash::erase(&mut spi_nor_flash, 100, 4096).unwrap_err();
+		assert_eq!(error.kind(), NorFlashErrorKind::NotAligned);
+		let error = NorFlash::write(&mut spi_nor_flash, MEMORY_SIZE, &[0]).unwrap_err();
+		assert_eq!(error.kind(), NorFlashErrorKind::OutOfBounds);
+
+		spi_nor_flash.power_down().unwrap();
+		let error = ReadNorFlash::read(&mut spi_nor_flash, 0, &mut [0]).unwrap_err();
+		assert!(matches!(error, SpiNorFlashError::PoweredDown));
+		assert_eq!(error.kind(), NorFlashErrorKind::Other);
+	}
+}
@@ -0,0 +1,84 @@
+use core::fmt::Debug;
+
+use crate::utils::physical_quantities::temperature::Temperature;
+
+/// A sensor that measures a [`Temperature`], like a [`Thermistor`] or a [`MAX6675`] thermocouple.
+///
+/// Code that only needs to read the temperature (like the [`TemperaturePidController`]) should be generic over this
+/// trait, so that it works with any sensor.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::temperature_sensor::TemperatureSensor, utils::physical_quantities::temperature::Temperature};
+/// #
+/// struct ConstantSensor(Temperature);
+///
+/// impl TemperatureSensor for ConstantSensor
+/// {
+///     type Context = ();
+///     type Error = ();
+///
+///     fn read_temperature(&mut self, _: &mut ()) -> Result<Temperature, ()>
+///     {
+///         Ok(self.0)
+///     }
+/// }
+///
+/// fn read_in_fahrenheit<S: TemperatureSensor>(sensor: &mut S, context: &mut S::Context) -> Option<f32>
+/// {
+///     sensor.read_temperature(context).ok().map(|temperature| temperature.as_fahrenheit())
+/// }
+///
+/// let mut sensor = ConstantSensor(Temperature::from_celsius(100.));
+/// assert_eq!(read_in_fahrenheit(&mut sensor, &mut ()), Some(212.));
+/// ```
+///
+/// [`Thermistor`]: super::thermistor::Thermistor
+/// [`MAX6675`]: super::max6675::MAX6675
+/// [`TemperaturePidController`]: crate::features::temperature::TemperaturePidController
+pub trait TemperatureSensor
+{
+	/// What is needed to read the sensor besides the sensor itself, for example the [`Adc`] a [`Thermistor`] is
+	/// connected to, which is usually shared with other pins. It's `()` if the sensor doesn't need anything.
+	///
+	/// [`Adc`]: crate::peripherals::adc::Adc
+	/// [`Thermistor`]: super::thermistor::Thermistor
+	type Context;
+
+	/// The error returned when the temperature can't be read.
+	type Error: Debug;
+
+	/// Reads the current [`Temperature`] measured by the sensor.
+	///
+	/// Returns `Ok(Temperature)` if the read was successful, otherwise returns `Err(Self::Error)`.
+	fn read_temperature(&mut self, context: &mut Self::Context) -> Result<Temperature, Self::Error>;
+
+	/// Returns the [`SensorFault`] that caused the provided `error`, or `None` if the sensor isn't faulty (for example
+	/// if the communication with it failed).
+	///
+	/// The default implementation always returns `None`, for sensors that can't detect their own faults.
+	fn get_sensor_fault(_error: &Self::Error) -> Option<SensorFault>
+	{
+		None
+	}
+}
+
+/// A fault of a [`TemperatureSensor`], which makes the temperature it reads wrong.
+///
+/// Open and short circuits are detected by the sensors themselves (check [`TemperatureSensor::get_sensor_fault`]), while
+/// the other faults are detected by looking at the temperatures read over time (check [`SensorFaultSafety`]).
+///
+/// [`SensorFaultSafety`]: crate::features::temperature::safety::sensor_fault::SensorFaultSafety
+#[derive(enumset::EnumSetType, Debug, Hash)]
+pub enum SensorFault
+{
+	/// The circuit of the sensor is open, for example because its wires are disconnected.
+	OpenCircuit,
+	/// The circuit of the sensor is shorted, for example because its wires touch each other.
+	ShortCircuit,
+	/// The temperature hasn't changed for too long while the heater was heating, so the sensor is probably stuck or
+	/// has fallen out of the heater.
+	StuckReading,
+	/// The temperature changed between two samples more than what's physically possible.
+	ImplausibleJump,
+}
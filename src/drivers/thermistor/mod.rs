@@ -0,0 +1,348 @@
+use core::{fmt::Debug, marker::PhantomData};
+
+use super::temperature_sensor::{SensorFault, TemperatureSensor};
+use crate::{
+	peripherals::adc::{self, Adc, AdcPin, AdcPinExt},
+	utils::{
+		math::Percentage,
+		physical_quantities::temperature::{self, Temperature},
+	},
+};
+
+mod model;
+pub mod presets;
+
+pub use model::*;
+
+/// The `25°C` temperature.
+pub const T0: Temperature = Temperature::from_kelvin(25. + temperature::ZERO_CELSIUS_IN_KELVIN);
+
+/// Where the other resistor of the voltage divider of a [`Thermistor`] is connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Divider
+{
+	/// The other resistor connects the ADC pin to the supply voltage of the divider, and the thermistor connects it to
+	/// ground.
+	PullUp,
+	/// The thermistor connects the ADC pin to the supply voltage of the divider, and the other resistor connects it to
+	/// ground.
+	PullDown,
+}
+
+/// The voltage divider through which a [`Thermistor`] is connected to the microcontroller.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermistorCircuit
+{
+	pub divider: Divider,
+	/// The resistance of the other resistor in the voltage divider, in `Ω`.
+	pub other_resistance: f32,
+	/// The resistance in series with the thermistor (for example a protection resistor or long wires), in `Ω`.
+	pub series_resistance: f32,
+	/// The reference voltage of the ADC divided by the supply voltage of the divider (`1` when they are the same).
+	pub adc_reference_ratio: f32,
+}
+
+impl ThermistorCircuit
+{
+	/// Returns a [`ThermistorCircuit`] where the other resistor of `other_resistance` Ω is a [`Divider::PullUp`].
+	pub const fn pull_up(other_resistance: f32) -> Self
+	{
+		Self {
+			divider: Divider::PullUp,
+			other_resistance,
+			series_resistance: 0.,
+			adc_reference_ratio: 1.,
+		}
+	}
+
+	/// Returns a [`ThermistorCircuit`] where the other resistor of `other_resistance` Ω is a [`Divider::PullDown`].
+	pub const fn pull_down(other_resistance: f32) -> Self
+	{
+		Self {
+			divider: Divider::PullDown,
+			..Self::pull_up(other_resistance)
+		}
+	}
+
+	/// Returns [`Self`] with a `series_resistance` Ω resistance in series with the thermistor.
+	pub const fn with_series_resistance(self, series_resistance: f32) -> Self
+	{
+		Self {
+			series_resistance,
+			..self
+		}
+	}
+
+	/// Returns [`Self`] with an ADC whose reference voltage is `adc_reference_ratio` times the supply voltage of the
+	/// divider.
+	pub const fn with_adc_reference_ratio(self, adc_reference_ratio: f32) -> Self
+	{
+		Self {
+			adc_reference_ratio,
+			..self
+		}
+	}
+
+	/// Returns the resistance of the thermistor in `Ω` when the ADC reads `adc_sample`.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{drivers::thermistor::ThermistorCircuit, utils::math::Percentage};
+	/// #
+	/// let circuit = ThermistorCircuit::pull_down(4_700.).with_series_resistance(100.);
+	/// let resistance = circuit.get_thermistor_resistance(Percentage::from_0_to_1(0.25).unwrap());
+	///
+	/// assert!((resistance - 14_000.).abs() < 0.1);
+	/// ```
+	pub fn get_thermistor_resistance(&self, adc_sample: Percentage) -> f32
+	{
+		// Avoid divisions by zero below, which happen when the ADC reads one of its rails
+		let divider_ratio = (adc_sample.into_0_to_1() * self.adc_reference_ratio).clamp(0.001, 0.999);
+
+		// Formulas to calculate resistance from voltage divider
+		let resistance = match self.divider
+		{
+			Divider::PullUp => self.other_resistance * (divider_ratio / (1. - divider_ratio)),
+			Divider::PullDown => self.other_resistance * ((1. - divider_ratio) / divider_ratio),
+		};
+
+		// The logarithm of the resistance isn't defined for the non-positive values that a wrong series resistance gives
+		(resistance - self.series_resistance).max(1.)
+	}
+}
+
+/// A thermistor [`connected to the microcontroller using a voltage divider`].
+///
+/// When the ADC reads a voltage too close to one of its rails, the thermistor is considered disconnected or shorted and
+/// no temperature is returned (check [`Self::with_rail_margin`]).
+///
+/// [`connected to the microcontroller using a voltage divider`]: https://circuitdigest.com/microcontroller-projects/interfacing-Thermistor-with-arduino
+pub struct Thermistor<A: Adc, P: AdcPin<A>>
+{
+	pin: P,
+	_adc: PhantomData<A>,
+	model: ThermistorModel,
+	circuit: ThermistorCircuit,
+	rail_margin: Percentage,
+}
+
+impl<A: Adc, P: AdcPin<A>> Thermistor<A, P>
+{
+	/// The default value of [`Self::with_rail_margin`], which with a `4.7kΩ` pull-up and a `100kΩ` thermistor is
+	/// reached below `-20°C` and above `400°C`.
+	pub const DEFAULT_RAIL_MARGIN: f32 = 0.005;
+
+	/// Returns a [`Thermistor`] that is connected to the microcontroller through the provided `pin` in a voltage divider setup,
+	/// that has the provided `beta` constant and has a `resistance_at_t0` resistance at [`T0`].
+	/// The other resistor in the voltage divider is of `other_resistance` Ω and is a [`Divider::PullUp`].
+	pub fn new(pin: P, beta: u32, resistance_at_t0: u32, other_resistance: u32) -> Self
+	{
+		let model = ThermistorModel::Beta {
+			beta: beta as f32,
+			resistance_at_t0: resistance_at_t0 as f32,
+		};
+
+		Self::from_model(pin, model, ThermistorCircuit::pull_up(other_resistance as f32))
+	}
+
+	/// Returns a [`Thermistor`] that is connected to the microcontroller through the provided `pin` using the provided
+	/// `circuit`, and whose resistance is converted to a temperature using the provided `model`.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{
+	/// #     drivers::thermistor::{presets, SteinhartHart, Thermistor, ThermistorCircuit, ThermistorModel},
+	/// #     hardware::mock::{MockAdc, MockAdcPin},
+	/// # };
+	/// #
+	/// // The voltage divider is powered by 5V, but the ADC measures up to 3.3V
+	/// let circuit = ThermistorCircuit::pull_up(4_700.).with_adc_reference_ratio(3.3 / 5.);
+	/// let model = ThermistorModel::SteinhartHart(SteinhartHart::fit(presets::SEMITEC_104GT_2).unwrap());
+	/// let mut thermistor = Thermistor::from_model(MockAdcPin::Ok { value: 1_000 }, model, circuit);
+	///
+	/// let temperature = thermistor.read_temperature(&mut MockAdc).unwrap().as_celsius();
+	/// assert!((temperature - 167.).abs() < 1.);
+	/// ```
+	pub fn from_model(pin: P, model: ThermistorModel, circuit: ThermistorCircuit) -> Self
+	{
+		Self {
+			pin,
+			_adc: PhantomData,
+			model,
+			circuit,
+			rail_margin: Percentage::from_0_to_1(Self::DEFAULT_RAIL_MARGIN).unwrap(),
+		}
+	}
+
+	/// Returns [`Self`] considering the thermistor disconnected or shorted when the ADC reads less than `rail_margin`
+	/// or more than `100% - rail_margin` of its reference voltage.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{
+	/// #     drivers::thermistor::{ReadError, Thermistor},
+	/// #     hardware::mock::{MockAdc, MockAdcPin},
+	/// #     utils::math::Percentage,
+	/// # };
+	/// #
+	/// let pin = MockAdcPin::Ok { value: 4_000 };
+	/// let mut thermistor = Thermistor::new(pin, 3950, 100_000, 4_700);
+	/// assert!(thermistor.read_temperature(&mut MockAdc).is_ok());
+	///
+	/// let mut thermistor = thermistor.with_rail_margin(Percentage::from_0_to_100(5.).unwrap());
+	/// assert!(matches!(thermistor.read_temperature(&mut MockAdc), Err(ReadError::OpenCircuit)));
+	/// ```
+	pub fn with_rail_margin(self, rail_margin: Percentage) -> Self
+	{
+		Self { rail_margin, ..self }
+	}
+
+	/// Returns the model used to convert the resistance of the thermistor to a temperature.
+	pub fn get_model(&self) -> ThermistorModel
+	{
+		self.model
+	}
+
+	/// Returns the circuit that connects the thermistor to the microcontroller.
+	pub fn get_circuit(&self) -> ThermistorCircuit
+	{
+		self.circuit
+	}
+
+	/// Reads the current [`Temperature`] from the thermistor.
+	///
+	/// Returns `Ok(Temperature)` if the read was successfull, otherwise `Err(ReadError)`.
+	pub fn read_temperature(&mut self, adc: &mut A) -> Result<Temperature, ReadError<A, P>>
+	{
+		let adc_sample = self.pin.read_percentage(adc).map_err(ReadError::Adc)?;
+		self.check_rails(adc_sample)?;
+
+		Ok(self.convert_adc_sample_to_temperature(adc_sample))
+	}
+
+	/// Returns an error if the `adc_sample` is within [`Self::rail_margin`] of one of the rails of the ADC.
+	fn check_rails(&self, adc_sample: Percentage) -> Result<(), ReadError<A, P>>
+	{
+		let (low_rail_error, high_rail_error) = match self.circuit.divider
+		{
+			Divider::PullUp => (ReadError::ShortCircuit, ReadError::OpenCircuit),
+			Divider::PullDown => (ReadError::OpenCircuit, ReadError::ShortCircuit),
+		};
+
+		let adc_sample = adc_sample.into_0_to_1();
+		let rail_margin = self.rail_margin.into_0_to_1();
+		// When the reference of the ADC is higher than the supply of the divider, the high rail is never read by the ADC
+		let divider_ratio = adc_sample * self.circuit.adc_reference_ratio;
+
+		if adc_sample <= rail_margin
+		{
+			Err(low_rail_error)
+		}
+		else if adc_sample >= 1. - rail_margin || divider_ratio >= 1. - rail_margin
+		{
+			Err(high_rail_error)
+		}
+		else
+		{
+			Ok(())
+		}
+	}
+
+	/// Converts an ADC sample from the [`Self::pin`] to a [`Temperature`] measurement.
+	///
+	/// Thanks to: https://dev.to/apollolabsbin/esp32-embedded-rust-at-the-hal-analog-temperature-sensing-using-the-adc-3106
+	fn convert_adc_sample_to_temperature(&self, adc_sample: Percentage) -> Temperature
+	{
+		let resistance = self.circuit.get_thermistor_resistance(adc_sample);
+		self.model.get_temperature(resistance)
+	}
+}
+
+impl<A: Adc, P: AdcPin<A>> TemperatureSensor for Thermistor<A, P>
+{
+	type Context = A;
+	type Error = ReadError<A, P>;
+
+	fn read_temperature(&mut self, adc: &mut A) -> Result<Temperature, Self::Error>
+	{
+		Thermistor::read_temperature(self, adc)
+	}
+
+	fn get_sensor_fault(error: &Self::Error) -> Option<SensorFault>
+	{
+		match error
+		{
+			ReadError::Adc(_) => None,
+			ReadError::OpenCircuit => Some(SensorFault::OpenCircuit),
+			ReadError::ShortCircuit => Some(SensorFault::ShortCircuit),
+		}
+	}
+}
+
+/// An error that occurred when [`reading the temperature`] of a [`Thermistor`].
+///
+/// [`reading the temperature`]: Thermistor::read_temperature
+pub enum ReadError<A: Adc, P: AdcPin<A>>
+{
+	/// It has been impossible to read the ADC.
+	Adc(adc::ReadPercentageError<A, P>),
+	/// The ADC reads the rail of the other resistor, so the thermistor is disconnected.
+	OpenCircuit,
+	/// The ADC reads the rail the thermistor is connected to, so the thermistor is shorted.
+	ShortCircuit,
+}
+
+impl<A: Adc, P: AdcPin<A>> Debug for ReadError<A, P>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Adc(arg0) => f.debug_tuple("Adc").field(arg0).finish(),
+			Self::OpenCircuit => write!(f, "OpenCircuit"),
+			Self::ShortCircuit => write!(f, "ShortCircuit"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::hardware::mock::{MockAdc, MockAdcPin};
+
+	fn read(circuit: ThermistorCircuit, value: u16) -> Result<Temperature, ReadError<MockAdc, MockAdcPin>>
+	{
+		let model = presets::VISHAY_NTCLE100E3104;
+		Thermistor::from_model(MockAdcPin::Ok { value }, model, circuit).read_temperature(&mut MockAdc)
+	}
+
+	#[test]
+	fn rails_depend_on_divider()
+	{
+		let pull_up = ThermistorCircuit::pull_up(4_700.);
+		assert!(matches!(
+			read(pull_up, MockAdc::MAX_READABLE_VALUE),
+			Err(ReadError::OpenCircuit)
+		));
+		assert!(matches!(read(pull_up, 0), Err(ReadError::ShortCircuit)));
+		assert!(matches!(read(pull_up, 1), Err(ReadError::ShortCircuit)));
+
+		let pull_down = ThermistorCircuit::pull_down(4_700.);
+		assert!(matches!(
+			read(pull_down, MockAdc::MAX_READABLE_VALUE),
+			Err(ReadError::ShortCircuit)
+		));
+		assert!(matches!(read(pull_down, 0), Err(ReadError::OpenCircuit)));
+
+		// The hotter the thermistor, the lower its resistance, so the higher the voltage read with a pull-down
+		let cold = read(pull_down, 200).unwrap();
+		let hot = read(pull_down, 3_000).unwrap();
+		assert!(cold < hot);
+
+		// With an ADC reference higher than the supply of the divider, the open circuit is read below the maximum value
+		let higher_reference = pull_up.with_adc_reference_ratio(5. / 3.3);
+		assert!(matches!(read(higher_reference, 2_700), Err(ReadError::OpenCircuit)));
+		assert!(read(higher_reference, 2_600).is_ok());
+	}
+}
@@ -0,0 +1,332 @@
+#[allow(unused_imports)]
+#[cfg(not(std))]
+use micromath::F32Ext;
+
+use super::T0;
+use crate::utils::physical_quantities::temperature::Temperature;
+
+/// The equation used by a [`Thermistor`] to convert its resistance to a [`Temperature`].
+///
+/// [`Thermistor`]: super::Thermistor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThermistorModel
+{
+	/// The [`beta equation`], which is accurate only in a small range around [`T0`].
+	///
+	/// [`beta equation`]: https://en.wikipedia.org/wiki/Thermistor#B_or_%CE%B2_parameter_equation
+	Beta
+	{
+		/// The `beta` constant of the thermistor.
+		beta: f32,
+		/// The resistance of the thermistor at [`T0`], in `Ω`.
+		resistance_at_t0: f32,
+	},
+	/// The [`Steinhart–Hart equation`], which is accurate across the whole range its coefficients were fitted on.
+	///
+	/// [`Steinhart–Hart equation`]: https://en.wikipedia.org/wiki/Steinhart%E2%80%93Hart_equation
+	SteinhartHart(SteinhartHart),
+	/// A table of resistances measured at known temperatures.
+	LookupTable(ThermistorTable),
+}
+
+impl ThermistorModel
+{
+	/// Returns the [`Temperature`] of a thermistor whose resistance is `resistance` Ω.
+	pub fn get_temperature(&self, resistance: f32) -> Temperature
+	{
+		match self
+		{
+			Self::Beta { beta, resistance_at_t0 } =>
+			{
+				// The difference of the logarithms is used instead of the logarithm of the ratio because the `ln`
+				// approximation of `micromath` is much less precise below `1`
+				let log_ratio = f32::ln(resistance) - f32::ln(*resistance_at_t0);
+				let temperature = 1. / (log_ratio / beta + (1. / T0.as_kelvin()));
+				Temperature::from_kelvin(temperature)
+			},
+			Self::SteinhartHart(coefficients) => coefficients.get_temperature(resistance),
+			Self::LookupTable(table) => table.get_temperature(resistance),
+		}
+	}
+}
+
+/// The coefficients of the [`Steinhart–Hart equation`] `1/T = A + B·ln(R) + C·ln(R)³`, where `T` is in Kelvin and `R`
+/// in `Ω`.
+///
+/// [`Steinhart–Hart equation`]: https://en.wikipedia.org/wiki/Steinhart%E2%80%93Hart_equation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SteinhartHart
+{
+	pub a: f32,
+	pub b: f32,
+	pub c: f32,
+}
+
+impl SteinhartHart
+{
+	/// Returns the coefficients that make the equation pass through the three provided `(temperature, resistance)`
+	/// calibration points.
+	///
+	/// The points should be spread across the range the thermistor will be used in (for example `25°C`, `150°C` and
+	/// `250°C` for the hotend of a 3D printer).
+	///
+	/// Returns `None` if the points don't describe a thermistor (for example if two of them have the same resistance).
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{
+	/// #     drivers::thermistor::SteinhartHart,
+	/// #     utils::physical_quantities::temperature::Temperature,
+	/// # };
+	/// #
+	/// let coefficients = SteinhartHart::fit([
+	///     (Temperature::from_celsius(25.), 100_000.),
+	///     (Temperature::from_celsius(150.), 1_641.9),
+	///     (Temperature::from_celsius(250.), 226.15),
+	/// ])
+	/// .unwrap();
+	///
+	/// assert!((coefficients.get_temperature(1_641.9).as_celsius() - 150.).abs() < 0.1);
+	///
+	/// let same_resistance = SteinhartHart::fit([
+	///     (Temperature::from_celsius(25.), 100_000.),
+	///     (Temperature::from_celsius(150.), 100_000.),
+	///     (Temperature::from_celsius(250.), 226.15),
+	/// ]);
+	/// assert_eq!(same_resistance, None);
+	/// ```
+	pub fn fit(calibration_points: [(Temperature, f32); 3]) -> Option<Self>
+	{
+		let [(t1, r1), (t2, r2), (t3, r3)] = calibration_points;
+		let (l1, l2, l3) = (f32::ln(r1), f32::ln(r2), f32::ln(r3));
+		let (y1, y2, y3) = (1. / t1.as_kelvin(), 1. / t2.as_kelvin(), 1. / t3.as_kelvin());
+
+		let gamma2 = (y2 - y1) / (l2 - l1);
+		let gamma3 = (y3 - y1) / (l3 - l1);
+		let c = (gamma3 - gamma2) / (l3 - l2) / (l1 + l2 + l3);
+		let b = gamma2 - c * (l1 * l1 + l1 * l2 + l2 * l2);
+		let a = y1 - (b + l1 * l1 * c) * l1;
+
+		if !(a.is_finite() && b.is_finite() && c.is_finite())
+		{
+			return None;
+		}
+
+		Some(Self { a, b, c })
+	}
+
+	/// Returns the [`Temperature`] of a thermistor whose resistance is `resistance` Ω.
+	pub fn get_temperature(&self, resistance: f32) -> Temperature
+	{
+		let log_resistance = f32::ln(resistance);
+		let inverse_temperature =
+			self.a + self.b * log_resistance + self.c * log_resistance * log_resistance * log_resistance;
+
+		Temperature::from_kelvin(1. / inverse_temperature)
+	}
+}
+
+/// The resistance of a thermistor measured at a known [`Temperature`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermistorTableEntry
+{
+	pub temperature: Temperature,
+	/// The resistance in `Ω`.
+	pub resistance: f32,
+}
+
+/// A resistance/temperature table of a thermistor, usually copied from its datasheet.
+///
+/// The temperature between two entries is interpolated linearly on the logarithm of the resistance, which follows the
+/// curve of an NTC thermistor much more closely than a linear interpolation on the resistance itself.
+/// Outside of the table, the temperature is extrapolated from the first or the last two entries.
+///
+/// The values of some common thermistors are in the [`presets`] module.
+///
+/// [`presets`]: super::presets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermistorTable
+{
+	entries: &'static [ThermistorTableEntry],
+}
+
+impl ThermistorTable
+{
+	/// Returns a [`ThermistorTable`] made of the provided `entries`, which must be sorted by temperature and whose
+	/// resistance must be strictly monotonic.
+	///
+	/// # Panics
+	/// If there are less than two `entries`.
+	pub const fn new(entries: &'static [ThermistorTableEntry]) -> Self
+	{
+		assert!(entries.len() >= 2, "a thermistor table needs at least two entries");
+
+		Self { entries }
+	}
+
+	/// Returns the entries of the table.
+	pub fn get_entries(&self) -> &'static [ThermistorTableEntry]
+	{
+		self.entries
+	}
+
+	/// Returns the [`Temperature`] of a thermistor whose resistance is `resistance` Ω.
+	///
+	/// # Examples
+	/// ```
+	/// # use a13c_embedded::{
+	/// #     drivers::thermistor::{ThermistorTable, ThermistorTableEntry},
+	/// #     utils::physical_quantities::temperature::Temperature,
+	/// # };
+	/// #
+	/// static ENTRIES: [ThermistorTableEntry; 2] = [
+	///     ThermistorTableEntry { temperature: Temperature::from_kelvin(300.), resistance: 1000. },
+	///     ThermistorTableEntry { temperature: Temperature::from_kelvin(400.), resistance: 10. },
+	/// ];
+	/// let table = ThermistorTable::new(&ENTRIES);
+	///
+	/// // 100Ω is halfway between 1000Ω and 10Ω on a logarithmic scale
+	/// assert!((table.get_temperature(100.).as_kelvin() - 350.).abs() < 0.5);
+	/// assert!((table.get_temperature(1.).as_kelvin() - 450.).abs() < 0.5);
+	/// ```
+	pub fn get_temperature(&self, resistance: f32) -> Temperature
+	{
+		let is_between = |entries: &&[ThermistorTableEntry]| {
+			(entries[0].resistance - resistance) * (entries[1].resistance - resistance) <= 0.
+		};
+
+		let segment = self.entries.windows(2).find(is_between).unwrap_or_else(|| {
+			let first = &self.entries[..2];
+			let is_before_first = (first[1].resistance - first[0].resistance) * (resistance - first[0].resistance) < 0.;
+
+			if is_before_first
+			{
+				first
+			}
+			else
+			{
+				&self.entries[self.entries.len() - 2..]
+			}
+		});
+
+		let (start, end) = (segment[0], segment[1]);
+		// Like in `ThermistorModel::Beta`, the difference of the logarithms is more precise than the logarithm of the ratio
+		let log_start_resistance = f32::ln(start.resistance);
+		let fraction = (f32::ln(resistance) - log_start_resistance) / (f32::ln(end.resistance) - log_start_resistance);
+		let temperature =
+			start.temperature.as_kelvin() + fraction * (end.temperature.as_kelvin() - start.temperature.as_kelvin());
+
+		Temperature::from_kelvin(temperature)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::drivers::thermistor::presets;
+
+	/// Returns the resistance of a thermistor with the provided `beta` constant and a `100kΩ` resistance at [`T0`].
+	fn beta_resistance(beta: f32, temperature: Temperature) -> f32
+	{
+		100_000. * f32::exp(beta * (1. / temperature.as_kelvin() - 1. / T0.as_kelvin()))
+	}
+
+	/// Returns the resistance at which the `steinhart_hart` equation gives the provided `temperature`.
+	fn get_resistance(steinhart_hart: &SteinhartHart, temperature: Temperature) -> f32
+	{
+		// The temperature decreases when the resistance increases, so the logarithm of the resistance is bisected
+		let (mut low, mut high) = (f32::ln(1.), f32::ln(10_000_000.));
+		for _ in 0..50
+		{
+			let middle = (low + high) / 2.;
+			match steinhart_hart.get_temperature(f32::exp(middle)) > temperature
+			{
+				true => low = middle,
+				false => high = middle,
+			}
+		}
+
+		f32::exp((low + high) / 2.)
+	}
+
+	#[test]
+	fn fitted_steinhart_hart_is_more_accurate_than_beta()
+	{
+		let steinhart_hart = ThermistorModel::SteinhartHart(SteinhartHart::fit(presets::SEMITEC_104GT_2).unwrap());
+		let beta = ThermistorModel::Beta {
+			beta: 4267.,
+			resistance_at_t0: 100_000.,
+		};
+
+		// The datasheet points that haven't been used for the fit: the `100kΩ` at `25°C` and the resistance at `85°C`
+		// given by the `B25/85` constant of `4267K`
+		let temperature_85 = Temperature::from_celsius(85.);
+		for (temperature, resistance) in [(T0, 100_000.), (temperature_85, beta_resistance(4267., temperature_85))]
+		{
+			let error = steinhart_hart.get_temperature(resistance).as_celsius() - temperature.as_celsius();
+			assert!(error.abs() < 0.2, "{error}");
+		}
+
+		// The beta equation instead drifts away from the datasheet at high temperatures
+		let (temperature, resistance) = presets::SEMITEC_104GT_2[2];
+		let error = beta.get_temperature(resistance).as_celsius() - temperature.as_celsius();
+		assert!(error > 10., "{error}");
+	}
+
+	#[test]
+	fn steinhart_hart_matches_b25_100_of_epcos()
+	{
+		let steinhart_hart = SteinhartHart::fit(presets::EPCOS_100K_B57560G104F).unwrap();
+
+		let temperature = Temperature::from_celsius(100.);
+		let resistance = beta_resistance(4092., temperature);
+		let error = steinhart_hart.get_temperature(resistance).as_celsius() - temperature.as_celsius();
+		assert!(error.abs() < 0.2, "{error}");
+	}
+
+	#[test]
+	fn lookup_table_interpolates_between_entries()
+	{
+		let steinhart_hart = SteinhartHart::fit(presets::EPCOS_100K_B57560G104F).unwrap();
+		// A table with an entry every `10°C`, like the ones in the datasheets
+		let entries = (0..=30)
+			.map(|index| {
+				let temperature = Temperature::from_celsius(index as f32 * 10.);
+				ThermistorTableEntry {
+					temperature,
+					resistance: get_resistance(&steinhart_hart, temperature),
+				}
+			})
+			.collect::<Vec<_>>();
+		let table = ThermistorModel::LookupTable(ThermistorTable::new(entries.leak()));
+
+		for resistance in [200_000., 100_000., 9_000., 1_000., 500., 120.]
+		{
+			let expected = steinhart_hart.get_temperature(resistance).as_celsius();
+			let interpolated = table.get_temperature(resistance).as_celsius();
+
+			assert!(
+				(interpolated - expected).abs() < 0.3,
+				"{resistance}Ω: {interpolated} != {expected}"
+			);
+		}
+
+		// Extrapolated from the first and the last two entries
+		assert!(table.get_temperature(500_000.).as_celsius() < 0.);
+		assert!(table.get_temperature(50.).as_celsius() > 300.);
+	}
+
+	#[test]
+	fn presets_are_100k_thermistors()
+	{
+		for model in [
+			ThermistorModel::SteinhartHart(SteinhartHart::fit(presets::EPCOS_100K_B57560G104F).unwrap()),
+			ThermistorModel::SteinhartHart(SteinhartHart::fit(presets::SEMITEC_104GT_2).unwrap()),
+			presets::VISHAY_NTCLE100E3104,
+		]
+		{
+			assert!((model.get_temperature(100_000.).as_celsius() - 25.).abs() < 0.2);
+		}
+	}
+}
@@ -0,0 +1,44 @@
+//! The published values of common `100kΩ` NTC thermistors, from their datasheets.
+//!
+//! The thermistors whose datasheet gives resistances across the range of a 3D printer have three of them, to
+//! [`fit`](super::SteinhartHart::fit) a [`SteinhartHart`](super::SteinhartHart) model, while the others only have
+//! their [`ThermistorModel::Beta`].
+//!
+//! # Examples
+//! ```
+//! # use a13c_embedded::drivers::thermistor::{presets, SteinhartHart, ThermistorModel};
+//! #
+//! let model = ThermistorModel::SteinhartHart(SteinhartHart::fit(presets::SEMITEC_104GT_2).unwrap());
+//!
+//! assert!((model.get_temperature(100_000.).as_celsius() - 25.).abs() < 0.1);
+//! ```
+
+use super::ThermistorModel;
+use crate::utils::physical_quantities::temperature::{Temperature, ZERO_CELSIUS_IN_KELVIN};
+
+/// The resistances of the `EPCOS B57560G104F` thermistor (R/T characteristic `8016`) at `25°C`, `150°C` and `250°C`.
+pub const EPCOS_100K_B57560G104F: [(Temperature, f32); 3] = [
+	(celsius(25.), 100_000.),
+	(celsius(150.), 1_641.9),
+	(celsius(250.), 226.15),
+];
+
+/// The resistances of the `ATC Semitec 104GT-2` thermistor at `20°C`, `150°C` and `300°C`.
+pub const SEMITEC_104GT_2: [(Temperature, f32); 3] = [
+	(celsius(20.), 126_800.),
+	(celsius(150.), 1_360.),
+	(celsius(300.), 80.65),
+];
+
+/// The `Vishay NTCLE100E3104` thermistor, whose datasheet gives its `B25/85` constant of `4190K`, so it's less accurate
+/// far from the `25°C`-`85°C` range.
+pub const VISHAY_NTCLE100E3104: ThermistorModel = ThermistorModel::Beta {
+	beta: 4190.,
+	resistance_at_t0: 100_000.,
+};
+
+/// Returns a [`Temperature`] of `degrees` °C.
+const fn celsius(degrees: f32) -> Temperature
+{
+	Temperature::from_kelvin(degrees + ZERO_CELSIUS_IN_KELVIN)
+}
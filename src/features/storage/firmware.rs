@@ -0,0 +1,807 @@
+//! A manager of 2 firmware image slots (`A` and `B`) on any flash memory that implements [`NorFlash`], like the
+//! [`SpiNorFlash`] and the [`SpiFlashMemory`] drivers, used to stage the over-the-air updates.
+//!
+//! ## Layout
+//! Each slot starts with a header sector, followed by the image. The header contains the version, the size, the
+//! CRC32 and the SHA-256 hash of the image, and it's written only after the whole image has been written and
+//! verified, so a slot with a valid header always contains a complete image. After the header there are some flags
+//! (each one in its own [`NorFlash::WRITE_SIZE`] bytes, so they can be set without erasing the sector) that keep the
+//! [`SlotState`] of the image.
+//!
+//! ## Test boot
+//! A new image is first booted as a test: if it isn't [`confirmed`] before the next boot, the bootloader rolls back to
+//! the previous confirmed image.
+//! 1. The application writes the new image in the slot that isn't active ([`begin_update`], [`write_chunk`] and
+//!    [`finish_update`]) and calls [`request_test_boot`].
+//! 2. The bootloader calls [`select_boot_slot`], which verifies the new image and boots it.
+//! 3. If the new image works, it calls [`confirm`]. Otherwise, at the next boot [`select_boot_slot`] rejects it and
+//!    boots the previous image again.
+//!
+//! [`SpiNorFlash`]: crate::drivers::spi_nor_flash::SpiNorFlash
+//! [`SpiFlashMemory`]: crate::drivers::spi_flash_memory::SpiFlashMemory
+//! [`confirmed`]: FirmwareSlots::confirm
+//! [`confirm`]: FirmwareSlots::confirm
+//! [`begin_update`]: FirmwareSlots::begin_update
+//! [`write_chunk`]: FirmwareSlots::write_chunk
+//! [`finish_update`]: FirmwareSlots::finish_update
+//! [`request_test_boot`]: FirmwareSlots::request_test_boot
+//! [`select_boot_slot`]: FirmwareSlots::select_boot_slot
+
+use core::fmt::Debug;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::utils::algorithms::{
+	crc::Crc32,
+	sha256::{Sha256, SHA256_SIZE},
+};
+
+const HEADER_MAGIC: u32 = u32::from_le_bytes(*b"FWS1");
+/// Size of the header of a slot, without its flags.
+const HEADER_SIZE: usize = 56;
+/// Size of the chunks used to read the images from the flash memory.
+const CHUNK_SIZE: usize = 64;
+
+/// One of the 2 slots of a [`FirmwareSlots`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Slot
+{
+	A,
+	B,
+}
+
+impl Slot
+{
+	/// Returns the other slot.
+	pub fn other(self) -> Self
+	{
+		match self
+		{
+			Slot::A => Slot::B,
+			Slot::B => Slot::A,
+		}
+	}
+}
+
+/// The state of the image in a slot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlotState
+{
+	/// The slot doesn't contain a complete image.
+	Empty,
+	/// The slot contains a complete image, that has never been booted.
+	Ready,
+	/// The image will be booted as a test at the next boot.
+	TestRequested,
+	/// The image has been booted as a test, and it's waiting to be confirmed.
+	Testing,
+	/// The image works.
+	Confirmed,
+	/// The image has been rolled back (or it was corrupted), so it won't be booted anymore.
+	Rejected,
+}
+
+/// The header of a slot, that describes its image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FirmwareHeader
+{
+	/// Version of the image.
+	pub version: u32,
+	/// Size in bytes of the image.
+	pub size: u32,
+	/// `CRC-32/ISO-HDLC` checksum of the image.
+	pub crc32: u32,
+	/// SHA-256 hash of the image.
+	pub sha256: [u8; SHA256_SIZE],
+	/// Increased every time an image is written, to know which one is the newest.
+	sequence: u32,
+}
+
+impl FirmwareHeader
+{
+	fn to_bytes(self) -> [u8; HEADER_SIZE]
+	{
+		let mut bytes = [0; HEADER_SIZE];
+		bytes[0..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+		bytes[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+		bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+		bytes[12..16].copy_from_slice(&self.size.to_le_bytes());
+		bytes[16..20].copy_from_slice(&self.crc32.to_le_bytes());
+		bytes[20..52].copy_from_slice(&self.sha256);
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..52]);
+		bytes[52..56].copy_from_slice(&crc.finish().to_le_bytes());
+
+		bytes
+	}
+
+	fn from_bytes(bytes: &[u8; HEADER_SIZE]) -> Option<Self>
+	{
+		let word = |i: usize| u32::from_le_bytes(bytes[i..(i + 4)].try_into().unwrap());
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..52]);
+		if word(0) != HEADER_MAGIC || crc.finish() != word(52)
+		{
+			return None;
+		}
+
+		Some(Self {
+			sequence: word(4),
+			version: word(8),
+			size: word(12),
+			crc32: word(16),
+			sha256: bytes[20..52].try_into().unwrap(),
+		})
+	}
+}
+
+/// The flags after the header of a slot, which can be set once after each erase.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Flag
+{
+	TestRequested = 0,
+	Booted = 1,
+	Confirmed = 2,
+	Rejected = 3,
+}
+
+/// Manages the 2 firmware image slots of `slot_size` bytes of a [`NorFlash`].
+///
+/// Check [`module's documentation`](self) for more info.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{features::storage::firmware::*, hardware::mock::MockNorFlash};
+/// #
+/// let mut buffer = [0; 256];
+/// let mut firmware_slots = FirmwareSlots::new(MockNorFlash::<1, 4096>::new(64 * 1024), 0, 32 * 1024, &mut buffer);
+///
+/// // In the application
+/// let image = [0x42; 10_000];
+/// let slot = firmware_slots.begin_update(2, image.len() as u32).unwrap();
+/// for chunk in image.chunks(1000)
+/// {
+///     firmware_slots.write_chunk(chunk).unwrap();
+/// }
+/// firmware_slots.finish_update(None).unwrap();
+/// firmware_slots.request_test_boot(slot).unwrap();
+///
+/// // In the bootloader
+/// assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(slot));
+///
+/// // In the new application
+/// firmware_slots.confirm().unwrap();
+/// assert_eq!(firmware_slots.get_active_slot().unwrap(), Some(slot));
+/// assert_eq!(firmware_slots.get_slot_state(slot).unwrap(), SlotState::Confirmed);
+/// ```
+pub struct FirmwareSlots<'a, F: NorFlash>
+{
+	flash: F,
+	start: u32,
+	slot_size: u32,
+	buffer: &'a mut [u8],
+
+	update: Option<Update>,
+}
+
+/// The state of the image being written.
+struct Update
+{
+	slot: Slot,
+	header: FirmwareHeader,
+	written: u32,
+	/// Number of bytes of the image already written in the flash memory.
+	flushed: u32,
+	buffer_used: usize,
+	crc32: Crc32,
+	sha256: Sha256,
+}
+
+impl<'a, F: NorFlash> FirmwareSlots<'a, F>
+{
+	/// Returns a [`FirmwareSlots`] that uses the 2 slots of `slot_size` bytes starting from the `start` address of the
+	/// `flash`. The `buffer` is used to write the images, so the bigger it is the fewer writes are needed.
+	///
+	/// # Panics
+	/// Panics if `start` or `slot_size` aren't multiples of [`NorFlash::ERASE_SIZE`], if a slot doesn't have space for
+	/// the header and at least a sector of image, if the 2 slots don't fit in the flash memory, if `buffer.len()` isn't
+	/// a multiple of [`NorFlash::WRITE_SIZE`], if the header doesn't fit in `buffer` or if [`NorFlash::READ_SIZE`]
+	/// isn't 1.
+	pub fn new(flash: F, start: u32, slot_size: u32, buffer: &'a mut [u8]) -> Self
+	{
+		assert!(F::READ_SIZE == 1);
+		assert!((slot_size as usize).is_multiple_of(F::ERASE_SIZE) && slot_size as usize >= 2 * F::ERASE_SIZE);
+		assert!((start as usize).is_multiple_of(F::ERASE_SIZE));
+		assert!(start as usize + 2 * slot_size as usize <= flash.capacity());
+		assert!(buffer.len().is_multiple_of(F::WRITE_SIZE));
+		assert!(buffer.len() >= HEADER_SIZE.next_multiple_of(F::WRITE_SIZE));
+		assert!(Self::get_flag_offset(Flag::Rejected) as usize + F::WRITE_SIZE <= F::ERASE_SIZE);
+
+		Self {
+			flash,
+			start,
+			slot_size,
+			buffer,
+			update: None,
+		}
+	}
+
+	/// Returns the max size in bytes of an image.
+	pub fn get_max_image_size(&self) -> u32
+	{
+		self.slot_size - F::ERASE_SIZE as u32
+	}
+
+	/// Returns `Ok(Some(header))` with the header of the image in `slot`, `Ok(None)` if the slot is empty, otherwise
+	/// returns `Err(...)`.
+	pub fn get_header(&mut self, slot: Slot) -> Result<Option<FirmwareHeader>, Error<F>>
+	{
+		let mut bytes = [0; HEADER_SIZE];
+		self.flash
+			.read(self.get_slot_start(slot), &mut bytes)
+			.map_err(Error::Flash)?;
+
+		Ok(FirmwareHeader::from_bytes(&bytes))
+	}
+
+	/// Returns `Ok(state)` with the state of the image in `slot`, otherwise returns `Err(...)`.
+	pub fn get_slot_state(&mut self, slot: Slot) -> Result<SlotState, Error<F>>
+	{
+		if self.get_header(slot)?.is_none()
+		{
+			return Ok(SlotState::Empty);
+		}
+
+		let state = match (
+			self.is_flag_set(slot, Flag::Rejected)?,
+			self.is_flag_set(slot, Flag::Confirmed)?,
+			self.is_flag_set(slot, Flag::Booted)?,
+			self.is_flag_set(slot, Flag::TestRequested)?,
+		)
+		{
+			(true, _, _, _) => SlotState::Rejected,
+			(false, true, _, _) => SlotState::Confirmed,
+			(false, false, true, _) => SlotState::Testing,
+			(false, false, false, true) => SlotState::TestRequested,
+			(false, false, false, false) => SlotState::Ready,
+		};
+
+		Ok(state)
+	}
+
+	/// Returns `Ok(Some(slot))` with the slot of the image that is running: the one being tested if there's one,
+	/// otherwise the newest confirmed one. Returns `Ok(None)` if there isn't one, otherwise returns `Err(...)`.
+	pub fn get_active_slot(&mut self) -> Result<Option<Slot>, Error<F>>
+	{
+		if let Some(slot) = self.find_slot_in_state(SlotState::Testing)?
+		{
+			return Ok(Some(slot));
+		}
+
+		self.get_newest_confirmed_slot()
+	}
+
+	/// Erases the slot that isn't active, to write an image of `size` bytes with the provided `version` in it.
+	///
+	/// Returns `Ok(slot)` with the slot that will contain the image, otherwise returns `Err(...)` (for example
+	/// `Err(Error::WrongState)` if there's an image being tested, because the other slot contains the image to roll
+	/// back to).
+	pub fn begin_update(&mut self, version: u32, size: u32) -> Result<Slot, Error<F>>
+	{
+		self.update = None;
+		if size > self.get_max_image_size()
+		{
+			return Err(Error::ImageTooBig);
+		}
+		if self.find_slot_in_state(SlotState::Testing)?.is_some()
+		{
+			return Err(Error::WrongState);
+		}
+
+		let slot = match self.get_active_slot()?
+		{
+			Some(active_slot) => active_slot.other(),
+			None => Slot::A,
+		};
+		let sequence = match self.get_header(slot.other())?
+		{
+			Some(header) => header.sequence.wrapping_add(1),
+			None => 0,
+		};
+
+		let slot_start = self.get_slot_start(slot);
+		self.flash
+			.erase(slot_start, slot_start + self.slot_size)
+			.map_err(Error::Flash)?;
+
+		self.update = Some(Update {
+			slot,
+			header: FirmwareHeader {
+				version,
+				size,
+				crc32: 0,
+				sha256: [0; SHA256_SIZE],
+				sequence,
+			},
+			written: 0,
+			flushed: 0,
+			buffer_used: 0,
+			crc32: Crc32::new(),
+			sha256: Sha256::new(),
+		});
+
+		Ok(slot)
+	}
+
+	/// Appends `data` to the image being written.
+	///
+	/// Returns `Ok(())` if the data has been written, otherwise returns `Err(...)` (for example
+	/// `Err(Error::NoUpdateInProgress)` if [`begin_update`] hasn't been called).
+	///
+	/// [`begin_update`]: Self::begin_update
+	pub fn write_chunk(&mut self, mut data: &[u8]) -> Result<(), Error<F>>
+	{
+		let update = self.update.as_mut().ok_or(Error::NoUpdateInProgress)?;
+		if update.written + data.len() as u32 > update.header.size
+		{
+			return Err(Error::ImageTooBig);
+		}
+
+		update.crc32.update(data);
+		update.sha256.update(data);
+		update.written += data.len() as u32;
+
+		while !data.is_empty()
+		{
+			let update = self.update.as_mut().unwrap();
+			let length = data.len().min(self.buffer.len() - update.buffer_used);
+			self.buffer[update.buffer_used..update.buffer_used + length].copy_from_slice(&data[..length]);
+			update.buffer_used += length;
+			data = &data[length..];
+
+			if update.buffer_used == self.buffer.len()
+			{
+				self.flush_buffer()?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Completes the image being written: checks that it matches the `expected_sha256` hash (if provided), verifies
+	/// it and writes the header of the slot.
+	///
+	/// Returns `Ok(header)` with the header of the image if it's ready to be [`tested`], otherwise returns `Err(...)`
+	/// (for example `Err(Error::HashMismatch)` if the image doesn't match `expected_sha256`).
+	///
+	/// [`tested`]: Self::request_test_boot
+	pub fn finish_update(&mut self, expected_sha256: Option<&[u8; SHA256_SIZE]>) -> Result<FirmwareHeader, Error<F>>
+	{
+		let update = self.update.as_mut().ok_or(Error::NoUpdateInProgress)?;
+		if update.written != update.header.size
+		{
+			return Err(Error::SizeMismatch);
+		}
+		update.header.crc32 = update.crc32.finish();
+		update.header.sha256 = update.sha256.finish();
+		if expected_sha256.is_some_and(|expected_sha256| *expected_sha256 != update.header.sha256)
+		{
+			self.update = None;
+			return Err(Error::HashMismatch);
+		}
+
+		let padded_len = update.buffer_used.next_multiple_of(F::WRITE_SIZE);
+		self.buffer[update.buffer_used..padded_len].fill(0xFF);
+		update.buffer_used = padded_len;
+		self.flush_buffer()?;
+
+		let Update { slot, header, .. } = self.update.take().unwrap();
+		if !self.verify_image(slot, &header)?
+		{
+			return Err(Error::VerificationFailed);
+		}
+
+		let header_len = HEADER_SIZE.next_multiple_of(F::WRITE_SIZE);
+		self.buffer[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+		self.buffer[HEADER_SIZE..header_len].fill(0xFF);
+		self.flash
+			.write(self.get_slot_start(slot), &self.buffer[..header_len])
+			.map_err(Error::Flash)?;
+
+		Ok(header)
+	}
+
+	/// Stops writing the current image, leaving its slot empty.
+	pub fn abort_update(&mut self)
+	{
+		self.update = None;
+	}
+
+	/// Returns `Ok(true)` if the image in `slot` matches the CRC32 and the SHA-256 hash in its header, `Ok(false)` if
+	/// it doesn't (or if the slot is empty), otherwise returns `Err(...)`.
+	pub fn verify(&mut self, slot: Slot) -> Result<bool, Error<F>>
+	{
+		match self.get_header(slot)?
+		{
+			Some(header) => self.verify_image(slot, &header),
+			None => Ok(false),
+		}
+	}
+
+	/// Reads `data.len()` bytes of the image in `slot` starting from `offset`.
+	///
+	/// Returns `Ok(())` if the data has been read, otherwise returns `Err(...)`.
+	pub fn read_image(&mut self, slot: Slot, offset: u32, data: &mut [u8]) -> Result<(), Error<F>>
+	{
+		let end = u32::try_from(data.len())
+			.ok()
+			.and_then(|length| offset.checked_add(length));
+		if end.is_none_or(|end| end > self.get_max_image_size())
+		{
+			return Err(Error::ImageTooBig);
+		}
+
+		self.flash
+			.read(self.get_image_start(slot) + offset, data)
+			.map_err(Error::Flash)
+	}
+
+	/// Makes the bootloader boot the image in `slot` as a test at the next boot.
+	///
+	/// Returns `Ok(())` if the test has been requested, otherwise returns `Err(...)` (for example
+	/// `Err(Error::WrongState)` if the image isn't [`SlotState::Ready`]).
+	pub fn request_test_boot(&mut self, slot: Slot) -> Result<(), Error<F>>
+	{
+		if self.get_slot_state(slot)? != SlotState::Ready
+		{
+			return Err(Error::WrongState);
+		}
+
+		self.set_flag(slot, Flag::TestRequested)
+	}
+
+	/// Chooses the slot to boot, to be called by the bootloader:
+	/// - if an image has been booted as a test but hasn't been confirmed, it's rejected.
+	/// - if an image is waiting to be tested, it's verified and booted as a test (or rejected if it's corrupted).
+	/// - otherwise the newest confirmed image is booted.
+	///
+	/// Returns `Ok(Some(slot))` with the slot to boot, `Ok(None)` if there isn't any image to boot, otherwise returns
+	/// `Err(...)`.
+	pub fn select_boot_slot(&mut self) -> Result<Option<Slot>, Error<F>>
+	{
+		if let Some(slot) = self.find_slot_in_state(SlotState::Testing)?
+		{
+			log::warn!("The firmware in slot {:?} hasn't been confirmed, rolling back", slot);
+			self.set_flag(slot, Flag::Rejected)?;
+		}
+
+		if let Some(slot) = self.find_slot_in_state(SlotState::TestRequested)?
+		{
+			if self.verify(slot)?
+			{
+				self.set_flag(slot, Flag::Booted)?;
+				return Ok(Some(slot));
+			}
+
+			log::warn!("The firmware in slot {:?} is corrupted", slot);
+			self.set_flag(slot, Flag::Rejected)?;
+		}
+
+		self.get_newest_confirmed_slot()
+	}
+
+	/// Confirms the image being tested, which won't be rolled back anymore.
+	///
+	/// Returns `Ok(())` if the image has been confirmed, otherwise returns `Err(...)` (for example
+	/// `Err(Error::WrongState)` if there isn't an image being tested).
+	pub fn confirm(&mut self) -> Result<(), Error<F>>
+	{
+		let slot = self.find_slot_in_state(SlotState::Testing)?.ok_or(Error::WrongState)?;
+
+		self.set_flag(slot, Flag::Confirmed)
+	}
+
+	/// Rejects the image being tested (or waiting to be tested), or the active image if the other slot contains a
+	/// confirmed image, so that the bootloader boots the other image at the next boot.
+	///
+	/// Returns `Ok(())` if the image has been rejected, otherwise returns `Err(...)` (for example
+	/// `Err(Error::WrongState)` if there isn't another image to roll back to).
+	pub fn roll_back(&mut self) -> Result<(), Error<F>>
+	{
+		for state in [SlotState::Testing, SlotState::TestRequested]
+		{
+			if let Some(slot) = self.find_slot_in_state(state)?
+			{
+				return self.set_flag(slot, Flag::Rejected);
+			}
+		}
+
+		match self.get_active_slot()?
+		{
+			Some(slot) if self.get_slot_state(slot.other())? == SlotState::Confirmed =>
+			{
+				self.set_flag(slot, Flag::Rejected)
+			},
+			_ => Err(Error::WrongState),
+		}
+	}
+
+	/// Returns a mutable reference to the underlying flash memory.
+	///
+	/// # Warning
+	/// Writing in the slots corrupts the images.
+	pub fn get_flash_mut(&mut self) -> &mut F
+	{
+		&mut self.flash
+	}
+
+	/// Consumes this struct and returns the underlying flash memory.
+	pub fn release(self) -> F
+	{
+		self.flash
+	}
+
+	fn get_slot_start(&self, slot: Slot) -> u32
+	{
+		self.start + slot as u32 * self.slot_size
+	}
+
+	fn get_image_start(&self, slot: Slot) -> u32
+	{
+		self.get_slot_start(slot) + F::ERASE_SIZE as u32
+	}
+
+	fn get_flag_offset(flag: Flag) -> u32
+	{
+		(HEADER_SIZE.next_multiple_of(F::WRITE_SIZE) + flag as usize * F::WRITE_SIZE) as u32
+	}
+
+	fn is_flag_set(&mut self, slot: Slot, flag: Flag) -> Result<bool, Error<F>>
+	{
+		let mut byte = [0];
+		self.flash
+			.read(self.get_slot_start(slot) + Self::get_flag_offset(flag), &mut byte)
+			.map_err(Error::Flash)?;
+
+		Ok(byte[0] != 0xFF)
+	}
+
+	fn set_flag(&mut self, slot: Slot, flag: Flag) -> Result<(), Error<F>>
+	{
+		self.buffer[..F::WRITE_SIZE].fill(0);
+		self.flash
+			.write(
+				self.get_slot_start(slot) + Self::get_flag_offset(flag),
+				&self.buffer[..F::WRITE_SIZE],
+			)
+			.map_err(Error::Flash)
+	}
+
+	fn find_slot_in_state(&mut self, state: SlotState) -> Result<Option<Slot>, Error<F>>
+	{
+		for slot in [Slot::A, Slot::B]
+		{
+			if self.get_slot_state(slot)? == state
+			{
+				return Ok(Some(slot));
+			}
+		}
+
+		Ok(None)
+	}
+
+	fn get_newest_confirmed_slot(&mut self) -> Result<Option<Slot>, Error<F>>
+	{
+		let mut newest_slot: Option<(Slot, u32)> = None;
+		for slot in [Slot::A, Slot::B]
+		{
+			if self.get_slot_state(slot)? == SlotState::Confirmed
+			{
+				let sequence = self.get_header(slot)?.unwrap().sequence;
+				// The sequence can wrap around, so the newest one is the one "after" the other
+				if newest_slot.is_none_or(|(_, newest_sequence)| sequence.wrapping_sub(newest_sequence) as i32 > 0)
+				{
+					newest_slot = Some((slot, sequence));
+				}
+			}
+		}
+
+		Ok(newest_slot.map(|(slot, _)| slot))
+	}
+
+	/// Writes the bytes in the buffer after the ones of the image already written.
+	fn flush_buffer(&mut self) -> Result<(), Error<F>>
+	{
+		let image_start = self.get_image_start(self.update.as_ref().unwrap().slot);
+		let update = self.update.as_mut().unwrap();
+		let address = image_start + update.flushed;
+		let buffer_used = update.buffer_used;
+		update.flushed += buffer_used as u32;
+		update.buffer_used = 0;
+
+		self.flash
+			.write(address, &self.buffer[..buffer_used])
+			.map_err(Error::Flash)
+	}
+
+	fn verify_image(&mut self, slot: Slot, header: &FirmwareHeader) -> Result<bool, Error<F>>
+	{
+		if header.size > self.get_max_image_size()
+		{
+			return Ok(false);
+		}
+
+		let mut crc32 = Crc32::new();
+		let mut sha256 = Sha256::new();
+		let mut chunk = [0; CHUNK_SIZE];
+		let image_start = self.get_image_start(slot);
+		let mut offset = 0;
+		while offset < header.size
+		{
+			let chunk = &mut chunk[..(header.size - offset).min(CHUNK_SIZE as u32) as usize];
+			self.flash.read(image_start + offset, chunk).map_err(Error::Flash)?;
+			crc32.update(chunk);
+			sha256.update(chunk);
+			offset += chunk.len() as u32;
+		}
+
+		Ok(crc32.finish() == header.crc32 && sha256.finish() == header.sha256)
+	}
+}
+
+/// An error returned from a [`FirmwareSlots`].
+pub enum Error<F: NorFlash>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(F::Error),
+	/// The image doesn't fit in a slot.
+	ImageTooBig,
+	/// [`begin_update`](FirmwareSlots::begin_update) hasn't been called.
+	NoUpdateInProgress,
+	/// The size of the written image is different from the one provided to
+	/// [`begin_update`](FirmwareSlots::begin_update).
+	SizeMismatch,
+	/// The SHA-256 hash of the written image is different from the expected one.
+	HashMismatch,
+	/// The image read back from the flash memory is different from the written one.
+	VerificationFailed,
+	/// The slots aren't in the right state for the operation.
+	WrongState,
+}
+
+impl<F: NorFlash> Debug for Error<F>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Flash(arg0) => f.debug_tuple("Flash").field(arg0).finish(),
+			Self::ImageTooBig => write!(f, "ImageTooBig"),
+			Self::NoUpdateInProgress => write!(f, "NoUpdateInProgress"),
+			Self::SizeMismatch => write!(f, "SizeMismatch"),
+			Self::HashMismatch => write!(f, "HashMismatch"),
+			Self::VerificationFailed => write!(f, "VerificationFailed"),
+			Self::WrongState => write!(f, "WrongState"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::{hardware::mock::MockNorFlash, utils::algorithms::sha256::sha256};
+
+	const SLOT_SIZE: u32 = 64 * 1024;
+
+	fn image(version: u32) -> [u8; 20_000]
+	{
+		core::array::from_fn(|i| (i as u32 * 7 + version * 13) as u8)
+	}
+
+	fn install<F: NorFlash>(firmware_slots: &mut FirmwareSlots<F>, version: u32) -> Slot
+	{
+		let image = image(version);
+		let slot = firmware_slots.begin_update(version, image.len() as u32).unwrap();
+		for chunk in image.chunks(333)
+		{
+			firmware_slots.write_chunk(chunk).unwrap();
+		}
+		firmware_slots.finish_update(Some(&sha256(&image))).unwrap();
+		firmware_slots.request_test_boot(slot).unwrap();
+
+		slot
+	}
+
+	#[test]
+	fn test_boot_confirm_and_roll_back()
+	{
+		let mut buffer = [0; 64];
+		let flash = MockNorFlash::<4, 4096>::new(256 * 1024);
+		let mut firmware_slots = FirmwareSlots::new(flash, 4096, SLOT_SIZE, &mut buffer);
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), None);
+
+		// The first image is confirmed
+		assert_eq!(install(&mut firmware_slots, 1), Slot::A);
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::A));
+		firmware_slots.confirm().unwrap();
+		assert_eq!(firmware_slots.get_header(Slot::A).unwrap().unwrap().version, 1);
+		let mut data = [0; 100];
+		firmware_slots.read_image(Slot::A, 1000, &mut data).unwrap();
+		assert_eq!(data, image(1)[1000..1100]);
+		assert!(matches!(
+			firmware_slots.read_image(Slot::A, u32::MAX - 10, &mut data),
+			Err(Error::ImageTooBig)
+		));
+
+		// The second one isn't confirmed before the next boot, so it's rolled back
+		assert_eq!(install(&mut firmware_slots, 2), Slot::B);
+		assert_eq!(firmware_slots.get_active_slot().unwrap(), Some(Slot::A));
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::B));
+		assert_eq!(firmware_slots.get_slot_state(Slot::B).unwrap(), SlotState::Testing);
+		assert_eq!(firmware_slots.get_active_slot().unwrap(), Some(Slot::B));
+		assert!(matches!(firmware_slots.begin_update(3, 10), Err(Error::WrongState)));
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::A));
+		assert_eq!(firmware_slots.get_slot_state(Slot::B).unwrap(), SlotState::Rejected);
+
+		// The third one is confirmed, and then rolled back by the application
+		assert_eq!(install(&mut firmware_slots, 3), Slot::B);
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::B));
+		firmware_slots.confirm().unwrap();
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::B));
+		firmware_slots.roll_back().unwrap();
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), Some(Slot::A));
+		assert!(matches!(firmware_slots.roll_back(), Err(Error::WrongState)));
+		assert!(matches!(firmware_slots.confirm(), Err(Error::WrongState)));
+	}
+
+	#[test]
+	fn invalid_images_are_never_booted()
+	{
+		let mut buffer = [0; 256];
+		let flash = MockNorFlash::<1, 4096>::new(128 * 1024);
+		let mut firmware_slots = FirmwareSlots::new(flash, 0, SLOT_SIZE, &mut buffer);
+
+		assert!(matches!(
+			firmware_slots.write_chunk(&[0]),
+			Err(Error::NoUpdateInProgress)
+		));
+		assert!(matches!(
+			firmware_slots.begin_update(1, SLOT_SIZE),
+			Err(Error::ImageTooBig)
+		));
+
+		firmware_slots.begin_update(1, 10).unwrap();
+		firmware_slots.write_chunk(&[1; 5]).unwrap();
+		assert!(matches!(firmware_slots.write_chunk(&[1; 6]), Err(Error::ImageTooBig)));
+		assert!(matches!(firmware_slots.finish_update(None), Err(Error::SizeMismatch)));
+		firmware_slots.write_chunk(&[1; 5]).unwrap();
+		assert!(matches!(
+			firmware_slots.finish_update(Some(&sha256(&[2; 10]))),
+			Err(Error::HashMismatch)
+		));
+		assert_eq!(firmware_slots.get_slot_state(Slot::A).unwrap(), SlotState::Empty);
+
+		// A bit flip in the image after it has been written
+		let slot = install(&mut firmware_slots, 1);
+		firmware_slots.get_flash_mut().get_memory_mut()[4096 + 1234] ^= 0x10;
+		assert!(!firmware_slots.verify(slot).unwrap());
+		assert_eq!(firmware_slots.select_boot_slot().unwrap(), None);
+		assert_eq!(firmware_slots.get_slot_state(slot).unwrap(), SlotState::Rejected);
+
+		// A power loss while writing the header
+		let image = image(2);
+		firmware_slots.begin_update(2, image.len() as u32).unwrap();
+		firmware_slots.write_chunk(&image).unwrap();
+		firmware_slots.get_flash_mut().set_power_loss_at_write(Some(2));
+		assert!(firmware_slots.finish_update(None).is_ok());
+		firmware_slots.get_flash_mut().set_power_loss_at_write(None);
+		assert_eq!(firmware_slots.get_slot_state(Slot::A).unwrap(), SlotState::Empty);
+		assert!(matches!(
+			firmware_slots.request_test_boot(Slot::A),
+			Err(Error::WrongState)
+		));
+	}
+}
@@ -0,0 +1,947 @@
+//! A log-structured key/value store that keeps small settings (PID gains, calibration offsets, Wi-Fi credentials, ...)
+//! on any flash memory that implements [`NorFlash`], like the [`SpiNorFlash`] and the [`SpiFlashMemory`] drivers.
+//!
+//! ## Layout
+//! The store uses 2 banks of the same size (a multiple of [`NorFlash::ERASE_SIZE`]), only one of which is active at a
+//! time. Each bank is a log of records, aligned to [`NorFlash::WRITE_SIZE`] and made of:
+//! - an 8 bytes header with the type of the record, the length of the key, the length of the value and a CRC32 of the
+//!   whole record.
+//! - the key.
+//! - the value.
+//!
+//! A bank starts with a bank record that contains its generation, followed by the records copied from the other bank
+//! during the last compaction and by a commit record, after which all the new records are appended. Setting a key
+//! appends a data record, removing it appends a delete record, and the last record of a key is its current value.
+//!
+//! ## Compaction
+//! When the active bank is full, the spare bank is erased and only the last value of each key is copied in it. The
+//! spare bank becomes the active one only when its commit record has been written, so a power loss in the middle of
+//! the compaction just leaves the old bank active.
+//!
+//! ## Power loss
+//! If the power is lost while a record is being written, the record is left torn, and its CRC doesn't match anymore.
+//! When the store is [`mounted`], the records after a torn record are ignored and the active bank is compacted right
+//! away, so that the torn record is never read.
+//!
+//! [`SpiNorFlash`]: crate::drivers::spi_nor_flash::SpiNorFlash
+//! [`SpiFlashMemory`]: crate::drivers::spi_flash_memory::SpiFlashMemory
+//! [`mounted`]: KvStore::mount
+
+use core::fmt::Debug;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::utils::algorithms::crc::Crc32;
+
+mod value;
+pub use value::*;
+
+/// Size of the header of a record.
+const HEADER_SIZE: u32 = 8;
+/// Size of the chunks used to read the records from the flash memory.
+const CHUNK_SIZE: usize = 32;
+
+/// A power-loss-safe key/value store that uses 2 banks of `bank_size` bytes of a [`NorFlash`].
+///
+/// Check [`module's documentation`](self) for more info.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{
+/// #     drivers::spi_nor_flash::SpiNorFlash,
+/// #     features::storage::kv_store::*,
+/// #     hardware::mock::MockSpiNorFlash,
+/// #     utils::physical_quantities::temperature::Temperature,
+/// # };
+/// #
+/// let flash = SpiNorFlash::from_sfdp(MockSpiNorFlash::new(1024 * 1024)).unwrap();
+/// let mut buffer = [0; 64];
+/// let mut kv_store = KvStore::new(flash, 0, 8 * 1024, &mut buffer);
+/// kv_store.format().unwrap();
+///
+/// kv_store.set("kp", &2.5f32).unwrap();
+/// kv_store.set("offset", &Temperature::from_kelvin(1.5)).unwrap();
+/// kv_store.set_bytes("ssid", b"My Wi-Fi").unwrap();
+///
+/// assert_eq!(kv_store.get::<f32>("kp").unwrap(), Some(2.5));
+/// assert_eq!(kv_store.get::<f32>("ki").unwrap(), None);
+/// let mut ssid = [0; 32];
+/// assert_eq!(kv_store.get_bytes("ssid", &mut ssid).unwrap(), Some(8));
+/// assert_eq!(&ssid[..8], b"My Wi-Fi");
+/// ```
+pub struct KvStore<'a, F: NorFlash>
+{
+	flash: F,
+	start: u32,
+	bank_size: u32,
+	buffer: &'a mut [u8],
+	is_mounted: bool,
+
+	active_bank: u32,
+	generation: u32,
+	write_offset: u32,
+}
+
+impl<'a, F: NorFlash> KvStore<'a, F>
+{
+	/// Returns a [`KvStore`] that uses the 2 banks of `bank_size` bytes starting from the `start` address of the `flash`.
+	/// The `buffer` is used to write the records, so the bigger it is the fewer writes are needed.
+	///
+	/// # Warning
+	/// Before using it, you need to [`mount`] it (or to [`format`] it the first time).
+	///
+	/// # Panics
+	/// Panics if `start` or `bank_size` aren't multiples of [`NorFlash::ERASE_SIZE`], if the 2 banks don't fit in the
+	/// flash memory, if `buffer.len()` isn't a multiple of [`NorFlash::WRITE_SIZE`] or if [`NorFlash::READ_SIZE`]
+	/// isn't 1.
+	///
+	/// [`mount`]: Self::mount
+	/// [`format`]: Self::format
+	pub fn new(flash: F, start: u32, bank_size: u32, buffer: &'a mut [u8]) -> Self
+	{
+		assert!(F::READ_SIZE == 1);
+		assert!(bank_size > 0 && (bank_size as usize).is_multiple_of(F::ERASE_SIZE));
+		assert!((start as usize).is_multiple_of(F::ERASE_SIZE));
+		assert!(start as usize + 2 * bank_size as usize <= flash.capacity());
+		assert!(!buffer.is_empty() && buffer.len().is_multiple_of(F::WRITE_SIZE));
+
+		Self {
+			flash,
+			start,
+			bank_size,
+			buffer,
+			is_mounted: false,
+			active_bank: 0,
+			generation: 0,
+			write_offset: 0,
+		}
+	}
+
+	/// Erases both the banks and creates an empty store.
+	///
+	/// Returns `Ok(())` if the store has been formatted, otherwise returns `Err(...)`.
+	pub fn format(&mut self) -> Result<(), Error<F>>
+	{
+		self.is_mounted = false;
+		self.flash
+			.erase(self.start, self.start + 2 * self.bank_size)
+			.map_err(Error::Flash)?;
+
+		self.write_offset = self.initialize_bank(0, 0)?;
+		self.write_offset = self.write_record(self.write_offset, RecordKind::Commit, b"", b"")?;
+		self.active_bank = 0;
+		self.generation = 0;
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Finds the active bank and the end of its log. If a torn record is found, the active bank is compacted.
+	///
+	/// Returns `Ok(())` if the store has been mounted, otherwise returns `Err(...)` (for example
+	/// `Err(Error::NotFormatted)` if no valid bank has been found, in which case you need to [`format`] it).
+	///
+	/// [`format`]: Self::format
+	pub fn mount(&mut self) -> Result<(), Error<F>>
+	{
+		self.is_mounted = false;
+
+		let mut active_bank = None;
+		for bank in 0..2
+		{
+			if let Some(state) = self.read_bank_state(bank)?
+			{
+				if active_bank
+					.is_none_or(|(_, active_state): (u32, BankState)| state.generation > active_state.generation)
+				{
+					active_bank = Some((bank, state));
+				}
+			}
+		}
+		let (bank, state) = active_bank.ok_or(Error::NotFormatted)?;
+
+		self.active_bank = bank;
+		self.generation = state.generation;
+		self.write_offset = state.end_offset;
+		self.is_mounted = true;
+
+		if state.is_torn
+		{
+			log::warn!("Found a torn record in the key/value store, compacting it");
+			self.compact()?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns `Ok(Some(value))` if `key` has a value of type `V`, `Ok(None)` if `key` hasn't been set, otherwise
+	/// returns `Err(...)` (for example `Err(Error::WrongType)` if the value of `key` isn't a valid `V`).
+	pub fn get<V: StorableValue>(&mut self, key: &str) -> Result<Option<V>, Error<F>>
+	{
+		let Some(record) = self.find_record(key)?
+		else
+		{
+			return Ok(None);
+		};
+		if record.value_len as usize != V::SIZE
+		{
+			return Err(Error::WrongType);
+		}
+
+		let mut bytes = [0; MAX_VALUE_SIZE];
+		self.flash
+			.read(record.get_value_offset(), &mut bytes[..V::SIZE])
+			.map_err(Error::Flash)?;
+		V::from_bytes(&bytes[..V::SIZE]).map(Some).ok_or(Error::WrongType)
+	}
+
+	/// Sets the `value` of `key`.
+	///
+	/// Returns `Ok(())` if the value has been saved, otherwise returns `Err(...)`.
+	pub fn set<V: StorableValue>(&mut self, key: &str, value: &V) -> Result<(), Error<F>>
+	{
+		let mut bytes = [0; MAX_VALUE_SIZE];
+		value.to_bytes(&mut bytes[..V::SIZE]);
+		self.set_bytes(key, &bytes[..V::SIZE])
+	}
+
+	/// Reads the value of `key` in `value`.
+	///
+	/// Returns `Ok(Some(length))` with the length of the value if `key` has been set, `Ok(None)` if it hasn't, otherwise
+	/// returns `Err(...)` (for example `Err(Error::ValueTooBig)` if the value doesn't fit in `value`).
+	pub fn get_bytes(&mut self, key: &str, value: &mut [u8]) -> Result<Option<usize>, Error<F>>
+	{
+		let Some(record) = self.find_record(key)?
+		else
+		{
+			return Ok(None);
+		};
+		let value_len = record.value_len as usize;
+		if value_len > value.len()
+		{
+			return Err(Error::ValueTooBig);
+		}
+
+		self.flash
+			.read(record.get_value_offset(), &mut value[..value_len])
+			.map_err(Error::Flash)?;
+		Ok(Some(value_len))
+	}
+
+	/// Sets the `value` of `key`. Nothing is written if `key` already has this value, to reduce the wear of the flash
+	/// memory.
+	///
+	/// Returns `Ok(())` if the value has been saved, otherwise returns `Err(...)` (for example `Err(Error::Full)` if
+	/// there isn't enough space left even after a compaction).
+	pub fn set_bytes(&mut self, key: &str, value: &[u8]) -> Result<(), Error<F>>
+	{
+		if key.is_empty() || key.len() > u8::MAX as usize
+		{
+			return Err(Error::KeyTooLong);
+		}
+		if value.len() > u16::MAX as usize
+		{
+			return Err(Error::ValueTooBig);
+		}
+
+		if let Some(record) = self.find_record(key)?
+		{
+			if record.value_len as usize == value.len()
+				&& flash_contains(&mut self.flash, record.get_value_offset(), value).map_err(Error::Flash)?
+			{
+				return Ok(());
+			}
+		}
+
+		self.append_record(RecordKind::Data, key, value)
+	}
+
+	/// Removes `key` from the store.
+	///
+	/// Returns `Ok(true)` if `key` has been removed, `Ok(false)` if it wasn't set, otherwise returns `Err(...)`.
+	pub fn remove(&mut self, key: &str) -> Result<bool, Error<F>>
+	{
+		if self.find_record(key)?.is_none()
+		{
+			return Ok(false);
+		}
+
+		self.append_record(RecordKind::Delete, key, b"")?;
+		Ok(true)
+	}
+
+	/// Returns `Ok(true)` if `key` has been set, otherwise returns `Ok(false)` or `Err(...)`.
+	pub fn contains_key(&mut self, key: &str) -> Result<bool, Error<F>>
+	{
+		Ok(self.find_record(key)?.is_some())
+	}
+
+	/// Copies the last value of each key in the spare bank, which becomes the active one. This is done automatically
+	/// when the active bank is full.
+	///
+	/// Returns `Ok(())` if the store has been compacted, otherwise returns `Err(...)`.
+	pub fn compact(&mut self) -> Result<(), Error<F>>
+	{
+		self.check_mounted()?;
+
+		let spare_bank = 1 - self.active_bank;
+		let generation = self.generation.wrapping_add(1);
+		let spare_bank_start = self.get_bank_start(spare_bank);
+		self.flash
+			.erase(spare_bank_start, spare_bank_start + self.bank_size)
+			.map_err(Error::Flash)?;
+		let mut offset = self.initialize_bank(spare_bank, generation)?;
+
+		let mut record_offset = self.get_bank_start(self.active_bank);
+		while let RecordSlot::Record(record) = self.read_record(record_offset, self.write_offset)?
+		{
+			if record.kind == RecordKind::Data && !self.is_overwritten(&record)?
+			{
+				offset = self.copy_record(&record, offset)?;
+			}
+			record_offset = record.next_offset;
+		}
+
+		self.write_offset = self.write_record(offset, RecordKind::Commit, b"", b"")?;
+		self.active_bank = spare_bank;
+		self.generation = generation;
+
+		Ok(())
+	}
+
+	/// Returns how many bytes are still free in the active bank.
+	pub fn get_free_space(&self) -> u32
+	{
+		self.get_bank_start(self.active_bank) + self.bank_size - self.write_offset
+	}
+
+	/// Returns a mutable reference to the underlying flash memory.
+	///
+	/// # Warning
+	/// Writing in the banks used by the store corrupts it.
+	pub fn get_flash_mut(&mut self) -> &mut F
+	{
+		&mut self.flash
+	}
+
+	/// Consumes this struct and returns the underlying flash memory.
+	pub fn release(self) -> F
+	{
+		self.flash
+	}
+
+	fn check_mounted(&self) -> Result<(), Error<F>>
+	{
+		match self.is_mounted
+		{
+			true => Ok(()),
+			false => Err(Error::NotMounted),
+		}
+	}
+
+	fn get_bank_start(&self, bank: u32) -> u32
+	{
+		self.start + bank * self.bank_size
+	}
+
+	fn get_record_size(content_len: usize) -> u32
+	{
+		(HEADER_SIZE as usize + content_len).next_multiple_of(F::WRITE_SIZE) as u32
+	}
+
+	/// Returns the last data record of `key` in the active bank, or `None` if `key` isn't set.
+	fn find_record(&mut self, key: &str) -> Result<Option<Record>, Error<F>>
+	{
+		self.check_mounted()?;
+
+		let mut last_record = None;
+		let mut offset = self.get_bank_start(self.active_bank);
+		while let RecordSlot::Record(record) = self.read_record(offset, self.write_offset)?
+		{
+			if record.kind.has_key() && self.is_record_of_key(&record, key.as_bytes())?
+			{
+				last_record = Some(record);
+			}
+			offset = record.next_offset;
+		}
+
+		Ok(last_record.filter(|record| record.kind == RecordKind::Data))
+	}
+
+	/// Returns `true` if there's another record with the same key of `record` after it in the active bank.
+	fn is_overwritten(&mut self, record: &Record) -> Result<bool, Error<F>>
+	{
+		let mut key = [0; u8::MAX as usize];
+		let key = &mut key[..record.key_len as usize];
+		self.flash.read(record.get_key_offset(), key).map_err(Error::Flash)?;
+
+		let mut offset = record.next_offset;
+		while let RecordSlot::Record(other_record) = self.read_record(offset, self.write_offset)?
+		{
+			if other_record.kind.has_key() && self.is_record_of_key(&other_record, key)?
+			{
+				return Ok(true);
+			}
+			offset = other_record.next_offset;
+		}
+
+		Ok(false)
+	}
+
+	fn is_record_of_key(&mut self, record: &Record, key: &[u8]) -> Result<bool, Error<F>>
+	{
+		Ok(record.key_len as usize == key.len()
+			&& flash_contains(&mut self.flash, record.get_key_offset(), key).map_err(Error::Flash)?)
+	}
+
+	/// Returns the state of `bank`, or `None` if it doesn't contain a committed store.
+	fn read_bank_state(&mut self, bank: u32) -> Result<Option<BankState>, Error<F>>
+	{
+		let bank_start = self.get_bank_start(bank);
+		let bank_end = bank_start + self.bank_size;
+
+		let generation = match self.read_record(bank_start, bank_end)?
+		{
+			RecordSlot::Record(record) if record.kind == RecordKind::Bank && record.value_len == 4 =>
+			{
+				let mut generation = [0; 4];
+				self.flash
+					.read(record.get_value_offset(), &mut generation)
+					.map_err(Error::Flash)?;
+				u32::from_le_bytes(generation)
+			},
+			_ => return Ok(None),
+		};
+
+		let mut is_committed = false;
+		let mut offset = bank_start;
+		loop
+		{
+			match self.read_record(offset, bank_end)?
+			{
+				RecordSlot::Record(record) =>
+				{
+					is_committed |= record.kind == RecordKind::Commit;
+					offset = record.next_offset;
+				},
+				slot =>
+				{
+					return Ok(is_committed.then_some(BankState {
+						generation,
+						end_offset: offset,
+						is_torn: slot == RecordSlot::Torn,
+					}))
+				},
+			}
+		}
+	}
+
+	/// Reads the record at `offset`.
+	///
+	/// Returns `Ok(RecordSlot::End)` if the log ends there (or if there isn't space for a record before `end_offset`),
+	/// otherwise returns `Ok(RecordSlot::Record(...))`, `Ok(RecordSlot::Torn)` or `Err(...)`.
+	fn read_record(&mut self, offset: u32, end_offset: u32) -> Result<RecordSlot, Error<F>>
+	{
+		if offset + HEADER_SIZE > end_offset
+		{
+			return Ok(RecordSlot::End);
+		}
+
+		let mut header = [0; HEADER_SIZE as usize];
+		self.flash.read(offset, &mut header).map_err(Error::Flash)?;
+		if header.iter().all(|&byte| byte == 0xFF)
+		{
+			return Ok(RecordSlot::End);
+		}
+
+		let Some(kind) = RecordKind::from_byte(header[0])
+		else
+		{
+			return Ok(RecordSlot::Torn);
+		};
+		let key_len = header[1];
+		let value_len = u16::from_le_bytes([header[2], header[3]]);
+		let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+		let next_offset = offset + Self::get_record_size(key_len as usize + value_len as usize);
+		if next_offset > end_offset
+		{
+			return Ok(RecordSlot::Torn);
+		}
+
+		let mut computed_crc = Crc32::new();
+		computed_crc.update(&header[..4]);
+		let mut chunk = [0; CHUNK_SIZE];
+		let mut content_offset = offset + HEADER_SIZE;
+		let content_end = content_offset + key_len as u32 + value_len as u32;
+		while content_offset < content_end
+		{
+			let chunk = &mut chunk[..(content_end - content_offset).min(CHUNK_SIZE as u32) as usize];
+			self.flash.read(content_offset, chunk).map_err(Error::Flash)?;
+			computed_crc.update(chunk);
+			content_offset += chunk.len() as u32;
+		}
+		if computed_crc.finish() != crc
+		{
+			return Ok(RecordSlot::Torn);
+		}
+
+		Ok(RecordSlot::Record(Record {
+			kind,
+			offset,
+			next_offset,
+			key_len,
+			value_len,
+		}))
+	}
+
+	/// Writes the bank record of `bank` with its `generation`, returning the offset after it.
+	fn initialize_bank(&mut self, bank: u32, generation: u32) -> Result<u32, Error<F>>
+	{
+		self.write_record(
+			self.get_bank_start(bank),
+			RecordKind::Bank,
+			b"",
+			&generation.to_le_bytes(),
+		)
+	}
+
+	/// Appends a record to the active bank, compacting it if there isn't enough space left.
+	fn append_record(&mut self, kind: RecordKind, key: &str, value: &[u8]) -> Result<(), Error<F>>
+	{
+		self.check_mounted()?;
+
+		let record_size = Self::get_record_size(key.len() + value.len());
+		if record_size > self.get_free_space()
+			|| !flash_is_erased(&mut self.flash, self.write_offset, record_size).map_err(Error::Flash)?
+		{
+			self.compact()?;
+			if record_size > self.get_free_space()
+			{
+				return Err(Error::Full);
+			}
+		}
+
+		self.write_offset = self.write_record(self.write_offset, kind, key.as_bytes(), value)?;
+		Ok(())
+	}
+
+	/// Writes a record at `offset`, returning the offset after it.
+	fn write_record(&mut self, offset: u32, kind: RecordKind, key: &[u8], value: &[u8]) -> Result<u32, Error<F>>
+	{
+		let mut header = [0; HEADER_SIZE as usize];
+		header[0] = kind as u8;
+		header[1] = key.len() as u8;
+		header[2..4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+		let mut crc = Crc32::new();
+		crc.update(&header[..4]);
+		crc.update(key);
+		crc.update(value);
+		header[4..].copy_from_slice(&crc.finish().to_le_bytes());
+
+		let mut writer = RecordWriter::new(&mut self.flash, self.buffer, offset);
+		writer.push(&header)?;
+		writer.push(key)?;
+		writer.push(value)?;
+		writer.finish()
+	}
+
+	/// Copies `record` at `offset`, returning the offset after it.
+	fn copy_record(&mut self, record: &Record, offset: u32) -> Result<u32, Error<F>>
+	{
+		let mut writer = RecordWriter::new(&mut self.flash, self.buffer, offset);
+		let mut chunk = [0; CHUNK_SIZE];
+		let mut read_offset = record.offset;
+		let record_end = record.get_value_offset() + record.value_len as u32;
+		while read_offset < record_end
+		{
+			let chunk = &mut chunk[..(record_end - read_offset).min(CHUNK_SIZE as u32) as usize];
+			writer.flash.read(read_offset, chunk).map_err(Error::Flash)?;
+			writer.push(chunk)?;
+			read_offset += chunk.len() as u32;
+		}
+		writer.finish()
+	}
+}
+
+/// Returns `true` if the flash memory at `offset` contains `data`.
+fn flash_contains<F: NorFlash>(flash: &mut F, offset: u32, data: &[u8]) -> Result<bool, F::Error>
+{
+	let mut chunk = [0; CHUNK_SIZE];
+	for (i, data) in data.chunks(CHUNK_SIZE).enumerate()
+	{
+		let chunk = &mut chunk[..data.len()];
+		flash.read(offset + (i * CHUNK_SIZE) as u32, chunk)?;
+		if chunk != data
+		{
+			return Ok(false);
+		}
+	}
+
+	Ok(true)
+}
+
+/// Returns `true` if the `length` bytes of the flash memory at `offset` are erased.
+fn flash_is_erased<F: NorFlash>(flash: &mut F, offset: u32, length: u32) -> Result<bool, F::Error>
+{
+	let mut chunk = [0; CHUNK_SIZE];
+	let end = offset + length;
+	let mut offset = offset;
+	while offset < end
+	{
+		let chunk = &mut chunk[..(end - offset).min(CHUNK_SIZE as u32) as usize];
+		flash.read(offset, chunk)?;
+		if chunk.iter().any(|&byte| byte != 0xFF)
+		{
+			return Ok(false);
+		}
+		offset += chunk.len() as u32;
+	}
+
+	Ok(true)
+}
+
+/// Writes a record in the flash memory through a buffer, so that all the writes are aligned to
+/// [`NorFlash::WRITE_SIZE`] and each part of the flash memory is written only once.
+struct RecordWriter<'b, F: NorFlash>
+{
+	flash: &'b mut F,
+	buffer: &'b mut [u8],
+	offset: u32,
+	buffer_len: usize,
+}
+
+impl<'b, F: NorFlash> RecordWriter<'b, F>
+{
+	fn new(flash: &'b mut F, buffer: &'b mut [u8], offset: u32) -> Self
+	{
+		Self {
+			flash,
+			buffer,
+			offset,
+			buffer_len: 0,
+		}
+	}
+
+	fn push(&mut self, mut data: &[u8]) -> Result<(), Error<F>>
+	{
+		while !data.is_empty()
+		{
+			let length = data.len().min(self.buffer.len() - self.buffer_len);
+			self.buffer[self.buffer_len..self.buffer_len + length].copy_from_slice(&data[..length]);
+			self.buffer_len += length;
+			data = &data[length..];
+
+			if self.buffer_len == self.buffer.len()
+			{
+				self.flush()?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Pads the record to [`NorFlash::WRITE_SIZE`] and writes what's left in the buffer, returning the offset after
+	/// the record.
+	fn finish(mut self) -> Result<u32, Error<F>>
+	{
+		let padded_len = self.buffer_len.next_multiple_of(F::WRITE_SIZE);
+		self.buffer[self.buffer_len..padded_len].fill(0xFF);
+		self.buffer_len = padded_len;
+		self.flush()?;
+
+		Ok(self.offset)
+	}
+
+	fn flush(&mut self) -> Result<(), Error<F>>
+	{
+		if self.buffer_len > 0
+		{
+			self.flash
+				.write(self.offset, &self.buffer[..self.buffer_len])
+				.map_err(Error::Flash)?;
+			self.offset += self.buffer_len as u32;
+			self.buffer_len = 0;
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+enum RecordKind
+{
+	/// The first record of a bank, whose value is the generation of the bank.
+	Bank = 0x10,
+	/// The end of the records copied during a compaction.
+	Commit = 0x20,
+	/// A new value of a key.
+	Data = 0x30,
+	/// The removal of a key.
+	Delete = 0x40,
+}
+
+impl RecordKind
+{
+	fn from_byte(byte: u8) -> Option<Self>
+	{
+		match byte
+		{
+			0x10 => Some(Self::Bank),
+			0x20 => Some(Self::Commit),
+			0x30 => Some(Self::Data),
+			0x40 => Some(Self::Delete),
+			_ => None,
+		}
+	}
+
+	fn has_key(&self) -> bool
+	{
+		matches!(self, Self::Data | Self::Delete)
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Record
+{
+	kind: RecordKind,
+	offset: u32,
+	next_offset: u32,
+	key_len: u8,
+	value_len: u16,
+}
+
+impl Record
+{
+	fn get_key_offset(&self) -> u32
+	{
+		self.offset + HEADER_SIZE
+	}
+
+	fn get_value_offset(&self) -> u32
+	{
+		self.get_key_offset() + self.key_len as u32
+	}
+}
+
+/// What has been found while reading a record.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RecordSlot
+{
+	Record(Record),
+	/// The end of the log.
+	End,
+	/// A record that has been only partially written, because the power has been lost while writing it.
+	Torn,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BankState
+{
+	generation: u32,
+	end_offset: u32,
+	is_torn: bool,
+}
+
+/// An error returned from a [`KvStore`].
+pub enum Error<F: NorFlash>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(F::Error),
+	/// You need to [`mount`](KvStore::mount) the store before using it.
+	NotMounted,
+	/// No valid bank has been found, so the store needs to be [`formatted`](KvStore::format).
+	NotFormatted,
+	/// The key is empty or longer than 255 bytes.
+	KeyTooLong,
+	/// The value is longer than 65535 bytes, or it doesn't fit in the provided buffer.
+	ValueTooBig,
+	/// There isn't enough space left in the store, even after a compaction.
+	Full,
+	/// The stored value has a different type than the requested one.
+	WrongType,
+}
+
+impl<F: NorFlash> Debug for Error<F>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Flash(arg0) => f.debug_tuple("Flash").field(arg0).finish(),
+			Self::NotMounted => write!(f, "NotMounted"),
+			Self::NotFormatted => write!(f, "NotFormatted"),
+			Self::KeyTooLong => write!(f, "KeyTooLong"),
+			Self::ValueTooBig => write!(f, "ValueTooBig"),
+			Self::Full => write!(f, "Full"),
+			Self::WrongType => write!(f, "WrongType"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::{
+		drivers::{
+			spi_flash_memory::{FlashMemoryChip, FlashMemoryChipExt, SpiFlashMemory, MT29F2G01ABAGDWB},
+			spi_nor_flash::SpiNorFlash,
+		},
+		hardware::mock::{MockSpiNandFlash, MockSpiNorFlash},
+		utils::physical_quantities::{duration::SmallDuration, temperature::Temperature},
+	};
+
+	const BANK_SIZE: u32 = 8 * 1024;
+
+	fn nor_flash() -> SpiNorFlash<MockSpiNorFlash>
+	{
+		SpiNorFlash::from_sfdp(MockSpiNorFlash::new(1024 * 1024)).unwrap()
+	}
+
+	#[test]
+	fn typed_values()
+	{
+		let mut buffer = [0; 16];
+		let mut kv_store = KvStore::new(nor_flash(), 0, BANK_SIZE, &mut buffer);
+		assert!(matches!(kv_store.get::<u8>("a"), Err(Error::NotMounted)));
+		assert!(matches!(kv_store.mount(), Err(Error::NotFormatted)));
+		kv_store.format().unwrap();
+
+		kv_store.set("temperature", &Temperature::from_kelvin(300.5)).unwrap();
+		kv_store
+			.set("period", &SmallDuration::from_tens_of_nanos(1234))
+			.unwrap();
+		kv_store
+			.set("timeout", &core::time::Duration::from_millis(1500))
+			.unwrap();
+		kv_store.set("enabled", &true).unwrap();
+		kv_store.set("enabled", &false).unwrap();
+
+		assert_eq!(
+			kv_store.get::<Temperature>("temperature").unwrap(),
+			Some(Temperature::from_kelvin(300.5))
+		);
+		assert_eq!(
+			kv_store
+				.get::<SmallDuration>("period")
+				.unwrap()
+				.map(|period| period.as_tens_of_nanos()),
+			Some(1234)
+		);
+		assert_eq!(
+			kv_store.get::<core::time::Duration>("timeout").unwrap(),
+			Some(core::time::Duration::from_millis(1500))
+		);
+		assert_eq!(kv_store.get::<bool>("enabled").unwrap(), Some(false));
+		assert!(matches!(kv_store.get::<u64>("enabled"), Err(Error::WrongType)));
+
+		assert!(kv_store.remove("enabled").unwrap());
+		assert!(!kv_store.remove("enabled").unwrap());
+		assert_eq!(kv_store.get::<bool>("enabled").unwrap(), None);
+		assert!(matches!(kv_store.set_bytes("", b"value"), Err(Error::KeyTooLong)));
+
+		// Everything survives a remount
+		let flash = kv_store.release();
+		let mut kv_store = KvStore::new(flash, 0, BANK_SIZE, &mut buffer);
+		kv_store.mount().unwrap();
+		assert_eq!(
+			kv_store.get::<Temperature>("temperature").unwrap(),
+			Some(Temperature::from_kelvin(300.5))
+		);
+		assert!(!kv_store.contains_key("enabled").unwrap());
+	}
+
+	#[test]
+	fn compaction()
+	{
+		let mut buffer = [0; 16];
+		let mut kv_store = KvStore::new(nor_flash(), 4096, BANK_SIZE, &mut buffer);
+		kv_store.format().unwrap();
+
+		kv_store.set_bytes("ssid", &[0xAB; 100]).unwrap();
+		for i in 0..2000u32
+		{
+			kv_store.set("counter", &i).unwrap();
+			kv_store.set("counter squared", &(i * i)).unwrap();
+		}
+		assert!(kv_store.generation > 0);
+		assert_eq!(kv_store.get::<u32>("counter").unwrap(), Some(1999));
+		assert_eq!(kv_store.get::<u32>("counter squared").unwrap(), Some(1999 * 1999));
+		let mut ssid = [0; 100];
+		assert_eq!(kv_store.get_bytes("ssid", &mut ssid).unwrap(), Some(100));
+		assert_eq!(ssid, [0xAB; 100]);
+
+		// Setting the same value doesn't write anything
+		let free_space = kv_store.get_free_space();
+		kv_store.set("counter", &1999u32).unwrap();
+		assert_eq!(kv_store.get_free_space(), free_space);
+
+		assert!(matches!(
+			kv_store.set_bytes("big", &[0; BANK_SIZE as usize]),
+			Err(Error::Full)
+		));
+		assert!(matches!(
+			kv_store.get_bytes("ssid", &mut [0; 10]),
+			Err(Error::ValueTooBig)
+		));
+
+		let generation = kv_store.generation;
+		let flash = kv_store.release();
+		let mut kv_store = KvStore::new(flash, 4096, BANK_SIZE, &mut buffer);
+		kv_store.mount().unwrap();
+		assert_eq!(kv_store.generation, generation);
+		assert_eq!(kv_store.get::<u32>("counter").unwrap(), Some(1999));
+	}
+
+	#[test]
+	fn torn_write_recovery()
+	{
+		let mut buffer = [0; 16];
+		let mut kv_store = KvStore::new(nor_flash(), 0, BANK_SIZE, &mut buffer);
+		kv_store.format().unwrap();
+		kv_store.set("kp", &1.5f32).unwrap();
+
+		// The power is lost while setting a new value, after the header has been written
+		let write_offset = kv_store.write_offset;
+		let mut header = [0; HEADER_SIZE as usize];
+		kv_store.get_flash_mut().read(write_offset - 14, &mut header).unwrap();
+		kv_store.get_flash_mut().write(write_offset, &header).unwrap();
+		kv_store.get_flash_mut().write(write_offset + 8, b"k").unwrap();
+
+		let flash = kv_store.release();
+		let mut kv_store = KvStore::new(flash, 0, BANK_SIZE, &mut buffer);
+		kv_store.mount().unwrap();
+		assert_eq!(kv_store.generation, 1);
+		assert_eq!(kv_store.get::<f32>("kp").unwrap(), Some(1.5));
+		kv_store.set("kp", &2.0f32).unwrap();
+		assert_eq!(kv_store.get::<f32>("kp").unwrap(), Some(2.0));
+
+		// The power is lost during a compaction, before the commit record is written
+		let spare_bank_start = kv_store.get_bank_start(0);
+		NorFlash::erase(kv_store.get_flash_mut(), spare_bank_start, spare_bank_start + BANK_SIZE).unwrap();
+		kv_store.initialize_bank(0, 2).unwrap();
+
+		let flash = kv_store.release();
+		let mut kv_store = KvStore::new(flash, 0, BANK_SIZE, &mut buffer);
+		kv_store.mount().unwrap();
+		assert_eq!((kv_store.active_bank, kv_store.generation), (1, 1));
+		assert_eq!(kv_store.get::<f32>("kp").unwrap(), Some(2.0));
+	}
+
+	#[test]
+	fn nand_flash_memory()
+	{
+		let mut buffer = [0; MT29F2G01ABAGDWB::PAGE_SIZE as usize];
+		let mut flash = SpiFlashMemory::new(MockSpiNandFlash::<MT29F2G01ABAGDWB>::new(), MT29F2G01ABAGDWB);
+		MT29F2G01ABAGDWB::initialize(&mut flash).unwrap();
+		let mut kv_store = KvStore::new(flash, 0, MT29F2G01ABAGDWB::BLOCK_SIZE, &mut buffer);
+		kv_store.format().unwrap();
+
+		for i in 0..1000u16
+		{
+			kv_store.set("setpoint", &i).unwrap();
+		}
+		assert_eq!(kv_store.get::<u16>("setpoint").unwrap(), Some(999));
+	}
+}
@@ -0,0 +1,149 @@
+use core::time::Duration;
+
+use crate::utils::physical_quantities::{
+	angle::Angle, duration::SmallDuration, frequency::Frequency, temperature::Temperature,
+};
+
+/// Max [`StorableValue::SIZE`] of a value.
+pub const MAX_VALUE_SIZE: usize = 16;
+
+/// A type that can be stored in a [`KvStore`](super::KvStore) with [`set`](super::KvStore::set) and read back with
+/// [`get`](super::KvStore::get).
+///
+/// All the values are stored in little endian, so that they can be read by any microcontroller.
+pub trait StorableValue: Sized
+{
+	/// Number of bytes used to store the value (at most [`MAX_VALUE_SIZE`]).
+	const SIZE: usize;
+
+	/// Writes the value in the provided `bytes`, whose length is [`Self::SIZE`].
+	fn to_bytes(&self, bytes: &mut [u8]);
+
+	/// Returns the value stored in the provided `bytes`, whose length is [`Self::SIZE`].
+	///
+	/// Returns `None` if the bytes don't contain a valid value.
+	fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_storable_value_for_numbers {
+	($($type:ty),*) => {
+		$(
+			impl StorableValue for $type
+			{
+				const SIZE: usize = core::mem::size_of::<$type>();
+
+				fn to_bytes(&self, bytes: &mut [u8])
+				{
+					bytes.copy_from_slice(&self.to_le_bytes());
+				}
+
+				fn from_bytes(bytes: &[u8]) -> Option<Self>
+				{
+					Some(Self::from_le_bytes(bytes.try_into().ok()?))
+				}
+			}
+		)*
+	};
+}
+impl_storable_value_for_numbers!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl StorableValue for bool
+{
+	const SIZE: usize = 1;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		bytes[0] = *self as u8;
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		match bytes[0]
+		{
+			0 => Some(false),
+			1 => Some(true),
+			_ => None,
+		}
+	}
+}
+
+impl StorableValue for Temperature
+{
+	const SIZE: usize = f32::SIZE;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		self.as_kelvin().to_bytes(bytes);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		f32::from_bytes(bytes).map(Temperature::from_kelvin)
+	}
+}
+
+impl StorableValue for Angle
+{
+	const SIZE: usize = f32::SIZE;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		self.into_radians().to_bytes(bytes);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		f32::from_bytes(bytes).map(Angle::from_radians)
+	}
+}
+
+impl StorableValue for Frequency
+{
+	const SIZE: usize = u32::SIZE;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		self.as_hertz().to_bytes(bytes);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		u32::from_bytes(bytes).map(Frequency::from_hertz)
+	}
+}
+
+impl StorableValue for SmallDuration
+{
+	const SIZE: usize = u32::SIZE;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		self.as_tens_of_nanos().to_bytes(bytes);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		u32::from_bytes(bytes).map(SmallDuration::from_tens_of_nanos)
+	}
+}
+
+impl StorableValue for Duration
+{
+	const SIZE: usize = u64::SIZE + u32::SIZE;
+
+	fn to_bytes(&self, bytes: &mut [u8])
+	{
+		self.as_secs().to_bytes(&mut bytes[..8]);
+		self.subsec_nanos().to_bytes(&mut bytes[8..]);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self>
+	{
+		let nanos = u32::from_bytes(&bytes[8..])?;
+		match nanos < 1_000_000_000
+		{
+			true => Some(Duration::new(u64::from_bytes(&bytes[..8])?, nanos)),
+			false => None,
+		}
+	}
+}
@@ -1,3 +1,8 @@
-//! Provides some ways to store data, based on the traits in [`embedded_storage`].
+//! Provides some ways to store data, based on the traits in [`embedded_storage`] or on a
+//! [`FlashMemory`](crate::drivers::spi_flash_memory::FlashMemory).
 
 pub use embedded_sdmmc;
+
+pub mod firmware;
+pub mod kv_store;
+pub mod ring_log;
@@ -0,0 +1,984 @@
+//! A circular log of timestamped records (like sensor data) on a [`FlashMemory`], where the oldest records are
+//! erased to make space for the new ones.
+//!
+//! ## Layout
+//! The records are appended in a page in RAM, which is programmed in the next free page of the flash memory when it's
+//! full (or when the log is [`flushed`]). Each record is made of its length, its timestamp, its data and its length
+//! again, so that the records can be read in both directions, and it can't be split across 2 pages.
+//!
+//! Each page contains in its ECC area a small tag with the sequence number of its block, the timestamps of its first
+//! and last records and the number of bytes used. The blocks are used in a circle, and the block after the newest one
+//! is erased (losing the oldest records) when the newest one is full.
+//!
+//! ## Mount
+//! At mount only the tag of the first page of each block is read, to find the newest and the oldest blocks, and the
+//! first free page of the newest block is found with a binary search. The same tags are used to quickly [`find`]
+//! the records written at a certain time.
+//!
+//! A page whose tag is corrupted (probably because of a power loss while programming it) is skipped.
+//!
+//! ## Timestamps
+//! The timestamps are provided by a [`LogClock`], which is implemented by all the [`SystemTime`]s and by the
+//! [`RealTimeClock`]s wrapped in a [`RealTimeLogClock`]. Since the time of a [`SystemTime`] restarts from zero at
+//! every boot, its timestamps are shifted to continue from the newest record in the log.
+//!
+//! # Warning
+//! The bad blocks aren't handled, so if the flash memory can contain bad blocks you should use a
+//! [`bad block manager`] as the flash memory of the log.
+//!
+//! [`flushed`]: RingLog::flush
+//! [`find`]: RingLog::find
+//! [`bad block manager`]: crate::drivers::spi_flash_memory::bad_block_manager::BadBlockManager
+
+use core::{convert::Infallible, fmt::Debug, time::Duration};
+
+use time::PrimitiveDateTime;
+
+use crate::{
+	drivers::spi_flash_memory::{EccStatus, FlashMemory, FlashMemoryChip, FlashMemoryChipExt, RowAddress},
+	peripherals::time::{real_time::RealTimeClock, system_time::SystemTime},
+	utils::algorithms::crc::Crc32,
+};
+
+/// Offset in the ECC area of a page where the tag is stored (the first bytes are used for the bad block mark).
+const TAG_OFFSET: usize = 4;
+/// Size of the tag stored in the ECC area of each page.
+const TAG_SIZE: usize = 28;
+/// Size of the length and of the timestamp before the data of a record.
+const RECORD_HEADER_SIZE: usize = 10;
+/// Size of the length after the data of a record.
+const RECORD_FOOTER_SIZE: usize = 2;
+
+/// A circular log of timestamped records that uses `blocks` blocks of a [`FlashMemory`].
+///
+/// Check [`module's documentation`](self) for more info.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{
+/// #     drivers::spi_flash_memory::*,
+/// #     features::storage::ring_log::*,
+/// #     hardware::mock::{MockFlashMemory, MockSystemTime},
+/// # };
+/// # use core::time::Duration;
+/// #
+/// let mut page_buffer = [0; MT29F2G01ABAGDWB::PAGE_SIZE as usize];
+/// let flash_memory = MockFlashMemory::<MT29F2G01ABAGDWB>::new();
+/// let clock = MockSystemTime { current_time: Duration::from_secs(10) };
+/// let mut ring_log = RingLog::new(flash_memory, clock, 0, 4, &mut page_buffer);
+/// ring_log.format().unwrap();
+///
+/// ring_log.append(&21.5f32.to_le_bytes()).unwrap();
+/// ring_log.get_clock_mut().current_time = Duration::from_secs(11);
+/// ring_log.append(&22.0f32.to_le_bytes()).unwrap();
+/// ring_log.flush().unwrap();
+///
+/// let mut cursor = ring_log.find(Duration::from_secs(11)).unwrap();
+/// let mut data = [0; 4];
+/// let record = ring_log.read_next(&mut cursor, &mut data).unwrap().unwrap();
+/// assert_eq!((record.timestamp, f32::from_le_bytes(data)), (Duration::from_secs(11), 22.0));
+/// let record = ring_log.read_previous(&mut cursor, &mut data).unwrap().unwrap();
+/// assert_eq!(record.timestamp, Duration::from_secs(11));
+/// let record = ring_log.read_previous(&mut cursor, &mut data).unwrap().unwrap();
+/// assert_eq!((record.timestamp, f32::from_le_bytes(data)), (Duration::from_secs(10), 21.5));
+/// ```
+pub struct RingLog<'a, F: FlashMemory, C: LogClock>
+{
+	flash: F,
+	clock: C,
+	first_block: u16,
+	blocks: u16,
+	page_buffer: &'a mut [u8],
+	is_mounted: bool,
+
+	/// Added to the timestamps of the clock, to continue from the newest record when the clock restarts at boot.
+	time_offset: Duration,
+	last_timestamp: Duration,
+
+	head_block: u16,
+	head_sequence: u32,
+	tail_sequence: u32,
+	/// Index of the next page to program in the head block.
+	next_page: u32,
+
+	/// Number of bytes of the page buffer used by the records not programmed yet.
+	buffer_used: usize,
+	buffer_first_timestamp: Duration,
+}
+
+impl<'a, F: FlashMemory, C: LogClock> RingLog<'a, F, C>
+{
+	const PAGES_PER_BLOCK: u32 = F::Chip::PAGES_PER_BLOCK;
+	const PAGE_SIZE: usize = F::Chip::PAGE_SIZE as usize;
+
+	/// Max length of the data of a record.
+	pub const MAX_RECORD_SIZE: usize = Self::PAGE_SIZE - RECORD_HEADER_SIZE - RECORD_FOOTER_SIZE;
+
+	/// Returns a [`RingLog`] that uses the `blocks` blocks of the provided `flash` starting from the one at
+	/// `first_block`, and the provided `clock` to timestamp the records. The `page_buffer` contains the records
+	/// that haven't been programmed yet.
+	///
+	/// # Warning
+	/// Before using it, you need to either [`format`] or [`mount`] it.
+	///
+	/// # Panics
+	/// Panics if `blocks < 2`, if the blocks don't exist in the flash memory or if
+	/// `page_buffer.len() < Chip::PAGE_SIZE`.
+	///
+	/// [`format`]: Self::format
+	/// [`mount`]: Self::mount
+	pub fn new(flash: F, clock: C, first_block: u16, blocks: u16, page_buffer: &'a mut [u8]) -> Self
+	{
+		assert!(blocks >= 2);
+		assert!((first_block as u32 + blocks as u32) <= F::Chip::BLOCKS_COUNT);
+		assert!(page_buffer.len() >= Self::PAGE_SIZE);
+
+		Self {
+			flash,
+			clock,
+			first_block,
+			blocks,
+			page_buffer,
+			is_mounted: false,
+			time_offset: Duration::ZERO,
+			last_timestamp: Duration::ZERO,
+			head_block: 0,
+			head_sequence: 0,
+			tail_sequence: 0,
+			next_page: 0,
+			buffer_used: 0,
+			buffer_first_timestamp: Duration::ZERO,
+		}
+	}
+
+	/// Erases all the blocks of the log, losing all the records written before.
+	pub fn format(&mut self) -> Result<(), Error<F, C>>
+	{
+		self.is_mounted = false;
+		self.flash
+			.erase_blocks(self.first_block..=(self.first_block + self.blocks - 1))
+			.map_err(Error::Flash)?;
+
+		self.set_empty();
+		self.time_offset = Duration::ZERO;
+		self.last_timestamp = Duration::ZERO;
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Finds the newest and the oldest records in the log.
+	///
+	/// Returns `Ok(())` if the log has been mounted, otherwise returns `Err(...)`.
+	pub fn mount(&mut self) -> Result<(), Error<F, C>>
+	{
+		self.is_mounted = false;
+		self.set_empty();
+		self.time_offset = Duration::ZERO;
+		self.last_timestamp = Duration::ZERO;
+
+		let mut head = None;
+		let mut tail_sequence = u32::MAX;
+		for block in 0..self.blocks
+		{
+			if let ReadTag::Valid(tag) = self.read_tag(block, 0)?
+			{
+				if head.is_none_or(|(_, head_sequence)| tag.sequence > head_sequence)
+				{
+					head = Some((block, tag.sequence));
+				}
+				tail_sequence = tail_sequence.min(tag.sequence);
+			}
+		}
+
+		if let Some((head_block, head_sequence)) = head
+		{
+			self.head_block = head_block;
+			self.head_sequence = head_sequence;
+			self.tail_sequence = tail_sequence.max(head_sequence.saturating_sub(self.blocks as u32 - 1));
+
+			// The pages are programmed in order, so the first free one can be found with a binary search
+			let (mut low, mut high) = (1, Self::PAGES_PER_BLOCK);
+			while low < high
+			{
+				let page = (low + high) / 2;
+				match self.read_tag(head_block, page)?
+				{
+					ReadTag::Erased => high = page,
+					_ => low = page + 1,
+				}
+			}
+			self.next_page = low;
+
+			for page in (0..self.next_page).rev()
+			{
+				if let ReadTag::Valid(tag) = self.read_tag(head_block, page)?
+				{
+					self.last_timestamp = tag.last_timestamp;
+					break;
+				}
+			}
+			if C::RESTARTS_AT_BOOT
+			{
+				self.time_offset = self.last_timestamp;
+			}
+		}
+
+		self.is_mounted = true;
+
+		Ok(())
+	}
+
+	/// Appends a record with the provided `data`, timestamped with the current time of the clock.
+	///
+	/// Returns `Ok(timestamp)` with the timestamp of the record if it has been appended, otherwise returns
+	/// `Err(...)`.
+	///
+	/// # Warning
+	/// The record is only kept in RAM until the page buffer is full, so call [`flush`] to make sure it's saved.
+	///
+	/// [`flush`]: Self::flush
+	pub fn append(&mut self, data: &[u8]) -> Result<Duration, Error<F, C>>
+	{
+		self.check_mounted()?;
+		if data.len() > Self::MAX_RECORD_SIZE
+		{
+			return Err(Error::RecordTooBig);
+		}
+
+		let record_size = RECORD_HEADER_SIZE + data.len() + RECORD_FOOTER_SIZE;
+		if self.buffer_used + record_size > Self::PAGE_SIZE
+		{
+			self.flush()?;
+		}
+
+		// The timestamps never go backwards, so that the records can be found with a binary search
+		let timestamp = self.clock.get_timestamp().map_err(Error::Clock)? + self.time_offset;
+		let timestamp = Duration::from_micros(timestamp.as_micros() as u64).max(self.last_timestamp);
+		self.last_timestamp = timestamp;
+		if self.buffer_used == 0
+		{
+			self.page_buffer[..Self::PAGE_SIZE].fill(0xFF);
+			self.buffer_first_timestamp = timestamp;
+		}
+
+		let record = &mut self.page_buffer[self.buffer_used..self.buffer_used + record_size];
+		record[0..2].copy_from_slice(&(data.len() as u16).to_le_bytes());
+		record[2..10].copy_from_slice(&(timestamp.as_micros() as u64).to_le_bytes());
+		record[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + data.len()].copy_from_slice(data);
+		record[RECORD_HEADER_SIZE + data.len()..].copy_from_slice(&(data.len() as u16).to_le_bytes());
+		self.buffer_used += record_size;
+
+		Ok(timestamp)
+	}
+
+	/// Programs the records kept in RAM in the next free page. If the newest block is full, the next one is erased
+	/// first, losing the oldest records.
+	///
+	/// Returns `Ok(())` if all the records have been saved, otherwise returns `Err(...)`.
+	///
+	/// # Note
+	/// A page can only be programmed once, so the space left in the page is wasted.
+	pub fn flush(&mut self) -> Result<(), Error<F, C>>
+	{
+		self.check_mounted()?;
+		if self.buffer_used == 0
+		{
+			return Ok(());
+		}
+
+		if self.next_page == Self::PAGES_PER_BLOCK
+		{
+			self.open_next_block()?;
+		}
+
+		let tag = PageTag {
+			sequence: self.head_sequence,
+			first_timestamp: self.buffer_first_timestamp,
+			last_timestamp: self.last_timestamp,
+			used: self.buffer_used as u16,
+		};
+		let mut ecc_data = [0xFF; TAG_OFFSET + TAG_SIZE];
+		ecc_data[TAG_OFFSET..].copy_from_slice(&tag.to_bytes());
+		self.flash
+			.program_page(
+				self.get_row_address(self.head_block, self.next_page),
+				&self.page_buffer[..self.buffer_used],
+				&ecc_data,
+			)
+			.map_err(Error::Flash)?;
+
+		self.next_page += 1;
+		self.buffer_used = 0;
+
+		Ok(())
+	}
+
+	/// Returns `true` if there aren't any records in the log.
+	pub fn is_empty(&self) -> bool
+	{
+		self.get_first_page() == self.get_buffer_page() && self.buffer_used == 0
+	}
+
+	/// Returns a [`LogCursor`] before the oldest record in the log.
+	pub fn get_first_cursor(&self) -> LogCursor
+	{
+		LogCursor {
+			page: self.get_first_page(),
+			offset: 0,
+		}
+	}
+
+	/// Returns a [`LogCursor`] after the newest record in the log.
+	pub fn get_last_cursor(&self) -> LogCursor
+	{
+		LogCursor {
+			page: self.get_buffer_page(),
+			offset: self.buffer_used as u16,
+		}
+	}
+
+	/// Returns `Ok(cursor)` with a [`LogCursor`] before the oldest record whose timestamp is at least `timestamp`
+	/// (or after the newest record if there isn't one), otherwise returns `Err(...)`.
+	pub fn find(&mut self, timestamp: Duration) -> Result<LogCursor, Error<F, C>>
+	{
+		self.check_mounted()?;
+
+		// Finds the newest block that starts before the timestamp, with a binary search
+		let (mut low, mut high) = (self.tail_sequence, self.head_sequence + 1);
+		while low < high
+		{
+			let sequence = low + (high - low) / 2;
+			let block = self.get_block_of_sequence(sequence);
+			match self.read_tag(block, 0)?
+			{
+				ReadTag::Valid(tag) if tag.first_timestamp >= timestamp => high = sequence,
+				_ => low = sequence + 1,
+			}
+		}
+
+		// Then finds the newest page that starts before the timestamp in it
+		let mut cursor = self.get_first_cursor();
+		if low > self.tail_sequence
+		{
+			let sequence = low - 1;
+			let block = self.get_block_of_sequence(sequence);
+			let mut page_in_block = 0;
+			let last_page = match sequence == self.head_sequence
+			{
+				true => self.next_page,
+				false => Self::PAGES_PER_BLOCK,
+			};
+			for page in 1..last_page
+			{
+				match self.read_tag(block, page)?
+				{
+					ReadTag::Valid(tag) if tag.first_timestamp >= timestamp => break,
+					ReadTag::Valid(_) => page_in_block = page,
+					_ => (),
+				}
+			}
+			cursor.page = sequence as u64 * Self::PAGES_PER_BLOCK as u64 + page_in_block as u64;
+		}
+
+		// And finally the record
+		loop
+		{
+			let mut next_cursor = cursor;
+			match self.read_next(&mut next_cursor, &mut [])?
+			{
+				Some(record) if record.timestamp < timestamp => cursor = next_cursor,
+				Some(_) => return Ok(cursor),
+				None => return Ok(self.get_last_cursor()),
+			}
+		}
+	}
+
+	/// Reads the data of the record after the `cursor` in `data`, and moves the `cursor` after it.
+	///
+	/// Returns `Ok(Some(record))` with the timestamp and the length of the record, `Ok(None)` if the `cursor` is
+	/// after the newest record, otherwise returns `Err(...)`. Only `data.len()` bytes of the data are read if the
+	/// record is longer.
+	///
+	/// # Note
+	/// If the record after the `cursor` has been erased, the oldest record is read.
+	pub fn read_next(&mut self, cursor: &mut LogCursor, data: &mut [u8]) -> Result<Option<LogRecord>, Error<F, C>>
+	{
+		self.check_mounted()?;
+		if cursor.page < self.get_first_page()
+		{
+			*cursor = self.get_first_cursor();
+		}
+
+		loop
+		{
+			if *cursor >= self.get_last_cursor()
+			{
+				return Ok(None);
+			}
+			if cursor.offset >= self.get_page_used(cursor.page)?
+			{
+				cursor.page += 1;
+				cursor.offset = 0;
+				continue;
+			}
+
+			let mut header = [0; RECORD_HEADER_SIZE];
+			self.read_page(cursor.page, cursor.offset as usize, &mut header)?;
+			let record = LogRecord::from_header(&header);
+			let data_len = record.length.min(data.len());
+			self.read_page(
+				cursor.page,
+				cursor.offset as usize + RECORD_HEADER_SIZE,
+				&mut data[..data_len],
+			)?;
+
+			cursor.offset += (RECORD_HEADER_SIZE + record.length + RECORD_FOOTER_SIZE) as u16;
+			return Ok(Some(record));
+		}
+	}
+
+	/// Reads the data of the record before the `cursor` in `data`, and moves the `cursor` before it.
+	///
+	/// Returns `Ok(Some(record))` with the timestamp and the length of the record, `Ok(None)` if the `cursor` is
+	/// before the oldest record, otherwise returns `Err(...)`. Only `data.len()` bytes of the data are read if the
+	/// record is longer.
+	pub fn read_previous(&mut self, cursor: &mut LogCursor, data: &mut [u8]) -> Result<Option<LogRecord>, Error<F, C>>
+	{
+		self.check_mounted()?;
+		if *cursor > self.get_last_cursor()
+		{
+			*cursor = self.get_last_cursor();
+		}
+
+		loop
+		{
+			if *cursor <= self.get_first_cursor()
+			{
+				return Ok(None);
+			}
+			if cursor.offset == 0
+			{
+				cursor.page -= 1;
+				cursor.offset = self.get_page_used(cursor.page)?;
+				continue;
+			}
+
+			let mut footer = [0; RECORD_FOOTER_SIZE];
+			self.read_page(cursor.page, cursor.offset as usize - RECORD_FOOTER_SIZE, &mut footer)?;
+			let record_offset =
+				cursor.offset as usize - RECORD_FOOTER_SIZE - u16::from_le_bytes(footer) as usize - RECORD_HEADER_SIZE;
+
+			let mut header = [0; RECORD_HEADER_SIZE];
+			self.read_page(cursor.page, record_offset, &mut header)?;
+			let record = LogRecord::from_header(&header);
+			let data_len = record.length.min(data.len());
+			self.read_page(cursor.page, record_offset + RECORD_HEADER_SIZE, &mut data[..data_len])?;
+
+			cursor.offset = record_offset as u16;
+			return Ok(Some(record));
+		}
+	}
+
+	/// Returns a mutable reference to the clock used to timestamp the records.
+	pub fn get_clock_mut(&mut self) -> &mut C
+	{
+		&mut self.clock
+	}
+
+	/// Returns a mutable reference to the underlying flash memory.
+	///
+	/// # Warning
+	/// Writing in the blocks used by the log corrupts it.
+	pub fn get_flash_mut(&mut self) -> &mut F
+	{
+		&mut self.flash
+	}
+
+	/// Consumes this struct and returns the underlying flash memory.
+	///
+	/// # Warning
+	/// The records that haven't been [`flushed`] are lost.
+	///
+	/// [`flushed`]: Self::flush
+	pub fn release(self) -> F
+	{
+		self.flash
+	}
+
+	fn check_mounted(&self) -> Result<(), Error<F, C>>
+	{
+		match self.is_mounted
+		{
+			true => Ok(()),
+			false => Err(Error::NotMounted),
+		}
+	}
+
+	/// Sets the state of an empty log, whose first record will be programmed in the first block.
+	fn set_empty(&mut self)
+	{
+		self.head_block = self.blocks - 1;
+		self.head_sequence = 0;
+		self.tail_sequence = 1;
+		self.next_page = Self::PAGES_PER_BLOCK;
+		self.buffer_used = 0;
+	}
+
+	/// Erases the block after the head one and makes it the head one. If all the blocks are used, the tail block is
+	/// the one erased.
+	fn open_next_block(&mut self) -> Result<(), Error<F, C>>
+	{
+		let block = (self.head_block + 1) % self.blocks;
+		let sequence = self.head_sequence + 1;
+		if sequence - self.tail_sequence >= self.blocks as u32
+		{
+			self.tail_sequence += 1;
+		}
+
+		let block_index = self.first_block + block;
+		self.flash
+			.erase_blocks(block_index..=block_index)
+			.map_err(Error::Flash)?;
+
+		self.head_block = block;
+		self.head_sequence = sequence;
+		self.next_page = 0;
+
+		Ok(())
+	}
+
+	/// Returns the absolute index of the oldest page.
+	fn get_first_page(&self) -> u64
+	{
+		self.tail_sequence as u64 * Self::PAGES_PER_BLOCK as u64
+	}
+
+	/// Returns the absolute index of the page that will contain the records in the page buffer.
+	fn get_buffer_page(&self) -> u64
+	{
+		self.head_sequence as u64 * Self::PAGES_PER_BLOCK as u64 + self.next_page as u64
+	}
+
+	fn get_block_of_sequence(&self, sequence: u32) -> u16
+	{
+		let blocks_before_head = ((self.head_sequence - sequence) % self.blocks as u32) as u16;
+		(self.head_block + self.blocks - blocks_before_head) % self.blocks
+	}
+
+	fn get_row_address(&self, block: u16, page_in_block: u32) -> RowAddress<F::Chip>
+	{
+		RowAddress::from_memory_address(self.get_address_of_page(block, page_in_block))
+	}
+
+	fn get_address_of_page(&self, block: u16, page_in_block: u32) -> u32
+	{
+		F::Chip::get_address_of_block_index(self.first_block + block) + page_in_block * F::Chip::PAGE_SIZE
+	}
+
+	fn read_tag(&mut self, block: u16, page_in_block: u32) -> Result<ReadTag, Error<F, C>>
+	{
+		let mut bytes = [0; TAG_OFFSET + TAG_SIZE];
+		self.flash
+			.read_ecc(self.get_row_address(block, page_in_block), &mut bytes)
+			.map_err(Error::Flash)?;
+
+		Ok(PageTag::from_bytes(&bytes[TAG_OFFSET..]))
+	}
+
+	/// Returns how many bytes of the page at the absolute index `page` contain records (0 if its tag is corrupted).
+	fn get_page_used(&mut self, page: u64) -> Result<u16, Error<F, C>>
+	{
+		if page == self.get_buffer_page()
+		{
+			return Ok(self.buffer_used as u16);
+		}
+
+		let sequence = (page / Self::PAGES_PER_BLOCK as u64) as u32;
+		let page_in_block = (page % Self::PAGES_PER_BLOCK as u64) as u32;
+		match self.read_tag(self.get_block_of_sequence(sequence), page_in_block)?
+		{
+			ReadTag::Valid(tag) => Ok(tag.used),
+			_ => Ok(0),
+		}
+	}
+
+	/// Reads the bytes at `offset` of the page at the absolute index `page`, from the page buffer if it hasn't been
+	/// programmed yet.
+	fn read_page(&mut self, page: u64, offset: usize, data: &mut [u8]) -> Result<(), Error<F, C>>
+	{
+		if page == self.get_buffer_page()
+		{
+			data.copy_from_slice(&self.page_buffer[offset..offset + data.len()]);
+			return Ok(());
+		}
+
+		let sequence = (page / Self::PAGES_PER_BLOCK as u64) as u32;
+		let page_in_block = (page % Self::PAGES_PER_BLOCK as u64) as u32;
+		let address = self.get_address_of_page(self.get_block_of_sequence(sequence), page_in_block) + offset as u32;
+		match self.flash.read(address, data).map_err(Error::Flash)?
+		{
+			EccStatus::Uncorrectable => Err(Error::Uncorrectable),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// A position between 2 records of a [`RingLog`], used to read the records in both directions.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct LogCursor
+{
+	/// Absolute index of the page, that keeps increasing as the log wraps around.
+	page: u64,
+	offset: u16,
+}
+
+/// A record read from a [`RingLog`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LogRecord
+{
+	/// When the record has been appended.
+	pub timestamp: Duration,
+	/// Length of the data of the record.
+	pub length: usize,
+}
+
+impl LogRecord
+{
+	fn from_header(header: &[u8; RECORD_HEADER_SIZE]) -> Self
+	{
+		Self {
+			length: u16::from_le_bytes([header[0], header[1]]) as usize,
+			timestamp: Duration::from_micros(u64::from_le_bytes(header[2..10].try_into().unwrap())),
+		}
+	}
+}
+
+/// A clock that provides the timestamps of the records of a [`RingLog`].
+pub trait LogClock
+{
+	/// Error type that can be returned when reading the time.
+	type Error: Debug;
+
+	/// `true` if the time restarts from zero at every boot, in which case the timestamps are shifted to continue
+	/// from the newest record in the log.
+	const RESTARTS_AT_BOOT: bool;
+
+	/// Returns the current time.
+	fn get_timestamp(&self) -> Result<Duration, Self::Error>;
+}
+
+impl<T: SystemTime> LogClock for T
+{
+	type Error = Infallible;
+
+	const RESTARTS_AT_BOOT: bool = true;
+
+	fn get_timestamp(&self) -> Result<Duration, Self::Error>
+	{
+		Ok(self.now())
+	}
+}
+
+/// A [`LogClock`] that timestamps the records with the time since the Unix epoch of a [`RealTimeClock`].
+pub struct RealTimeLogClock<R: RealTimeClock>(pub R);
+
+impl<R: RealTimeClock> LogClock for RealTimeLogClock<R>
+{
+	type Error = R::Error;
+
+	const RESTARTS_AT_BOOT: bool = false;
+
+	fn get_timestamp(&self) -> Result<Duration, Self::Error>
+	{
+		let (date, time) = self.0.now()?;
+		let nanos = PrimitiveDateTime::new(date, time)
+			.assume_utc()
+			.unix_timestamp_nanos()
+			.max(0);
+
+		Ok(Duration::from_nanos(nanos as u64))
+	}
+}
+
+/// The data stored in the ECC area of each page written by the [`RingLog`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct PageTag
+{
+	sequence: u32,
+	first_timestamp: Duration,
+	last_timestamp: Duration,
+	used: u16,
+}
+
+impl PageTag
+{
+	fn to_bytes(self) -> [u8; TAG_SIZE]
+	{
+		let mut bytes = [0; TAG_SIZE];
+		bytes[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+		bytes[4..12].copy_from_slice(&(self.first_timestamp.as_micros() as u64).to_le_bytes());
+		bytes[12..20].copy_from_slice(&(self.last_timestamp.as_micros() as u64).to_le_bytes());
+		bytes[20..22].copy_from_slice(&self.used.to_le_bytes());
+		bytes[22..24].fill(0xFF);
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..24]);
+		bytes[24..28].copy_from_slice(&crc.finish().to_le_bytes());
+
+		bytes
+	}
+
+	fn from_bytes(bytes: &[u8]) -> ReadTag
+	{
+		if bytes.iter().all(|byte| *byte == 0xFF)
+		{
+			return ReadTag::Erased;
+		}
+
+		let micros = |i: usize| Duration::from_micros(u64::from_le_bytes(bytes[i..(i + 8)].try_into().unwrap()));
+		let tag = Self {
+			sequence: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+			first_timestamp: micros(4),
+			last_timestamp: micros(12),
+			used: u16::from_le_bytes([bytes[20], bytes[21]]),
+		};
+
+		let mut crc = Crc32::new();
+		crc.update(&bytes[0..24]);
+		match crc.finish() == u32::from_le_bytes(bytes[24..28].try_into().unwrap())
+		{
+			true => ReadTag::Valid(tag),
+			false => ReadTag::Corrupted(tag),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ReadTag
+{
+	Erased,
+	Valid(PageTag),
+	/// The page has been programmed, but the tag isn't valid (probably because of a power loss while programming).
+	Corrupted(PageTag),
+}
+
+/// An error returned from a [`RingLog`].
+pub enum Error<F: FlashMemory, C: LogClock>
+{
+	/// There has been an error while communicating with the flash memory.
+	Flash(F::Error),
+	/// There has been an error while reading the time from the clock.
+	Clock(C::Error),
+	/// You need to [`format`] or [`mount`] the log before using it.
+	///
+	/// [`format`]: RingLog::format
+	/// [`mount`]: RingLog::mount
+	NotMounted,
+	/// The data of the record is longer than [`RingLog::MAX_RECORD_SIZE`].
+	RecordTooBig,
+	/// The page containing the record has more flipped bits than the ECC can correct, so its data is corrupted.
+	Uncorrectable,
+}
+
+impl<F: FlashMemory, C: LogClock> Debug for Error<F, C>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Flash(arg0) => f.debug_tuple("Flash").field(arg0).finish(),
+			Self::Clock(arg0) => f.debug_tuple("Clock").field(arg0).finish(),
+			Self::NotMounted => write!(f, "NotMounted"),
+			Self::RecordTooBig => write!(f, "RecordTooBig"),
+			Self::Uncorrectable => write!(f, "Uncorrectable"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::{
+		drivers::spi_flash_memory::MT29F2G01ABAGDWB,
+		hardware::mock::{MockFlashMemory, MockSystemTime},
+	};
+
+	const PAGE_SIZE: usize = MT29F2G01ABAGDWB::PAGE_SIZE as usize;
+	type Log<'a> = RingLog<'a, MockFlashMemory<MT29F2G01ABAGDWB>, MockSystemTime>;
+
+	fn new_log(flash: MockFlashMemory<MT29F2G01ABAGDWB>, blocks: u16, page_buffer: &mut [u8]) -> Log<'_>
+	{
+		RingLog::new(
+			flash,
+			MockSystemTime {
+				current_time: Duration::ZERO,
+			},
+			5,
+			blocks,
+			page_buffer,
+		)
+	}
+
+	fn collect_timestamps(ring_log: &mut Log, reversed: bool) -> Vec<u64>
+	{
+		let mut timestamps = Vec::new();
+		let mut cursor = match reversed
+		{
+			true => ring_log.get_last_cursor(),
+			false => ring_log.get_first_cursor(),
+		};
+		let mut data = [0; 8];
+		loop
+		{
+			let record = match reversed
+			{
+				true => ring_log.read_previous(&mut cursor, &mut data).unwrap(),
+				false => ring_log.read_next(&mut cursor, &mut data).unwrap(),
+			};
+			let Some(record) = record
+			else
+			{
+				return timestamps;
+			};
+			assert_eq!(record.length, 8 + (u64::from_le_bytes(data) % 50) as usize);
+			assert_eq!(record.timestamp.as_secs(), u64::from_le_bytes(data));
+			timestamps.push(u64::from_le_bytes(data));
+		}
+	}
+
+	fn append_seconds(ring_log: &mut Log, seconds: core::ops::Range<u64>)
+	{
+		for second in seconds
+		{
+			ring_log.get_clock_mut().current_time = Duration::from_secs(second);
+			let mut data = [0xAA; 8 + 50];
+			let timestamp = ring_log.get_clock_mut().current_time + ring_log.time_offset;
+			data[..8].copy_from_slice(&timestamp.as_secs().to_le_bytes());
+			ring_log
+				.append(&data[..8 + (timestamp.as_secs() % 50) as usize])
+				.unwrap();
+		}
+	}
+
+	#[test]
+	fn iterate_in_both_directions()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ring_log = new_log(MockFlashMemory::new(), 3, &mut page_buffer);
+		assert!(matches!(ring_log.append(&[0]), Err(Error::NotMounted)));
+		ring_log.format().unwrap();
+		assert!(ring_log.is_empty());
+
+		// Some records are programmed and some are still in RAM
+		append_seconds(&mut ring_log, 0..200);
+		let timestamps = collect_timestamps(&mut ring_log, false);
+		assert_eq!(timestamps, (0..200).collect::<Vec<_>>());
+		let timestamps = collect_timestamps(&mut ring_log, true);
+		assert_eq!(timestamps, (0..200).rev().collect::<Vec<_>>());
+
+		// After a reboot the time of the clock restarts from zero, but the timestamps don't
+		ring_log.flush().unwrap();
+		let flash = ring_log.release();
+		let mut ring_log = new_log(flash, 3, &mut page_buffer);
+		ring_log.mount().unwrap();
+		assert_eq!(ring_log.time_offset, Duration::from_secs(199));
+		append_seconds(&mut ring_log, 1..11);
+		ring_log.flush().unwrap();
+		let timestamps = collect_timestamps(&mut ring_log, false);
+		assert_eq!(timestamps, (0..210).collect::<Vec<_>>());
+
+		let mut cursor = ring_log.find(Duration::from_secs(150)).unwrap();
+		let mut data = [0; 8];
+		let record = ring_log.read_next(&mut cursor, &mut data).unwrap().unwrap();
+		assert_eq!(record.timestamp, Duration::from_secs(150));
+		let record = ring_log.read_previous(&mut cursor, &mut data).unwrap().unwrap();
+		assert_eq!(record.timestamp, Duration::from_secs(150));
+		let record = ring_log.read_previous(&mut cursor, &mut data).unwrap().unwrap();
+		assert_eq!(record.timestamp, Duration::from_secs(149));
+
+		assert_eq!(
+			ring_log.find(Duration::from_secs(1000)).unwrap(),
+			ring_log.get_last_cursor()
+		);
+		assert!(matches!(ring_log.append(&[0; PAGE_SIZE]), Err(Error::RecordTooBig)));
+	}
+
+	#[test]
+	fn oldest_block_is_erased_when_full()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ring_log = new_log(MockFlashMemory::new(), 2, &mut page_buffer);
+		ring_log.format().unwrap();
+
+		// Each page contains about 30 records, so each block contains about 1900 records
+		append_seconds(&mut ring_log, 0..10_000);
+		ring_log.flush().unwrap();
+		assert_eq!(ring_log.get_flash_mut().get_erase_count(5), 3);
+
+		let timestamps = collect_timestamps(&mut ring_log, false);
+		let first_timestamp = timestamps[0];
+		assert!(first_timestamp > 5000);
+		assert_eq!(timestamps, (first_timestamp..10_000).collect::<Vec<_>>());
+
+		let mut cursor = ring_log.find(Duration::from_secs(7777)).unwrap();
+		let mut data = [0; 8];
+		let record = ring_log.read_next(&mut cursor, &mut data).unwrap().unwrap();
+		assert_eq!(record.timestamp, Duration::from_secs(7777));
+		assert_eq!(ring_log.find(Duration::ZERO).unwrap(), ring_log.get_first_cursor());
+
+		let flash = ring_log.release();
+		let mut ring_log = new_log(flash, 2, &mut page_buffer);
+		ring_log.mount().unwrap();
+		assert_eq!(collect_timestamps(&mut ring_log, true).len(), timestamps.len());
+	}
+
+	#[test]
+	fn corrupted_page_is_skipped()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ring_log = new_log(MockFlashMemory::new(), 2, &mut page_buffer);
+		ring_log.format().unwrap();
+		append_seconds(&mut ring_log, 0..10);
+		ring_log.flush().unwrap();
+
+		// The power is lost while programming the next page
+		let row_address = ring_log.get_row_address(0, 1);
+		let mut ecc_data = [0xFF; TAG_OFFSET + TAG_SIZE];
+		ecc_data[TAG_OFFSET..TAG_OFFSET + 4].copy_from_slice(&[1, 0, 0, 0]);
+		ring_log
+			.get_flash_mut()
+			.program_page(row_address, &[0x12; 100], &ecc_data)
+			.unwrap();
+
+		let flash = ring_log.release();
+		let mut ring_log = new_log(flash, 2, &mut page_buffer);
+		ring_log.mount().unwrap();
+		assert_eq!(ring_log.next_page, 2);
+		append_seconds(&mut ring_log, 1..6);
+		ring_log.flush().unwrap();
+		assert_eq!(collect_timestamps(&mut ring_log, false), (0..15).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn uncorrectable_page_isnt_returned()
+	{
+		let mut page_buffer = [0; PAGE_SIZE];
+		let mut ring_log = new_log(MockFlashMemory::new(), 2, &mut page_buffer);
+		ring_log.format().unwrap();
+		append_seconds(&mut ring_log, 0..10);
+		ring_log.flush().unwrap();
+
+		let page_index = ring_log.get_row_address(0, 0).get_page_index();
+		ring_log
+			.get_flash_mut()
+			.set_ecc_status(page_index, EccStatus::Uncorrectable);
+		let mut cursor = ring_log.get_first_cursor();
+		let mut data = [0; 8];
+		assert!(matches!(
+			ring_log.read_next(&mut cursor, &mut data),
+			Err(Error::Uncorrectable)
+		));
+	}
+}
@@ -0,0 +1,637 @@
+//! A manager of several heaters (zones) that share the same power supply, like the bed, the hotend and the chamber of a
+//! 3D printer.
+
+use core::cmp::Reverse;
+
+use embedded_hal::digital::OutputPin;
+
+use super::pid::{PidController, ThermalFault, TickError};
+use crate::{
+	drivers::temperature_sensor::TemperatureSensor,
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
+
+/// A heater managed by a [`HeaterManager`], which is usually a [`TemperaturePidController`].
+///
+/// It's also implemented for `&mut Z`, so that a manager can be made of `&mut dyn HeaterZone<Context = C>` when the
+/// zones aren't all of the same type (for example when they use different sensors).
+///
+/// [`TemperaturePidController`]: super::TemperaturePidController
+pub trait HeaterZone
+{
+	/// What is needed to tick the zone (check [`TemperatureSensor::Context`]).
+	type Context;
+
+	/// Check [`PidController::request_heat`].
+	fn request_heat(&mut self, delta_time: f32, context: &mut Self::Context) -> Result<Percentage, TickError>;
+	/// Check [`PidController::give_heat`].
+	fn give_heat(&mut self, heat_percentage: Percentage) -> Result<(), TickError>;
+	/// Check [`PidController::get_target_temperature`].
+	fn get_target_temperature(&self) -> Temperature;
+	/// Check [`PidController::get_last_sample_of_current_temperature`].
+	fn get_last_sample_of_current_temperature(&self) -> Option<Temperature>;
+	/// Check [`PidController::get_fault`].
+	fn get_fault(&self) -> Option<ThermalFault>;
+	/// Check [`PidController::shut_down`].
+	fn shut_down(&mut self);
+	/// Check [`PidController::set_pwm_phase`].
+	///
+	/// Returns `Err(TickError::SetPwmHeaterPercentage)` if it has been impossible to set the phase.
+	fn set_pwm_phase(&mut self, phase: Percentage) -> Result<(), TickError>;
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> HeaterZone
+	for PidController<CHP, S, K, W, N>
+{
+	type Context = S::Context;
+
+	fn request_heat(&mut self, delta_time: f32, context: &mut Self::Context) -> Result<Percentage, TickError>
+	{
+		self.request_heat(delta_time, context)
+	}
+
+	fn give_heat(&mut self, heat_percentage: Percentage) -> Result<(), TickError>
+	{
+		self.give_heat(heat_percentage)
+	}
+
+	fn get_target_temperature(&self) -> Temperature
+	{
+		self.get_target_temperature()
+	}
+
+	fn get_last_sample_of_current_temperature(&self) -> Option<Temperature>
+	{
+		self.get_last_sample_of_current_temperature()
+	}
+
+	fn get_fault(&self) -> Option<ThermalFault>
+	{
+		self.get_fault()
+	}
+
+	fn shut_down(&mut self)
+	{
+		self.shut_down()
+	}
+
+	fn set_pwm_phase(&mut self, phase: Percentage) -> Result<(), TickError>
+	{
+		self.set_pwm_phase(phase).map_err(|_| TickError::SetPwmHeaterPercentage)
+	}
+}
+
+impl<Z: HeaterZone + ?Sized> HeaterZone for &mut Z
+{
+	type Context = Z::Context;
+
+	fn request_heat(&mut self, delta_time: f32, context: &mut Self::Context) -> Result<Percentage, TickError>
+	{
+		(**self).request_heat(delta_time, context)
+	}
+
+	fn give_heat(&mut self, heat_percentage: Percentage) -> Result<(), TickError>
+	{
+		(**self).give_heat(heat_percentage)
+	}
+
+	fn get_target_temperature(&self) -> Temperature
+	{
+		(**self).get_target_temperature()
+	}
+
+	fn get_last_sample_of_current_temperature(&self) -> Option<Temperature>
+	{
+		(**self).get_last_sample_of_current_temperature()
+	}
+
+	fn get_fault(&self) -> Option<ThermalFault>
+	{
+		(**self).get_fault()
+	}
+
+	fn shut_down(&mut self)
+	{
+		(**self).shut_down()
+	}
+
+	fn set_pwm_phase(&mut self, phase: Percentage) -> Result<(), TickError>
+	{
+		(**self).set_pwm_phase(phase)
+	}
+}
+
+/// The parameters of a zone of a [`HeaterManager`].
+#[derive(Clone, Copy, Debug)]
+pub struct ZoneConfig
+{
+	/// The power of the heater when its heat percentage is `100%`, in `W`.
+	pub heater_power: f32,
+	/// The zones with a higher priority get their power first, and the ones with the same priority are served in the
+	/// order they have been provided to [`HeaterManager::new`].
+	pub priority: u8,
+}
+
+/// The parameters of a [`HeaterManager`].
+#[derive(Clone, Copy, Debug)]
+pub struct HeaterManagerConfig
+{
+	/// The maximum total power that can be given to the heaters, in `W`.
+	pub power_budget: f32,
+	/// How many degrees the temperature of a zone can be away from its target temperature to be considered at the
+	/// target temperature.
+	pub at_target_hysteresis: f32,
+	/// If `true`, a fault in a zone [`shuts down`] all the other zones.
+	///
+	/// [`shuts down`]: HeaterZone::shut_down
+	pub shut_down_all_on_fault: bool,
+}
+
+/// An error that occurred in a zone of a [`HeaterManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ZoneError
+{
+	/// The index of the zone in the array provided to [`HeaterManager::new`].
+	pub zone_index: usize,
+	pub error: TickError,
+}
+
+struct ManagedZone<Z: HeaterZone>
+{
+	zone: Z,
+	config: ZoneConfig,
+	given_heat: Percentage,
+	/// The temperature (in kelvin) at the start of the window used to measure the heating rate, and the seconds passed
+	/// since then.
+	rate_window: Option<(f32, f32)>,
+	/// The heating rate measured in the last window, in `K/s`.
+	heating_rate: Option<f32>,
+}
+
+/// A manager of several heaters (zones) that share the same power supply, which allocates a total power budget between
+/// them according to their priorities.
+///
+/// Each [`tick`], every zone computes the heat it needs, then the zones get it in order of priority until the power
+/// budget is exhausted (the remaining zones get less heat, or none). The PWM signals of the zones can also be
+/// [`staggered`], so that the heaters aren't switched on at the same time.
+///
+/// [`tick`]: Self::tick
+/// [`staggered`]: Self::stagger_pwm_phases
+pub struct HeaterManager<Z: HeaterZone, const N: usize>
+{
+	zones: [ManagedZone<Z>; N],
+	config: HeaterManagerConfig,
+}
+
+impl<Z: HeaterZone, const N: usize> HeaterManager<Z, N>
+{
+	/// Returns a [`HeaterManager`] of the provided `zones`.
+	///
+	/// # Panics
+	/// Panics if the [`ZoneConfig::heater_power`] of a zone isn't bigger than `0`.
+	pub fn new(zones: [(Z, ZoneConfig); N], config: HeaterManagerConfig) -> Self
+	{
+		assert!(zones.iter().all(|(_, config)| config.heater_power > 0.));
+
+		Self {
+			zones: zones.map(|(zone, config)| ManagedZone {
+				zone,
+				config,
+				given_heat: Percentage::ZERO,
+				rate_window: None,
+				heating_rate: None,
+			}),
+			config,
+		}
+	}
+
+	/// Spreads the phases of the PWM signals of the zones evenly over the period, to reduce the inrush current of the
+	/// power supply.
+	///
+	/// Returns `Err(ZoneError)` with the first zone whose phase couldn't be set.
+	pub fn stagger_pwm_phases(&mut self) -> Result<(), ZoneError>
+	{
+		for (zone_index, zone) in self.zones.iter_mut().enumerate()
+		{
+			let phase = Percentage::from_0_to_1(zone_index as f32 / N as f32).unwrap();
+			zone.zone
+				.set_pwm_phase(phase)
+				.map_err(|error| ZoneError { zone_index, error })?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns a reference to the zone at `zone_index`.
+	///
+	/// # Panics
+	/// Panics if `zone_index` isn't less than `N`.
+	pub fn get_zone(&self, zone_index: usize) -> &Z
+	{
+		&self.zones[zone_index].zone
+	}
+
+	/// Returns a mutable reference to the zone at `zone_index`, for example to change its target temperature.
+	///
+	/// # Panics
+	/// Panics if `zone_index` isn't less than `N`.
+	pub fn get_zone_mut(&mut self, zone_index: usize) -> &mut Z
+	{
+		&mut self.zones[zone_index].zone
+	}
+
+	/// Returns the heat percentage given to the zone at `zone_index` by the last tick.
+	///
+	/// # Panics
+	/// Panics if `zone_index` isn't less than `N`.
+	pub fn get_given_heat(&self, zone_index: usize) -> Percentage
+	{
+		self.zones[zone_index].given_heat
+	}
+
+	/// Makes all the zones work to reach their target temperatures, sharing the power budget between them. All the zones
+	/// are ticked even if some of them fail.
+	///
+	/// Returns `Err(ZoneError)` with the first zone that has failed (check [`Self::get_fault`]).
+	pub fn tick(&mut self, delta_time: f32, context: &mut Z::Context) -> Result<(), ZoneError>
+	{
+		let mut first_error = None;
+		let mut requested_heats = [Percentage::ZERO; N];
+		for (zone_index, zone) in self.zones.iter_mut().enumerate()
+		{
+			match zone.zone.request_heat(delta_time, context)
+			{
+				Ok(heat_percentage) => requested_heats[zone_index] = heat_percentage,
+				Err(error) =>
+				{
+					first_error.get_or_insert(ZoneError { zone_index, error });
+				},
+			}
+		}
+
+		if self.config.shut_down_all_on_fault && self.get_fault().is_some()
+		{
+			self.shut_down_all();
+		}
+
+		let mut zones_order: [usize; N] = core::array::from_fn(|zone_index| zone_index);
+		zones_order.sort_unstable_by_key(|&zone_index| (Reverse(self.zones[zone_index].config.priority), zone_index));
+
+		let mut remaining_power = self.config.power_budget;
+		for zone_index in zones_order
+		{
+			let zone = &mut self.zones[zone_index];
+			zone.given_heat = Percentage::ZERO;
+			if zone.zone.get_fault().is_some()
+			{
+				continue;
+			}
+
+			let power = (requested_heats[zone_index].into_0_to_1() * zone.config.heater_power).min(remaining_power);
+			remaining_power -= power;
+			let heat_percentage = Percentage::from_0_to_1((power / zone.config.heater_power).clamp(0., 1.)).unwrap();
+
+			match zone.zone.give_heat(heat_percentage)
+			{
+				Ok(()) => zone.given_heat = heat_percentage,
+				Err(error) =>
+				{
+					first_error.get_or_insert(ZoneError { zone_index, error });
+				},
+			}
+		}
+
+		for zone in self.zones.iter_mut()
+		{
+			zone.update_heating_rate(delta_time);
+		}
+
+		match first_error
+		{
+			Some(error) => Err(error),
+			None => Ok(()),
+		}
+	}
+
+	/// [`Shuts down`] all the zones.
+	///
+	/// [`Shuts down`]: HeaterZone::shut_down
+	pub fn shut_down_all(&mut self)
+	{
+		for zone in self.zones.iter_mut()
+		{
+			zone.zone.shut_down();
+			zone.given_heat = Percentage::ZERO;
+		}
+	}
+
+	/// Returns the index and the [`ThermalFault`] of the first zone that has faulted, or `None` if no zone has.
+	///
+	/// The zones that have only been [`shut down`] (for example because of
+	/// [`HeaterManagerConfig::shut_down_all_on_fault`]) are returned only if no zone has faulted by itself, so that the
+	/// zone that caused the fault is returned.
+	///
+	/// [`shut down`]: HeaterZone::shut_down
+	pub fn get_fault(&self) -> Option<(usize, ThermalFault)>
+	{
+		let faults = || {
+			self.zones
+				.iter()
+				.enumerate()
+				.filter_map(|(zone_index, zone)| zone.zone.get_fault().map(|fault| (zone_index, fault)))
+		};
+
+		faults()
+			.find(|(_, fault)| fault.cause != TickError::ShutDown)
+			.or_else(|| faults().next())
+	}
+
+	/// Returns `true` if the temperatures of all the zones are within [`HeaterManagerConfig::at_target_hysteresis`]
+	/// degrees of their target temperatures.
+	pub fn are_all_at_target(&self) -> bool
+	{
+		self.zones
+			.iter()
+			.all(|zone| zone.get_distance_from_target(self.config.at_target_hysteresis) == Some(0.))
+	}
+
+	/// Estimates how many seconds are needed for all the zones to reach their target temperatures, using the rate at
+	/// which their temperatures have changed in the last seconds.
+	///
+	/// Returns `None` if it can't be estimated, because a zone has faulted or its temperature isn't changing towards the
+	/// target temperature.
+	pub fn estimate_seconds_to_target(&self) -> Option<f32>
+	{
+		self.zones.iter().try_fold(0_f32, |max_seconds, zone| {
+			if zone.zone.get_fault().is_some()
+			{
+				return None;
+			}
+
+			let distance = zone.get_distance_from_target(self.config.at_target_hysteresis)?;
+			if distance == 0.
+			{
+				return Some(max_seconds);
+			}
+
+			let seconds = distance / zone.heating_rate?;
+			(seconds > 0.).then(|| max_seconds.max(seconds))
+		})
+	}
+}
+
+impl<Z: HeaterZone> ManagedZone<Z>
+{
+	/// The window over which the heating rate is measured, in seconds.
+	const HEATING_RATE_WINDOW_IN_SECONDS: f32 = 2.;
+
+	/// Returns how many degrees the zone must heat to reach its target temperature (it's negative if it must cool), `0`
+	/// if it's within `at_target_hysteresis` degrees of it, or `None` if its temperature has never been read.
+	fn get_distance_from_target(&self, at_target_hysteresis: f32) -> Option<f32>
+	{
+		let current_temperature = self.zone.get_last_sample_of_current_temperature()?;
+		let distance = (self.zone.get_target_temperature() - current_temperature).as_kelvin();
+
+		Some(match distance.abs() <= at_target_hysteresis
+		{
+			true => 0.,
+			false => distance,
+		})
+	}
+
+	fn update_heating_rate(&mut self, delta_time: f32)
+	{
+		let Some(current_temperature) = self.zone.get_last_sample_of_current_temperature()
+		else
+		{
+			return;
+		};
+		let current_temperature = current_temperature.as_kelvin();
+
+		match &mut self.rate_window
+		{
+			None => self.rate_window = Some((current_temperature, 0.)),
+			Some((start_temperature, elapsed_seconds)) =>
+			{
+				*elapsed_seconds += delta_time;
+				if *elapsed_seconds >= Self::HEATING_RATE_WINDOW_IN_SECONDS
+				{
+					self.heating_rate = Some((current_temperature - *start_temperature) / *elapsed_seconds);
+					self.rate_window = Some((current_temperature, 0.));
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::time::Duration;
+
+	use super::*;
+	use crate::{
+		drivers::{heater::PwmHeater, thermistor::Thermistor},
+		features::temperature::{
+			pid::{ControlMode, MpcModel, PidGains},
+			safety::{temperature_change::TemperatureChangeConfig, TemperatureError, TemperatureSafety},
+		},
+		hardware::mock::{MockAdc, MockThermalPlant, SimulatedHeaterPin, SimulatedThermistorPin, ThermalPlantConfig},
+	};
+
+	type Controller = PidController<SimulatedHeaterPin, Thermistor<MockAdc, SimulatedThermistorPin>>;
+
+	const TICK_PERIOD: Duration = Duration::from_millis(100);
+
+	fn new_controller(plant: &MockThermalPlant, target_temperature: Temperature) -> Controller
+	{
+		let config = ThermalPlantConfig::HOTEND;
+		let thermistor = Thermistor::new(
+			plant.get_thermistor_pin(),
+			config.thermistor.beta,
+			config.thermistor.resistance_at_t0,
+			config.thermistor.other_resistance,
+		);
+		let safety = TemperatureSafety::new(
+			Temperature::from_celsius(0.)..=Temperature::from_celsius(300.),
+			TemperatureChangeConfig {
+				period: Duration::from_secs(40),
+				hysteresis: 10.,
+			},
+			TemperatureChangeConfig {
+				period: Duration::from_secs(20),
+				hysteresis: 2.,
+			},
+		);
+
+		let mut controller = PidController::new(
+			thermistor,
+			PwmHeater::new(plant.get_heater_pin()),
+			PidGains { p: 0., i: 0., d: 0. },
+			safety,
+		);
+		controller.set_control_mode(ControlMode::ModelPredictive(MpcModel {
+			heater_power: config.heater_power,
+			block_heat_capacity: config.heat_capacity,
+			sensor_responsiveness: 1. / config.sensor_lag_in_seconds,
+			ambient_transfer_coefficient: config.ambient_loss,
+			fan_transfer_coefficient: 0.,
+			filament_heat_capacity_per_mm: 0.,
+			ambient_temperature: config.ambient_temperature,
+		}));
+		controller.set_target_temperature(target_temperature);
+
+		controller
+	}
+
+	fn zone_config(priority: u8) -> ZoneConfig
+	{
+		ZoneConfig {
+			heater_power: ThermalPlantConfig::HOTEND.heater_power,
+			priority,
+		}
+	}
+
+	fn tick<Z: HeaterZone<Context = MockAdc>, const N: usize>(
+		manager: &mut HeaterManager<Z, N>, plants: &mut [MockThermalPlant; N],
+	) -> Result<(), ZoneError>
+	{
+		let result = manager.tick(TICK_PERIOD.as_secs_f32(), &mut MockAdc);
+		for plant in plants.iter_mut()
+		{
+			plant.advance(TICK_PERIOD);
+		}
+
+		result
+	}
+
+	#[test]
+	fn power_budget_follows_priorities()
+	{
+		let mut plants: [MockThermalPlant; 3] =
+			core::array::from_fn(|_| MockThermalPlant::new(ThermalPlantConfig::HOTEND));
+		let target_temperature = Temperature::from_celsius(200.);
+		let zones = [
+			(new_controller(&plants[0], target_temperature), zone_config(1)),
+			(new_controller(&plants[1], target_temperature), zone_config(2)),
+			(new_controller(&plants[2], target_temperature), zone_config(1)),
+		];
+		let mut manager = HeaterManager::new(
+			zones,
+			HeaterManagerConfig {
+				power_budget: 60.,
+				at_target_hysteresis: 2.,
+				shut_down_all_on_fault: true,
+			},
+		);
+
+		tick(&mut manager, &mut plants).unwrap();
+		let expected_heats = [Percentage::HALF, Percentage::FULL, Percentage::ZERO];
+		for (zone_index, expected_heat) in expected_heats.into_iter().enumerate()
+		{
+			assert_eq!(manager.get_given_heat(zone_index), expected_heat);
+			assert_eq!(plants[zone_index].get_heater_pin().get_duty_cycle(), expected_heat);
+		}
+	}
+
+	#[test]
+	fn fault_shuts_down_all_zones()
+	{
+		let mut plants: [MockThermalPlant; 2] =
+			core::array::from_fn(|_| MockThermalPlant::new(ThermalPlantConfig::HOTEND));
+		let target_temperature = Temperature::from_celsius(200.);
+		let zones = [
+			(new_controller(&plants[0], target_temperature), zone_config(0)),
+			(new_controller(&plants[1], target_temperature), zone_config(0)),
+		];
+		let mut manager = HeaterManager::new(
+			zones,
+			HeaterManagerConfig {
+				power_budget: 100.,
+				at_target_hysteresis: 2.,
+				shut_down_all_on_fault: true,
+			},
+		);
+		tick(&mut manager, &mut plants).unwrap();
+
+		plants[1].set_sensor_disconnected(true);
+		let error = tick(&mut manager, &mut plants).unwrap_err();
+		assert_eq!(error.zone_index, 1);
+		assert!(matches!(
+			error.error,
+			TickError::ReadTemperatureIsWrong(errors) if errors.contains(TemperatureError::SensorOpenCircuit)
+		));
+
+		// The zone that caused the fault is returned, instead of the first zone that has been shut down
+		let (zone_index, fault) = manager.get_fault().unwrap();
+		assert_eq!(zone_index, 1);
+		assert_eq!(fault.cause, error.error);
+		assert_eq!(manager.get_zone(0).get_fault().unwrap().cause, TickError::ShutDown);
+		for plant in plants.iter()
+		{
+			assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+		}
+	}
+
+	#[test]
+	#[should_panic]
+	fn heater_without_power_is_rejected()
+	{
+		let plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let zone_config = ZoneConfig {
+			heater_power: 0.,
+			priority: 0,
+		};
+		HeaterManager::new(
+			[(new_controller(&plant, Temperature::from_celsius(200.)), zone_config)],
+			HeaterManagerConfig {
+				power_budget: 100.,
+				at_target_hysteresis: 2.,
+				shut_down_all_on_fault: true,
+			},
+		);
+	}
+
+	#[test]
+	fn zones_of_different_types_reach_target()
+	{
+		let mut plants: [MockThermalPlant; 2] =
+			core::array::from_fn(|_| MockThermalPlant::new(ThermalPlantConfig::HOTEND));
+		let mut hotend = new_controller(&plants[0], Temperature::from_celsius(180.));
+		let mut bed = new_controller(&plants[1], Temperature::from_celsius(100.));
+		let zones: [(&mut dyn HeaterZone<Context = MockAdc>, ZoneConfig); 2] =
+			[(&mut hotend, zone_config(1)), (&mut bed, zone_config(0))];
+		let mut manager = HeaterManager::new(
+			zones,
+			HeaterManagerConfig {
+				power_budget: 50.,
+				at_target_hysteresis: 2.,
+				shut_down_all_on_fault: true,
+			},
+		);
+		manager.stagger_pwm_phases().unwrap();
+		assert_eq!(manager.estimate_seconds_to_target(), None);
+
+		for _ in 0..100
+		{
+			tick(&mut manager, &mut plants).unwrap();
+		}
+		let estimated_seconds = manager.estimate_seconds_to_target().unwrap();
+
+		let mut elapsed_seconds = 0.;
+		while !manager.are_all_at_target()
+		{
+			tick(&mut manager, &mut plants).unwrap();
+			elapsed_seconds += TICK_PERIOD.as_secs_f32();
+			assert!(elapsed_seconds < 300.);
+		}
+		// The estimate uses the current heating rates, which change when the hotend stops taking most of the power
+		assert!(
+			estimated_seconds > elapsed_seconds / 2. && estimated_seconds < elapsed_seconds * 2.,
+			"{estimated_seconds} {elapsed_seconds}"
+		);
+		assert_eq!(manager.estimate_seconds_to_target(), Some(0.));
+		assert!(manager.get_given_heat(0) > Percentage::ZERO && manager.get_given_heat(1) > Percentage::ZERO);
+	}
+}
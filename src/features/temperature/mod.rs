@@ -1,6 +1,11 @@
+mod manager;
 mod pid;
 pub mod safety;
 
+pub use manager::{HeaterManager, HeaterManagerConfig, HeaterZone, ZoneConfig, ZoneError};
 pub use pid::{
-	PidController as TemperaturePidController, PidGains as TemperaturePidGains, TickError as PidUpdateError,
+	AutotuneConfig, AutotuneError, AutotuneResult, AutotuneStatus, ControlMode, MpcCalibration, MpcCalibrationConfig,
+	MpcCalibrationError, MpcCalibrationStatus, MpcFeedForward, MpcModel, NoKillPin, NoWatchdog, PidAutotune,
+	PidController as TemperaturePidController, PidGains as TemperaturePidGains, ProfileError, ProfileSegment,
+	ProfileStatus, TemperatureProfile, ThermalFault, TickError as PidUpdateError, TuningRule,
 };
@@ -0,0 +1,539 @@
+//! A [`relay feedback`] autotune (the Åström–Hägglund method) that finds the [`PidGains`] of a heater without tuning
+//! them by hand.
+//!
+//! The heater is switched on and off around a target temperature (like a thermostat does), which makes the temperature
+//! oscillate. The period and the amplitude of these oscillations are then used to compute the gains.
+//!
+//! [`relay feedback`]: <https://en.wikipedia.org/wiki/Ziegler%E2%80%93Nichols_method>
+
+use core::f32::consts::PI;
+
+use embedded_hal::digital::OutputPin;
+use enumset::EnumSet;
+#[allow(unused_imports)]
+#[cfg(not(std))]
+use micromath::F32Ext;
+
+use super::{delta_time_as_duration, PidController, PidGains, TickError};
+use crate::{
+	drivers::temperature_sensor::TemperatureSensor,
+	features::temperature::safety,
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
+
+/// The parameters of a [`PidAutotune`].
+#[derive(Clone, Copy, Debug)]
+pub struct AutotuneConfig
+{
+	/// The temperature around which the temperature oscillates. It should be the temperature the heater usually works
+	/// at, because the best gains change with the temperature.
+	pub target_temperature: Temperature,
+	/// The heat percentage given to the heater while it's on.
+	pub heater_power: Percentage,
+	/// How many degrees the temperature must go above (or below) the target temperature before the heater is switched
+	/// off (or on). It should be bigger than the noise of the temperature reads.
+	pub hysteresis: f32,
+	/// How many oscillations are measured (and averaged) to compute the gains.
+	pub cycles_count: u8,
+	/// The seconds after which the autotune fails with [`AutotuneError::Timeout`] if it hasn't finished yet.
+	pub timeout_in_seconds: f32,
+}
+
+/// A [`relay feedback`] autotune procedure of the gains of a PID controller.
+///
+/// Call [`PidController::autotune_tick`] (or [`Self::update`] to control the heater yourself) periodically until it
+/// returns [`AutotuneStatus::Finished`], then choose the gains using [`AutotuneResult::get_pid_gains`].
+///
+/// The first oscillation is ignored, because it starts from the room temperature.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{
+/// #     features::temperature::*,
+/// #     utils::{math::Percentage, physical_quantities::temperature::Temperature},
+/// # };
+/// #
+/// let mut autotune = PidAutotune::new(AutotuneConfig {
+///     target_temperature: Temperature::from_celsius(200.),
+///     heater_power: Percentage::FULL,
+///     hysteresis: 1.,
+///     cycles_count: 3,
+///     timeout_in_seconds: 1000.,
+/// });
+///
+/// // A heater whose temperature reacts to the heat after 2 seconds
+/// let mut temperature = 25.;
+/// let mut heater_history = [0.; 20];
+/// let result = loop
+/// {
+///     match autotune.update(Temperature::from_celsius(temperature), 0.1)
+///     {
+///         Ok(AutotuneStatus::Running { .. }) => (),
+///         Ok(AutotuneStatus::Finished(result)) => break result,
+///         Err(error) => panic!("{error:?}"),
+///     }
+///
+///     heater_history.rotate_left(1);
+///     heater_history[19] = autotune.get_heater_percentage().into_0_to_1();
+///     temperature += (heater_history[0] * 300. - (temperature - 25.)) / 30. * 0.1;
+/// };
+///
+/// assert!(result.ultimate_period_in_seconds > 4. && result.ultimate_period_in_seconds < 12.);
+/// let pid_gains = result.get_pid_gains(TuningRule::NoOvershoot, 0.1);
+/// assert!(pid_gains.p > 0. && pid_gains.i > 0. && pid_gains.d > 0.);
+/// ```
+///
+/// [`relay feedback`]: <https://en.wikipedia.org/wiki/Ziegler%E2%80%93Nichols_method>
+pub struct PidAutotune
+{
+	config: AutotuneConfig,
+	is_heating: bool,
+	elapsed_seconds: f32,
+	outcome: Option<Result<AutotuneResult, AutotuneError>>,
+
+	/// When the heater has been switched on the last time (which is when an oscillation starts).
+	current_cycle_start_in_seconds: Option<f32>,
+	current_cycle_temperatures: (f32, f32),
+	completed_cycles: u8,
+	periods_sum: f32,
+	amplitudes_sum: f32,
+}
+
+impl PidAutotune
+{
+	/// Returns a [`PidAutotune`] that hasn't started yet.
+	///
+	/// # Panics
+	/// Panics if `config.cycles_count` is `0`.
+	pub fn new(config: AutotuneConfig) -> Self
+	{
+		assert!(config.cycles_count > 0);
+
+		Self {
+			config,
+			is_heating: true,
+			elapsed_seconds: 0.,
+			outcome: None,
+			current_cycle_start_in_seconds: None,
+			current_cycle_temperatures: (f32::MAX, f32::MIN),
+			completed_cycles: 0,
+			periods_sum: 0.,
+			amplitudes_sum: 0.,
+		}
+	}
+
+	/// Returns the [`AutotuneConfig`] you provided to [`Self::new`].
+	pub fn get_config(&self) -> &AutotuneConfig
+	{
+		&self.config
+	}
+
+	/// Returns the heat percentage that must be given to the heater, which is `0%` once the autotune is over.
+	pub fn get_heater_percentage(&self) -> Percentage
+	{
+		match self.is_heating && self.outcome.is_none()
+		{
+			true => self.config.heater_power,
+			false => Percentage::ZERO,
+		}
+	}
+
+	/// Returns how many seconds have passed since the autotune has started.
+	pub fn get_elapsed_seconds(&self) -> f32
+	{
+		self.elapsed_seconds
+	}
+
+	/// Updates the autotune using the `current_temperature` of the heater, `delta_time` seconds after the last update.
+	/// Then [`Self::get_heater_percentage`] returns the heat percentage to give to the heater.
+	///
+	/// Returns `Ok(AutotuneStatus)` with the progress of the autotune, otherwise returns `Err(AutotuneError)` if the
+	/// autotune has failed. Once the autotune is over, this always returns the same result.
+	pub fn update(&mut self, current_temperature: Temperature, delta_time: f32)
+		-> Result<AutotuneStatus, AutotuneError>
+	{
+		if let Some(outcome) = self.outcome
+		{
+			return outcome.map(AutotuneStatus::Finished);
+		}
+
+		self.elapsed_seconds += delta_time;
+		if self.elapsed_seconds > self.config.timeout_in_seconds
+		{
+			return self.finish(Err(AutotuneError::Timeout));
+		}
+
+		let temperature = current_temperature.as_kelvin();
+		let target_temperature = self.config.target_temperature.as_kelvin();
+		let (min_temperature, max_temperature) = &mut self.current_cycle_temperatures;
+		*min_temperature = min_temperature.min(temperature);
+		*max_temperature = max_temperature.max(temperature);
+
+		if self.is_heating && temperature > target_temperature + self.config.hysteresis
+		{
+			self.is_heating = false;
+		}
+		else if !self.is_heating && temperature < target_temperature - self.config.hysteresis
+		{
+			self.is_heating = true;
+
+			if let Some(cycle_start_in_seconds) = self.current_cycle_start_in_seconds
+			{
+				let (min_temperature, max_temperature) = self.current_cycle_temperatures;
+				self.periods_sum += self.elapsed_seconds - cycle_start_in_seconds;
+				self.amplitudes_sum += (max_temperature - min_temperature) / 2.;
+				self.completed_cycles += 1;
+
+				if self.completed_cycles == self.config.cycles_count
+				{
+					let result = self.compute_result();
+					return self.finish(result);
+				}
+			}
+			self.current_cycle_start_in_seconds = Some(self.elapsed_seconds);
+			self.current_cycle_temperatures = (temperature, temperature);
+		}
+
+		Ok(AutotuneStatus::Running {
+			completed_cycles: self.completed_cycles,
+		})
+	}
+
+	fn compute_result(&self) -> Result<AutotuneResult, AutotuneError>
+	{
+		let cycles_count = self.completed_cycles as f32;
+		let amplitude = self.amplitudes_sum / cycles_count;
+		// Otherwise the result would depend more on the hysteresis than on the heater
+		if amplitude < 2. * self.config.hysteresis
+		{
+			return Err(AutotuneError::OscillationTooSmall);
+		}
+
+		// The heater switches between `0%` and `heater_power`, so the relay amplitude is half of it, in the same units of
+		// the output of the PID control
+		let relay_amplitude = self.config.heater_power.into_0_to_100() / 2.;
+		let hysteresis = self.config.hysteresis;
+		Ok(AutotuneResult {
+			ultimate_gain: 4. * relay_amplitude / (PI * (amplitude * amplitude - hysteresis * hysteresis).sqrt()),
+			ultimate_period_in_seconds: self.periods_sum / cycles_count,
+		})
+	}
+
+	fn finish(&mut self, outcome: Result<AutotuneResult, AutotuneError>) -> Result<AutotuneStatus, AutotuneError>
+	{
+		self.outcome = Some(outcome);
+		outcome.map(AutotuneStatus::Finished)
+	}
+}
+
+/// The progress of a [`PidAutotune`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AutotuneStatus
+{
+	/// The autotune is still oscillating around the target temperature, and `completed_cycles` out of
+	/// [`AutotuneConfig::cycles_count`] oscillations have been measured.
+	Running
+	{
+		completed_cycles: u8
+	},
+	/// The autotune has finished successfully and the heater has been turned off.
+	Finished(AutotuneResult),
+}
+
+/// The characteristics of the oscillations measured by a [`PidAutotune`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AutotuneResult
+{
+	/// The proportional gain at which the heater would oscillate forever.
+	pub ultimate_gain: f32,
+	/// The period of the oscillations, in seconds.
+	pub ultimate_period_in_seconds: f32,
+}
+
+impl AutotuneResult
+{
+	/// Returns the [`PidGains`] computed using the provided `tuning_rule`, for a [`PidController`] that is ticked every
+	/// `tick_period_in_seconds` seconds (because the integral and derivative gains are applied once per tick).
+	pub fn get_pid_gains(&self, tuning_rule: TuningRule, tick_period_in_seconds: f32) -> PidGains
+	{
+		let (proportional_factor, integral_time_factor, derivative_time_factor) = match tuning_rule
+		{
+			TuningRule::ZieglerNichols => (0.6, 0.5, 0.125),
+			TuningRule::SomeOvershoot => (1. / 3., 0.5, 1. / 3.),
+			TuningRule::NoOvershoot => (0.2, 0.5, 1. / 3.),
+		};
+
+		let p = proportional_factor * self.ultimate_gain;
+		let integral_time = integral_time_factor * self.ultimate_period_in_seconds;
+		let derivative_time = derivative_time_factor * self.ultimate_period_in_seconds;
+		PidGains {
+			p,
+			i: p / integral_time * tick_period_in_seconds,
+			d: p * derivative_time / tick_period_in_seconds,
+		}
+	}
+}
+
+/// The rule used to compute the [`PidGains`] from an [`AutotuneResult`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TuningRule
+{
+	/// The classic [`Ziegler–Nichols`] rule, which reacts quickly but overshoots the target temperature.
+	///
+	/// [`Ziegler–Nichols`]: <https://en.wikipedia.org/wiki/Ziegler%E2%80%93Nichols_method>
+	ZieglerNichols,
+	/// A more conservative rule, which overshoots less than [`Self::ZieglerNichols`].
+	SomeOvershoot,
+	/// The most conservative rule, which reaches the target temperature slowly but (almost) without overshooting it.
+	NoOvershoot,
+}
+
+/// An error that occurred during a [`PidAutotune`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AutotuneError
+{
+	/// It has been impossible to [`read`] the sensor's temperature.
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	CantReadTemperature,
+
+	/// The sensor's `temperature` has been [`read`], but it's an irregular value (check
+	/// [`TickError::ReadTemperatureIsWrong`]).
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	/// [`TickError::ReadTemperatureIsWrong`]: super::TickError::ReadTemperatureIsWrong
+	ReadTemperatureIsWrong(EnumSet<safety::TemperatureError>),
+
+	/// It has been impossible to set the heater's heat percentage.
+	SetPwmHeaterPercentage,
+
+	/// The autotune hasn't finished within [`AutotuneConfig::timeout_in_seconds`], for example because the heater isn't
+	/// powerful enough to reach the target temperature.
+	Timeout,
+
+	/// A [`ThermalFault`] is latched by the [`PidController`], so the autotune can't run until it's [`cleared`].
+	///
+	/// [`ThermalFault`]: super::ThermalFault
+	/// [`cleared`]: PidController::clear_fault
+	Faulted,
+
+	/// The amplitude of the oscillations is less than twice [`AutotuneConfig::hysteresis`], so the gains can't be
+	/// computed reliably. Try again with a smaller hysteresis.
+	OscillationTooSmall,
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> PidController<CHP, S, K, W, N>
+{
+	/// Makes the `autotune` control the heater, like [`Self::tick`] does with the PID control. The temperature is
+	/// still checked by the [`TemperatureSafety`] of this controller, using [`AutotuneConfig::target_temperature`] as
+	/// the target temperature.
+	///
+	/// The heater is turned off once the autotune has finished, or as soon as an error occurs. The errors that also make
+	/// [`Self::tick`] fail latch a [`ThermalFault`] like it does.
+	///
+	/// Returns `Ok(AutotuneStatus)` with the progress of the autotune, otherwise returns `Err(AutotuneError)`.
+	///
+	/// [`TemperatureSafety`]: safety::TemperatureSafety
+	/// [`ThermalFault`]: super::ThermalFault
+	pub fn autotune_tick(
+		&mut self, autotune: &mut PidAutotune, delta_time: f32, context: &mut S::Context,
+	) -> Result<AutotuneStatus, AutotuneError>
+	{
+		self.uptime += delta_time_as_duration(delta_time);
+		if self.fault.is_some()
+		{
+			return Err(AutotuneError::Faulted);
+		}
+
+		let status = self.try_autotune_tick(autotune, delta_time, context);
+		match status
+		{
+			Ok(AutotuneStatus::Running { .. }) => self.feed_watchdog(),
+			Err(AutotuneError::CantReadTemperature) => self.latch_fault(TickError::CantReadTemperature),
+			Err(AutotuneError::ReadTemperatureIsWrong(errors)) =>
+			{
+				self.latch_fault(TickError::ReadTemperatureIsWrong(errors))
+			},
+			Err(AutotuneError::SetPwmHeaterPercentage) => self.latch_fault(TickError::SetPwmHeaterPercentage),
+			_ =>
+			{
+				if self.pwm_heater.set_heat_percentage(Percentage::ZERO).is_err()
+				{
+					self.latch_fault(TickError::SetPwmHeaterPercentage);
+					return Err(AutotuneError::SetPwmHeaterPercentage);
+				}
+				self.feed_watchdog();
+			},
+		}
+
+		status
+	}
+
+	fn try_autotune_tick(
+		&mut self, autotune: &mut PidAutotune, delta_time: f32, context: &mut S::Context,
+	) -> Result<AutotuneStatus, AutotuneError>
+	{
+		let current_temperature = self.read_current_temperature(context).map_err(|error| match error
+		{
+			TickError::ReadTemperatureIsWrong(errors) => AutotuneError::ReadTemperatureIsWrong(errors),
+			_ => AutotuneError::CantReadTemperature,
+		})?;
+
+		let safety_errors = self.safety.is_temperature_safe(
+			current_temperature,
+			autotune.get_config().target_temperature,
+			self.pwm_heater.get_heat_percentage(),
+			delta_time_as_duration(delta_time),
+		);
+		if !safety_errors.is_empty()
+		{
+			return Err(AutotuneError::ReadTemperatureIsWrong(safety_errors));
+		}
+
+		let status = autotune.update(current_temperature, delta_time)?;
+		self.pwm_heater
+			.set_heat_percentage(autotune.get_heater_percentage())
+			.map_err(|_| AutotuneError::SetPwmHeaterPercentage)?;
+
+		Ok(status)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// Simulates a heater with a first order response, that reacts to the heat after `DELAY_STEPS` steps.
+	struct Plant<const DELAY_STEPS: usize>
+	{
+		temperature: f32,
+		heater_history: [f32; DELAY_STEPS],
+	}
+
+	impl<const DELAY_STEPS: usize> Plant<DELAY_STEPS>
+	{
+		const AMBIENT_TEMPERATURE: f32 = 25.;
+		/// The temperature reached with the heater always at `100%`, relative to the ambient temperature.
+		const GAIN: f32 = 300.;
+		const TIME_CONSTANT: f32 = 30.;
+		const STEP: f32 = 0.05;
+
+		fn new() -> Self
+		{
+			Self {
+				temperature: Self::AMBIENT_TEMPERATURE,
+				heater_history: [0.; DELAY_STEPS],
+			}
+		}
+
+		fn step(&mut self, heater_percentage: Percentage)
+		{
+			self.heater_history.rotate_left(1);
+			self.heater_history[DELAY_STEPS - 1] = heater_percentage.into_0_to_1();
+			self.temperature += (self.heater_history[0] * Self::GAIN - (self.temperature - Self::AMBIENT_TEMPERATURE))
+				/ Self::TIME_CONSTANT
+				* Self::STEP;
+		}
+	}
+
+	fn run<const DELAY_STEPS: usize>(
+		autotune: &mut PidAutotune, plant: &mut Plant<DELAY_STEPS>,
+	) -> Result<AutotuneResult, AutotuneError>
+	{
+		loop
+		{
+			match autotune.update(Temperature::from_celsius(plant.temperature), Plant::<DELAY_STEPS>::STEP)?
+			{
+				AutotuneStatus::Running { .. } => plant.step(autotune.get_heater_percentage()),
+				AutotuneStatus::Finished(result) => return Ok(result),
+			}
+		}
+	}
+
+	fn config() -> AutotuneConfig
+	{
+		AutotuneConfig {
+			target_temperature: Temperature::from_celsius(200.),
+			heater_power: Percentage::FULL,
+			hysteresis: 0.5,
+			cycles_count: 4,
+			timeout_in_seconds: 1000.,
+		}
+	}
+
+	#[test]
+	fn measures_ultimate_gain_and_period()
+	{
+		// 2 seconds of delay
+		let mut plant = Plant::<40>::new();
+		let mut autotune = PidAutotune::new(config());
+		let result = run(&mut autotune, &mut plant).unwrap();
+
+		// The ultimate frequency `w` of a first order plus dead time system is the solution of
+		// `w * delay + atan(w * time_constant) = PI`, and the ultimate gain is `sqrt(1 + (w * time_constant)^2) / gain`.
+		// The relay method only approximates them, especially when the oscillation isn't symmetric
+		let ultimate_frequency = 2. * PI / 7.8;
+		let expected_ultimate_gain = (1_f32 + (ultimate_frequency * 30.).powi(2)).sqrt() / (300. / 100.);
+		let relative_error = |value: f32, expected: f32| (value - expected).abs() / expected;
+		assert!(relative_error(result.ultimate_period_in_seconds, 7.8) < 0.15);
+		assert!(relative_error(result.ultimate_gain, expected_ultimate_gain) < 0.25);
+
+		// The heater is turned off and the result doesn't change anymore
+		assert_eq!(autotune.get_heater_percentage(), Percentage::ZERO);
+		assert_eq!(
+			autotune.update(Temperature::from_celsius(0.), 1.),
+			Ok(AutotuneStatus::Finished(result))
+		);
+
+		let aggressive_gains = result.get_pid_gains(TuningRule::ZieglerNichols, 0.1);
+		let conservative_gains = result.get_pid_gains(TuningRule::NoOvershoot, 0.1);
+		assert!(conservative_gains.p < aggressive_gains.p);
+		assert!(conservative_gains.i < aggressive_gains.i);
+	}
+
+	#[test]
+	fn reports_progress()
+	{
+		let mut plant = Plant::<40>::new();
+		let mut autotune = PidAutotune::new(config());
+
+		let mut last_completed_cycles = 0;
+		while let AutotuneStatus::Running { completed_cycles } = autotune
+			.update(Temperature::from_celsius(plant.temperature), 0.05)
+			.unwrap()
+		{
+			assert!(completed_cycles == last_completed_cycles || completed_cycles == last_completed_cycles + 1);
+			last_completed_cycles = completed_cycles;
+			plant.step(autotune.get_heater_percentage());
+		}
+		assert_eq!(last_completed_cycles, 3);
+	}
+
+	#[test]
+	fn fails_if_target_temperature_is_unreachable()
+	{
+		let mut plant = Plant::<40>::new();
+		let mut autotune = PidAutotune::new(AutotuneConfig {
+			target_temperature: Temperature::from_celsius(400.),
+			..config()
+		});
+
+		assert_eq!(run(&mut autotune, &mut plant), Err(AutotuneError::Timeout));
+		assert_eq!(autotune.get_heater_percentage(), Percentage::ZERO);
+	}
+
+	#[test]
+	fn fails_if_oscillation_is_within_hysteresis()
+	{
+		// Without any delay the temperature barely goes past the hysteresis
+		let mut plant = Plant::<1>::new();
+		let mut autotune = PidAutotune::new(AutotuneConfig {
+			hysteresis: 5.,
+			..config()
+		});
+
+		assert_eq!(run(&mut autotune, &mut plant), Err(AutotuneError::OscillationTooSmall));
+	}
+}
@@ -0,0 +1,331 @@
+use core::{convert::Infallible, time::Duration};
+
+use embedded_hal::digital::{ErrorType, OutputPin, PinState};
+
+use super::{PidController, TickError};
+use crate::{
+	drivers::temperature_sensor::TemperatureSensor,
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::math::Percentage,
+};
+
+/// A fault latched by a [`PidController`], which keeps the heater off until it's [`cleared`].
+///
+/// [`cleared`]: PidController::clear_fault
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalFault
+{
+	/// The error that caused the fault, which also contains the [`TemperatureError`]s that fired.
+	///
+	/// [`TemperatureError`]: crate::features::temperature::safety::TemperatureError
+	pub cause: TickError,
+	/// When the fault happened, since the creation of the [`PidController`] (it's the sum of all the `delta_time`s it
+	/// has been ticked with).
+	pub time: Duration,
+	/// `true` if it has been impossible to turn off the heater or to trip the kill pin, so the power must be cut in
+	/// another way.
+	pub shutdown_failed: bool,
+}
+
+/// The kill pin of a [`PidController`] that doesn't have one.
+pub struct NoKillPin;
+
+impl ErrorType for NoKillPin
+{
+	type Error = Infallible;
+}
+
+impl OutputPin for NoKillPin
+{
+	fn set_low(&mut self) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+
+	fn set_high(&mut self) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+}
+
+/// The watchdog of a [`PidController`] that doesn't have one.
+pub struct NoWatchdog;
+
+impl Watchdog for NoWatchdog
+{
+	type Error = Infallible;
+
+	fn feed(&mut self) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> PidController<CHP, S, K, W, N>
+{
+	/// Makes the controller set the provided `kill_pin` to `active_state` when a fault is latched (for example to cut
+	/// the power of the heater with a relay), and to the opposite state when it's cleared.
+	///
+	/// Returns `Err(NewK::Error)` if it has been impossible to set the pin to its inactive state.
+	pub fn with_kill_pin<NewK: OutputPin>(
+		self, mut kill_pin: NewK, active_state: PinState,
+	) -> Result<PidController<CHP, S, NewK, W, N>, NewK::Error>
+	{
+		kill_pin.set_state(!active_state)?;
+
+		Ok(PidController {
+			sensor: self.sensor,
+			pwm_heater: self.pwm_heater,
+			pid_control: self.pid_control,
+			safety: self.safety,
+			control_mode: self.control_mode,
+			mpc_state: self.mpc_state,
+			feed_forward: self.feed_forward,
+			last_current_temperature_sample: self.last_current_temperature_sample,
+			fault: self.fault,
+			uptime: self.uptime,
+			kill_pin,
+			kill_pin_active_state: active_state,
+			watchdog: self.watchdog,
+		})
+	}
+
+	/// Makes the controller feed the provided `watchdog` after each successful tick, so that the microcontroller is
+	/// reset if the controller stops being ticked or a fault is latched.
+	///
+	/// The errors returned by [`Watchdog::feed`] are ignored, since a watchdog that isn't fed resets the
+	/// microcontroller anyway.
+	pub fn with_watchdog<NewW: Watchdog>(self, watchdog: NewW) -> PidController<CHP, S, K, NewW, N>
+	{
+		PidController {
+			sensor: self.sensor,
+			pwm_heater: self.pwm_heater,
+			pid_control: self.pid_control,
+			safety: self.safety,
+			control_mode: self.control_mode,
+			mpc_state: self.mpc_state,
+			feed_forward: self.feed_forward,
+			last_current_temperature_sample: self.last_current_temperature_sample,
+			fault: self.fault,
+			uptime: self.uptime,
+			kill_pin: self.kill_pin,
+			kill_pin_active_state: self.kill_pin_active_state,
+			watchdog,
+		}
+	}
+
+	/// Returns the [`ThermalFault`] latched by the controller, or `None` if it's working normally.
+	pub fn get_fault(&self) -> Option<ThermalFault>
+	{
+		self.fault
+	}
+
+	/// Clears the latched fault, so that the controller can be ticked again. The integral term of the PID control is
+	/// reset, to avoid a heat burst caused by the error accumulated before the fault, and so are the modeled
+	/// temperatures of the model predictive control.
+	///
+	/// # Warning
+	/// Only clear the fault after its cause has been fixed (or checked to be a false positive).
+	///
+	/// Returns `Err(K::Error)` if it has been impossible to set the kill pin to its inactive state, in which case the
+	/// fault is still latched.
+	pub fn clear_fault(&mut self) -> Result<(), K::Error>
+	{
+		self.kill_pin.set_state(!self.kill_pin_active_state)?;
+		self.pid_control.reset_integral_term();
+		self.mpc_state = None;
+		self.fault = None;
+
+		Ok(())
+	}
+
+	/// Turns off the heater (and trips the kill pin) by latching a [`ThermalFault`] caused by [`TickError::ShutDown`],
+	/// for example because another heater sharing the same power supply has faulted. It does nothing if a fault is
+	/// already latched.
+	pub fn shut_down(&mut self)
+	{
+		if self.fault.is_none()
+		{
+			self.latch_fault(TickError::ShutDown);
+		}
+	}
+
+	/// Latches a fault caused by the provided `cause`, turning off the heater and tripping the kill pin.
+	pub(super) fn latch_fault(&mut self, cause: TickError)
+	{
+		let is_heater_off = self.pwm_heater.set_heat_percentage(Percentage::ZERO).is_ok();
+		let is_kill_pin_tripped = self.kill_pin.set_state(self.kill_pin_active_state).is_ok();
+
+		self.fault = Some(ThermalFault {
+			cause,
+			time: self.uptime,
+			shutdown_failed: !is_heater_off || !is_kill_pin_tripped,
+		});
+	}
+
+	/// Feeds the watchdog, which must be done only when the controller is working normally.
+	pub(super) fn feed_watchdog(&mut self)
+	{
+		let _ = self.watchdog.feed();
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::time::Duration;
+
+	use enumset::EnumSet;
+
+	use super::*;
+	use crate::{
+		drivers::heater::PwmHeater,
+		features::temperature::{
+			pid::PidGains,
+			safety::{temperature_change::TemperatureChangeConfig, TemperatureError, TemperatureSafety},
+		},
+		hardware::mock::{MockOutputPin, MockPwmPin},
+		utils::physical_quantities::{frequency::Frequency, temperature::Temperature},
+	};
+
+	/// A sensor that returns the temperature it's set to, or an error if it's `None`.
+	struct FakeSensor(Option<Temperature>);
+
+	impl TemperatureSensor for FakeSensor
+	{
+		type Context = ();
+		type Error = ();
+
+		fn read_temperature(&mut self, _: &mut ()) -> Result<Temperature, ()>
+		{
+			self.0.ok_or(())
+		}
+	}
+
+	/// A watchdog that counts how many times it has been fed.
+	struct CountingWatchdog(u32);
+
+	impl Watchdog for CountingWatchdog
+	{
+		type Error = Infallible;
+
+		fn feed(&mut self) -> Result<(), Self::Error>
+		{
+			self.0 += 1;
+			Ok(())
+		}
+	}
+
+	fn new_pid_controller(
+		temperature: Option<Temperature>,
+	) -> PidController<MockPwmPin, FakeSensor, MockOutputPin, CountingWatchdog>
+	{
+		let heater = PwmHeater::new(MockPwmPin::Ok {
+			duty_cycle: Percentage::ZERO,
+			frequency: Frequency::from_hertz(1000),
+		});
+		let config = TemperatureChangeConfig {
+			period: Duration::from_secs(60),
+			hysteresis: 10.,
+		};
+		let safety = TemperatureSafety::new(
+			Temperature::from_celsius(0.)..=Temperature::from_celsius(300.),
+			config,
+			config,
+		);
+		let pid_gains = PidGains { p: 10., i: 0., d: 0. };
+
+		let mut pid_controller = PidController::new(FakeSensor(temperature), heater, pid_gains, safety)
+			.with_kill_pin(MockOutputPin::Ok { is_high: false }, PinState::High)
+			.unwrap()
+			.with_watchdog(CountingWatchdog(0));
+		pid_controller.set_target_temperature(Temperature::from_celsius(200.));
+		pid_controller
+	}
+
+	fn get_heat_percentage(
+		pid_controller: &PidController<MockPwmPin, FakeSensor, MockOutputPin, CountingWatchdog>,
+	) -> Percentage
+	{
+		pid_controller.pwm_heater.get_pin_ref().get_duty_cycle()
+	}
+
+	fn is_kill_pin_high(pid_controller: &PidController<MockPwmPin, FakeSensor, MockOutputPin, CountingWatchdog>)
+		-> bool
+	{
+		matches!(pid_controller.kill_pin, MockOutputPin::Ok { is_high: true })
+	}
+
+	#[test]
+	fn safety_error_latches_fault()
+	{
+		let mut pid_controller = new_pid_controller(Some(Temperature::from_celsius(100.)));
+		pid_controller.tick(1., &mut ()).unwrap();
+		assert_eq!(get_heat_percentage(&pid_controller), Percentage::FULL);
+		assert_eq!(pid_controller.watchdog.0, 1);
+
+		pid_controller.sensor.0 = Some(Temperature::from_celsius(350.));
+		let expected_cause =
+			TickError::ReadTemperatureIsWrong(EnumSet::only(TemperatureError::CurrentTemperatureOutsideAllowedRange));
+		assert_eq!(pid_controller.tick(1., &mut ()), Err(expected_cause));
+		assert_eq!(
+			pid_controller.get_fault(),
+			Some(ThermalFault {
+				cause: expected_cause,
+				time: Duration::from_secs(2),
+				shutdown_failed: false
+			})
+		);
+		assert_eq!(get_heat_percentage(&pid_controller), Percentage::ZERO);
+		assert!(is_kill_pin_high(&pid_controller));
+
+		// The ticks are refused (and the watchdog isn't fed) even if the temperature is normal again
+		pid_controller.sensor.0 = Some(Temperature::from_celsius(100.));
+		assert_eq!(pid_controller.tick(1., &mut ()), Err(TickError::Faulted));
+		assert_eq!(get_heat_percentage(&pid_controller), Percentage::ZERO);
+		assert_eq!(pid_controller.watchdog.0, 1);
+
+		pid_controller.clear_fault().unwrap();
+		assert_eq!(pid_controller.get_fault(), None);
+		assert!(!is_kill_pin_high(&pid_controller));
+		pid_controller.tick(1., &mut ()).unwrap();
+		assert_eq!(get_heat_percentage(&pid_controller), Percentage::FULL);
+		assert_eq!(pid_controller.watchdog.0, 2);
+	}
+
+	#[test]
+	fn fault_time_keeps_advancing_on_long_running_machines()
+	{
+		let mut pid_controller = new_pid_controller(None);
+		// A 10ms tick added to 3 days in an `f32` wouldn't change it
+		let three_days = Duration::from_secs(3 * 24 * 60 * 60);
+		pid_controller.uptime = three_days;
+
+		assert!(pid_controller.tick(0.01, &mut ()).is_err());
+		let time = pid_controller.get_fault().unwrap().time;
+		assert!(time > three_days && time - three_days <= Duration::from_millis(11));
+	}
+
+	#[test]
+	fn read_error_latches_fault()
+	{
+		let mut pid_controller = new_pid_controller(None);
+
+		assert_eq!(pid_controller.tick(1., &mut ()), Err(TickError::CantReadTemperature));
+		assert_eq!(
+			pid_controller.get_fault().map(|fault| fault.cause),
+			Some(TickError::CantReadTemperature)
+		);
+		assert!(is_kill_pin_high(&pid_controller));
+	}
+
+	#[test]
+	fn failed_shutdown_is_reported()
+	{
+		let mut pid_controller = new_pid_controller(None);
+		*pid_controller.pwm_heater.get_pin_mut() = MockPwmPin::Err;
+
+		assert!(pid_controller.tick(1., &mut ()).is_err());
+		assert!(pid_controller.get_fault().unwrap().shutdown_failed);
+	}
+}
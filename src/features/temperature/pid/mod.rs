@@ -0,0 +1,369 @@
+use core::time::Duration;
+
+use embedded_hal::digital::{OutputPin, PinState};
+use enumset::EnumSet;
+use pid::Pid;
+
+use super::safety::{self, TemperatureSafety, DEFAULT_RISING_SAMPLES_COUNT};
+use crate::{
+	drivers::{heater::PwmHeater, temperature_sensor::TemperatureSensor},
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::{
+		math::{self, Percentage},
+		physical_quantities::temperature::Temperature,
+	},
+};
+
+mod autotune;
+mod fault;
+mod mpc;
+mod profile;
+
+pub use autotune::*;
+pub use fault::*;
+pub use mpc::*;
+pub use profile::*;
+
+/// A [`PID controller`] used to control the temperature of a system in a closed loop.
+///
+/// To use it, first [`create`] the controller, than whenever you want you can [`choose the target temperature`]
+/// and you must continually call [`tick`] to make the controller actually do the work.
+///
+/// ## Model predictive control
+/// Instead of the PID control, the controller can use a [`model predictive control`] (check the [`ControlMode`]),
+/// which computes the heat from a thermal model of the heater measured by an [`MpcCalibration`]. It reaches the target
+/// temperature faster and with less overshoot, and it can compensate the [`fan and the extrusion`] before the
+/// temperature drops.
+///
+/// ## Faults
+/// When a tick fails (for example because the temperature can't be read, or it isn't safe) the controller latches a
+/// [`ThermalFault`]: the heater is turned off and all the following ticks are refused until the fault is [`cleared`].
+/// The controller can also trip a [`kill pin`] when this happens, and it can feed a [`watchdog`] only while it's working
+/// normally.
+///
+/// [`PID controller`]: https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller
+/// [`create`]: `Self::new`
+/// [`choose the target temperature`]: `Self::set_target_temperature`
+/// [`tick`]: `Self::tick`
+/// [`model predictive control`]: `ControlMode::ModelPredictive`
+/// [`fan and the extrusion`]: `Self::set_feed_forward`
+/// [`cleared`]: `Self::clear_fault`
+/// [`kill pin`]: `Self::with_kill_pin`
+/// [`watchdog`]: `Self::with_watchdog`
+pub struct PidController<
+	CHP: PwmPin,
+	S: TemperatureSensor,
+	K: OutputPin = NoKillPin,
+	W: Watchdog = NoWatchdog,
+	const RISING_SAMPLES_COUNT: usize = DEFAULT_RISING_SAMPLES_COUNT,
+> {
+	sensor: S,
+	pwm_heater: PwmHeater<CHP>,
+	pid_control: Pid<f32>,
+	safety: TemperatureSafety<RISING_SAMPLES_COUNT>,
+	control_mode: ControlMode,
+	/// The modeled temperatures of the model predictive control, which are `None` until the first tick.
+	mpc_state: Option<MpcState>,
+	feed_forward: MpcFeedForward,
+
+	last_current_temperature_sample: Option<Temperature>,
+	fault: Option<ThermalFault>,
+	/// The sum of the `delta_time`s of all the ticks, accumulated as a [`Duration`] so that it keeps advancing on
+	/// long-running machines (an `f32` stops increasing by small `delta_time`s after a few days).
+	uptime: Duration,
+	kill_pin: K,
+	kill_pin_active_state: PinState,
+	watchdog: W,
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, const N: usize> PidController<CHP, S, NoKillPin, NoWatchdog, N>
+{
+	/// Returns a [`PidController`] that will control the `cartridge heater`'s current based on the [`set target temperature`]
+	/// and the [`current temperature`] read by the provided `sensor` using the provided gains.
+	///
+	/// [`set target temperature`]: `Self::set_target_temperature`
+	/// [`current temperature`]: `Self::get_current_temperature`
+	pub fn new(sensor: S, heater: PwmHeater<CHP>, pid_gains: PidGains, safety: TemperatureSafety<N>) -> Self
+	{
+		let mut pid_control = Pid::new(0., Self::PID_CONTROL_MAX_LIMIT);
+		pid_control.p(pid_gains.p, Self::PID_CONTROL_MAX_LIMIT);
+		pid_control.i(pid_gains.i, Self::PID_CONTROL_MAX_LIMIT);
+		pid_control.d(pid_gains.d, Self::PID_CONTROL_MAX_LIMIT);
+
+		Self {
+			sensor,
+			pwm_heater: heater,
+			pid_control,
+			safety,
+			control_mode: ControlMode::Pid,
+			mpc_state: None,
+			feed_forward: MpcFeedForward::default(),
+			last_current_temperature_sample: None,
+			fault: None,
+			uptime: Duration::ZERO,
+			kill_pin: NoKillPin,
+			kill_pin_active_state: PinState::High,
+			watchdog: NoWatchdog,
+		}
+	}
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> PidController<CHP, S, K, W, N>
+{
+	/// The minimum limit output by the PID control. Take this in consideration when setting the `PidGains`.
+	pub const PID_CONTROL_MIN_LIMIT: f32 = 0.;
+	/// The maximum limit output by the PID control. Take this in consideration when setting the `PidGains`.
+	pub const PID_CONTROL_MAX_LIMIT: f32 = 100.;
+
+	/// Returns the [`PidGains`] previously set on this PID controller.
+	pub fn get_pid_gains(&self) -> PidGains
+	{
+		PidGains {
+			p: self.pid_control.kp,
+			i: self.pid_control.ki,
+			d: self.pid_control.kd,
+		}
+	}
+
+	/// Set the PID gains of this controller. Check [`Self::PID_CONTROL_MIN_LIMIT`] and [`Self::PID_CONTROL_MAX_LIMIT`] to see in what
+	/// range the values should be.
+	pub fn set_pid_gains(&mut self, pid_gains: &PidGains)
+	{
+		self.pid_control.kp = pid_gains.p;
+		self.pid_control.ki = pid_gains.i;
+		self.pid_control.kd = pid_gains.d;
+	}
+
+	/// Reads the current [`Temperature`] of the PID controller, using the `context` needed by its sensor (check
+	/// [`TemperatureSensor::Context`]).
+	///
+	/// Returns `Ok(Temperature)` if the read was succesful, otherwise `Err(S::Error)`.
+	pub fn get_current_temperature(&mut self, context: &mut S::Context) -> Result<Temperature, S::Error>
+	{
+		match self.sensor.read_temperature(context)
+		{
+			Ok(temperature) =>
+			{
+				self.last_current_temperature_sample = Some(temperature);
+				Ok(temperature)
+			},
+			Err(error) => Err(error),
+		}
+	}
+
+	/// Gets the [`Temperature`] read using [`Self::get_current_temperature`] the last time you called that function.
+	///
+	/// Returns `None` if [`Self::get_current_temperature`] has never been successfull since the instantation of this
+	/// struct.
+	pub fn get_last_sample_of_current_temperature(&self) -> Option<Temperature>
+	{
+		self.last_current_temperature_sample
+	}
+
+	/// Returns the [`Temperature`] the PID controller is trying to reach.
+	pub fn get_target_temperature(&self) -> Temperature
+	{
+		Temperature::from_kelvin(self.pid_control.setpoint)
+	}
+
+	/// Sets the [`Temperature`] the PID controller will try to reach.
+	///
+	/// # Warning
+	/// You need to call [`PidController::tick`] after this to effectively make the PID controller work to reach it.
+	pub fn set_target_temperature(&mut self, target_temperature: Temperature)
+	{
+		self.pid_control.setpoint(target_temperature.as_kelvin());
+	}
+
+	/// Make the PID controller work to try to reach its [`target temperature`].
+	///
+	/// If this fails, a [`ThermalFault`] is latched (check the [`struct's documentation`](Self)).
+	///
+	/// [`target temperature`]: `Self::get_target_temperature`
+	pub fn tick(&mut self, delta_time: f32, context: &mut S::Context) -> Result<(), TickError>
+	{
+		let heat_percentage = self.request_heat(delta_time, context)?;
+		self.give_heat(heat_percentage)
+	}
+
+	/// Does the work of [`Self::tick`] except giving the heat to the heater: returns the heat percentage computed by the
+	/// control mode, so that it can be limited (for example by a [`HeaterManager`]) before giving it using
+	/// [`Self::give_heat`].
+	///
+	/// If this fails, a [`ThermalFault`] is latched (check the [`struct's documentation`](Self)).
+	///
+	/// [`HeaterManager`]: super::HeaterManager
+	pub fn request_heat(&mut self, delta_time: f32, context: &mut S::Context) -> Result<Percentage, TickError>
+	{
+		self.uptime += delta_time_as_duration(delta_time);
+		if self.fault.is_some()
+		{
+			return Err(TickError::Faulted);
+		}
+
+		let result = self.try_request_heat(delta_time, context);
+		if let Err(error) = result
+		{
+			self.latch_fault(error);
+		}
+
+		result
+	}
+
+	/// Gives the provided `heat_percentage` to the heater, which should be at most the one returned by
+	/// [`Self::request_heat`], and then feeds the watchdog.
+	///
+	/// If this fails, a [`ThermalFault`] is latched (check the [`struct's documentation`](Self)).
+	pub fn give_heat(&mut self, heat_percentage: Percentage) -> Result<(), TickError>
+	{
+		if self.fault.is_some()
+		{
+			return Err(TickError::Faulted);
+		}
+
+		if self.pwm_heater.set_heat_percentage(heat_percentage).is_err()
+		{
+			self.latch_fault(TickError::SetPwmHeaterPercentage);
+			return Err(TickError::SetPwmHeaterPercentage);
+		}
+		self.set_mpc_given_heat(heat_percentage);
+		self.feed_watchdog();
+
+		Ok(())
+	}
+
+	/// Shifts the phase of the PWM signal of the heater (check [`PwmPin::set_phase`]).
+	///
+	/// Returns `Err(CHP::Error)` if it has been impossible to set the phase.
+	pub fn set_pwm_phase(&mut self, phase: Percentage) -> Result<(), CHP::Error>
+	{
+		self.pwm_heater.set_phase(phase)
+	}
+
+	fn try_request_heat(&mut self, delta_time: f32, context: &mut S::Context) -> Result<Percentage, TickError>
+	{
+		let current_temperature = self.read_current_temperature(context)?;
+
+		self.compute_heat_percentage(current_temperature, delta_time)
+	}
+
+	/// Reads the current temperature like [`Self::get_current_temperature`], but a [`SensorFault`] of the sensor is
+	/// returned as a [`TickError::ReadTemperatureIsWrong`].
+	///
+	/// [`SensorFault`]: crate::drivers::temperature_sensor::SensorFault
+	fn read_current_temperature(&mut self, context: &mut S::Context) -> Result<Temperature, TickError>
+	{
+		self.get_current_temperature(context)
+			.map_err(|error| match S::get_sensor_fault(&error)
+			{
+				Some(fault) => TickError::ReadTemperatureIsWrong(EnumSet::only(fault.into())),
+				None => TickError::CantReadTemperature,
+			})
+	}
+
+	/// Checks that the `current_temperature` is safe, then gives the heater the heat computed by the control mode.
+	fn control(&mut self, current_temperature: Temperature, delta_time: f32) -> Result<(), TickError>
+	{
+		let heat_percentage = self.compute_heat_percentage(current_temperature, delta_time)?;
+		self.pwm_heater
+			.set_heat_percentage(heat_percentage)
+			.map_err(|_| TickError::SetPwmHeaterPercentage)
+	}
+
+	/// Checks that the `current_temperature` is safe, then returns the heat computed by the control mode.
+	fn compute_heat_percentage(
+		&mut self, current_temperature: Temperature, delta_time: f32,
+	) -> Result<Percentage, TickError>
+	{
+		let safety_errors = self.safety.is_temperature_safe(
+			current_temperature,
+			self.get_target_temperature(),
+			self.pwm_heater.get_heat_percentage(),
+			delta_time_as_duration(delta_time),
+		);
+		if !safety_errors.is_empty()
+		{
+			return Err(TickError::ReadTemperatureIsWrong(safety_errors));
+		}
+
+		let heat_percentage = match self.control_mode
+		{
+			ControlMode::Pid =>
+			{
+				let mut pwm_value = self
+					.pid_control
+					.next_control_output(current_temperature.as_kelvin())
+					.output;
+				pwm_value = math::map(
+					pwm_value,
+					Self::PID_CONTROL_MIN_LIMIT..=Self::PID_CONTROL_MAX_LIMIT,
+					0_f32..=1_f32,
+				);
+				Percentage::from_0_to_1(pwm_value).unwrap()
+			},
+			ControlMode::ModelPredictive(model) =>
+			{
+				self.get_mpc_heat_percentage(&model, current_temperature, delta_time)
+			},
+		};
+
+		Ok(heat_percentage)
+	}
+}
+
+/// Converts the `delta_time` of a tick (in seconds) to the [`Duration`] used by the [`TemperatureSafety`], treating the
+/// invalid ones (like negative values) as no time elapsed.
+fn delta_time_as_duration(delta_time: f32) -> Duration
+{
+	Duration::try_from_secs_f32(delta_time).unwrap_or(Duration::ZERO)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// An error that occurred when calling [`tick`] on a PID controller.
+///
+/// [`tick`]: PidController::tick
+pub enum TickError
+{
+	/// It has been impossible to [`read`] the sensor's temperature.
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	CantReadTemperature,
+
+	/// The sensor's `temperature` has been [`read`], but it's an irregular value.
+	///
+	/// **It could be that the sensor is damaged, or its connection to the microcontroller is damaged...**
+	/// It could also be a false positive: but it's always better to abort the print and turn off the heaters
+	/// to prevent fire hazards. Then if it was a false positive, it means that the parameters passed
+	/// to [`Safety::new`] are too strict.
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	/// [`Safety::new`]: `safety::TemperatureSafety::new`
+	ReadTemperatureIsWrong(EnumSet<safety::TemperatureError>),
+
+	/// It has been impossible to [`set`] the cartridge heater's heat percentage.
+	///
+	/// [`set`]: `PwmHeater::set_heat_percentage`
+	SetPwmHeaterPercentage,
+
+	/// A [`ThermalFault`] is latched, so the controller refuses to work until it's [`cleared`].
+	///
+	/// [`cleared`]: `PidController::clear_fault`
+	Faulted,
+	/// The controller has been [`shut down`] from outside, for example because another heater sharing the same power
+	/// supply has faulted.
+	///
+	/// [`shut down`]: `PidController::shut_down`
+	ShutDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Values of the `proportional`, `integral` and `derivative` gains of a PID controller.
+pub struct PidGains
+{
+	/// [`Proportial component`](https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller#Proportional).
+	pub p: f32,
+	/// [`Integral component`](https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller#Integral).
+	pub i: f32,
+	/// [`Derivative component`](https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller#Derivative).
+	pub d: f32,
+}
@@ -0,0 +1,898 @@
+//! A model predictive control (like [`Marlin's MPC`]), which computes the heat to give to the heater using a thermal
+//! model of it, instead of reacting to the error like the PID control does.
+//!
+//! The model is a heated block that loses heat to the ambient, measured by a sensor that follows the temperature of the
+//! block with a first order lag. Each tick the modeled temperatures are advanced using the power given to the heater,
+//! corrected using the measured temperature, and then the power needed to bring the block to the target temperature
+//! (and keep it there, compensating the losses) is given to the heater.
+//!
+//! [`Marlin's MPC`]: <https://marlinfw.org/docs/features/model_predictive_control.html>
+
+use embedded_hal::digital::OutputPin;
+use enumset::EnumSet;
+#[allow(unused_imports)]
+#[cfg(not(std))]
+use micromath::F32Ext;
+
+use super::{delta_time_as_duration, PidController, TickError};
+use crate::{
+	drivers::temperature_sensor::TemperatureSensor,
+	features::temperature::safety,
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
+
+/// How the [`PidController`] computes the heat to give to the heater.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ControlMode
+{
+	/// The PID control, using the [`PidGains`] of the controller.
+	///
+	/// [`PidGains`]: super::PidGains
+	Pid,
+	/// The model predictive control, using the provided [`MpcModel`] of the heater.
+	ModelPredictive(MpcModel),
+}
+
+/// The thermal model of a heater used by the model predictive control, which can be measured using an
+/// [`MpcCalibration`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MpcModel
+{
+	/// The power of the heater when its heat percentage is `100%`, in `W`.
+	pub heater_power: f32,
+	/// The energy needed to raise the temperature of the heated block by `1K`, in `J/K`.
+	pub block_heat_capacity: f32,
+	/// How quickly the sensor follows the temperature of the block, in `1/s` (it's the inverse of the time constant of
+	/// the sensor).
+	pub sensor_responsiveness: f32,
+	/// The power lost to the ambient for each degree of difference between the block and the ambient, in `W/K`.
+	pub ambient_transfer_coefficient: f32,
+	/// The power lost to the ambient when the fan is at `100%`, in addition to
+	/// [`Self::ambient_transfer_coefficient`], in `W/K`.
+	pub fan_transfer_coefficient: f32,
+	/// The energy needed to raise the temperature of `1mm` of filament by `1K`, in `J/K/mm`.
+	pub filament_heat_capacity_per_mm: f32,
+	/// The temperature of the ambient, which is only the initial estimate: the controller adjusts it while the
+	/// temperature is close to the target temperature, to compensate for the errors of the model.
+	pub ambient_temperature: Temperature,
+}
+
+/// The inputs that make the heater lose more heat, which the model predictive control compensates before the
+/// temperature changes (check [`PidController::set_feed_forward`]).
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct MpcFeedForward
+{
+	/// The speed of the fan that cools the heated block.
+	pub fan_speed: Percentage,
+	/// How many millimeters of filament per second are pushed through the heated block.
+	pub extrusion_speed: f32,
+}
+
+/// The temperatures modeled by the model predictive control, in kelvin.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) struct MpcState
+{
+	block_temperature: f32,
+	sensor_temperature: f32,
+	ambient_temperature: f32,
+	/// The power given to the heater by the last update, in `W`.
+	last_power: f32,
+}
+
+impl MpcState
+{
+	/// The fraction of the difference between the measured and the modeled temperature that is corrected in one
+	/// second.
+	const MODEL_CORRECTION_PER_SECOND: f32 = 0.5;
+	/// How close the sensor's temperature must be to the target temperature for the ambient temperature to be adjusted.
+	const STEADY_STATE_THRESHOLD: f32 = 1.;
+	/// The minimum speed at which the ambient temperature is adjusted, in `K/s`.
+	const MIN_AMBIENT_CHANGE_PER_SECOND: f32 = 1.;
+
+	/// Returns an [`MpcState`] of a heater whose temperature is the `current_temperature` everywhere.
+	pub(super) fn new(model: &MpcModel, current_temperature: Temperature) -> Self
+	{
+		Self {
+			block_temperature: current_temperature.as_kelvin(),
+			sensor_temperature: current_temperature.as_kelvin(),
+			ambient_temperature: model.ambient_temperature.as_kelvin(),
+			last_power: 0.,
+		}
+	}
+
+	/// Advances the model by `delta_time` seconds, corrects it using the `current_temperature` and returns the heat
+	/// percentage needed to reach the `target_temperature`.
+	///
+	/// If no time has elapsed (or `delta_time` is invalid) the model isn't changed, and the last heat percentage is
+	/// returned.
+	pub(super) fn update(
+		&mut self, model: &MpcModel, feed_forward: &MpcFeedForward, current_temperature: Temperature,
+		target_temperature: Temperature, delta_time: f32,
+	) -> Percentage
+	{
+		if delta_time.is_nan() || delta_time <= 0.
+		{
+			return Self::power_as_heat_percentage(model, self.last_power);
+		}
+
+		let transfer_coefficient = model.ambient_transfer_coefficient
+			+ feed_forward.fan_speed.into_0_to_1() * model.fan_transfer_coefficient
+			+ feed_forward.extrusion_speed.max(0.) * model.filament_heat_capacity_per_mm;
+
+		// Predict the temperatures using the power given during the last `delta_time` seconds
+		let lost_power = (self.block_temperature - self.ambient_temperature) * transfer_coefficient;
+		self.block_temperature += (self.last_power - lost_power) * delta_time / model.block_heat_capacity;
+		self.sensor_temperature +=
+			(self.block_temperature - self.sensor_temperature) * (model.sensor_responsiveness * delta_time).min(1.);
+
+		// Correct them using the measured temperature
+		let correction_factor = 1. - (1. - Self::MODEL_CORRECTION_PER_SECOND).powf(delta_time);
+		let correction = (current_temperature.as_kelvin() - self.sensor_temperature) * correction_factor;
+		self.block_temperature += correction;
+		self.sensor_temperature += correction;
+
+		// If the temperature is stable but the model is still wrong, a different ambient temperature explains the
+		// difference (which also compensates for the errors of the other parameters)
+		let target_temperature = target_temperature.as_kelvin();
+		if (self.sensor_temperature - target_temperature).abs() < Self::STEADY_STATE_THRESHOLD
+		{
+			let min_change = Self::MIN_AMBIENT_CHANGE_PER_SECOND * delta_time;
+			self.ambient_temperature += match correction > 0.
+			{
+				true => correction.max(min_change),
+				false => correction.min(-min_change),
+			};
+		}
+
+		// The power that brings the block to the target temperature in one tick, and then keeps it there
+		let power = (target_temperature - self.block_temperature) * model.block_heat_capacity / delta_time
+			+ (target_temperature - self.ambient_temperature) * transfer_coefficient;
+		let heat_percentage = Self::power_as_heat_percentage(model, power);
+		self.set_given_heat(model, heat_percentage);
+
+		heat_percentage
+	}
+
+	/// Returns the heat percentage that makes the heater give the provided `power`, or `0%` if it can't be computed
+	/// (for example if it's `NaN`).
+	fn power_as_heat_percentage(model: &MpcModel, power: f32) -> Percentage
+	{
+		Percentage::from_0_to_1((power / model.heater_power).clamp(0., 1.)).unwrap_or(Percentage::ZERO)
+	}
+
+	/// Makes the next update use the `heat_percentage` that has actually been given to the heater, if it's different
+	/// from the computed one.
+	pub(super) fn set_given_heat(&mut self, model: &MpcModel, heat_percentage: Percentage)
+	{
+		self.last_power = heat_percentage.into_0_to_1() * model.heater_power;
+	}
+}
+
+/// The parameters of an [`MpcCalibration`].
+#[derive(Clone, Copy, Debug)]
+pub struct MpcCalibrationConfig
+{
+	/// The power of the heater when its heat percentage is `100%`, in `W` (it's usually written on the heater).
+	///
+	/// It can't be measured, because the temperature only depends on the ratio between the power and the heat
+	/// capacity: the other parameters of the [`MpcModel`] are measured relatively to it.
+	pub heater_power: f32,
+	/// The temperature the heater is heated to and then kept at. It should be the temperature the heater usually works
+	/// at.
+	pub target_temperature: Temperature,
+	/// For how many seconds the target temperature is kept after it has been reached: the power needed in the second
+	/// half of this time is used to measure the heat lost to the ambient.
+	pub hold_time_in_seconds: f32,
+	/// The seconds after which the calibration fails with [`MpcCalibrationError::Timeout`] if it hasn't finished yet.
+	pub timeout_in_seconds: f32,
+}
+
+/// A calibration procedure that measures the [`MpcModel`] of a heater.
+///
+/// It must start when the heater is at the ambient temperature. The heater is then heated at full power up to the
+/// target temperature: the temperature rise (an exponential curve) gives the heat capacity of the block, the heat lost
+/// to the ambient and the responsiveness of the sensor. Finally the target temperature is kept for a while using the
+/// model measured so far, and the average power needed to do it gives a more accurate measure of the heat lost to the
+/// ambient.
+///
+/// Call [`PidController::mpc_calibration_tick`] (or [`Self::update`] to control the heater yourself) periodically
+/// until it returns [`MpcCalibrationStatus::Finished`].
+///
+/// The fan and the filament aren't calibrated, so [`MpcModel::fan_transfer_coefficient`] and
+/// [`MpcModel::filament_heat_capacity_per_mm`] are `0`.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{features::temperature::*, utils::physical_quantities::temperature::Temperature};
+/// #
+/// let mut calibration = MpcCalibration::new(MpcCalibrationConfig {
+///     heater_power: 40.,
+///     target_temperature: Temperature::from_celsius(200.),
+///     hold_time_in_seconds: 60.,
+///     timeout_in_seconds: 600.,
+/// });
+///
+/// // A block of `10J/K` that loses `0.1W/K`, with a sensor that has a time constant of `2s`
+/// let (mut block_temperature, mut sensor_temperature) = (25_f32, 25_f32);
+/// let model = loop
+/// {
+///     match calibration.update(Temperature::from_celsius(sensor_temperature), 0.1)
+///     {
+///         Ok(MpcCalibrationStatus::Finished(model)) => break model,
+///         Ok(_) => (),
+///         Err(error) => panic!("{error:?}"),
+///     }
+///
+///     let power = calibration.get_heater_percentage().into_0_to_1() * 40.;
+///     block_temperature += (power - (block_temperature - 25.) * 0.1) / 10. * 0.1;
+///     sensor_temperature += (block_temperature - sensor_temperature) / 2. * 0.1;
+/// };
+///
+/// assert!((model.block_heat_capacity - 10.).abs() < 1.);
+/// assert!((model.ambient_transfer_coefficient - 0.1).abs() < 0.01);
+/// ```
+pub struct MpcCalibration
+{
+	config: MpcCalibrationConfig,
+	elapsed_seconds: f32,
+	heater_percentage: Percentage,
+	phase: CalibrationPhase,
+	outcome: Option<Result<MpcModel, MpcCalibrationError>>,
+}
+
+enum CalibrationPhase
+{
+	NotStarted,
+	Heating
+	{
+		ambient_temperature: f32,
+		/// The temperatures measured every `sample_interval` seconds since the heater has been switched on.
+		samples: [f32; MpcCalibration::SAMPLES_CAPACITY],
+		samples_count: usize,
+		sample_interval: f32,
+	},
+	Holding
+	{
+		model: MpcModel,
+		state: MpcState,
+		block_responsiveness: f32,
+		elapsed_seconds: f32,
+		energy: f32,
+		temperature_integral: f32,
+		measured_seconds: f32,
+	},
+}
+
+impl MpcCalibration
+{
+	/// When all the samples have been taken, half of them are discarded and the interval is doubled, so that they
+	/// always cover the whole heating.
+	const SAMPLES_CAPACITY: usize = 16;
+	const INITIAL_SAMPLE_INTERVAL_IN_SECONDS: f32 = 1.;
+
+	/// Returns an [`MpcCalibration`] that hasn't started yet.
+	pub fn new(config: MpcCalibrationConfig) -> Self
+	{
+		Self {
+			config,
+			elapsed_seconds: 0.,
+			heater_percentage: Percentage::ZERO,
+			phase: CalibrationPhase::NotStarted,
+			outcome: None,
+		}
+	}
+
+	/// Returns the [`MpcCalibrationConfig`] you provided to [`Self::new`].
+	pub fn get_config(&self) -> &MpcCalibrationConfig
+	{
+		&self.config
+	}
+
+	/// Returns the heat percentage that must be given to the heater, which is `0%` once the calibration is over.
+	pub fn get_heater_percentage(&self) -> Percentage
+	{
+		match self.outcome
+		{
+			None => self.heater_percentage,
+			Some(_) => Percentage::ZERO,
+		}
+	}
+
+	/// Returns how many seconds have passed since the calibration has started.
+	pub fn get_elapsed_seconds(&self) -> f32
+	{
+		self.elapsed_seconds
+	}
+
+	/// Updates the calibration using the `current_temperature` of the heater, `delta_time` seconds after the last
+	/// update. Then [`Self::get_heater_percentage`] returns the heat percentage to give to the heater.
+	///
+	/// Returns `Ok(MpcCalibrationStatus)` with the progress of the calibration, otherwise returns
+	/// `Err(MpcCalibrationError)` if the calibration has failed. Once the calibration is over, this always returns the
+	/// same result.
+	pub fn update(
+		&mut self, current_temperature: Temperature, delta_time: f32,
+	) -> Result<MpcCalibrationStatus, MpcCalibrationError>
+	{
+		if let Some(outcome) = self.outcome
+		{
+			return outcome.map(MpcCalibrationStatus::Finished);
+		}
+
+		self.elapsed_seconds += delta_time;
+		if self.elapsed_seconds > self.config.timeout_in_seconds
+		{
+			return self.finish(Err(MpcCalibrationError::Timeout));
+		}
+
+		let temperature = current_temperature.as_kelvin();
+		let target_temperature = self.config.target_temperature.as_kelvin();
+		match &mut self.phase
+		{
+			CalibrationPhase::NotStarted =>
+			{
+				let mut samples = [0.; Self::SAMPLES_CAPACITY];
+				samples[0] = temperature;
+				self.phase = CalibrationPhase::Heating {
+					ambient_temperature: temperature,
+					samples,
+					samples_count: 1,
+					sample_interval: Self::INITIAL_SAMPLE_INTERVAL_IN_SECONDS,
+				};
+				self.elapsed_seconds = 0.;
+				self.heater_percentage = Percentage::FULL;
+			},
+			CalibrationPhase::Heating {
+				samples,
+				samples_count,
+				sample_interval,
+				..
+			} =>
+			{
+				if self.elapsed_seconds >= *samples_count as f32 * *sample_interval
+				{
+					if *samples_count == Self::SAMPLES_CAPACITY
+					{
+						for i in 0..Self::SAMPLES_CAPACITY / 2
+						{
+							samples[i] = samples[2 * i];
+						}
+						*samples_count = Self::SAMPLES_CAPACITY / 2;
+						*sample_interval *= 2.;
+					}
+					else
+					{
+						samples[*samples_count] = temperature;
+						*samples_count += 1;
+					}
+				}
+
+				if temperature >= target_temperature
+				{
+					let model = self.fit_heating_curve();
+					match model
+					{
+						Ok((model, block_responsiveness)) =>
+						{
+							self.phase = CalibrationPhase::Holding {
+								model,
+								state: MpcState::new(&model, current_temperature),
+								block_responsiveness,
+								elapsed_seconds: 0.,
+								energy: 0.,
+								temperature_integral: 0.,
+								measured_seconds: 0.,
+							};
+						},
+						Err(error) => return self.finish(Err(error)),
+					}
+				}
+			},
+			CalibrationPhase::Holding {
+				model,
+				state,
+				block_responsiveness,
+				elapsed_seconds,
+				energy,
+				temperature_integral,
+				measured_seconds,
+			} =>
+			{
+				self.heater_percentage = state.update(
+					model,
+					&MpcFeedForward::default(),
+					current_temperature,
+					self.config.target_temperature,
+					delta_time,
+				);
+
+				*elapsed_seconds += delta_time;
+				if *elapsed_seconds > self.config.hold_time_in_seconds / 2.
+				{
+					*energy += self.heater_percentage.into_0_to_1() * model.heater_power * delta_time;
+					*temperature_integral += temperature * delta_time;
+					*measured_seconds += delta_time;
+				}
+
+				if *elapsed_seconds >= self.config.hold_time_in_seconds
+				{
+					let average_power = *energy / *measured_seconds;
+					let average_temperature = *temperature_integral / *measured_seconds;
+					let mut model = *model;
+					model.ambient_transfer_coefficient =
+						average_power / (average_temperature - model.ambient_temperature.as_kelvin());
+					model.block_heat_capacity = model.ambient_transfer_coefficient / *block_responsiveness;
+
+					return self.finish(Ok(model));
+				}
+			},
+		}
+
+		Ok(match self.phase
+		{
+			CalibrationPhase::NotStarted | CalibrationPhase::Heating { .. } => MpcCalibrationStatus::Heating,
+			CalibrationPhase::Holding { .. } => MpcCalibrationStatus::Holding,
+		})
+	}
+
+	/// Fits the temperatures sampled while heating to the response of the model to a constant power, returning the
+	/// model and the responsiveness of the block (in `1/s`).
+	fn fit_heating_curve(&self) -> Result<(MpcModel, f32), MpcCalibrationError>
+	{
+		let CalibrationPhase::Heating {
+			ambient_temperature,
+			samples,
+			samples_count,
+			sample_interval,
+		} = &self.phase
+		else
+		{
+			unreachable!()
+		};
+
+		// The block's temperature rises like `asymptotic - (asymptotic - ambient) * e^(-block_responsiveness * t)`,
+		// which can be solved using three equally spaced samples. The earliest samples are skipped, because the lag of
+		// the sensor distorts them
+		let spacing = (samples_count - 1) / 3;
+		if spacing == 0
+		{
+			return Err(MpcCalibrationError::InvalidHeatingCurve);
+		}
+		let first_index = samples_count - 1 - 2 * spacing;
+		let (t1, t2, t3) = (
+			samples[first_index],
+			samples[first_index + spacing],
+			samples[first_index + 2 * spacing],
+		);
+		let curvature = t1 + t3 - 2. * t2;
+		if t1 >= t2 || t2 >= t3 || curvature >= 0.
+		{
+			return Err(MpcCalibrationError::InvalidHeatingCurve);
+		}
+
+		let asymptotic_temperature = (t1 * t3 - t2 * t2) / curvature;
+		let block_responsiveness =
+			((t2 - asymptotic_temperature) / (t3 - asymptotic_temperature)).ln() / (spacing as f32 * sample_interval);
+
+		// The sensor lags behind the block, which makes its temperature look like the one of a block that started
+		// heating later: how much later gives the responsiveness of the sensor
+		let first_time = first_index as f32 * sample_interval;
+		let lag_ratio = (t1 - asymptotic_temperature)
+			/ ((ambient_temperature - asymptotic_temperature) * (-block_responsiveness * first_time).exp());
+		if lag_ratio <= 1.
+		{
+			return Err(MpcCalibrationError::InvalidHeatingCurve);
+		}
+
+		let ambient_transfer_coefficient = self.config.heater_power / (asymptotic_temperature - ambient_temperature);
+		let model = MpcModel {
+			heater_power: self.config.heater_power,
+			block_heat_capacity: ambient_transfer_coefficient / block_responsiveness,
+			sensor_responsiveness: block_responsiveness * lag_ratio / (lag_ratio - 1.),
+			ambient_transfer_coefficient,
+			fan_transfer_coefficient: 0.,
+			filament_heat_capacity_per_mm: 0.,
+			ambient_temperature: Temperature::from_kelvin(*ambient_temperature),
+		};
+
+		Ok((model, block_responsiveness))
+	}
+
+	fn finish(
+		&mut self, outcome: Result<MpcModel, MpcCalibrationError>,
+	) -> Result<MpcCalibrationStatus, MpcCalibrationError>
+	{
+		self.outcome = Some(outcome);
+		outcome.map(MpcCalibrationStatus::Finished)
+	}
+}
+
+/// The progress of an [`MpcCalibration`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MpcCalibrationStatus
+{
+	/// The heater is being heated at full power up to the target temperature.
+	Heating,
+	/// The target temperature has been reached and it's being kept, to measure the heat lost to the ambient.
+	Holding,
+	/// The calibration has finished successfully and the heater has been turned off.
+	Finished(MpcModel),
+}
+
+/// An error that occurred during an [`MpcCalibration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MpcCalibrationError
+{
+	/// It has been impossible to [`read`] the sensor's temperature.
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	CantReadTemperature,
+
+	/// The sensor's `temperature` has been [`read`], but it's an irregular value (check
+	/// [`TickError::ReadTemperatureIsWrong`]).
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	ReadTemperatureIsWrong(EnumSet<safety::TemperatureError>),
+
+	/// It has been impossible to set the heater's heat percentage.
+	SetPwmHeaterPercentage,
+
+	/// The calibration hasn't finished within [`MpcCalibrationConfig::timeout_in_seconds`], for example because the
+	/// heater isn't powerful enough to reach the target temperature.
+	Timeout,
+
+	/// A [`ThermalFault`] is latched by the [`PidController`], so the calibration can't run until it's [`cleared`].
+	///
+	/// [`ThermalFault`]: super::ThermalFault
+	/// [`cleared`]: PidController::clear_fault
+	Faulted,
+
+	/// The temperature didn't rise like the model predicts while heating, for example because the target temperature
+	/// has been reached too quickly or the calibration didn't start at the ambient temperature.
+	InvalidHeatingCurve,
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> PidController<CHP, S, K, W, N>
+{
+	/// Returns the [`ControlMode`] of this controller.
+	pub fn get_control_mode(&self) -> ControlMode
+	{
+		self.control_mode
+	}
+
+	/// Sets how this controller computes the heat to give to the heater. Each heater can use a different mode, for
+	/// example the model predictive control for the hotend and the PID control for the bed.
+	///
+	/// The modeled temperatures of the model predictive control are reset, and they start from the temperature read by
+	/// the next tick.
+	pub fn set_control_mode(&mut self, control_mode: ControlMode)
+	{
+		self.control_mode = control_mode;
+		self.mpc_state = None;
+	}
+
+	/// Returns the [`MpcFeedForward`] previously set on this controller.
+	pub fn get_feed_forward(&self) -> MpcFeedForward
+	{
+		self.feed_forward
+	}
+
+	/// Sets the fan speed and the extrusion speed, so that the model predictive control gives the heat they take away
+	/// before the temperature drops. It's ignored by the PID control.
+	pub fn set_feed_forward(&mut self, feed_forward: MpcFeedForward)
+	{
+		self.feed_forward = feed_forward;
+	}
+
+	/// Makes the `calibration` control the heater, like [`Self::tick`] does with the control mode. The temperature is
+	/// still checked by the [`TemperatureSafety`] of this controller, using [`MpcCalibrationConfig::target_temperature`]
+	/// as the target temperature.
+	///
+	/// The heater is turned off once the calibration has finished, or as soon as an error occurs. The errors that also
+	/// make [`Self::tick`] fail latch a [`ThermalFault`] like it does.
+	///
+	/// Returns `Ok(MpcCalibrationStatus)` with the progress of the calibration, otherwise returns
+	/// `Err(MpcCalibrationError)`.
+	///
+	/// [`TemperatureSafety`]: safety::TemperatureSafety
+	/// [`ThermalFault`]: super::ThermalFault
+	pub fn mpc_calibration_tick(
+		&mut self, calibration: &mut MpcCalibration, delta_time: f32, context: &mut S::Context,
+	) -> Result<MpcCalibrationStatus, MpcCalibrationError>
+	{
+		self.uptime += delta_time_as_duration(delta_time);
+		if self.fault.is_some()
+		{
+			return Err(MpcCalibrationError::Faulted);
+		}
+
+		let status = self.try_mpc_calibration_tick(calibration, delta_time, context);
+		match status
+		{
+			Ok(MpcCalibrationStatus::Heating | MpcCalibrationStatus::Holding) => self.feed_watchdog(),
+			Err(MpcCalibrationError::CantReadTemperature) => self.latch_fault(TickError::CantReadTemperature),
+			Err(MpcCalibrationError::ReadTemperatureIsWrong(errors)) =>
+			{
+				self.latch_fault(TickError::ReadTemperatureIsWrong(errors))
+			},
+			Err(MpcCalibrationError::SetPwmHeaterPercentage) => self.latch_fault(TickError::SetPwmHeaterPercentage),
+			_ =>
+			{
+				if self.pwm_heater.set_heat_percentage(Percentage::ZERO).is_err()
+				{
+					self.latch_fault(TickError::SetPwmHeaterPercentage);
+					return Err(MpcCalibrationError::SetPwmHeaterPercentage);
+				}
+				self.feed_watchdog();
+			},
+		}
+
+		status
+	}
+
+	fn try_mpc_calibration_tick(
+		&mut self, calibration: &mut MpcCalibration, delta_time: f32, context: &mut S::Context,
+	) -> Result<MpcCalibrationStatus, MpcCalibrationError>
+	{
+		let current_temperature = self.read_current_temperature(context).map_err(|error| match error
+		{
+			TickError::ReadTemperatureIsWrong(errors) => MpcCalibrationError::ReadTemperatureIsWrong(errors),
+			_ => MpcCalibrationError::CantReadTemperature,
+		})?;
+
+		let safety_errors = self.safety.is_temperature_safe(
+			current_temperature,
+			calibration.get_config().target_temperature,
+			self.pwm_heater.get_heat_percentage(),
+			delta_time_as_duration(delta_time),
+		);
+		if !safety_errors.is_empty()
+		{
+			return Err(MpcCalibrationError::ReadTemperatureIsWrong(safety_errors));
+		}
+
+		let status = calibration.update(current_temperature, delta_time)?;
+		self.pwm_heater
+			.set_heat_percentage(calibration.get_heater_percentage())
+			.map_err(|_| MpcCalibrationError::SetPwmHeaterPercentage)?;
+
+		Ok(status)
+	}
+
+	/// Makes the model predictive control (if it's used) know the heat percentage that has actually been given to the
+	/// heater.
+	pub(super) fn set_mpc_given_heat(&mut self, heat_percentage: Percentage)
+	{
+		if let (ControlMode::ModelPredictive(model), Some(mpc_state)) = (&self.control_mode, &mut self.mpc_state)
+		{
+			mpc_state.set_given_heat(model, heat_percentage);
+		}
+	}
+
+	/// Returns the heat percentage computed by the model predictive control using the provided `model`.
+	pub(super) fn get_mpc_heat_percentage(
+		&mut self, model: &MpcModel, current_temperature: Temperature, delta_time: f32,
+	) -> Percentage
+	{
+		let target_temperature = self.get_target_temperature();
+		self.mpc_state
+			.get_or_insert_with(|| MpcState::new(model, current_temperature))
+			.update(
+				model,
+				&self.feed_forward,
+				current_temperature,
+				target_temperature,
+				delta_time,
+			)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::time::Duration;
+
+	use super::*;
+	use crate::{
+		drivers::{heater::PwmHeater, thermistor::Thermistor},
+		features::temperature::{
+			pid::PidGains,
+			safety::{temperature_change::TemperatureChangeConfig, TemperatureSafety},
+		},
+		hardware::mock::{MockAdc, MockThermalPlant, SimulatedHeaterPin, SimulatedThermistorPin, ThermalPlantConfig},
+	};
+
+	type Controller = PidController<SimulatedHeaterPin, Thermistor<MockAdc, SimulatedThermistorPin>>;
+
+	const TICK_PERIOD: Duration = Duration::from_millis(100);
+
+	fn new_controller(plant: &MockThermalPlant) -> Controller
+	{
+		let thermistor = ThermalPlantConfig::HOTEND.thermistor;
+		let thermistor = Thermistor::new(
+			plant.get_thermistor_pin(),
+			thermistor.beta,
+			thermistor.resistance_at_t0,
+			thermistor.other_resistance,
+		);
+		let safety = TemperatureSafety::new(
+			Temperature::from_celsius(0.)..=Temperature::from_celsius(300.),
+			TemperatureChangeConfig {
+				period: Duration::from_secs(40),
+				hysteresis: 10.,
+			},
+			TemperatureChangeConfig {
+				period: Duration::from_secs(20),
+				hysteresis: 2.,
+			},
+		);
+
+		PidController::new(
+			thermistor,
+			PwmHeater::new(plant.get_heater_pin()),
+			PidGains { p: 0., i: 0., d: 0. },
+			safety,
+		)
+	}
+
+	fn calibrate(plant: &mut MockThermalPlant, controller: &mut Controller) -> MpcModel
+	{
+		let mut calibration = MpcCalibration::new(MpcCalibrationConfig {
+			heater_power: ThermalPlantConfig::HOTEND.heater_power,
+			target_temperature: Temperature::from_celsius(200.),
+			hold_time_in_seconds: 120.,
+			timeout_in_seconds: 600.,
+		});
+
+		loop
+		{
+			match controller
+				.mpc_calibration_tick(&mut calibration, TICK_PERIOD.as_secs_f32(), &mut MockAdc)
+				.unwrap()
+			{
+				MpcCalibrationStatus::Finished(model) => break model,
+				_ => plant.advance(TICK_PERIOD),
+			}
+		}
+	}
+
+	/// Ticks the `controller` for `duration` of virtual time, returning the maximum temperature of the sensor.
+	fn run(plant: &mut MockThermalPlant, controller: &mut Controller, duration: Duration) -> f32
+	{
+		let end_time = plant.get_elapsed_time() + duration;
+		let mut max_temperature = f32::MIN;
+		while plant.get_elapsed_time() < end_time
+		{
+			controller.tick(TICK_PERIOD.as_secs_f32(), &mut MockAdc).unwrap();
+			plant.advance(TICK_PERIOD);
+			max_temperature = max_temperature.max(plant.get_sensor_temperature().as_celsius());
+		}
+
+		max_temperature
+	}
+
+	fn assert_relative_error(value: f32, expected_value: f32, max_relative_error: f32)
+	{
+		assert!(
+			((value - expected_value) / expected_value).abs() < max_relative_error,
+			"{value} isn't close enough to {expected_value}"
+		);
+	}
+
+	#[test]
+	fn calibration_measures_the_plant()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut controller = new_controller(&plant);
+
+		let model = calibrate(&mut plant, &mut controller);
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+
+		let config = ThermalPlantConfig::HOTEND;
+		assert_relative_error(model.block_heat_capacity, config.heat_capacity, 0.15);
+		assert_relative_error(model.ambient_transfer_coefficient, config.ambient_loss, 0.1);
+		// The dead time of the plant also looks like a lag of the sensor
+		assert_relative_error(model.sensor_responsiveness, 1. / config.sensor_lag_in_seconds, 0.5);
+		assert!((model.ambient_temperature.as_celsius() - 25.).abs() < 0.5);
+	}
+
+	#[test]
+	fn model_predictive_control_keeps_target_temperature()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut controller = new_controller(&plant);
+		let model = calibrate(&mut plant, &mut controller);
+		plant.advance(Duration::from_secs(300));
+
+		controller.set_control_mode(ControlMode::ModelPredictive(model));
+		controller.set_target_temperature(Temperature::from_celsius(210.));
+		let max_temperature = run(&mut plant, &mut controller, Duration::from_secs(120));
+		assert!(max_temperature < 211., "{max_temperature}");
+		assert!((plant.get_sensor_temperature().as_celsius() - 210.).abs() < 1.);
+	}
+
+	#[test]
+	fn feed_forward_compensates_fan()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut controller = new_controller(&plant);
+		let mut model = calibrate(&mut plant, &mut controller);
+		model.fan_transfer_coefficient = 0.05;
+
+		controller.set_control_mode(ControlMode::ModelPredictive(model));
+		controller.set_target_temperature(Temperature::from_celsius(200.));
+		run(&mut plant, &mut controller, Duration::from_secs(60));
+
+		// The heat is increased as soon as the fan is switched on, so the temperature barely drops
+		plant.set_fan_draft(0.05);
+		controller.set_feed_forward(MpcFeedForward {
+			fan_speed: Percentage::FULL,
+			extrusion_speed: 0.,
+		});
+		let end_time = plant.get_elapsed_time() + Duration::from_secs(60);
+		let mut min_temperature = f32::MAX;
+		while plant.get_elapsed_time() < end_time
+		{
+			run(&mut plant, &mut controller, TICK_PERIOD);
+			min_temperature = min_temperature.min(plant.get_sensor_temperature().as_celsius());
+		}
+		assert!(min_temperature > 199., "{min_temperature}");
+	}
+
+	#[test]
+	fn update_without_elapsed_time_keeps_last_heat()
+	{
+		let model = MpcModel {
+			heater_power: 40.,
+			block_heat_capacity: 15.,
+			sensor_responsiveness: 0.5,
+			ambient_transfer_coefficient: 0.1,
+			fan_transfer_coefficient: 0.05,
+			filament_heat_capacity_per_mm: 0.005,
+			ambient_temperature: Temperature::from_celsius(25.),
+		};
+		let feed_forward = MpcFeedForward::default();
+		let temperature = Temperature::from_celsius(200.);
+		let mut state = MpcState::new(&model, temperature);
+
+		// The target temperature is the block's one, which would make the power `0 / 0`
+		let heat_percentage = state.update(&model, &feed_forward, temperature, temperature, 0.);
+		assert_eq!(heat_percentage, Percentage::ZERO);
+
+		let heat_percentage = state.update(&model, &feed_forward, temperature, temperature, 0.1);
+		assert!(heat_percentage > Percentage::ZERO);
+		assert_eq!(
+			state.update(&model, &feed_forward, temperature, temperature, 0.),
+			heat_percentage
+		);
+		assert_eq!(
+			state.update(&model, &feed_forward, temperature, temperature, f32::NAN),
+			heat_percentage
+		);
+	}
+
+	#[test]
+	fn linear_heating_curve_is_rejected()
+	{
+		let mut calibration = MpcCalibration::new(MpcCalibrationConfig {
+			heater_power: 40.,
+			target_temperature: Temperature::from_celsius(200.),
+			hold_time_in_seconds: 60.,
+			timeout_in_seconds: 600.,
+		});
+
+		// The temperature rises linearly, which isn't the response of a heater
+		let mut temperature = 25.;
+		let result = loop
+		{
+			match calibration.update(Temperature::from_celsius(temperature), 0.1)
+			{
+				Ok(MpcCalibrationStatus::Heating) => temperature += 0.5,
+				result => break result,
+			}
+		};
+		assert_eq!(result, Err(MpcCalibrationError::InvalidHeatingCurve));
+		assert_eq!(calibration.get_heater_percentage(), Percentage::ZERO);
+	}
+}
@@ -0,0 +1,717 @@
+//! A scheduler of temperature profiles, like the ones of reflow ovens, kilns and curing chambers: the target temperature
+//! of a [`PidController`] is changed over time following a sequence of [`ProfileSegment`]s.
+
+use core::time::Duration;
+
+use embedded_hal::digital::OutputPin;
+use enumset::EnumSet;
+#[allow(unused_imports)]
+#[cfg(not(std))]
+use micromath::F32Ext;
+
+use super::{delta_time_as_duration, PidController, TickError};
+use crate::{
+	drivers::temperature_sensor::TemperatureSensor,
+	features::temperature::safety::{self, temperature_change::TemperatureChangeConfig, PlannedRamp},
+	peripherals::{pwm::PwmPin, watchdog::Watchdog},
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
+
+/// A part of a [`TemperatureProfile`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProfileSegment
+{
+	/// Changes the target temperature by `rate_per_second` degrees per second (upwards or downwards) until it's
+	/// `target_temperature`.
+	Ramp
+	{
+		target_temperature: Temperature,
+		rate_per_second: f32,
+	},
+	/// Keeps the target temperature reached by the previous segment for `duration_in_seconds` seconds.
+	Soak
+	{
+		duration_in_seconds: f32
+	},
+	/// Sets the target temperature to `target_temperature` and waits until the temperature has cooled down to it, as
+	/// fast as the heater can cool without heating.
+	Cool
+	{
+		target_temperature: Temperature
+	},
+}
+
+/// The progress of a [`TemperatureProfile`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ProfileStatus
+{
+	/// The segment at `segment_index` is running.
+	Running
+	{
+		segment_index: usize
+	},
+	/// The profile has been [`paused`] during the segment at `segment_index`, so the target temperature is kept.
+	///
+	/// [`paused`]: TemperatureProfile::pause
+	Paused
+	{
+		segment_index: usize
+	},
+	/// All the segments have finished.
+	Finished,
+	/// The profile has been [`aborted`], or the temperature has deviated too much from the expected one.
+	///
+	/// [`aborted`]: TemperatureProfile::abort
+	Aborted,
+}
+
+/// A scheduler that runs a sequence of [`ProfileSegment`]s, computing the target temperature at each update.
+///
+/// The profile starts from the temperature of the first update. Call [`PidController::profile_tick`] (or
+/// [`Self::update`] to set the target temperature yourself) periodically, using the delta time of a [`Clock`], until
+/// it returns [`ProfileStatus::Finished`].
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{features::temperature::*, utils::physical_quantities::temperature::Temperature};
+/// #
+/// let segments = [
+///     ProfileSegment::Ramp {
+///         target_temperature: Temperature::from_celsius(150.),
+///         rate_per_second: 2.,
+///     },
+///     ProfileSegment::Soak { duration_in_seconds: 60. },
+///     ProfileSegment::Cool { target_temperature: Temperature::from_celsius(50.) },
+/// ];
+/// let mut profile = TemperatureProfile::new(&segments, None);
+///
+/// profile.update(Temperature::from_celsius(50.), 0.).unwrap();
+/// assert_eq!(profile.update(Temperature::from_celsius(50.), 10.), Ok(ProfileStatus::Running { segment_index: 0 }));
+/// assert_eq!(profile.get_target_temperature(), Some(Temperature::from_celsius(70.)));
+///
+/// assert_eq!(profile.update(Temperature::from_celsius(150.), 60.), Ok(ProfileStatus::Running { segment_index: 1 }));
+/// assert_eq!(profile.update(Temperature::from_celsius(150.), 60.), Ok(ProfileStatus::Running { segment_index: 2 }));
+/// assert_eq!(profile.update(Temperature::from_celsius(50.), 1.), Ok(ProfileStatus::Finished));
+/// ```
+///
+/// [`Clock`]: crate::peripherals::time::system_time::Clock
+pub struct TemperatureProfile<'a>
+{
+	segments: &'a [ProfileSegment],
+	max_deviation: Option<TemperatureChangeConfig>,
+	status: ProfileStatus,
+
+	/// It's `None` until the first update.
+	target_temperature: Option<Temperature>,
+	/// The target temperature when the current segment has started.
+	segment_start_temperature: Temperature,
+	segment_elapsed_seconds: f32,
+	deviation: Option<f32>,
+	/// For how long in a row the deviation has been bigger than the maximum one.
+	deviation_time: Duration,
+}
+
+impl<'a> TemperatureProfile<'a>
+{
+	/// Returns a [`TemperatureProfile`] that will run the provided `segments`.
+	///
+	/// If `max_deviation` isn't `None`, the profile is aborted when the temperature differs from the expected one by
+	/// more than [`TemperatureChangeConfig::hysteresis`] degrees for [`TemperatureChangeConfig::period`]
+	/// in a row. It isn't checked during the [`ProfileSegment::Cool`] segments, since the expected temperature
+	/// isn't known.
+	///
+	/// # Panics
+	/// Panics if the `rate_per_second` of a [`ProfileSegment::Ramp`] isn't bigger than `0`.
+	pub fn new(segments: &'a [ProfileSegment], max_deviation: Option<TemperatureChangeConfig>) -> Self
+	{
+		assert!(segments.iter().all(|segment| match segment
+		{
+			ProfileSegment::Ramp { rate_per_second, .. } => *rate_per_second > 0.,
+			_ => true,
+		}));
+
+		Self {
+			segments,
+			max_deviation,
+			status: ProfileStatus::Running { segment_index: 0 },
+			target_temperature: None,
+			segment_start_temperature: Temperature::from_kelvin(0.),
+			segment_elapsed_seconds: 0.,
+			deviation: None,
+			deviation_time: Duration::ZERO,
+		}
+	}
+
+	/// Returns the [`ProfileStatus`] of the last update.
+	pub fn get_status(&self) -> ProfileStatus
+	{
+		self.status
+	}
+
+	/// Returns the index and the [`ProfileSegment`] that is running (or paused), or `None` if the profile is over.
+	pub fn get_current_segment(&self) -> Option<(usize, &'a ProfileSegment)>
+	{
+		match self.status
+		{
+			ProfileStatus::Running { segment_index } | ProfileStatus::Paused { segment_index } =>
+			{
+				self.segments.get(segment_index).map(|segment| (segment_index, segment))
+			},
+			ProfileStatus::Finished | ProfileStatus::Aborted => None,
+		}
+	}
+
+	/// Returns the target temperature computed by the last update, or `None` if the profile hasn't been updated yet.
+	pub fn get_target_temperature(&self) -> Option<Temperature>
+	{
+		self.target_temperature
+	}
+
+	/// Returns how many degrees the temperature of the last update was above the expected one (it's negative if it was
+	/// below), or `None` if it isn't known (check [`Self::new`]).
+	pub fn get_deviation(&self) -> Option<f32>
+	{
+		self.deviation
+	}
+
+	/// Returns the [`PlannedRamp`] that a [`TemperatureSafety`] should follow during the current segment (check
+	/// [`TemperatureSafety::set_planned_ramp`]).
+	///
+	/// [`TemperatureSafety`]: safety::TemperatureSafety
+	/// [`TemperatureSafety::set_planned_ramp`]: safety::TemperatureSafety::set_planned_ramp
+	pub fn get_planned_ramp(&self) -> Option<PlannedRamp>
+	{
+		let ProfileStatus::Running { .. } = self.status
+		else
+		{
+			return None;
+		};
+
+		match self.get_current_segment()?.1
+		{
+			ProfileSegment::Ramp {
+				target_temperature,
+				rate_per_second,
+			} => Some(PlannedRamp {
+				final_temperature: *target_temperature,
+				rate_per_second: match *target_temperature >= self.segment_start_temperature
+				{
+					true => *rate_per_second,
+					false => -*rate_per_second,
+				},
+			}),
+			ProfileSegment::Soak { .. } => None,
+			ProfileSegment::Cool { target_temperature } => Some(PlannedRamp {
+				final_temperature: *target_temperature,
+				rate_per_second: 0.,
+			}),
+		}
+	}
+
+	/// Pauses the profile: the target temperature is kept and the time of the current segment stops until it's
+	/// [`resumed`]. It does nothing if the profile is over.
+	///
+	/// [`resumed`]: Self::resume
+	pub fn pause(&mut self)
+	{
+		if let ProfileStatus::Running { segment_index } = self.status
+		{
+			self.status = ProfileStatus::Paused { segment_index };
+		}
+	}
+
+	/// Resumes a [`paused`] profile.
+	///
+	/// [`paused`]: Self::pause
+	pub fn resume(&mut self)
+	{
+		if let ProfileStatus::Paused { segment_index } = self.status
+		{
+			self.status = ProfileStatus::Running { segment_index };
+		}
+	}
+
+	/// Aborts the profile, which can't be resumed anymore. It does nothing if the profile has already finished.
+	pub fn abort(&mut self)
+	{
+		if self.status != ProfileStatus::Finished
+		{
+			self.status = ProfileStatus::Aborted;
+		}
+	}
+
+	/// Updates the profile using the `current_temperature`, `delta_time` seconds after the last update. Then
+	/// [`Self::get_target_temperature`] returns the new target temperature.
+	///
+	/// Returns `Ok(ProfileStatus)` with the progress of the profile, otherwise returns
+	/// `Err(ProfileError::DeviationTooLarge)` if the profile has been aborted because the temperature deviated too much
+	/// from the expected one (check [`Self::new`]).
+	pub fn update(&mut self, current_temperature: Temperature, delta_time: f32) -> Result<ProfileStatus, ProfileError>
+	{
+		let ProfileStatus::Running { mut segment_index } = self.status
+		else
+		{
+			return Ok(self.status);
+		};
+
+		let mut target_temperature = match self.target_temperature
+		{
+			Some(target_temperature) => target_temperature,
+			None =>
+			{
+				self.segment_start_temperature = current_temperature;
+				current_temperature
+			},
+		};
+		self.segment_elapsed_seconds += delta_time;
+
+		// More than one segment can end in a single update, so the time left over by a segment is given to the next one
+		let mut is_deviation_checked = false;
+		while let Some(segment) = self.segments.get(segment_index)
+		{
+			let start_temperature = self.segment_start_temperature.as_kelvin();
+			let leftover_seconds = match *segment
+			{
+				ProfileSegment::Ramp {
+					target_temperature: final_temperature,
+					rate_per_second,
+				} =>
+				{
+					let final_temperature = final_temperature.as_kelvin();
+					let ramp_seconds = (final_temperature - start_temperature).abs() / rate_per_second;
+					let change = (rate_per_second * self.segment_elapsed_seconds)
+						.min((final_temperature - start_temperature).abs())
+						.copysign(final_temperature - start_temperature);
+					target_temperature = Temperature::from_kelvin(start_temperature + change);
+					is_deviation_checked = true;
+
+					self.segment_elapsed_seconds - ramp_seconds
+				},
+				ProfileSegment::Soak { duration_in_seconds } =>
+				{
+					is_deviation_checked = true;
+
+					self.segment_elapsed_seconds - duration_in_seconds
+				},
+				ProfileSegment::Cool {
+					target_temperature: final_temperature,
+				} =>
+				{
+					target_temperature = final_temperature;
+					is_deviation_checked = false;
+
+					match current_temperature <= final_temperature
+					{
+						true => 0.,
+						false => -1.,
+					}
+				},
+			};
+
+			if leftover_seconds < 0.
+			{
+				break;
+			}
+
+			segment_index += 1;
+			self.segment_start_temperature = target_temperature;
+			self.segment_elapsed_seconds = leftover_seconds;
+		}
+		self.target_temperature = Some(target_temperature);
+
+		self.status = match segment_index < self.segments.len()
+		{
+			true => ProfileStatus::Running { segment_index },
+			false => ProfileStatus::Finished,
+		};
+
+		self.deviation = is_deviation_checked.then(|| (current_temperature - target_temperature).as_kelvin());
+		if let (Some(deviation), Some(max_deviation)) = (self.deviation, self.max_deviation)
+		{
+			match deviation.abs() > max_deviation.hysteresis
+			{
+				true => self.deviation_time += delta_time_as_duration(delta_time),
+				false => self.deviation_time = Duration::ZERO,
+			}
+
+			if self.deviation_time > max_deviation.period
+			{
+				self.status = ProfileStatus::Aborted;
+				return Err(ProfileError::DeviationTooLarge);
+			}
+		}
+
+		Ok(self.status)
+	}
+}
+
+/// An error that occurred while running a [`TemperatureProfile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileError
+{
+	/// It has been impossible to [`read`] the sensor's temperature.
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	CantReadTemperature,
+
+	/// The sensor's `temperature` has been [`read`], but it's an irregular value (check
+	/// [`TickError::ReadTemperatureIsWrong`]).
+	///
+	/// [`read`]: `TemperatureSensor::read_temperature`
+	ReadTemperatureIsWrong(EnumSet<safety::TemperatureError>),
+
+	/// It has been impossible to set the heater's heat percentage.
+	SetPwmHeaterPercentage,
+
+	/// A [`ThermalFault`] is latched by the [`PidController`], so the profile can't run until it's [`cleared`].
+	///
+	/// [`ThermalFault`]: super::ThermalFault
+	/// [`cleared`]: PidController::clear_fault
+	Faulted,
+
+	/// The temperature has deviated from the expected one for too long, so the profile has been aborted (check
+	/// [`TemperatureProfile::new`]).
+	DeviationTooLarge,
+}
+
+impl From<TickError> for ProfileError
+{
+	fn from(error: TickError) -> Self
+	{
+		match error
+		{
+			TickError::CantReadTemperature => Self::CantReadTemperature,
+			TickError::ReadTemperatureIsWrong(errors) => Self::ReadTemperatureIsWrong(errors),
+			TickError::SetPwmHeaterPercentage => Self::SetPwmHeaterPercentage,
+			TickError::Faulted | TickError::ShutDown => Self::Faulted,
+		}
+	}
+}
+
+impl<CHP: PwmPin, S: TemperatureSensor, K: OutputPin, W: Watchdog, const N: usize> PidController<CHP, S, K, W, N>
+{
+	/// Makes the controller follow the `profile`: its target temperature is updated by the profile and then the
+	/// controller works like [`Self::tick`] does, with its [`TemperatureSafety`] following the ramps of the profile
+	/// (check [`TemperatureProfile::get_planned_ramp`]).
+	///
+	/// The heater is turned off once the profile has finished or has been aborted. The errors that also make
+	/// [`Self::tick`] fail latch a [`ThermalFault`] like it does, and abort the profile.
+	///
+	/// Returns `Ok(ProfileStatus)` with the progress of the profile, otherwise returns `Err(ProfileError)`.
+	///
+	/// [`TemperatureSafety`]: safety::TemperatureSafety
+	/// [`ThermalFault`]: super::ThermalFault
+	pub fn profile_tick(
+		&mut self, profile: &mut TemperatureProfile, delta_time: f32, context: &mut S::Context,
+	) -> Result<ProfileStatus, ProfileError>
+	{
+		self.uptime += delta_time_as_duration(delta_time);
+		if self.fault.is_some()
+		{
+			return Err(ProfileError::Faulted);
+		}
+
+		let status = self.try_profile_tick(profile, delta_time, context);
+		if !matches!(status, Ok(ProfileStatus::Running { .. } | ProfileStatus::Paused { .. }))
+		{
+			profile.abort();
+			self.safety.set_planned_ramp(None);
+		}
+
+		match status
+		{
+			Ok(ProfileStatus::Running { .. } | ProfileStatus::Paused { .. }) => self.feed_watchdog(),
+			Err(ProfileError::CantReadTemperature) => self.latch_fault(TickError::CantReadTemperature),
+			Err(ProfileError::ReadTemperatureIsWrong(errors)) =>
+			{
+				self.latch_fault(TickError::ReadTemperatureIsWrong(errors))
+			},
+			Err(ProfileError::SetPwmHeaterPercentage) => self.latch_fault(TickError::SetPwmHeaterPercentage),
+			_ =>
+			{
+				if self.pwm_heater.set_heat_percentage(Percentage::ZERO).is_err()
+				{
+					self.latch_fault(TickError::SetPwmHeaterPercentage);
+					return Err(ProfileError::SetPwmHeaterPercentage);
+				}
+				self.feed_watchdog();
+			},
+		}
+
+		status
+	}
+
+	fn try_profile_tick(
+		&mut self, profile: &mut TemperatureProfile, delta_time: f32, context: &mut S::Context,
+	) -> Result<ProfileStatus, ProfileError>
+	{
+		let current_temperature = self.read_current_temperature(context)?;
+
+		let status = profile.update(current_temperature, delta_time)?;
+		if let (ProfileStatus::Running { .. } | ProfileStatus::Paused { .. }, Some(target_temperature)) =
+			(status, profile.get_target_temperature())
+		{
+			self.set_target_temperature(target_temperature);
+			self.safety.set_planned_ramp(profile.get_planned_ramp());
+			self.control(current_temperature, delta_time)?;
+		}
+
+		Ok(status)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::time::Duration;
+
+	use super::*;
+	use crate::{
+		drivers::{heater::PwmHeater, thermistor::Thermistor},
+		features::temperature::{
+			pid::{ControlMode, MpcModel, PidGains},
+			safety::{TemperatureError, TemperatureSafety},
+		},
+		hardware::mock::{MockAdc, MockThermalPlant, SimulatedHeaterPin, SimulatedThermistorPin, ThermalPlantConfig},
+	};
+
+	type Controller = PidController<SimulatedHeaterPin, Thermistor<MockAdc, SimulatedThermistorPin>>;
+
+	const TICK_PERIOD: Duration = Duration::from_millis(100);
+
+	fn get_segments() -> [ProfileSegment; 4]
+	{
+		[
+			ProfileSegment::Ramp {
+				target_temperature: Temperature::from_celsius(150.),
+				rate_per_second: 1.,
+			},
+			ProfileSegment::Soak {
+				duration_in_seconds: 30.,
+			},
+			ProfileSegment::Ramp {
+				target_temperature: Temperature::from_celsius(220.),
+				rate_per_second: 2.,
+			},
+			ProfileSegment::Cool {
+				target_temperature: Temperature::from_celsius(100.),
+			},
+		]
+	}
+
+	/// Returns a controller of the `plant` using the model predictive control, whose safety expects the temperature to
+	/// rise by at least `30°C` every `20` seconds, which is faster than the first ramp of [`get_segments`].
+	fn new_controller(plant: &MockThermalPlant) -> Controller
+	{
+		let config = ThermalPlantConfig::HOTEND;
+		let thermistor = Thermistor::new(
+			plant.get_thermistor_pin(),
+			config.thermistor.beta,
+			config.thermistor.resistance_at_t0,
+			config.thermistor.other_resistance,
+		);
+		let safety = TemperatureSafety::new(
+			Temperature::from_celsius(0.)..=Temperature::from_celsius(300.),
+			TemperatureChangeConfig {
+				period: Duration::from_secs(40),
+				hysteresis: 10.,
+			},
+			TemperatureChangeConfig {
+				period: Duration::from_secs(20),
+				hysteresis: 30.,
+			},
+		);
+
+		let mut controller = PidController::new(
+			thermistor,
+			PwmHeater::new(plant.get_heater_pin()),
+			PidGains { p: 0., i: 0., d: 0. },
+			safety,
+		);
+		controller.set_control_mode(ControlMode::ModelPredictive(MpcModel {
+			heater_power: config.heater_power,
+			block_heat_capacity: config.heat_capacity,
+			sensor_responsiveness: 1. / config.sensor_lag_in_seconds,
+			ambient_transfer_coefficient: config.ambient_loss,
+			fan_transfer_coefficient: 0.,
+			filament_heat_capacity_per_mm: 0.,
+			ambient_temperature: config.ambient_temperature,
+		}));
+
+		controller
+	}
+
+	/// Ticks the `controller` until the `profile` is over, returning the result and the maximum absolute deviation.
+	fn run(
+		plant: &mut MockThermalPlant, controller: &mut Controller, profile: &mut TemperatureProfile,
+	) -> (Result<ProfileStatus, ProfileError>, f32)
+	{
+		let mut max_deviation: f32 = 0.;
+		loop
+		{
+			let status = controller.profile_tick(profile, TICK_PERIOD.as_secs_f32(), &mut MockAdc);
+			max_deviation = max_deviation.max(profile.get_deviation().unwrap_or(0.).abs());
+			match status
+			{
+				Ok(ProfileStatus::Running { .. } | ProfileStatus::Paused { .. }) => plant.advance(TICK_PERIOD),
+				result => return (result, max_deviation),
+			}
+		}
+	}
+
+	#[test]
+	fn profile_follows_segments()
+	{
+		let segments = get_segments();
+		let mut profile = TemperatureProfile::new(&segments, None);
+		let mut temperature = Temperature::from_celsius(50.);
+		assert_eq!(
+			profile.update(temperature, 0.),
+			Ok(ProfileStatus::Running { segment_index: 0 })
+		);
+		assert_eq!(
+			profile.get_planned_ramp(),
+			Some(PlannedRamp {
+				final_temperature: Temperature::from_celsius(150.),
+				rate_per_second: 1.
+			})
+		);
+
+		// 100s of the first ramp, then 20s of the soak
+		assert_eq!(
+			profile.update(temperature, 120.),
+			Ok(ProfileStatus::Running { segment_index: 1 })
+		);
+		assert_eq!(profile.get_target_temperature(), Some(Temperature::from_celsius(150.)));
+		assert_eq!(profile.get_planned_ramp(), None);
+
+		// The time doesn't pass while paused
+		profile.pause();
+		assert_eq!(
+			profile.update(temperature, 100.),
+			Ok(ProfileStatus::Paused { segment_index: 1 })
+		);
+		assert_eq!(profile.get_planned_ramp(), None);
+		profile.resume();
+		assert_eq!(
+			profile.update(temperature, 15.),
+			Ok(ProfileStatus::Running { segment_index: 2 })
+		);
+		assert_eq!(profile.get_target_temperature(), Some(Temperature::from_celsius(160.)));
+
+		// The cooling only ends when the temperature has dropped
+		temperature = Temperature::from_celsius(220.);
+		assert_eq!(
+			profile.update(temperature, 100.),
+			Ok(ProfileStatus::Running { segment_index: 3 })
+		);
+		assert_eq!(profile.get_target_temperature(), Some(Temperature::from_celsius(100.)));
+		assert_eq!(profile.get_deviation(), None);
+		temperature = Temperature::from_celsius(100.);
+		assert_eq!(profile.update(temperature, 1.), Ok(ProfileStatus::Finished));
+		assert_eq!(profile.get_current_segment(), None);
+	}
+
+	#[test]
+	fn deviation_aborts_profile()
+	{
+		let max_deviation = TemperatureChangeConfig {
+			period: Duration::from_secs(5),
+			hysteresis: 3.,
+		};
+		let segments = get_segments();
+		let mut profile = TemperatureProfile::new(&segments, Some(max_deviation));
+		let temperature = Temperature::from_celsius(50.);
+		profile.update(temperature, 0.).unwrap();
+
+		// The temperature doesn't follow the ramp
+		let result = loop
+		{
+			match profile.update(temperature, 1.)
+			{
+				Ok(_) => (),
+				Err(error) => break error,
+			}
+		};
+		assert_eq!(result, ProfileError::DeviationTooLarge);
+		assert_eq!(profile.get_status(), ProfileStatus::Aborted);
+		assert!(profile.get_deviation().unwrap() < -3. - 5.);
+	}
+
+	#[test]
+	fn controller_follows_profile()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut controller = new_controller(&plant);
+		let max_deviation = TemperatureChangeConfig {
+			period: Duration::from_secs(10),
+			hysteresis: 5.,
+		};
+		let segments = get_segments();
+		let mut profile = TemperatureProfile::new(&segments, Some(max_deviation));
+
+		let (result, max_deviation) = run(&mut plant, &mut controller, &mut profile);
+		assert_eq!(result, Ok(ProfileStatus::Finished));
+		assert!(max_deviation < 5., "{max_deviation}");
+		assert!(plant.get_sensor_temperature().as_celsius() <= 100.);
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+		assert_eq!(controller.safety.get_planned_ramp(), None);
+	}
+
+	#[test]
+	fn broken_heater_is_detected_during_ramp()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		plant.set_heater_broken(true);
+		let mut controller = new_controller(&plant);
+		let segments = get_segments();
+		let mut profile = TemperatureProfile::new(&segments, None);
+
+		// The rising check follows the ramp, even if the target temperature changes at every tick
+		let (result, _) = run(&mut plant, &mut controller, &mut profile);
+		match result
+		{
+			Err(ProfileError::ReadTemperatureIsWrong(errors)) =>
+			{
+				assert!(errors.contains(TemperatureError::CantRiseFastEnoughToTargetTemperature))
+			},
+			result => panic!("{result:?}"),
+		}
+		assert!(controller.get_fault().is_some());
+		assert_eq!(profile.get_status(), ProfileStatus::Aborted);
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+	}
+
+	#[test]
+	fn heater_stuck_on_is_detected_while_cooling()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut controller = new_controller(&plant);
+		let segments = get_segments();
+		let mut profile = TemperatureProfile::new(&segments, None);
+
+		let mut result = Ok(ProfileStatus::Running { segment_index: 0 });
+		while result != Ok(ProfileStatus::Running { segment_index: 3 })
+		{
+			result = controller.profile_tick(&mut profile, TICK_PERIOD.as_secs_f32(), &mut MockAdc);
+			plant.advance(TICK_PERIOD);
+		}
+
+		// The heater keeps heating during the cooling, which the range check would only detect at 300°C
+		plant.set_heater_stuck_on(true);
+		let (result, _) = run(&mut plant, &mut controller, &mut profile);
+		match result
+		{
+			Err(ProfileError::ReadTemperatureIsWrong(errors)) =>
+			{
+				assert_eq!(
+					errors,
+					EnumSet::only(TemperatureError::RisingWhileFallingToTargetTemperature)
+				)
+			},
+			result => panic!("{result:?}"),
+		}
+		assert!(controller.get_fault().is_some());
+		assert!(plant.get_sensor_temperature().as_celsius() < 300.);
+	}
+}
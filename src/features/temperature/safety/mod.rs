@@ -1,48 +1,128 @@
-use core::ops::RangeInclusive;
+use core::{ops::RangeInclusive, time::Duration};
 
 use enumset::EnumSet;
 
 use self::{
 	allowed_range::AllowedTemperatureRangeSafety,
+	sensor_fault::{SensorFaultConfig, SensorFaultSafety},
 	temperature_change::{modes::*, *},
 };
-use crate::utils::physical_quantities::temperature::Temperature;
+use crate::{
+	drivers::temperature_sensor::SensorFault,
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
 
 pub mod allowed_range;
+pub mod sensor_fault;
 pub mod temperature_change;
 
-pub struct TemperatureSafety
+/// The default number of samples used by a [`TemperatureSafety`] to check that the temperature rises fast enough.
+pub const DEFAULT_RISING_SAMPLES_COUNT: usize = 5;
+
+/// Checks that the temperature of a heater is safe, without using the heap: the temperature is checked to rise fast
+/// enough with a [`FixedRisingMode`] of `RISING_SAMPLES_COUNT` samples.
+pub struct TemperatureSafety<const RISING_SAMPLES_COUNT: usize = DEFAULT_RISING_SAMPLES_COUNT>
 {
 	allowed_temperature_range: AllowedTemperatureRangeSafety,
 	keep_target_temperature: TemperatureChangeSafety<KeepMode>,
-	rise_to_target_temperature: TemperatureChangeSafety<RisingMode>,
+	rise_to_target_temperature: TemperatureChangeSafety<FixedRisingMode<RISING_SAMPLES_COUNT>>,
+	/// Checks that the temperature isn't rising while following a [`PlannedRamp`] that doesn't rise.
+	fall_to_target_temperature: TemperatureChangeSafety<FallingMode>,
+	/// The config provided to [`Self::new`], which is replaced while following a [`PlannedRamp`].
+	rise_to_target_temperature_config: TemperatureChangeConfig,
+	planned_ramp: Option<PlannedRamp>,
+	sensor_fault: Option<SensorFaultSafety>,
 }
 
-impl TemperatureSafety
+impl<const RISING_SAMPLES_COUNT: usize> TemperatureSafety<RISING_SAMPLES_COUNT>
 {
 	pub fn new(
 		allowed_temperature_range: RangeInclusive<Temperature>,
 		keep_target_temperature_config: TemperatureChangeConfig,
-		rise_to_target_temperature_config: TemperatureChangeConfig, rise_to_target_temperature_samples_count: usize,
+		rise_to_target_temperature_config: TemperatureChangeConfig,
 	) -> Self
 	{
 		Self {
 			allowed_temperature_range: AllowedTemperatureRangeSafety::new(allowed_temperature_range),
 			keep_target_temperature: TemperatureChangeSafety::new(KeepMode, keep_target_temperature_config),
 			rise_to_target_temperature: TemperatureChangeSafety::new(
-				RisingMode::new(rise_to_target_temperature_samples_count),
+				FixedRisingMode::new(),
 				rise_to_target_temperature_config,
 			),
+			rise_to_target_temperature_config,
+			fall_to_target_temperature: TemperatureChangeSafety::new(
+				FallingMode::new(),
+				keep_target_temperature_config,
+			),
+			planned_ramp: None,
+			sensor_fault: None,
+		}
+	}
+
+	/// Returns [`Self`] also checking that the sensor isn't faulty, using a [`SensorFaultSafety`] with the provided
+	/// `config`.
+	pub fn with_sensor_fault_detection(self, config: SensorFaultConfig) -> Self
+	{
+		Self {
+			sensor_fault: Some(SensorFaultSafety::new(config)),
+			..self
+		}
+	}
+
+	/// While following a ramp, the temperature must rise at least this fraction of the planned rate.
+	pub const MIN_RAMP_RATE_FRACTION: f32 = 0.5;
+
+	/// Returns the [`PlannedRamp`] the checks are following, if any.
+	pub fn get_planned_ramp(&self) -> Option<PlannedRamp>
+	{
+		self.planned_ramp
+	}
+
+	/// Makes the checks of the temperature change follow the provided `planned_ramp` instead of the configs provided to
+	/// [`Self::new`], or go back to them if it's `None`.
+	///
+	/// The target temperature of a ramp changes at every check, which would restart the timers of the checks and make
+	/// them useless. So while following a ramp:
+	/// - The temperature is checked to rise at least [`Self::MIN_RAMP_RATE_FRACTION`] of the planned rate (if the ramp
+	///   rises), using [`PlannedRamp::final_temperature`] as the target temperature.
+	/// - The temperature is checked not to rise (check [`FallingMode`]) if the ramp doesn't rise, using the
+	///   `keep_target_temperature_config` provided to [`Self::new`] and [`PlannedRamp::final_temperature`] as the target
+	///   temperature, so that a heater stuck on is detected while cooling down.
+	/// - The temperature isn't checked to be kept near the target temperature, since it isn't kept.
+	pub fn set_planned_ramp(&mut self, planned_ramp: Option<PlannedRamp>)
+	{
+		if self.planned_ramp == planned_ramp
+		{
+			return;
 		}
+		self.planned_ramp = planned_ramp;
+
+		let mut config = self.rise_to_target_temperature_config;
+		if let Some(planned_ramp) = planned_ramp.filter(|planned_ramp| planned_ramp.rate_per_second > 0.)
+		{
+			config.hysteresis =
+				planned_ramp.rate_per_second * config.period.as_secs_f32() * Self::MIN_RAMP_RATE_FRACTION;
+		}
+		self.rise_to_target_temperature.set_config(config);
 	}
 
-	/// Returns a set of all the errors that happened. If no error has happened the set is empty.
+	/// Returns a set of all the errors that happened, given that the heater has been driven with `heater_percentage`
+	/// for the `delta_time` since the previous check. If no error has happened the set is empty.
 	pub fn is_temperature_safe(
-		&mut self, current_temperature: Temperature, target_temperature: Temperature, delta_time: f32,
+		&mut self, current_temperature: Temperature, target_temperature: Temperature, heater_percentage: Percentage,
+		delta_time: Duration,
 	) -> EnumSet<TemperatureError>
 	{
 		let mut errors = EnumSet::empty();
 
+		if let Some(sensor_fault) = &mut self.sensor_fault
+		{
+			for fault in sensor_fault.check(current_temperature, heater_percentage, delta_time)
+			{
+				errors.insert(fault.into());
+			}
+		}
+
 		if !self.allowed_temperature_range.is_temperature_safe(current_temperature)
 		{
 			errors.insert(TemperatureError::CurrentTemperatureOutsideAllowedRange);
@@ -53,24 +133,70 @@ impl TemperatureSafety
 			errors.insert(TemperatureError::TargetTemperatureOutsideAllowedRange);
 		}
 
-		if !self
-			.keep_target_temperature
-			.is_temperature_safe(current_temperature, target_temperature, delta_time)
+		match self.planned_ramp
 		{
-			errors.insert(TemperatureError::CantKeepTargetTemperature);
-		}
+			None =>
+			{
+				if !self.keep_target_temperature.is_temperature_safe(
+					current_temperature,
+					target_temperature,
+					delta_time,
+				)
+				{
+					errors.insert(TemperatureError::CantKeepTargetTemperature);
+				}
 
-		if !self
-			.rise_to_target_temperature
-			.is_temperature_safe(current_temperature, target_temperature, delta_time)
-		{
-			errors.insert(TemperatureError::CantRiseFastEnoughToTargetTemperature);
+				if !self.rise_to_target_temperature.is_temperature_safe(
+					current_temperature,
+					target_temperature,
+					delta_time,
+				)
+				{
+					errors.insert(TemperatureError::CantRiseFastEnoughToTargetTemperature);
+				}
+			},
+			Some(planned_ramp) if planned_ramp.rate_per_second > 0. =>
+			{
+				if !self.rise_to_target_temperature.is_temperature_safe(
+					current_temperature,
+					planned_ramp.final_temperature,
+					delta_time,
+				)
+				{
+					errors.insert(TemperatureError::CantRiseFastEnoughToTargetTemperature);
+				}
+			},
+			Some(planned_ramp) =>
+			{
+				if !self.fall_to_target_temperature.is_temperature_safe(
+					current_temperature,
+					planned_ramp.final_temperature,
+					delta_time,
+				)
+				{
+					errors.insert(TemperatureError::RisingWhileFallingToTargetTemperature);
+				}
+			},
 		}
 
 		errors
 	}
 }
 
+/// A planned change of the target temperature, like a ramp of a [`TemperatureProfile`], that the checks of a
+/// [`TemperatureSafety`] can follow (check [`TemperatureSafety::set_planned_ramp`]).
+///
+/// [`TemperatureProfile`]: super::TemperatureProfile
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PlannedRamp
+{
+	/// The target temperature at the end of the ramp.
+	pub final_temperature: Temperature,
+	/// How many degrees per second the target temperature rises, which is negative when it decreases. It's `0` when the
+	/// temperature is left to reach the final temperature on its own (like when cooling naturally).
+	pub rate_per_second: f32,
+}
+
 #[derive(enumset::EnumSetType, Debug, Hash)]
 pub enum TemperatureError
 {
@@ -82,7 +208,7 @@ pub enum TemperatureError
 
 	/// After `current_temperature` reached the `target_temperature` in a previous call to the function,
 	/// the `current_temperature` wasn't kept in the range `target_temperature ± keep_target_temperature_config.hysteresis`
-	/// for more than the `keep_target_temperature_config.period_in_seconds` you provided to [`TemperatureSafety::new`].
+	/// for more than the `keep_target_temperature_config.period` you provided to [`TemperatureSafety::new`].
 	///
 	/// Check [`this`] for more info.
 	///
@@ -96,4 +222,40 @@ pub enum TemperatureError
 	///
 	/// [`this`]: temperature_change::modes::RisingMode
 	CantRiseFastEnoughToTargetTemperature,
+
+	/// While following a [`PlannedRamp`] that doesn't rise (check [`TemperatureSafety::set_planned_ramp`]), the
+	/// `current_temperature` rose above the lowest temperature it had fallen to, like when the heater is stuck on.
+	///
+	/// Check [`this`] for more info.
+	///
+	/// [`this`]: temperature_change::modes::FallingMode
+	RisingWhileFallingToTargetTemperature,
+
+	/// The sensor has a [`SensorFault::OpenCircuit`].
+	SensorOpenCircuit,
+
+	/// The sensor has a [`SensorFault::ShortCircuit`].
+	SensorShortCircuit,
+
+	/// The sensor has a [`SensorFault::StuckReading`], detected with the config provided to
+	/// [`TemperatureSafety::with_sensor_fault_detection`].
+	SensorStuckReading,
+
+	/// The sensor has a [`SensorFault::ImplausibleJump`], detected with the config provided to
+	/// [`TemperatureSafety::with_sensor_fault_detection`].
+	ImplausibleTemperatureJump,
+}
+
+impl From<SensorFault> for TemperatureError
+{
+	fn from(fault: SensorFault) -> Self
+	{
+		match fault
+		{
+			SensorFault::OpenCircuit => Self::SensorOpenCircuit,
+			SensorFault::ShortCircuit => Self::SensorShortCircuit,
+			SensorFault::StuckReading => Self::SensorStuckReading,
+			SensorFault::ImplausibleJump => Self::ImplausibleTemperatureJump,
+		}
+	}
 }
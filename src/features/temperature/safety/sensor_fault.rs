@@ -0,0 +1,179 @@
+use core::time::Duration;
+
+use enumset::EnumSet;
+
+use crate::{
+	drivers::temperature_sensor::SensorFault,
+	utils::{math::Percentage, physical_quantities::temperature::Temperature},
+};
+
+/// The parameters used by a [`SensorFaultSafety`] to detect a faulty sensor.
+#[derive(Clone, Copy, Debug)]
+pub struct SensorFaultConfig
+{
+	/// The maximum change of the temperature (in Kelvin) between two consecutive samples. Anything more is a
+	/// [`SensorFault::ImplausibleJump`].
+	pub max_jump: f32,
+	/// The minimum heat percentage for the heater to be considered driven.
+	///
+	/// It should be higher than the heat percentage needed to keep the highest target temperature, otherwise a stable
+	/// temperature could be detected as a [`SensorFault::StuckReading`].
+	pub stuck_min_heater_percentage: Percentage,
+	/// The minimum change of the temperature (in Kelvin) that must happen every [`Self::stuck_period`] while the heater
+	/// is driven.
+	pub stuck_hysteresis: f32,
+	/// How long the heater can be driven without the temperature changing by [`Self::stuck_hysteresis`], before the
+	/// reading is a [`SensorFault::StuckReading`].
+	pub stuck_period: Duration,
+}
+
+/// Detects the faults of a sensor that can only be seen by looking at the temperatures it reads over time:
+/// - [`SensorFault::ImplausibleJump`]: the temperature changed between two samples more than
+///   [`SensorFaultConfig::max_jump`], which the thermal mass of a heater makes impossible (it's usually a loose
+///   connection).
+/// - [`SensorFault::StuckReading`]: the heater has been driven with at least
+///   [`SensorFaultConfig::stuck_min_heater_percentage`] for more than [`SensorFaultConfig::stuck_period`],
+///   but the temperature didn't change by [`SensorFaultConfig::stuck_hysteresis`].
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{
+/// #     drivers::temperature_sensor::SensorFault,
+/// #     features::temperature::safety::sensor_fault::*,
+/// #     utils::{math::Percentage, physical_quantities::temperature::Temperature},
+/// # };
+/// # use core::time::Duration;
+/// #
+/// let mut sensor_fault_safety = SensorFaultSafety::new(SensorFaultConfig {
+///     max_jump: 10.,
+///     stuck_min_heater_percentage: Percentage::from_0_to_100(80.).unwrap(),
+///     stuck_hysteresis: 2.,
+///     stuck_period: Duration::from_secs(10),
+/// });
+///
+/// let temperature = Temperature::from_celsius(25.);
+/// let second = Duration::from_secs(1);
+/// for _ in 0..11
+/// {
+///     assert!(sensor_fault_safety.check(temperature, Percentage::FULL, second).is_empty());
+/// }
+/// assert!(sensor_fault_safety.check(temperature, Percentage::FULL, second).contains(SensorFault::StuckReading));
+///
+/// let jumped_temperature = Temperature::from_celsius(40.);
+/// let faults = sensor_fault_safety.check(jumped_temperature, Percentage::ZERO, second);
+/// assert!(faults.contains(SensorFault::ImplausibleJump));
+/// ```
+pub struct SensorFaultSafety
+{
+	config: SensorFaultConfig,
+	last_temperature: Option<Temperature>,
+	/// The temperature when the heater started being driven or the last time it changed by
+	/// [`SensorFaultConfig::stuck_hysteresis`], and the time elapsed since then.
+	stuck_reference: Option<(Temperature, Duration)>,
+}
+
+impl SensorFaultSafety
+{
+	/// Check [`struct's documentation`](Self).
+	pub fn new(config: SensorFaultConfig) -> Self
+	{
+		Self {
+			config,
+			last_temperature: None,
+			stuck_reference: None,
+		}
+	}
+
+	/// Returns the [`SensorFaultConfig`] used by this safety.
+	pub fn get_config(&self) -> SensorFaultConfig
+	{
+		self.config
+	}
+
+	/// Returns the faults detected with the provided `current_temperature`, given that the heater has been driven with
+	/// `heater_percentage` for the `delta_time` since the previous check. If no fault is detected the set is
+	/// empty.
+	pub fn check(
+		&mut self, current_temperature: Temperature, heater_percentage: Percentage, delta_time: Duration,
+	) -> EnumSet<SensorFault>
+	{
+		let mut faults = EnumSet::empty();
+
+		if let Some(last_temperature) = self.last_temperature
+		{
+			if (current_temperature.as_kelvin() - last_temperature.as_kelvin()).abs() > self.config.max_jump
+			{
+				faults.insert(SensorFault::ImplausibleJump);
+			}
+		}
+		self.last_temperature = Some(current_temperature);
+
+		if heater_percentage < self.config.stuck_min_heater_percentage
+		{
+			self.stuck_reference = None;
+			return faults;
+		}
+
+		match self.stuck_reference
+		{
+			Some((reference_temperature, elapsed_time))
+				if (current_temperature.as_kelvin() - reference_temperature.as_kelvin()).abs()
+					< self.config.stuck_hysteresis =>
+			{
+				let elapsed_time = elapsed_time + delta_time;
+				if elapsed_time > self.config.stuck_period
+				{
+					faults.insert(SensorFault::StuckReading);
+				}
+				self.stuck_reference = Some((reference_temperature, elapsed_time));
+			},
+			_ => self.stuck_reference = Some((current_temperature, Duration::ZERO)),
+		}
+
+		faults
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const SECOND: Duration = Duration::from_secs(1);
+
+	#[test]
+	fn stuck_timer_restarts_when_heater_is_idle_or_temperature_changes()
+	{
+		let mut sensor_fault_safety = SensorFaultSafety::new(SensorFaultConfig {
+			max_jump: 10.,
+			stuck_min_heater_percentage: Percentage::HALF,
+			stuck_hysteresis: 2.,
+			stuck_period: Duration::from_secs(10),
+		});
+		let temperature = Temperature::from_celsius(200.);
+
+		// Keeping the temperature with little heat is fine
+		for _ in 0..100
+		{
+			assert!(sensor_fault_safety
+				.check(temperature, Percentage::ZERO, SECOND)
+				.is_empty());
+		}
+
+		// Heating while the temperature slowly rises is fine
+		for i in 0..100
+		{
+			let temperature = Temperature::from_celsius(200. + i as f32 * 0.25);
+			assert!(sensor_fault_safety
+				.check(temperature, Percentage::FULL, SECOND)
+				.is_empty());
+		}
+
+		// A temperature that swings back and forth by less than the hysteresis is stuck
+		let faults = (0..20)
+			.map(|i| Temperature::from_celsius(230. + (i % 2) as f32))
+			.map(|temperature| sensor_fault_safety.check(temperature, Percentage::FULL, SECOND))
+			.fold(EnumSet::empty(), |all_faults, faults| all_faults | faults);
+		assert_eq!(faults, SensorFault::StuckReading);
+	}
+}
@@ -1,5 +1,7 @@
+use core::time::Duration;
+
 #[derive(Clone, Copy, Debug)]
 pub struct TemperatureChangeConfig {
-	pub period_in_seconds: f32,
+	pub period: Duration,
 	pub hysteresis: f32,
 }
@@ -0,0 +1,93 @@
+use core::time::Duration;
+
+use super::{config::TemperatureChangeConfig, ProtectionModeTrait};
+use crate::utils::physical_quantities::temperature::Temperature;
+
+/// While the current temperature is above the target temperature and should fall to it (for example while cooling
+/// down), check that it doesn't stay more than [`TemperatureChangeConfig::hysteresis`] degrees above the lowest
+/// temperature it has fallen to for [`TemperatureChangeConfig::period`] in a row, like it does when the heater is stuck
+/// on.
+///
+/// The lowest temperature is forgotten when the target temperature changes.
+///
+/// # Examples
+/// Here the temperature rises while it should fall to the target temperature:
+/// ```
+/// # use core::time::Duration;
+/// #
+/// # use a13c_embedded::
+/// # {
+/// #     features::temperature::safety::temperature_change::{*, modes::*},
+/// #     utils::physical_quantities::temperature::Temperature
+/// # };
+/// #
+/// let mut falling_temperature_safety = TemperatureChangeSafety::new(FallingMode::new(),
+/// TemperatureChangeConfig
+/// {
+///     period: Duration::from_secs(4),
+///     hysteresis: 10.
+/// });
+/// let target_temperature = Temperature::from_celsius(100.);
+/// let seconds = Duration::from_secs;
+///
+/// assert!(falling_temperature_safety.is_temperature_safe(Temperature::from_celsius(200.), target_temperature, seconds(0)));
+/// assert!(falling_temperature_safety.is_temperature_safe(Temperature::from_celsius(190.), target_temperature, seconds(2)));
+/// assert!(falling_temperature_safety.is_temperature_safe(Temperature::from_celsius(205.), target_temperature, seconds(2)));
+/// assert!(!falling_temperature_safety.is_temperature_safe(Temperature::from_celsius(205.), target_temperature, seconds(3)));
+/// ```
+#[derive(Default)]
+pub struct FallingMode
+{
+	lowest_temperature: Option<Temperature>,
+	last_target_temperature: Option<Temperature>,
+}
+
+impl FallingMode
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	/// Returns the lowest temperature since the target temperature has been set, including the `current_temperature`.
+	fn update_lowest_temperature(
+		&mut self, current_temperature: Temperature, target_temperature: Temperature,
+	) -> Temperature
+	{
+		if self.last_target_temperature != Some(target_temperature)
+		{
+			self.last_target_temperature = Some(target_temperature);
+			self.lowest_temperature = None;
+		}
+
+		let lowest_temperature = match self.lowest_temperature
+		{
+			Some(lowest_temperature) if lowest_temperature <= current_temperature => lowest_temperature,
+			_ => current_temperature,
+		};
+		self.lowest_temperature = Some(lowest_temperature);
+
+		lowest_temperature
+	}
+}
+
+impl ProtectionModeTrait for FallingMode
+{
+	fn should_start_timer(&mut self, current_temperature: Temperature, target_temperature: Temperature) -> bool
+	{
+		self.update_lowest_temperature(current_temperature, target_temperature);
+
+		current_temperature > target_temperature
+	}
+
+	fn should_continue_timer(
+		&mut self, current_temperature: Temperature, target_temperature: Temperature, config: TemperatureChangeConfig,
+		_: Duration,
+	) -> bool
+	{
+		let lowest_temperature = self.update_lowest_temperature(current_temperature, target_temperature);
+
+		current_temperature > target_temperature
+			&& (current_temperature - lowest_temperature).as_kelvin() > config.hysteresis
+	}
+}
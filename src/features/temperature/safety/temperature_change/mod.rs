@@ -1,10 +1,13 @@
-//! There are 2 modes of protection for temperature change:
+//! There are 3 modes of protection for temperature change:
 //! - [`RisingMode`](modes::RisingMode): Makes sure that before the current temperature reaches the target temperature,
-//! the current temperature is rising fast enough.
+//!   the current temperature is rising fast enough.
 //! - [`KeepMode`](modes::KeepMode): Makes sure that after the current temperature reaches the target temperature,
-//! the current temperature is kept within a range near the target temperature.
+//!   the current temperature is kept within a range near the target temperature.
+//! - [`FallingMode`](modes::FallingMode): Makes sure that while the current temperature should fall to the target
+//!   temperature, the current temperature isn't rising.
 
 mod config;
+mod falling_mode;
 mod keep_mode;
 mod rising_mode;
 
@@ -12,9 +15,11 @@ pub use config::*;
 
 pub mod modes
 {
-	pub use super::{keep_mode::*, rising_mode::*};
+	pub use super::{falling_mode::*, keep_mode::*, rising_mode::*};
 }
 
+use core::time::Duration;
+
 use crate::utils::physical_quantities::temperature::Temperature;
 
 /// Makes sure the temperature change is "normal". What "normal" means depends on the `ProtectionMode`
@@ -26,7 +31,8 @@ pub struct TemperatureChangeSafety<ProtectionMode: ProtectionModeTrait>
 	config: TemperatureChangeConfig,
 	last_target_temperature: Option<Temperature>,
 	protection_mode: ProtectionMode,
-	current_timer_in_seconds: Option<f32>,
+	/// The time left before the timer expires.
+	current_timer: Option<Duration>,
 }
 
 impl<ProtectionMode: ProtectionModeTrait> TemperatureChangeSafety<ProtectionMode>
@@ -37,12 +43,25 @@ impl<ProtectionMode: ProtectionModeTrait> TemperatureChangeSafety<ProtectionMode
 			config,
 			last_target_temperature: None,
 			protection_mode,
-			current_timer_in_seconds: None,
+			current_timer: None,
 		}
 	}
 
+	/// Returns the [`TemperatureChangeConfig`] used by this safety.
+	pub fn get_config(&self) -> TemperatureChangeConfig
+	{
+		self.config
+	}
+
+	/// Sets the [`TemperatureChangeConfig`] used by this safety. A timer that is already running isn't restarted, so the
+	/// new period is only used from the next time the timer starts.
+	pub fn set_config(&mut self, config: TemperatureChangeConfig)
+	{
+		self.config = config;
+	}
+
 	pub fn is_temperature_safe(
-		&mut self, current_temperature: Temperature, target_temperature: Temperature, delta_time: f32,
+		&mut self, current_temperature: Temperature, target_temperature: Temperature, delta_time: Duration,
 	) -> bool
 	{
 		// Stop the timer if the target temperature has changed
@@ -53,7 +72,7 @@ impl<ProtectionMode: ProtectionModeTrait> TemperatureChangeSafety<ProtectionMode
 			self.stop_timer();
 		}
 
-		if let Some(current_timer_in_seconds) = self.current_timer_in_seconds.as_mut()
+		if let Some(current_timer) = self.current_timer.as_mut()
 		{
 			if self.protection_mode.should_continue_timer(
 				current_temperature,
@@ -62,8 +81,8 @@ impl<ProtectionMode: ProtectionModeTrait> TemperatureChangeSafety<ProtectionMode
 				delta_time,
 			)
 			{
-				*current_timer_in_seconds -= delta_time;
-				if *current_timer_in_seconds <= 0.
+				*current_timer = current_timer.saturating_sub(delta_time);
+				if current_timer.is_zero()
 				{
 					self.stop_timer();
 
@@ -87,20 +106,22 @@ impl<ProtectionMode: ProtectionModeTrait> TemperatureChangeSafety<ProtectionMode
 
 	fn stop_timer(&mut self)
 	{
-		self.current_timer_in_seconds = None;
+		self.current_timer = None;
 	}
 
 	fn restart_timer(&mut self)
 	{
-		self.current_timer_in_seconds = Some(self.config.period_in_seconds);
+		self.current_timer = Some(self.config.period);
 	}
 }
 
 pub trait ProtectionModeTrait
 {
-	fn should_start_timer(&self, current_temperature: Temperature, target_temperature: Temperature) -> bool;
+	/// Returns `true` if the timer should start. It takes `&mut self` so that a mode can remember what happened in the
+	/// previous ticks (like [`RisingMode`](modes::RisingMode) remembering that the target temperature was reached).
+	fn should_start_timer(&mut self, current_temperature: Temperature, target_temperature: Temperature) -> bool;
 	fn should_continue_timer(
 		&mut self, current_temperature: Temperature, target_temperature: Temperature, config: TemperatureChangeConfig,
-		delta_time: f32,
+		delta_time: Duration,
 	) -> bool;
 }
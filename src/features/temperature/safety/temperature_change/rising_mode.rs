@@ -1,86 +1,149 @@
-use ringbuffer::{AllocRingBuffer, RingBuffer};
+use core::time::Duration;
+
+#[cfg(feature = "alloc")]
+use ringbuffer::AllocRingBuffer;
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 
 use super::{config::TemperatureChangeConfig, ProtectionModeTrait};
 use crate::utils::physical_quantities::temperature::Temperature;
 
 /// While the current temperature is trying to reach the target temperature, check that it is always rising at least
-/// [`TemperatureChangeConfig::hysteresis`] degrees for [`TemperatureChangeConfig::period_in_seconds`] seconds in a row.
+/// [`TemperatureChangeConfig::hysteresis`] degrees for [`TemperatureChangeConfig::period`] in a row.
+///
+/// Once the current temperature has reached the target temperature it isn't checked anymore (that's what [`KeepMode`]
+/// does), until the target temperature changes.
+///
+/// The samples used to check the temperature are kept in the `B` buffer: usually a [`FixedRisingMode`], whose buffer
+/// has a fixed capacity, or an `AllocRisingMode` (only with the `alloc` feature), whose buffer is on the heap.
 ///
 /// # Examples
 /// Here the temperature rises fast enough (because is is always rising for more than `10°C` within `20` seconds):
 /// ```
+/// # use core::time::Duration;
+/// #
 /// # use a13c_embedded::
 /// # {
 /// #     features::temperature::safety::temperature_change::{*, modes::*},
 /// #     utils::physical_quantities::temperature::Temperature
 /// # };
 /// #
-/// let mut rising_temperature_safety = TemperatureChangeSafety::new(RisingMode::new(5),
+/// let mut rising_temperature_safety = TemperatureChangeSafety::new(FixedRisingMode::<5>::new(),
 /// TemperatureChangeConfig
 /// {
-///     period_in_seconds: 20.,
+///     period: Duration::from_secs(20),
 ///     hysteresis: 10.
 /// });
 /// let target_temperature = Temperature::from_celsius(200.);
+/// let seconds = Duration::from_secs;
 ///
-/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(30.), target_temperature, 4.));
-/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(40.), target_temperature, 4.));
-/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(50.), target_temperature, 4.));
-/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(60.), target_temperature, 4.));
+/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(30.), target_temperature, seconds(4)));
+/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(40.), target_temperature, seconds(4)));
+/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(50.), target_temperature, seconds(4)));
+/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(60.), target_temperature, seconds(4)));
 /// ```
 ///
 /// Here the temperature doesn't rise fast enough (it rises by only `5°C` in `3` seconds which is below the config):
 /// ```
+/// # use core::time::Duration;
+/// #
 /// # use a13c_embedded::
 /// # {
 /// #   features::temperature::safety::temperature_change::{*, modes::*},
 /// #   utils::physical_quantities::temperature::Temperature
 /// # };
 /// #
-/// let mut rising_temperature_safety = TemperatureChangeSafety::new(RisingMode::new(5),
+/// let mut rising_temperature_safety = TemperatureChangeSafety::new(FixedRisingMode::<5>::new(),
 /// TemperatureChangeConfig
 /// {
-///     period_in_seconds: 2.,
+///     period: Duration::from_secs(2),
 ///     hysteresis: 10.
 /// });
 /// let target_temperature = Temperature::from_celsius(200.);
+/// let seconds = Duration::from_secs;
 ///
-/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(30.), target_temperature, 1.));
-/// assert!(!rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(35.), target_temperature, 3.));
+/// assert!(rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(30.), target_temperature, seconds(1)));
+/// assert!(!rising_temperature_safety.is_temperature_safe(Temperature::from_celsius(35.), target_temperature, seconds(3)));
 /// ```
-pub struct RisingMode
+///
+/// [`KeepMode`]: super::modes::KeepMode
+pub struct RisingMode<B: RingBuffer<Temperature>>
+{
+	samples: B,
+	time_until_new_sample: Duration,
+	last_target_temperature: Option<Temperature>,
+	has_reached_target_temperature: bool,
+}
+
+/// A [`RisingMode`] that keeps `SAMPLES_COUNT` samples in a buffer of fixed capacity, so it doesn't need a heap.
+pub type FixedRisingMode<const SAMPLES_COUNT: usize> = RisingMode<ConstGenericRingBuffer<Temperature, SAMPLES_COUNT>>;
+
+/// A [`RisingMode`] that keeps its samples in a buffer allocated on the heap, whose capacity is chosen at runtime.
+#[cfg(feature = "alloc")]
+pub type AllocRisingMode = RisingMode<AllocRingBuffer<Temperature>>;
+
+impl<const SAMPLES_COUNT: usize> FixedRisingMode<SAMPLES_COUNT>
 {
-	samples: AllocRingBuffer<Temperature>,
-	remaining_seconds_for_new_sample: f32,
+	/// Returns a [`FixedRisingMode`] that takes `SAMPLES_COUNT` samples every [`TemperatureChangeConfig::period`].
+	pub fn new() -> Self
+	{
+		Self::from_buffer(ConstGenericRingBuffer::new())
+	}
 }
 
-impl RisingMode
+impl<const SAMPLES_COUNT: usize> Default for FixedRisingMode<SAMPLES_COUNT>
 {
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+#[cfg(feature = "alloc")]
+impl AllocRisingMode
+{
+	/// Returns an [`AllocRisingMode`] that takes `samples_count` samples every [`TemperatureChangeConfig::period`].
 	pub fn new(samples_count: usize) -> Self
+	{
+		Self::from_buffer(AllocRingBuffer::new(samples_count))
+	}
+}
+
+impl<B: RingBuffer<Temperature>> RisingMode<B>
+{
+	fn from_buffer(samples: B) -> Self
 	{
 		Self {
-			samples: AllocRingBuffer::new(samples_count),
-			remaining_seconds_for_new_sample: 0.,
+			samples,
+			time_until_new_sample: Duration::ZERO,
+			last_target_temperature: None,
+			has_reached_target_temperature: false,
 		}
 	}
 
 	fn reset(&mut self)
 	{
 		self.samples.clear();
-		self.remaining_seconds_for_new_sample = 0.;
+		self.time_until_new_sample = Duration::ZERO;
 	}
 }
 
-impl ProtectionModeTrait for RisingMode
+impl<B: RingBuffer<Temperature>> ProtectionModeTrait for RisingMode<B>
 {
-	fn should_start_timer(&self, current_temperature: Temperature, target_temperature: Temperature) -> bool
+	fn should_start_timer(&mut self, current_temperature: Temperature, target_temperature: Temperature) -> bool
 	{
-		current_temperature < target_temperature
+		if self.last_target_temperature != Some(target_temperature)
+		{
+			self.last_target_temperature = Some(target_temperature);
+			self.has_reached_target_temperature = false;
+		}
+		self.has_reached_target_temperature |= current_temperature >= target_temperature;
+
+		!self.has_reached_target_temperature
 	}
 
 	fn should_continue_timer(
 		&mut self, current_temperature: Temperature, target_temperature: Temperature, config: TemperatureChangeConfig,
-		delta_time: f32,
+		delta_time: Duration,
 	) -> bool
 	{
 		let mut should_continue = self.should_start_timer(current_temperature, target_temperature);
@@ -95,15 +158,19 @@ impl ProtectionModeTrait for RisingMode
 
 			if should_continue
 			{
-				self.remaining_seconds_for_new_sample -= delta_time;
-
-				if self.remaining_seconds_for_new_sample <= 0.
+				if self.time_until_new_sample <= delta_time
 				{
-					let seconds_to_take_sample = config.period_in_seconds / self.samples.len() as f32;
-					self.remaining_seconds_for_new_sample += seconds_to_take_sample;
+					// The time elapsed after the sample was due is taken from the time until the next one
+					let time_to_take_sample = config.period / self.samples.capacity() as u32;
+					self.time_until_new_sample =
+						(self.time_until_new_sample + time_to_take_sample).saturating_sub(delta_time);
 
 					self.samples.push(current_temperature);
 				}
+				else
+				{
+					self.time_until_new_sample -= delta_time;
+				}
 			}
 		}
 
@@ -115,3 +182,86 @@ impl ProtectionModeTrait for RisingMode
 		should_continue
 	}
 }
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::features::temperature::safety::temperature_change::TemperatureChangeSafety;
+
+	fn is_always_safe<M: ProtectionModeTrait>(mode: M, delta_time: Duration, rise_per_second: f32) -> bool
+	{
+		let mut safety = TemperatureChangeSafety::new(
+			mode,
+			TemperatureChangeConfig {
+				period: Duration::from_secs(10),
+				hysteresis: 5.,
+			},
+		);
+		let target_temperature = Temperature::from_celsius(200.);
+		let ticks = (Duration::from_secs(60).as_millis() / delta_time.as_millis()) as usize;
+
+		(0..ticks).all(|tick| {
+			let elapsed_time = (delta_time * tick as u32).as_secs_f32();
+			let current_temperature = Temperature::from_celsius(30. + elapsed_time * rise_per_second);
+			safety.is_temperature_safe(current_temperature, target_temperature, delta_time)
+		})
+	}
+
+	#[test]
+	fn isnt_checked_after_reaching_target_temperature()
+	{
+		let mut safety = TemperatureChangeSafety::new(
+			FixedRisingMode::<5>::new(),
+			TemperatureChangeConfig {
+				period: Duration::from_secs(10),
+				hysteresis: 5.,
+			},
+		);
+		let target_temperature = Temperature::from_celsius(200.);
+		let second = Duration::from_secs(1);
+
+		// The temperature rises to the target temperature by 1°C per second
+		for celsius in 150..=200
+		{
+			assert!(safety.is_temperature_safe(Temperature::from_celsius(celsius as f32), target_temperature, second));
+		}
+
+		// Then it's kept just below the target temperature (like a PID controller does), which isn't rising anymore
+		for _ in 0..60
+		{
+			assert!(safety.is_temperature_safe(Temperature::from_celsius(199.), target_temperature, second));
+		}
+
+		// A new target temperature must be reached again
+		let target_temperature = Temperature::from_celsius(250.);
+		assert!(!(0..60).all(|_| safety.is_temperature_safe(
+			Temperature::from_celsius(199.),
+			target_temperature,
+			second
+		)));
+	}
+
+	#[test]
+	fn sampling_doesnt_depend_on_delta_time()
+	{
+		for delta_time in [
+			Duration::from_millis(10),
+			Duration::from_millis(100),
+			Duration::from_millis(250),
+		]
+		{
+			// The temperature rises by 10°C or 3°C every 10 seconds
+			assert!(is_always_safe(FixedRisingMode::<5>::new(), delta_time, 1.));
+			assert!(is_always_safe(FixedRisingMode::<20>::new(), delta_time, 1.));
+			assert!(!is_always_safe(FixedRisingMode::<5>::new(), delta_time, 0.3));
+			assert!(!is_always_safe(FixedRisingMode::<20>::new(), delta_time, 0.3));
+
+			#[cfg(feature = "alloc")]
+			{
+				assert!(is_always_safe(AllocRisingMode::new(5), delta_time, 1.));
+				assert!(!is_always_safe(AllocRisingMode::new(5), delta_time, 0.3));
+			}
+		}
+	}
+}
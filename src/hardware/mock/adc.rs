@@ -0,0 +1,63 @@
+use core::ops::Div;
+
+use super::MockEmptyError;
+use crate::{
+	peripherals::adc::{Adc, AdcPin},
+	utils::math::Percentage,
+};
+
+/// A `12-bit` [`Adc`].
+pub struct MockAdc;
+
+impl MockAdc
+{
+	/// The value read when the voltage of a pin is the reference voltage.
+	pub const MAX_READABLE_VALUE: u16 = 4095;
+}
+
+impl Adc for MockAdc
+{
+	type ReadableValue = MockAdcValue;
+
+	fn max_readable_value(&self) -> Self::ReadableValue
+	{
+		MockAdcValue(Self::MAX_READABLE_VALUE)
+	}
+}
+
+/// A value read by the [`MockAdc`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct MockAdcValue(pub u16);
+
+impl Div<MockAdcValue> for MockAdcValue
+{
+	type Output = Result<Percentage, ()>;
+
+	fn div(self, rhs: MockAdcValue) -> Self::Output
+	{
+		Percentage::from_0_to_1(self.0 as f32 / rhs.0 as f32).map_err(|_| ())
+	}
+}
+
+pub enum MockAdcPin
+{
+	Ok
+	{
+		value: u16,
+	},
+	Err,
+}
+
+impl AdcPin<MockAdc> for MockAdcPin
+{
+	type Error = MockEmptyError;
+
+	fn read(&mut self, _: &mut MockAdc) -> Result<MockAdcValue, Self::Error>
+	{
+		match self
+		{
+			MockAdcPin::Ok { value } => Ok(MockAdcValue(*value)),
+			MockAdcPin::Err => Err(MockEmptyError),
+		}
+	}
+}
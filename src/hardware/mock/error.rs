@@ -2,7 +2,7 @@
 pub struct MockEmptyError;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct MockSpiError(embedded_hal::spi::ErrorKind);
+pub struct MockSpiError(pub(super) embedded_hal::spi::ErrorKind);
 
 impl embedded_hal::spi::Error for MockSpiError
 {
@@ -23,6 +23,15 @@ impl embedded_hal::digital::Error for MockDigitalError
 	}
 }
 
+/// Error returned by a [`MockFlashMemory`](super::MockFlashMemory) when an operation is done on a
+/// [`worn out block`](super::MockFlashMemory::wear_out_block).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MockFlashMemoryError
+{
+	ProgramFailed,
+	EraseFailed,
+}
+
 #[cfg(feature = "embedded-svc")]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct MockIoError(embedded_svc::io::ErrorKind);
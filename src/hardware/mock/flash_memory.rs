@@ -0,0 +1,260 @@
+use core::{marker::PhantomData, ops::RangeInclusive};
+
+use super::MockFlashMemoryError;
+use crate::drivers::spi_flash_memory::{EccStatus, FlashMemory, FlashMemoryChip, FlashMemoryChipExt, RowAddress};
+
+extern crate alloc;
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+/// An in-memory [`FlashMemory`] with the layout of the `Chip` that behaves like a NAND flash memory: an erased page
+/// has all its bytes set to `0xFF`, and programming a page can only clear its bits.
+///
+/// Only the pages that have been programmed are kept in memory, so it can be used to simulate big chips.
+pub struct MockFlashMemory<Chip: FlashMemoryChip>
+{
+	pages: BTreeMap<u32, Vec<u8>>,
+	factory_bad_blocks: Vec<u16>,
+	worn_out_blocks: Vec<u16>,
+	ecc_statuses: BTreeMap<u32, EccStatus>,
+	erase_counts: BTreeMap<u16, u32>,
+	_chip: PhantomData<Chip>,
+}
+
+impl<Chip: FlashMemoryChip> MockFlashMemory<Chip>
+{
+	/// Returns a [`MockFlashMemory`] with all its blocks erased.
+	pub fn new() -> Self
+	{
+		Self {
+			pages: BTreeMap::new(),
+			factory_bad_blocks: Vec::new(),
+			worn_out_blocks: Vec::new(),
+			ecc_statuses: BTreeMap::new(),
+			erase_counts: BTreeMap::new(),
+			_chip: PhantomData,
+		}
+	}
+
+	/// Returns a [`MockFlashMemory`] with all its blocks erased, except the provided `factory_bad_blocks` that contain
+	/// a bad block mark (the first byte of the ECC area of their first page is `0x00`).
+	pub fn with_factory_bad_blocks(factory_bad_blocks: &[u16]) -> Self
+	{
+		let mut self_ = Self::new();
+		self_.factory_bad_blocks.extend_from_slice(factory_bad_blocks);
+		for block_index in factory_bad_blocks
+		{
+			self_.write_bad_block_mark(*block_index);
+		}
+
+		self_
+	}
+
+	/// Returns how many times the block at the provided `block_index` has been erased.
+	pub fn get_erase_count(&self, block_index: u16) -> u32
+	{
+		self.erase_counts.get(&block_index).copied().unwrap_or(0)
+	}
+
+	/// Makes every following program and erase operation on the block at the provided `block_index` fail, like it
+	/// happens when a block wears out.
+	///
+	/// The data already in the block can still be read.
+	pub fn wear_out_block(&mut self, block_index: u16)
+	{
+		self.worn_out_blocks.push(block_index);
+	}
+
+	/// Makes every following read of the page at the provided `page_index` return the provided `ecc_status`, until
+	/// its block is erased.
+	///
+	/// The read data doesn't change.
+	pub fn set_ecc_status(&mut self, page_index: u32, ecc_status: EccStatus)
+	{
+		self.ecc_statuses.insert(page_index, ecc_status);
+	}
+
+	fn get_ecc_status(&self, page_index: u32) -> EccStatus
+	{
+		self.ecc_statuses
+			.get(&page_index)
+			.copied()
+			.unwrap_or(EccStatus::NoErrors)
+	}
+
+	fn is_worn_out(&self, page_index: u32) -> bool
+	{
+		self.worn_out_blocks
+			.contains(&((page_index / Chip::PAGES_PER_BLOCK) as u16))
+	}
+
+	fn write_bad_block_mark(&mut self, block_index: u16)
+	{
+		let first_page_index = Chip::get_address_of_block_index(block_index) / Chip::PAGE_SIZE;
+		self.get_page_mut(first_page_index)[Chip::PAGE_SIZE as usize] = 0x00;
+	}
+
+	fn get_page(&self, page_index: u32) -> Option<&Vec<u8>>
+	{
+		assert!(page_index < Chip::MEMORY_SIZE / Chip::PAGE_SIZE);
+
+		self.pages.get(&page_index)
+	}
+
+	fn get_page_mut(&mut self, page_index: u32) -> &mut Vec<u8>
+	{
+		assert!(page_index < Chip::MEMORY_SIZE / Chip::PAGE_SIZE);
+
+		self.pages
+			.entry(page_index)
+			.or_insert_with(|| vec![0xFF; (Chip::PAGE_SIZE + Chip::PAGE_ECC_SIZE) as usize])
+	}
+
+	fn program_bytes(&mut self, page_index: u32, column: usize, data: &[u8])
+	{
+		let page = self.get_page_mut(page_index);
+		page[column..(column + data.len())]
+			.iter_mut()
+			.zip(data)
+			.for_each(|(byte, new_byte)| *byte &= *new_byte);
+	}
+
+	fn read_bytes(&self, page_index: u32, column: usize, data: &mut [u8])
+	{
+		match self.get_page(page_index)
+		{
+			Some(page) => data.copy_from_slice(&page[column..(column + data.len())]),
+			None => data.fill(0xFF),
+		}
+	}
+}
+
+impl<Chip: FlashMemoryChip> Default for MockFlashMemory<Chip>
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+impl<Chip: FlashMemoryChip> FlashMemory for MockFlashMemory<Chip>
+{
+	type Chip = Chip;
+	type Error = MockFlashMemoryError;
+
+	fn program(&mut self, data: &[u8], address: u32) -> Result<(), Self::Error>
+	{
+		let mut written_bytes = 0;
+		while written_bytes < data.len()
+		{
+			let current_address = address + written_bytes as u32;
+			let column = (current_address % Chip::PAGE_SIZE) as usize;
+			let bytes_to_write = (Chip::PAGE_SIZE as usize - column).min(data.len() - written_bytes);
+
+			if self.is_worn_out(current_address / Chip::PAGE_SIZE)
+			{
+				return Err(MockFlashMemoryError::ProgramFailed);
+			}
+			self.program_bytes(
+				current_address / Chip::PAGE_SIZE,
+				column,
+				&data[written_bytes..(written_bytes + bytes_to_write)],
+			);
+			written_bytes += bytes_to_write;
+		}
+
+		Ok(())
+	}
+
+	fn program_page(
+		&mut self, row_address: RowAddress<Self::Chip>, data: &[u8], ecc_data: &[u8],
+	) -> Result<(), Self::Error>
+	{
+		assert!(data.len() as u32 <= Chip::PAGE_SIZE);
+		assert!(ecc_data.len() as u32 <= Chip::PAGE_ECC_SIZE);
+
+		if self.is_worn_out(row_address.get_page_index())
+		{
+			return Err(MockFlashMemoryError::ProgramFailed);
+		}
+		self.program_bytes(row_address.get_page_index(), 0, data);
+		self.program_bytes(row_address.get_page_index(), Chip::PAGE_SIZE as usize, ecc_data);
+
+		Ok(())
+	}
+
+	fn read(&mut self, address: u32, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		let mut ecc_status = EccStatus::NoErrors;
+		let mut read_bytes = 0;
+		while read_bytes < data.len()
+		{
+			let current_address = address + read_bytes as u32;
+			let column = (current_address % Chip::PAGE_SIZE) as usize;
+			let bytes_to_read = (Chip::PAGE_SIZE as usize - column).min(data.len() - read_bytes);
+
+			self.read_bytes(
+				current_address / Chip::PAGE_SIZE,
+				column,
+				&mut data[read_bytes..(read_bytes + bytes_to_read)],
+			);
+			ecc_status = ecc_status.max(self.get_ecc_status(current_address / Chip::PAGE_SIZE));
+			read_bytes += bytes_to_read;
+		}
+
+		Ok(ecc_status)
+	}
+
+	fn read_ecc(&mut self, row_address: RowAddress<Self::Chip>, data: &mut [u8]) -> Result<EccStatus, Self::Error>
+	{
+		assert!(data.len() as u32 <= Chip::PAGE_ECC_SIZE);
+
+		self.read_bytes(row_address.get_page_index(), Chip::PAGE_SIZE as usize, data);
+
+		Ok(self.get_ecc_status(row_address.get_page_index()))
+	}
+
+	fn erase_blocks(&mut self, block_indices_to_erase: RangeInclusive<u16>) -> Result<(), Self::Error>
+	{
+		for block_index in block_indices_to_erase
+		{
+			if self.worn_out_blocks.contains(&block_index)
+			{
+				return Err(MockFlashMemoryError::EraseFailed);
+			}
+
+			let first_page_index = Chip::get_address_of_block_index(block_index) / Chip::PAGE_SIZE;
+			for page_index in first_page_index..(first_page_index + Chip::PAGES_PER_BLOCK)
+			{
+				self.pages.remove(&page_index);
+				self.ecc_statuses.remove(&page_index);
+			}
+
+			*self.erase_counts.entry(block_index).or_insert(0) += 1;
+
+			// The factory bad block marks can't be removed
+			if self.factory_bad_blocks.contains(&block_index)
+			{
+				self.write_bad_block_mark(block_index);
+			}
+		}
+
+		Ok(())
+	}
+
+	fn contains_bad_block_mark(&mut self, block_index: u16) -> Result<bool, Self::Error>
+	{
+		let mut mark = 0;
+		let row_address = RowAddress::from_memory_address(Chip::get_address_of_block_index(block_index));
+		self.read_ecc(row_address, core::slice::from_mut(&mut mark))?;
+
+		Ok(mark != 0xFF)
+	}
+
+	fn is_block_failure(error: &Self::Error) -> bool
+	{
+		matches!(
+			error,
+			MockFlashMemoryError::ProgramFailed | MockFlashMemoryError::EraseFailed
+		)
+	}
+}
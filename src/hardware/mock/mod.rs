@@ -4,10 +4,16 @@ mod adc;
 #[cfg(feature = "embedded-svc")]
 mod connection;
 mod error;
+mod flash_memory;
 mod input;
+#[cfg(feature = "storage")]
+mod nor_flash;
 mod output;
 mod pwm;
 mod spi;
+mod spi_nand_flash;
+mod spi_nor_flash;
+mod thermal_plant;
 mod time;
 mod timer;
 mod uart;
@@ -17,10 +23,16 @@ pub use adc::*;
 #[cfg(feature = "embedded-svc")]
 pub use connection::*;
 pub use error::*;
+pub use flash_memory::*;
 pub use input::*;
+#[cfg(feature = "storage")]
+pub use nor_flash::*;
 pub use output::*;
 pub use pwm::*;
 pub use spi::*;
+pub use spi_nand_flash::*;
+pub use spi_nor_flash::*;
+pub use thermal_plant::*;
 pub use time::*;
 pub use timer::*;
 pub use uart::*;
@@ -0,0 +1,138 @@
+use embedded_storage::nor_flash::{
+	check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+/// An in-memory [`NorFlash`] of `capacity` bytes, that can be used to test the code built on the
+/// [`embedded_storage`] traits without a real flash memory.
+///
+/// Like a real flash memory, an erased byte is `0xFF` and a write can only clear bits, it must be aligned to
+/// `WRITE_SIZE`, and the erases must be aligned to `ERASE_SIZE`.
+pub struct MockNorFlash<const WRITE_SIZE: usize = 1, const ERASE_SIZE: usize = 4096>
+{
+	memory: Vec<u8>,
+	erase_counts: Vec<u32>,
+	writes_left_before_power_loss: Option<usize>,
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> MockNorFlash<WRITE_SIZE, ERASE_SIZE>
+{
+	/// Returns a completely erased [`MockNorFlash`] of `capacity` bytes.
+	///
+	/// # Panics
+	/// Panics if `capacity` isn't a multiple of `ERASE_SIZE`.
+	pub fn new(capacity: usize) -> Self
+	{
+		assert!(capacity.is_multiple_of(ERASE_SIZE));
+
+		Self {
+			memory: vec![0xFF; capacity],
+			erase_counts: vec![0; capacity / ERASE_SIZE],
+			writes_left_before_power_loss: None,
+		}
+	}
+
+	/// Returns the content of the memory.
+	pub fn get_memory(&self) -> &[u8]
+	{
+		&self.memory
+	}
+
+	/// Returns a mutable reference to the content of the memory, to change it without any constraint.
+	pub fn get_memory_mut(&mut self) -> &mut [u8]
+	{
+		&mut self.memory
+	}
+
+	/// Returns how many times the sector at the provided `sector_index` has been erased.
+	pub fn get_erase_count(&self, sector_index: usize) -> u32
+	{
+		self.erase_counts[sector_index]
+	}
+
+	/// Simulates a power loss during the `write`-th next write (counting from 1), which only writes the first half of
+	/// its bytes. All the following writes and erases are ignored, until this is called again with `None`.
+	pub fn set_power_loss_at_write(&mut self, write: Option<usize>)
+	{
+		self.writes_left_before_power_loss = write;
+	}
+
+	/// Returns `true` if the power has been lost (check [`set_power_loss_at_write`]).
+	///
+	/// [`set_power_loss_at_write`]: Self::set_power_loss_at_write
+	pub fn is_power_lost(&self) -> bool
+	{
+		self.writes_left_before_power_loss == Some(0)
+	}
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ErrorType for MockNorFlash<WRITE_SIZE, ERASE_SIZE>
+{
+	type Error = NorFlashErrorKind;
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ReadNorFlash for MockNorFlash<WRITE_SIZE, ERASE_SIZE>
+{
+	const READ_SIZE: usize = 1;
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>
+	{
+		check_read(self, offset, bytes.len())?;
+
+		bytes.copy_from_slice(&self.memory[offset as usize..offset as usize + bytes.len()]);
+		Ok(())
+	}
+
+	fn capacity(&self) -> usize
+	{
+		self.memory.len()
+	}
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> NorFlash for MockNorFlash<WRITE_SIZE, ERASE_SIZE>
+{
+	const WRITE_SIZE: usize = WRITE_SIZE;
+	const ERASE_SIZE: usize = ERASE_SIZE;
+
+	fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error>
+	{
+		check_erase(self, from, to)?;
+		if self.is_power_lost()
+		{
+			return Ok(());
+		}
+
+		self.memory[from as usize..to as usize].fill(0xFF);
+		for sector_index in (from as usize / ERASE_SIZE)..(to as usize / ERASE_SIZE)
+		{
+			self.erase_counts[sector_index] += 1;
+		}
+
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>
+	{
+		check_write(self, offset, bytes.len())?;
+
+		let bytes = match self.writes_left_before_power_loss
+		{
+			Some(0) => return Ok(()),
+			Some(1) => &bytes[..bytes.len() / 2],
+			_ => bytes,
+		};
+		if let Some(writes_left) = self.writes_left_before_power_loss.as_mut()
+		{
+			*writes_left -= 1;
+		}
+
+		for (memory_byte, byte) in self.memory[offset as usize..].iter_mut().zip(bytes)
+		{
+			*memory_byte &= *byte;
+		}
+
+		Ok(())
+	}
+}
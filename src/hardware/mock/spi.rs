@@ -1,10 +1,15 @@
-use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use embedded_hal::spi::{ErrorKind, ErrorType, Operation, SpiDevice};
 
 use super::MockSpiError;
 
 extern crate alloc;
 use alloc::vec::*;
 
+/// A [`SpiDevice`] that records the words written to it and answers the reads with words set in advance.
+///
+/// Every read (and the read half of every transfer) consumes the first of the `read_operations`, while every write
+/// (and the write half of every transfer) is appended to the `write_operations`. A read fails if there are no
+/// `read_operations` left, or if the first one is shorter than the read.
 pub enum MockSpi<Word: Copy + 'static = u8>
 {
 	Ok
@@ -15,6 +20,20 @@ pub enum MockSpi<Word: Copy + 'static = u8>
 	},
 	Err(MockSpiError),
 }
+
+impl<Word: Copy + 'static> MockSpi<Word>
+{
+	/// Returns a [`MockSpi`] that will answer its reads with the provided `read_operations`, in order.
+	pub fn new(read_operations: Vec<Vec<Word>>) -> Self
+	{
+		Self::Ok {
+			read_operations,
+			write_operations: Vec::new(),
+			delays_operations: Vec::new(),
+		}
+	}
+}
+
 impl<Word: Copy + 'static> SpiDevice<Word> for MockSpi<Word>
 {
 	fn transaction(&mut self, new_operations: &mut [Operation<'_, Word>]) -> Result<(), Self::Error>
@@ -27,28 +46,40 @@ impl<Word: Copy + 'static> SpiDevice<Word> for MockSpi<Word>
 				delays_operations,
 			} =>
 			{
-				new_operations.iter_mut().for_each(|operation| match operation
+				let mut read = |words: &mut [Word]| {
+					if read_operations.is_empty()
+					{
+						return Err(MockSpiError(ErrorKind::Other));
+					}
+					let read_operation = read_operations.remove(0);
+					if read_operation.len() < words.len()
+					{
+						return Err(MockSpiError(ErrorKind::Other));
+					}
+
+					words.copy_from_slice(&read_operation[..words.len()]);
+					Ok(())
+				};
+
+				for operation in new_operations.iter_mut()
 				{
-					Operation::Read(operation) => match read_operations.pop()
+					match operation
 					{
-						Some(read_operation) =>
+						Operation::Read(operation) => read(operation)?,
+						Operation::Write(operation) => write_operations.push(operation.to_vec()),
+						Operation::Transfer(read_operation, write_operation) =>
+						{
+							write_operations.push(write_operation.to_vec());
+							read(read_operation)?;
+						},
+						Operation::TransferInPlace(operation) =>
 						{
-							operation
-								.iter_mut()
-								.enumerate()
-								.for_each(|(i, byte)| match read_operation.get(i)
-								{
-									Some(new_byte) => *byte = *new_byte,
-									None => todo!(),
-								})
+							write_operations.push(operation.to_vec());
+							read(operation)?;
 						},
-						None => todo!(),
-					},
-					Operation::Write(operation) => write_operations.push(operation.to_vec()),
-					Operation::Transfer(_, _) => todo!(),
-					Operation::TransferInPlace(_) => todo!(),
-					Operation::DelayNs(operation) => delays_operations.push(*operation),
-				});
+						Operation::DelayNs(operation) => delays_operations.push(*operation),
+					}
+				}
 				Ok(())
 			},
 			MockSpi::Err(error) => Err(*error),
@@ -60,3 +91,37 @@ impl<Word: Copy + 'static> ErrorType for MockSpi<Word>
 {
 	type Error = MockSpiError;
 }
+
+#[cfg(test)]
+mod tests
+{
+	use alloc::vec;
+
+	use super::*;
+
+	#[test]
+	fn reads_are_answered_in_order()
+	{
+		let mut spi = MockSpi::new(vec![vec![1, 2], vec![3]]);
+		let mut words = [0; 2];
+		spi.transfer(&mut words, &[0xAA]).unwrap();
+		assert_eq!(words, [1, 2]);
+		spi.read(&mut words[..1]).unwrap();
+		assert_eq!(words[0], 3);
+
+		let MockSpi::Ok { write_operations, .. } = &spi
+		else
+		{
+			unreachable!()
+		};
+		assert_eq!(write_operations, &[vec![0xAA]]);
+	}
+
+	#[test]
+	fn missing_read_data_is_an_error()
+	{
+		let mut words = [0; 2];
+		assert!(MockSpi::new(vec![]).read(&mut words).is_err());
+		assert!(MockSpi::new(vec![vec![1]]).read(&mut words).is_err());
+	}
+}
@@ -0,0 +1,742 @@
+use core::{convert::Infallible, marker::PhantomData, time::Duration};
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+use crate::{
+	drivers::spi_flash_memory::{
+		BlockLockRegister, DieSelection, FeatureRegister, FeatureRegisterValue, FlashMemoryChip, FlashMemoryChipExt,
+		LockedBlocks, OnfiParameterPage, RowAddress, PARAMETER_PAGE_COPIES, PARAMETER_PAGE_SIZE,
+	},
+	peripherals::spi::{BusWidth, MultiIoSpiDevice},
+};
+
+extern crate alloc;
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+const STATUS_OPERATION_IN_PROGRESS: u8 = 0b0000_0001;
+const STATUS_WRITE_ENABLE_LATCH: u8 = 0b0000_0010;
+const STATUS_ERASE_FAILED: u8 = 0b0000_0100;
+const STATUS_PROGRAM_FAILED: u8 = 0b0000_1000;
+const STATUS_ECC_MASK: u8 = 0b0111_0000;
+const CONFIGURATION_ECC_ENABLED: u8 = 0b0001_0000;
+const CONFIGURATION_OTP_ACCESS: u8 = 0b0100_0000;
+const CONFIGURATION_OTP_PROTECT: u8 = 0b1000_0000;
+const BLOCK_LOCK_PROTECTION_BITS: u8 = 0b0111_1000;
+const PARAMETER_PAGE_INDEX: u32 = 0x01;
+/// The pages of the OTP area that can be programmed.
+const USER_OTP_PAGE_INDICES: core::ops::Range<u32> = 0x02..0x20;
+
+/// A simulated SPI NAND flash memory with the layout of the `Chip`, that can be used as the [`SpiDevice`] of a
+/// [`SpiFlashMemory`] to test it (and everything built on top of it) without the real hardware.
+///
+/// It decodes the bytes sent over SPI like a real chip, and it models:
+/// - the pages of the memory (with their data and ECC area), which are erased to `0xFF` and whose bits can only be
+///   cleared by a program operation.
+/// - the LUNs (dies) of the chips made of multiple LUNs, selected like described by [`FlashMemoryChip::DIE_SELECTION`],
+///   each one with its own cache registers and block lock register.
+/// - a cache register for each plane, loaded by the `PAGE READ` and `PROGRAM LOAD` commands, and the cache read
+///   commands (`READ PAGE CACHE RANDOM` and `READ PAGE CACHE LAST`).
+/// - the `x2` and `x4` `READ FROM CACHE` commands, through its [`MultiIoSpiDevice`] implementation.
+/// - the feature registers (block lock, configuration, status and die select), with the protected blocks encoded
+///   like in [`LockedBlocks`].
+/// - the time each operation keeps the chip busy (check [`MockSpiNandTimings`]), during which all the commands except
+///   `GET FEATURES` and `RESET` are ignored.
+/// - the factory bad blocks, the blocks that wear out and the bit flips corrected by the on-die ECC.
+/// - the OTP area, which contains the ONFI parameter page (describing the geometry of the `Chip`) and the pages
+///   `0x02`-`0x1F` that can be programmed once, until the OTP area is locked.
+///
+/// The ECC status bits of the status register use the encoding of the [`MT29F2G01ABAGDWB`].
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::{drivers::spi_flash_memory::*, hardware::mock::MockSpiNandFlash};
+/// #
+/// let mut spi_flash_memory = SpiFlashMemory::new(MockSpiNandFlash::<MT29F2G01ABAGDWB>::new(), MT29F2G01ABAGDWB);
+/// spi_flash_memory.validate_id().unwrap();
+/// MT29F2G01ABAGDWB::initialize(&mut spi_flash_memory).unwrap();
+///
+/// spi_flash_memory.program(b"Hello", 100).unwrap();
+/// let mut data = [0; 5];
+/// assert_eq!(spi_flash_memory.read(100, &mut data).unwrap(), EccStatus::NoErrors);
+/// assert_eq!(&data, b"Hello");
+/// ```
+///
+/// [`SpiFlashMemory`]: crate::drivers::spi_flash_memory::SpiFlashMemory
+/// [`MT29F2G01ABAGDWB`]: crate::drivers::spi_flash_memory::MT29F2G01ABAGDWB
+pub struct MockSpiNandFlash<Chip: FlashMemoryChip>
+{
+	pages: BTreeMap<u32, Vec<u8>>,
+	caches: Vec<Vec<u8>>,
+	/// The page read by the last `PAGE READ` or `READ PAGE CACHE RANDOM` command.
+	data_register_page: Option<u32>,
+	factory_bad_blocks: Vec<u16>,
+	worn_out_blocks: Vec<u16>,
+	bit_flips: BTreeMap<u32, u32>,
+	parameter_page: Option<OnfiParameterPage>,
+	otp_pages: BTreeMap<u32, Vec<u8>>,
+	is_otp_locked: bool,
+
+	block_locks: Vec<u8>,
+	configuration: u8,
+	status: u8,
+	die_select: u8,
+	/// The die selected by the `SOFTWARE DIE SELECT` command.
+	software_selected_die: u8,
+
+	max_read_bus_width: BusWidth,
+	commands_count: BTreeMap<u8, usize>,
+
+	timings: MockSpiNandTimings,
+	busy_time_left: Duration,
+	is_stuck_busy: bool,
+	elapsed_time: Duration,
+
+	_chip: PhantomData<Chip>,
+}
+
+impl<Chip: FlashMemoryChip> MockSpiNandFlash<Chip>
+{
+	const FULL_PAGE_SIZE: usize = (Chip::PAGE_SIZE + Chip::PAGE_ECC_SIZE) as usize;
+
+	/// Returns a [`MockSpiNandFlash`] with all its blocks erased and in the state of a chip that has just been powered
+	/// on (so all the blocks are locked and the ECC is enabled).
+	pub fn new() -> Self
+	{
+		Self {
+			pages: BTreeMap::new(),
+			caches: vec![vec![0xFF; Self::FULL_PAGE_SIZE]; (Chip::LUNS_PER_DEVICE * Chip::PLANES_PER_LUN) as usize],
+			data_register_page: None,
+			factory_bad_blocks: Vec::new(),
+			worn_out_blocks: Vec::new(),
+			bit_flips: BTreeMap::new(),
+			parameter_page: Some(OnfiParameterPage::from_chip::<Chip>(
+				*b"MOCK        ",
+				*b"MOCK SPI NAND FLASH ",
+			)),
+			otp_pages: BTreeMap::new(),
+			is_otp_locked: false,
+			block_locks: vec![BLOCK_LOCK_PROTECTION_BITS; Chip::LUNS_PER_DEVICE as usize],
+			configuration: CONFIGURATION_ECC_ENABLED,
+			status: 0,
+			die_select: 0,
+			software_selected_die: 0,
+			max_read_bus_width: BusWidth::Quad,
+			commands_count: BTreeMap::new(),
+			timings: MockSpiNandTimings::default(),
+			busy_time_left: Duration::ZERO,
+			is_stuck_busy: false,
+			elapsed_time: Duration::ZERO,
+			_chip: PhantomData,
+		}
+	}
+
+	/// Returns a [`MockSpiNandFlash`] like [`Self::new`], except the provided `factory_bad_blocks` that contain a bad
+	/// block mark (the first byte of the ECC area of their first page is `0x00`) and can't be erased.
+	pub fn with_factory_bad_blocks(factory_bad_blocks: &[u16]) -> Self
+	{
+		let mut self_ = Self::new();
+		self_.factory_bad_blocks.extend_from_slice(factory_bad_blocks);
+		for block_index in factory_bad_blocks
+		{
+			let first_page_index = Chip::get_address_of_block_index(*block_index) / Chip::PAGE_SIZE;
+			self_.get_page_mut(first_page_index)[Chip::PAGE_SIZE as usize] = 0x00;
+		}
+
+		self_
+	}
+
+	/// Sets the ONFI parameter page stored in the OTP area, or removes it if `parameter_page` is `None`.
+	pub fn set_parameter_page(&mut self, parameter_page: Option<OnfiParameterPage>)
+	{
+		self.parameter_page = parameter_page;
+	}
+
+	/// Sets how much time each operation keeps the chip busy.
+	pub fn set_timings(&mut self, timings: MockSpiNandTimings)
+	{
+		self.timings = timings;
+	}
+
+	/// Makes every following program and erase operation on the block at the provided `block_index` fail, like it
+	/// happens when a block wears out.
+	pub fn wear_out_block(&mut self, block_index: u16)
+	{
+		self.worn_out_blocks.push(block_index);
+	}
+
+	/// Flips `bit_flips_count` bits in the page at the provided `page_index` (until its block is erased), like it
+	/// happens when the charge of some cells leaks.
+	///
+	/// If the ECC is enabled, up to 8 flipped bits are corrected (and reported in the status register), otherwise the
+	/// read data contains the flipped bits.
+	pub fn inject_bit_flips(&mut self, page_index: u32, bit_flips_count: u32)
+	{
+		*self.bit_flips.entry(page_index).or_insert(0) += bit_flips_count;
+	}
+
+	/// If `is_stuck_busy` is `true`, the chip will never finish the current operation (and the next ones), like a
+	/// broken chip.
+	pub fn set_stuck_busy(&mut self, is_stuck_busy: bool)
+	{
+		self.is_stuck_busy = is_stuck_busy;
+		match self.is_busy()
+		{
+			true => self.status |= STATUS_OPERATION_IN_PROGRESS,
+			false => self.status &= !STATUS_OPERATION_IN_PROGRESS,
+		}
+	}
+
+	/// Returns the simulated time passed since the chip has been created, which is incremented by each read of the
+	/// status register and by the [`delays`](Operation::DelayNs) in the transactions.
+	pub fn get_elapsed_time(&self) -> Duration
+	{
+		self.elapsed_time
+	}
+
+	/// Returns the data and ECC area of the page at the provided `page_index`, as they are stored in the memory
+	/// (without the bit flips).
+	pub fn get_page(&self, page_index: u32) -> Vec<u8>
+	{
+		self.pages
+			.get(&page_index)
+			.cloned()
+			.unwrap_or_else(|| vec![0xFF; Self::FULL_PAGE_SIZE])
+	}
+
+	fn get_page_mut(&mut self, page_index: u32) -> &mut Vec<u8>
+	{
+		assert!(page_index < Chip::MEMORY_SIZE / Chip::PAGE_SIZE);
+
+		self.pages
+			.entry(page_index)
+			.or_insert_with(|| vec![0xFF; Self::FULL_PAGE_SIZE])
+	}
+
+	/// Sets the widest bus that the [`MultiIoSpiDevice`] implementation advertises (by default [`BusWidth::Quad`]).
+	pub fn set_max_read_bus_width(&mut self, bus_width: BusWidth)
+	{
+		self.max_read_bus_width = bus_width;
+	}
+
+	/// Returns how many commands with the provided `op_code` have been executed.
+	pub fn get_commands_count(&self, op_code: u8) -> usize
+	{
+		self.commands_count.get(&op_code).copied().unwrap_or(0)
+	}
+
+	/// Returns `true` if the OTP area has been locked, so its pages can't be programmed anymore.
+	pub fn is_otp_locked(&self) -> bool
+	{
+		self.is_otp_locked
+	}
+
+	fn is_busy(&self) -> bool
+	{
+		self.is_stuck_busy || !self.busy_time_left.is_zero()
+	}
+
+	fn pass_time(&mut self, time: Duration)
+	{
+		self.elapsed_time += time;
+		self.busy_time_left = self.busy_time_left.saturating_sub(time);
+		if !self.is_busy()
+		{
+			self.status &= !STATUS_OPERATION_IN_PROGRESS;
+		}
+	}
+
+	fn start_operation(&mut self, duration: Duration)
+	{
+		self.busy_time_left = duration;
+		self.status |= STATUS_OPERATION_IN_PROGRESS;
+	}
+
+	fn get_register_mut(&mut self, address: u8) -> Option<&mut u8>
+	{
+		match address
+		{
+			_ if address == FeatureRegister::BlockLock.address() =>
+			{
+				let lun_index = self.get_selected_lun();
+				Some(&mut self.block_locks[lun_index])
+			},
+			_ if address == FeatureRegister::Configuration.address() => Some(&mut self.configuration),
+			_ if address == FeatureRegister::Status.address() => Some(&mut self.status),
+			_ if address == FeatureRegister::DieSelect.address() => Some(&mut self.die_select),
+			_ => None,
+		}
+	}
+
+	fn get_selected_lun(&self) -> usize
+	{
+		let lun_index = match Chip::DIE_SELECTION
+		{
+			DieSelection::FeatureRegister => (self.die_select >> 6) & 1,
+			DieSelection::Command => self.software_selected_die,
+		};
+		(lun_index as usize).min(Chip::LUNS_PER_DEVICE as usize - 1)
+	}
+
+	/// Returns the index of the cache register (of the selected LUN) selected by the provided column address, and the
+	/// column in that cache.
+	fn decode_column_address(&self, column_address: u16) -> (usize, usize)
+	{
+		let plane_index = match Chip::PLANES_PER_LUN
+		{
+			1 => 0,
+			_ => ((column_address >> 12) & 1) as usize,
+		};
+		(
+			self.get_selected_lun() * Chip::PLANES_PER_LUN as usize + plane_index,
+			(column_address & 0x0FFF) as usize,
+		)
+	}
+
+	/// Returns the index of the page identified by the provided `row_address` in the selected LUN, and the index of
+	/// the cache register of its plane.
+	fn get_page_index_and_cache(&self, row_address: [u8; 3]) -> (u32, usize)
+	{
+		let page_index = self.get_selected_lun() as u32 * Chip::PAGES_PER_LUN
+			+ u32::from_be_bytes([0, row_address[0], row_address[1], row_address[2]]);
+
+		(page_index, Self::get_cache_index(page_index))
+	}
+
+	fn get_cache_index(page_index: u32) -> usize
+	{
+		let row_address = RowAddress::<Chip>::from_page_index(page_index);
+		(row_address.get_lun_index() as u32 * Chip::PLANES_PER_LUN + row_address.get_plane_index() as u32) as usize
+	}
+
+	fn page_read(&mut self, row_address: [u8; 3])
+	{
+		let (page_index, _) = self.get_page_index_and_cache(row_address);
+		self.load_page_in_cache(page_index);
+		self.data_register_page = Some(page_index);
+		self.start_operation(self.timings.page_read);
+	}
+
+	/// Moves the page read by the previous command in the cache register, and starts reading the page at the provided
+	/// `row_address` (or no page if it's `None`, for `READ PAGE CACHE LAST`).
+	fn page_cache_read(&mut self, row_address: Option<[u8; 3]>)
+	{
+		let Some(previous_page_index) = self.data_register_page
+		else
+		{
+			return;
+		};
+
+		self.load_page_in_cache(previous_page_index);
+		self.data_register_page = row_address.map(|row_address| self.get_page_index_and_cache(row_address).0);
+		self.start_operation(self.timings.page_read);
+	}
+
+	fn load_page_in_cache(&mut self, page_index: u32)
+	{
+		let cache_index = Self::get_cache_index(page_index);
+		if self.configuration & CONFIGURATION_OTP_ACCESS != 0
+		{
+			self.load_otp_page_in_cache(page_index, cache_index);
+			return;
+		}
+		let mut cache = self.get_page(page_index);
+
+		let bit_flips_count = self.bit_flips.get(&page_index).copied().unwrap_or(0);
+		let is_ecc_enabled = self.configuration & CONFIGURATION_ECC_ENABLED != 0;
+		let ecc_status = match bit_flips_count
+		{
+			0 => 0b000,
+			1..=3 => 0b001,
+			4..=6 => 0b011,
+			7..=8 => 0b101,
+			_ => 0b010,
+		};
+		if !is_ecc_enabled || bit_flips_count > 8
+		{
+			for i in 0..bit_flips_count as usize
+			{
+				cache[(i * 97) % Chip::PAGE_SIZE as usize] ^= 1 << (i % 8);
+			}
+		}
+
+		self.caches[cache_index] = cache;
+		self.status &= !STATUS_ECC_MASK;
+		if is_ecc_enabled
+		{
+			self.status |= ecc_status << 4;
+		}
+	}
+
+	fn load_otp_page_in_cache(&mut self, page_index: u32, cache_index: usize)
+	{
+		let cache = &mut self.caches[cache_index];
+		cache.fill(0xFF);
+		if let Some(otp_page) = self.otp_pages.get(&page_index)
+		{
+			cache.copy_from_slice(otp_page);
+		}
+		else if let (PARAMETER_PAGE_INDEX, Some(parameter_page)) = (page_index, self.parameter_page)
+		{
+			let bytes = parameter_page.to_bytes();
+			cache
+				.chunks_mut(PARAMETER_PAGE_SIZE)
+				.take(PARAMETER_PAGE_COPIES)
+				.for_each(|copy| copy.copy_from_slice(&bytes));
+		}
+
+		self.status &= !STATUS_ECC_MASK;
+	}
+
+	fn program_execute(&mut self, row_address: [u8; 3])
+	{
+		let (page_index, cache_index) = self.get_page_index_and_cache(row_address);
+		let block_index = (page_index / Chip::PAGES_PER_BLOCK) as u16;
+
+		self.status &= !STATUS_PROGRAM_FAILED;
+		if self.status & STATUS_WRITE_ENABLE_LATCH == 0
+		{
+			return;
+		}
+
+		if self.configuration & CONFIGURATION_OTP_ACCESS != 0
+		{
+			self.program_otp_page(page_index, cache_index);
+		}
+		else if self.is_block_locked(block_index) || self.worn_out_blocks.contains(&block_index)
+		{
+			self.status |= STATUS_PROGRAM_FAILED;
+		}
+		else
+		{
+			let cache = core::mem::take(&mut self.caches[cache_index]);
+			self.get_page_mut(page_index)
+				.iter_mut()
+				.zip(cache.iter())
+				.for_each(|(byte, new_byte)| *byte &= *new_byte);
+			self.caches[cache_index] = cache;
+		}
+		self.status &= !STATUS_WRITE_ENABLE_LATCH;
+		self.start_operation(self.timings.program);
+	}
+
+	fn program_otp_page(&mut self, page_index: u32, cache_index: usize)
+	{
+		if self.configuration & CONFIGURATION_OTP_PROTECT != 0
+		{
+			self.is_otp_locked = true;
+		}
+		else if self.is_otp_locked || !USER_OTP_PAGE_INDICES.contains(&page_index)
+		{
+			self.status |= STATUS_PROGRAM_FAILED;
+		}
+		else
+		{
+			let cache = &self.caches[cache_index];
+			self.otp_pages
+				.entry(page_index)
+				.or_insert_with(|| vec![0xFF; Self::FULL_PAGE_SIZE])
+				.iter_mut()
+				.zip(cache.iter())
+				.for_each(|(byte, new_byte)| *byte &= *new_byte);
+		}
+	}
+
+	fn is_block_locked(&self, block_index: u16) -> bool
+	{
+		let blocks_per_lun = (Chip::PLANES_PER_LUN * Chip::BLOCKS_PER_PLANE) as u16;
+		let block_lock = self.block_locks[(block_index / blocks_per_lun) as usize];
+		LockedBlocks::from_register(&BlockLockRegister::from_bits(block_lock))
+			.get_block_indices::<Chip>()
+			.contains(&(block_index % blocks_per_lun))
+	}
+
+	fn block_erase(&mut self, row_address: [u8; 3])
+	{
+		let (page_index, _) = self.get_page_index_and_cache(row_address);
+		let block_index = (page_index / Chip::PAGES_PER_BLOCK) as u16;
+
+		self.status &= !STATUS_ERASE_FAILED;
+		if self.status & STATUS_WRITE_ENABLE_LATCH == 0
+		{
+			return;
+		}
+
+		if self.is_block_locked(block_index)
+			|| self.worn_out_blocks.contains(&block_index)
+			|| self.factory_bad_blocks.contains(&block_index)
+		{
+			self.status |= STATUS_ERASE_FAILED;
+		}
+		else
+		{
+			let first_page_index = block_index as u32 * Chip::PAGES_PER_BLOCK;
+			for page_index in first_page_index..(first_page_index + Chip::PAGES_PER_BLOCK)
+			{
+				self.pages.remove(&page_index);
+				self.bit_flips.remove(&page_index);
+			}
+		}
+		self.status &= !STATUS_WRITE_ENABLE_LATCH;
+		self.start_operation(self.timings.erase);
+	}
+
+	fn reset(&mut self)
+	{
+		self.status &= !(STATUS_WRITE_ENABLE_LATCH | STATUS_PROGRAM_FAILED | STATUS_ERASE_FAILED);
+		self.busy_time_left = Duration::ZERO;
+		self.start_operation(self.timings.reset);
+	}
+
+	/// Returns how many bytes (address and dummy bytes) are sent after the op code of a command and before its data.
+	fn get_header_length(op_code: u8) -> usize
+	{
+		match op_code
+		{
+			// Row address, or column address and a dummy byte
+			0x13 | 0xD8 | 0x10 | 0x30 | 0x03 | 0x3B | 0x6B => 3,
+			0x02 | 0x84 => 2,
+			0x9F | 0x0F | 0x1F => 1,
+			_ => 0,
+		}
+	}
+}
+
+impl<Chip: FlashMemoryChip> Default for MockSpiNandFlash<Chip>
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// How much time each operation keeps a [`MockSpiNandFlash`] busy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MockSpiNandTimings
+{
+	/// Time to load a page in the cache register (`tRD`).
+	pub page_read: Duration,
+	/// Time to program a page (`tPROG`).
+	pub program: Duration,
+	/// Time to erase a block (`tBERS`).
+	pub erase: Duration,
+	/// Time to reset the chip (`tRST`).
+	pub reset: Duration,
+	/// Time that passes each time the status register is read.
+	pub status_read: Duration,
+}
+
+impl Default for MockSpiNandTimings
+{
+	/// The typical timings of the [`MT29F2G01ABAGDWB`](crate::drivers::spi_flash_memory::MT29F2G01ABAGDWB).
+	fn default() -> Self
+	{
+		Self {
+			page_read: Duration::from_micros(25),
+			program: Duration::from_micros(200),
+			erase: Duration::from_millis(2),
+			reset: Duration::from_micros(5),
+			status_read: Duration::from_micros(10),
+		}
+	}
+}
+
+/// The state of a transaction while its bytes are exchanged.
+struct Transaction
+{
+	op_code: Option<u8>,
+	header: [u8; 3],
+	header_length: usize,
+	data_index: usize,
+	is_ignored: bool,
+}
+
+impl<Chip: FlashMemoryChip> MockSpiNandFlash<Chip>
+{
+	/// Receives the `input` byte of the provided `transaction` and returns the byte sent back by the chip.
+	fn exchange(&mut self, transaction: &mut Transaction, input: u8) -> u8
+	{
+		let Some(op_code) = transaction.op_code
+		else
+		{
+			transaction.op_code = Some(input);
+			transaction.is_ignored = self.is_busy() && input != 0x0F && input != 0xFF;
+			return 0xFF;
+		};
+		if transaction.is_ignored
+		{
+			return 0xFF;
+		}
+
+		let header_length = Self::get_header_length(op_code);
+		if transaction.header_length < header_length
+		{
+			transaction.header[transaction.header_length] = input;
+			transaction.header_length += 1;
+
+			// PROGRAM LOAD resets the cache register of the plane selected by the column address
+			if transaction.header_length == header_length && op_code == 0x02
+			{
+				let (cache_index, _) =
+					self.decode_column_address(u16::from_be_bytes([transaction.header[0], transaction.header[1]]));
+				self.caches[cache_index].fill(0xFF);
+			}
+			return 0xFF;
+		}
+
+		let data_index = transaction.data_index;
+		transaction.data_index += 1;
+		let column_address = u16::from_be_bytes([transaction.header[0], transaction.header[1]]);
+		match op_code
+		{
+			0x03 | 0x3B | 0x6B =>
+			{
+				let (cache_index, column) = self.decode_column_address(column_address);
+				self.caches[cache_index]
+					.get(column + data_index)
+					.copied()
+					.unwrap_or(0xFF)
+			},
+			0x02 | 0x84 =>
+			{
+				let (cache_index, column) = self.decode_column_address(column_address);
+				if let Some(byte) = self.caches[cache_index].get_mut(column + data_index)
+				{
+					*byte = input;
+				}
+				0xFF
+			},
+			0x9F => match data_index
+			{
+				0 => Chip::MANUFACTURER_ID,
+				1 => Chip::DEVICE_ID,
+				_ => Chip::EXTENDED_DEVICE_ID.get(data_index - 2).copied().unwrap_or(0xFF),
+			},
+			0xC2 =>
+			{
+				if data_index == 0 && Chip::DIE_SELECTION == DieSelection::Command
+				{
+					self.software_selected_die = input;
+				}
+				0xFF
+			},
+			0x0F =>
+			{
+				if transaction.header[0] == FeatureRegister::Status.address()
+				{
+					self.pass_time(self.timings.status_read);
+				}
+				self.get_register_mut(transaction.header[0])
+					.map(|register| *register)
+					.unwrap_or(0)
+			},
+			0x1F =>
+			{
+				if data_index == 0 && transaction.header[0] != FeatureRegister::Status.address()
+				{
+					if let Some(register) = self.get_register_mut(transaction.header[0])
+					{
+						*register = input;
+					}
+				}
+				0xFF
+			},
+			_ => 0xFF,
+		}
+	}
+
+	/// Executes the command of the provided `transaction` when the chip select is deasserted.
+	fn finish(&mut self, transaction: Transaction)
+	{
+		let Some(op_code) = transaction.op_code
+		else
+		{
+			return;
+		};
+		if transaction.is_ignored || transaction.header_length < Self::get_header_length(op_code)
+		{
+			return;
+		}
+
+		*self.commands_count.entry(op_code).or_insert(0) += 1;
+		match op_code
+		{
+			0xFF => self.reset(),
+			0x06 => self.status |= STATUS_WRITE_ENABLE_LATCH,
+			0x04 => self.status &= !STATUS_WRITE_ENABLE_LATCH,
+			0x13 => self.page_read(transaction.header),
+			0x30 => self.page_cache_read(Some(transaction.header)),
+			0x3F => self.page_cache_read(None),
+			0x10 => self.program_execute(transaction.header),
+			0xD8 => self.block_erase(transaction.header),
+			_ => (),
+		}
+	}
+}
+
+impl<Chip: FlashMemoryChip> SpiDevice<u8> for MockSpiNandFlash<Chip>
+{
+	fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error>
+	{
+		let mut transaction = Transaction {
+			op_code: None,
+			header: [0; 3],
+			header_length: 0,
+			data_index: 0,
+			is_ignored: false,
+		};
+
+		for operation in operations
+		{
+			match operation
+			{
+				Operation::Read(words) => words
+					.iter_mut()
+					.for_each(|word| *word = self.exchange(&mut transaction, 0x00)),
+				Operation::Write(words) => words.iter().for_each(|word| {
+					self.exchange(&mut transaction, *word);
+				}),
+				Operation::Transfer(read, write) =>
+				{
+					for i in 0..read.len().max(write.len())
+					{
+						let output = self.exchange(&mut transaction, write.get(i).copied().unwrap_or(0x00));
+						if let Some(word) = read.get_mut(i)
+						{
+							*word = output;
+						}
+					}
+				},
+				Operation::TransferInPlace(words) => words
+					.iter_mut()
+					.for_each(|word| *word = self.exchange(&mut transaction, *word)),
+				Operation::DelayNs(nanoseconds) => self.pass_time(Duration::from_nanos(*nanoseconds as u64)),
+			}
+		}
+
+		self.finish(transaction);
+
+		Ok(())
+	}
+}
+
+impl<Chip: FlashMemoryChip> ErrorType for MockSpiNandFlash<Chip>
+{
+	type Error = Infallible;
+}
+
+impl<Chip: FlashMemoryChip> MultiIoSpiDevice for MockSpiNandFlash<Chip>
+{
+	fn get_max_read_bus_width(&self) -> BusWidth
+	{
+		self.max_read_bus_width
+	}
+
+	fn transaction_with_read_bus_width(
+		&mut self, operations: &mut [Operation<'_, u8>], bus_width: BusWidth,
+	) -> Result<(), Self::Error>
+	{
+		assert!(bus_width <= self.max_read_bus_width);
+
+		self.transaction(operations)
+	}
+}
@@ -0,0 +1,488 @@
+use core::{convert::Infallible, time::Duration};
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+extern crate alloc;
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+const SECTOR_SIZE: u32 = 4 * 1024;
+const PAGE_SIZE: u32 = 256;
+const STATUS_BUSY: u8 = 0b0000_0001;
+const STATUS_WRITE_ENABLE_LATCH: u8 = 0b0000_0010;
+const STATUS_BLOCK_PROTECTION_BITS: u8 = 0b0001_1100;
+const STATUS_TOP_BOTTOM_PROTECTION: u8 = 0b0010_0000;
+const STATUS_SECTOR_PROTECTION: u8 = 0b0100_0000;
+/// Address of the Basic Flash Parameter Table in the SFDP area.
+const BASIC_FLASH_PARAMETER_TABLE_ADDRESS: usize = 0x30;
+
+/// A simulated SPI NOR flash memory like the `W25Q` family, that can be used as the [`SpiDevice`] of a
+/// [`SpiNorFlash`] to test it (and everything built on top of it) without the real hardware.
+///
+/// It decodes the bytes sent over SPI like a real chip, and it models:
+/// - the memory, which is erased to `0xFF` in 4KB sectors, 32KB blocks, 64KB blocks or all at once, and whose bits can
+///   only be cleared by a page program (that wraps around at the end of a 256 bytes page).
+/// - the JEDEC ID and the SFDP tables, which describe the memory.
+/// - the 3 status registers, with the write enable latch and the block protection bits (`BP`, `TB` and `SEC`).
+/// - the 4 bytes addresses mode, needed by the memories bigger than 16MB.
+/// - the deep power-down mode, where all the commands except `RELEASE POWER-DOWN` are ignored.
+/// - the time each operation keeps the chip busy (check [`MockSpiNorTimings`]), during which all the commands except
+///   the read of the status register are ignored.
+///
+/// [`SpiNorFlash`]: crate::drivers::spi_nor_flash::SpiNorFlash
+pub struct MockSpiNorFlash
+{
+	memory_size: u32,
+	sectors: BTreeMap<u32, Vec<u8>>,
+	status_registers: [u8; 3],
+	is_four_byte_address_mode: bool,
+	is_powered_down: bool,
+	is_reset_enabled: bool,
+
+	timings: MockSpiNorTimings,
+	busy_time_left: Duration,
+	is_stuck_busy: bool,
+	elapsed_time: Duration,
+}
+
+impl MockSpiNorFlash
+{
+	/// Returns a [`MockSpiNorFlash`] of the provided `memory_size` (a power of 2 of at least 64KB), completely erased.
+	pub fn new(memory_size: u32) -> Self
+	{
+		assert!(memory_size.is_power_of_two() && memory_size >= 64 * 1024);
+
+		Self {
+			memory_size,
+			sectors: BTreeMap::new(),
+			status_registers: [0; 3],
+			is_four_byte_address_mode: false,
+			is_powered_down: false,
+			is_reset_enabled: false,
+			timings: MockSpiNorTimings::default(),
+			busy_time_left: Duration::ZERO,
+			is_stuck_busy: false,
+			elapsed_time: Duration::ZERO,
+		}
+	}
+
+	/// Sets how much time each operation keeps the chip busy.
+	pub fn set_timings(&mut self, timings: MockSpiNorTimings)
+	{
+		self.timings = timings;
+	}
+
+	/// If `is_stuck_busy` is `true`, the chip will never finish the current operation (and the next ones), like a
+	/// broken chip.
+	pub fn set_stuck_busy(&mut self, is_stuck_busy: bool)
+	{
+		self.is_stuck_busy = is_stuck_busy;
+		match self.is_busy()
+		{
+			true => self.status_registers[0] |= STATUS_BUSY,
+			false => self.status_registers[0] &= !STATUS_BUSY,
+		}
+	}
+
+	/// Returns the simulated time passed since the chip has been created, which is incremented by each read of the
+	/// status register and by the [`delays`](Operation::DelayNs) in the transactions.
+	pub fn get_elapsed_time(&self) -> Duration
+	{
+		self.elapsed_time
+	}
+
+	/// Returns `true` if the chip is in the deep power-down mode.
+	pub fn is_powered_down(&self) -> bool
+	{
+		self.is_powered_down
+	}
+
+	/// Returns the byte stored at the provided `address`.
+	pub fn get_byte(&self, address: u32) -> u8
+	{
+		self.sectors
+			.get(&(address / SECTOR_SIZE))
+			.map_or(0xFF, |sector| sector[(address % SECTOR_SIZE) as usize])
+	}
+
+	/// Returns the JEDEC ID of the chip: the one of Winbond, the memory type of the `W25Q` family, and the base 2
+	/// logarithm of the size.
+	pub fn get_jedec_id(&self) -> [u8; 3]
+	{
+		[0xEF, 0x40, self.memory_size.trailing_zeros() as u8]
+	}
+
+	/// Returns the bytes of the SFDP area: the header, the parameter header of the Basic Flash Parameter Table, and
+	/// the table itself (with the timings of the `W25Q128JV`).
+	pub fn get_sfdp(&self) -> Vec<u8>
+	{
+		let mut sfdp = vec![0xFF; BASIC_FLASH_PARAMETER_TABLE_ADDRESS];
+		sfdp[0..8].copy_from_slice(b"SFDP\x06\x01\x00\xFF");
+		sfdp[8..16].copy_from_slice(&[
+			0x00,
+			0x06,
+			0x01,
+			11,
+			BASIC_FLASH_PARAMETER_TABLE_ADDRESS as u8,
+			0x00,
+			0x00,
+			0xFF,
+		]);
+
+		let address_bytes = match self.memory_size > 1 << 24
+		{
+			true => 0b01 << 17,
+			false => 0b00 << 17,
+		};
+		let basic_flash_parameter_table: [u32; 11] = [
+			0xFFF1_20E5 & !(0b11 << 17) | address_bytes,
+			self.memory_size * 8 - 1,
+			0x6B08_EB44,
+			0xBB42_3B08,
+			0xFFFF_FFFE,
+			0xFF00_FFFF,
+			0xEB40_FFFF,
+			0x520F_200C,
+			0x0000_D810,
+			0x0090_9222,
+			0x3C66_8281,
+		];
+		basic_flash_parameter_table
+			.iter()
+			.for_each(|dword| sfdp.extend_from_slice(&dword.to_le_bytes()));
+
+		sfdp
+	}
+
+	fn is_busy(&self) -> bool
+	{
+		self.is_stuck_busy || !self.busy_time_left.is_zero()
+	}
+
+	fn pass_time(&mut self, time: Duration)
+	{
+		self.elapsed_time += time;
+		self.busy_time_left = self.busy_time_left.saturating_sub(time);
+		if !self.is_busy()
+		{
+			self.status_registers[0] &= !STATUS_BUSY;
+		}
+	}
+
+	fn start_operation(&mut self, duration: Duration)
+	{
+		self.status_registers[0] &= !STATUS_WRITE_ENABLE_LATCH;
+		self.busy_time_left = duration;
+		self.status_registers[0] |= STATUS_BUSY;
+	}
+
+	fn is_write_enabled(&self) -> bool
+	{
+		self.status_registers[0] & STATUS_WRITE_ENABLE_LATCH != 0
+	}
+
+	/// Returns `true` if the byte at the provided `address` is protected by the block protection bits.
+	fn is_protected(&self, address: u32) -> bool
+	{
+		let status = self.status_registers[0];
+		let block_protection = (status & STATUS_BLOCK_PROTECTION_BITS) >> 2;
+		let protected_size = match (block_protection, status & STATUS_SECTOR_PROTECTION != 0)
+		{
+			(0, _) => 0,
+			(0b111, _) => self.memory_size,
+			(_, false) => ((64 * 1024) << (block_protection - 1)).min(self.memory_size),
+			(_, true) => (SECTOR_SIZE << (block_protection - 1)).min(32 * 1024),
+		};
+
+		match status & STATUS_TOP_BOTTOM_PROTECTION != 0
+		{
+			true => address < protected_size,
+			false => address >= self.memory_size - protected_size,
+		}
+	}
+
+	fn get_address_length(&self, op_code: u8) -> usize
+	{
+		match (op_code, self.is_four_byte_address_mode)
+		{
+			(0x03 | 0x02 | 0x20 | 0x52 | 0xD8, true) => 4,
+			(0x03 | 0x02 | 0x20 | 0x52 | 0xD8, false) => 3,
+			// Address and dummy byte
+			(0x5A, _) => 4,
+			_ => 0,
+		}
+	}
+
+	fn program_byte(&mut self, address: u32, byte: u8)
+	{
+		let sector = self
+			.sectors
+			.entry(address / SECTOR_SIZE)
+			.or_insert_with(|| vec![0xFF; SECTOR_SIZE as usize]);
+		sector[(address % SECTOR_SIZE) as usize] &= byte;
+	}
+
+	fn erase(&mut self, address: u32, size: u32, duration: Duration)
+	{
+		if !self.is_write_enabled()
+		{
+			return;
+		}
+
+		let start_address = address / size * size;
+		if (start_address..(start_address + size))
+			.step_by(SECTOR_SIZE as usize)
+			.any(|address| self.is_protected(address))
+		{
+			self.status_registers[0] &= !STATUS_WRITE_ENABLE_LATCH;
+			return;
+		}
+		for sector_index in (start_address / SECTOR_SIZE)..((start_address + size) / SECTOR_SIZE)
+		{
+			self.sectors.remove(&sector_index);
+		}
+		self.start_operation(duration);
+	}
+}
+
+/// How much time each operation keeps a [`MockSpiNorFlash`] busy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MockSpiNorTimings
+{
+	/// Time to program a page (`tPP`).
+	pub page_program: Duration,
+	/// Time to erase a 4KB sector (`tSE`).
+	pub sector_erase: Duration,
+	/// Time to erase a 32KB or 64KB block (`tBE1` and `tBE2`).
+	pub block_erase: Duration,
+	/// Time to erase the whole chip (`tCE`).
+	pub chip_erase: Duration,
+	/// Time to write a status register (`tW`).
+	pub write_status_register: Duration,
+	/// Time that passes each time a status register is read.
+	pub status_read: Duration,
+}
+
+impl Default for MockSpiNorTimings
+{
+	/// The typical timings of the `W25Q128JV`.
+	fn default() -> Self
+	{
+		Self {
+			page_program: Duration::from_micros(400),
+			sector_erase: Duration::from_millis(45),
+			block_erase: Duration::from_millis(120),
+			chip_erase: Duration::from_secs(40),
+			write_status_register: Duration::from_millis(10),
+			status_read: Duration::from_micros(10),
+		}
+	}
+}
+
+/// The state of a transaction while its bytes are exchanged.
+struct Transaction
+{
+	op_code: Option<u8>,
+	header: [u8; 4],
+	header_length: usize,
+	data_index: usize,
+	is_ignored: bool,
+}
+
+impl Transaction
+{
+	fn get_address(&self) -> u32
+	{
+		match self.header_length
+		{
+			4 => u32::from_be_bytes(self.header),
+			_ => u32::from_be_bytes([0, self.header[0], self.header[1], self.header[2]]),
+		}
+	}
+}
+
+impl MockSpiNorFlash
+{
+	/// Receives the `input` byte of the provided `transaction` and returns the byte sent back by the chip.
+	fn exchange(&mut self, transaction: &mut Transaction, input: u8) -> u8
+	{
+		let Some(op_code) = transaction.op_code
+		else
+		{
+			transaction.op_code = Some(input);
+			transaction.is_ignored = match (self.is_powered_down, self.is_busy())
+			{
+				(true, _) => input != 0xAB,
+				(false, true) => !matches!(input, 0x05 | 0x35 | 0x15),
+				(false, false) => false,
+			};
+			return 0xFF;
+		};
+		if transaction.is_ignored
+		{
+			return 0xFF;
+		}
+
+		let address_length = self.get_address_length(op_code);
+		if transaction.header_length < address_length
+		{
+			transaction.header[transaction.header_length] = input;
+			transaction.header_length += 1;
+			return 0xFF;
+		}
+
+		let data_index = transaction.data_index;
+		transaction.data_index += 1;
+		match op_code
+		{
+			0x03 =>
+			{
+				let address = (transaction.get_address() + data_index as u32) % self.memory_size;
+				self.get_byte(address)
+			},
+			0x02 =>
+			{
+				// The program is done at the end of the transaction, but the bytes are stored in the page right away
+				// since nothing can read them in the meantime
+				let address = transaction.get_address();
+				let page_start = address / PAGE_SIZE * PAGE_SIZE;
+				let address = page_start + (address - page_start + data_index as u32) % PAGE_SIZE;
+				if self.is_write_enabled() && !self.is_protected(address) && address < self.memory_size
+				{
+					self.program_byte(address, input);
+				}
+				0xFF
+			},
+			0x05 | 0x35 | 0x15 =>
+			{
+				let register_index = match op_code
+				{
+					0x05 => 0,
+					0x35 => 1,
+					_ => 2,
+				};
+				self.pass_time(self.timings.status_read);
+				self.status_registers[register_index]
+			},
+			0x01 | 0x31 | 0x11 =>
+			{
+				if data_index == 0 && self.is_write_enabled()
+				{
+					let register_index = match op_code
+					{
+						0x01 => 0,
+						0x31 => 1,
+						_ => 2,
+					};
+					// The `BUSY` and `WEL` bits are read only
+					let read_only_bits = match register_index
+					{
+						0 => STATUS_BUSY | STATUS_WRITE_ENABLE_LATCH,
+						_ => 0,
+					};
+					let register = &mut self.status_registers[register_index];
+					*register = (*register & read_only_bits) | (input & !read_only_bits);
+				}
+				0xFF
+			},
+			0x9F => self.get_jedec_id().get(data_index).copied().unwrap_or(0xFF),
+			0x5A =>
+			{
+				let address =
+					u32::from_be_bytes([0, transaction.header[0], transaction.header[1], transaction.header[2]]);
+				self.get_sfdp()
+					.get(address as usize + data_index)
+					.copied()
+					.unwrap_or(0xFF)
+			},
+			_ => 0xFF,
+		}
+	}
+
+	/// Executes the command of the provided `transaction` when the chip select is deasserted.
+	fn finish(&mut self, transaction: Transaction)
+	{
+		let Some(op_code) = transaction.op_code
+		else
+		{
+			return;
+		};
+		if transaction.is_ignored || transaction.header_length < self.get_address_length(op_code)
+		{
+			return;
+		}
+
+		let is_reset_enabled = core::mem::replace(&mut self.is_reset_enabled, false);
+		match op_code
+		{
+			0x06 => self.status_registers[0] |= STATUS_WRITE_ENABLE_LATCH,
+			0x04 => self.status_registers[0] &= !STATUS_WRITE_ENABLE_LATCH,
+			0x02 if self.is_write_enabled() => self.start_operation(self.timings.page_program),
+			0x01 | 0x31 | 0x11 if self.is_write_enabled() => self.start_operation(self.timings.write_status_register),
+			0x20 => self.erase(transaction.get_address(), SECTOR_SIZE, self.timings.sector_erase),
+			0x52 => self.erase(transaction.get_address(), 32 * 1024, self.timings.block_erase),
+			0xD8 => self.erase(transaction.get_address(), 64 * 1024, self.timings.block_erase),
+			0xC7 | 0x60 => self.erase(0, self.memory_size, self.timings.chip_erase),
+			0xB9 => self.is_powered_down = true,
+			0xAB => self.is_powered_down = false,
+			0xB7 => self.is_four_byte_address_mode = true,
+			0x66 => self.is_reset_enabled = true,
+			0x99 if is_reset_enabled =>
+			{
+				self.status_registers[0] &= !STATUS_WRITE_ENABLE_LATCH;
+				self.is_four_byte_address_mode = false;
+			},
+			_ => (),
+		}
+	}
+}
+
+impl SpiDevice<u8> for MockSpiNorFlash
+{
+	fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error>
+	{
+		let mut transaction = Transaction {
+			op_code: None,
+			header: [0; 4],
+			header_length: 0,
+			data_index: 0,
+			is_ignored: false,
+		};
+
+		for operation in operations
+		{
+			match operation
+			{
+				Operation::Read(words) => words
+					.iter_mut()
+					.for_each(|word| *word = self.exchange(&mut transaction, 0x00)),
+				Operation::Write(words) => words.iter().for_each(|word| {
+					self.exchange(&mut transaction, *word);
+				}),
+				Operation::Transfer(read, write) =>
+				{
+					for i in 0..read.len().max(write.len())
+					{
+						let output = self.exchange(&mut transaction, write.get(i).copied().unwrap_or(0x00));
+						if let Some(word) = read.get_mut(i)
+						{
+							*word = output;
+						}
+					}
+				},
+				Operation::TransferInPlace(words) => words
+					.iter_mut()
+					.for_each(|word| *word = self.exchange(&mut transaction, *word)),
+				Operation::DelayNs(nanoseconds) => self.pass_time(Duration::from_nanos(*nanoseconds as u64)),
+			}
+		}
+
+		self.finish(transaction);
+
+		Ok(())
+	}
+}
+
+impl ErrorType for MockSpiNorFlash
+{
+	type Error = Infallible;
+}
@@ -0,0 +1,558 @@
+use core::{cell::RefCell, time::Duration};
+
+#[allow(unused_imports)]
+#[cfg(not(std))]
+use micromath::F32Ext;
+
+use super::{MockAdc, MockAdcValue, MockEmptyError};
+use crate::{
+	drivers::thermistor::T0,
+	peripherals::{adc::AdcPin, pwm::PwmPin},
+	utils::{
+		math::Percentage,
+		physical_quantities::{frequency::Frequency, temperature::Temperature},
+	},
+};
+
+extern crate alloc;
+use alloc::{collections::VecDeque, rc::Rc};
+
+/// The physical parameters of a [`MockThermalPlant`].
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalPlantConfig
+{
+	/// The energy needed to raise the temperature of the heated block by `1K`, in `J/K`.
+	pub heat_capacity: f32,
+	/// The power of the heater when its duty cycle is `100%`, in `W`.
+	pub heater_power: f32,
+	/// The power lost to the ambient for each degree of difference between the block and the ambient, in `W/K`.
+	pub ambient_loss: f32,
+	/// The temperature of the ambient, which is also the initial temperature of the block.
+	pub ambient_temperature: Temperature,
+	/// The time constant of the sensor, which follows the temperature of the block with a first order lag.
+	pub sensor_lag_in_seconds: f32,
+	/// The time it takes for a change of the heater's duty cycle to start heating the block.
+	pub dead_time_in_seconds: f32,
+	/// The thermistor that measures the temperature of the block.
+	pub thermistor: SimulatedThermistor,
+}
+
+impl ThermalPlantConfig
+{
+	/// A typical hotend of a 3D printer, with a `40W` heater and a `100kΩ` thermistor.
+	pub const HOTEND: Self = Self {
+		heat_capacity: 10.,
+		heater_power: 40.,
+		ambient_loss: 0.1,
+		ambient_temperature: T0,
+		sensor_lag_in_seconds: 2.,
+		dead_time_in_seconds: 0.5,
+		thermistor: SimulatedThermistor {
+			beta: 3950,
+			resistance_at_t0: 100_000,
+			other_resistance: 4_700,
+		},
+	};
+}
+
+/// The parameters of a thermistor, with the same meaning of the ones of [`Thermistor::new`].
+///
+/// [`Thermistor::new`]: crate::drivers::thermistor::Thermistor::new
+#[derive(Clone, Copy, Debug)]
+pub struct SimulatedThermistor
+{
+	pub beta: u32,
+	pub resistance_at_t0: u32,
+	pub other_resistance: u32,
+}
+
+/// A simulated heated block (like the hotend of a 3D printer), heated by a [`SimulatedHeaterPin`] and measured by a
+/// thermistor read through a [`SimulatedThermistorPin`], which can be used to test temperature control code without
+/// a real heater.
+///
+/// The block is modeled as a first order system with a dead time: the heater's power reaches the block after
+/// [`ThermalPlantConfig::dead_time_in_seconds`], and the block loses heat to the ambient proportionally to the
+/// temperature difference. The sensor follows the block's temperature with a first order lag.
+///
+/// The simulation runs in virtual time, which only advances when [`Self::advance`] is called.
+///
+/// # Examples
+/// ```
+/// # use core::time::Duration;
+/// # use a13c_embedded::{
+/// #     drivers::{heater::PwmHeater, thermistor::Thermistor},
+/// #     hardware::mock::*,
+/// #     utils::math::Percentage,
+/// # };
+/// #
+/// let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+/// let thermistor_config = ThermalPlantConfig::HOTEND.thermistor;
+/// let mut thermistor = Thermistor::new(
+///     plant.get_thermistor_pin(),
+///     thermistor_config.beta,
+///     thermistor_config.resistance_at_t0,
+///     thermistor_config.other_resistance,
+/// );
+/// let mut heater = PwmHeater::new(plant.get_heater_pin());
+///
+/// heater.set_heat_percentage(Percentage::FULL).unwrap();
+/// plant.advance(Duration::from_secs(30));
+///
+/// let temperature = thermistor.read_temperature(&mut MockAdc).unwrap().as_celsius();
+/// assert!(temperature > 100. && temperature < 140.);
+/// ```
+pub struct MockThermalPlant
+{
+	state: Rc<RefCell<ThermalPlantState>>,
+}
+
+impl MockThermalPlant
+{
+	/// The step used to integrate the model.
+	pub const SIMULATION_STEP: Duration = Duration::from_millis(10);
+
+	/// Returns a [`MockThermalPlant`] whose block and sensor are at the ambient temperature.
+	pub fn new(config: ThermalPlantConfig) -> Self
+	{
+		let dead_time_steps = (config.dead_time_in_seconds / Self::SIMULATION_STEP.as_secs_f32()).round() as usize;
+		let ambient_temperature = config.ambient_temperature.as_kelvin();
+
+		Self {
+			state: Rc::new(RefCell::new(ThermalPlantState {
+				config,
+				block_temperature: ambient_temperature,
+				sensor_temperature: ambient_temperature,
+				duty_cycle: Percentage::ZERO,
+				frequency: Frequency::from_hertz(1000),
+				delayed_powers: VecDeque::from_iter(core::iter::repeat_n(0., dead_time_steps)),
+				elapsed_time: Duration::ZERO,
+				pending_time: Duration::ZERO,
+				is_sensor_disconnected: false,
+				stuck_sensor_temperature: None,
+				is_heater_broken: false,
+				is_heater_stuck_on: false,
+				fan_draft: 0.,
+			})),
+		}
+	}
+
+	/// Returns a [`PwmPin`] that controls the heater of this plant.
+	pub fn get_heater_pin(&self) -> SimulatedHeaterPin
+	{
+		SimulatedHeaterPin {
+			state: self.state.clone(),
+		}
+	}
+
+	/// Returns an [`AdcPin`] that reads the thermistor of this plant.
+	pub fn get_thermistor_pin(&self) -> SimulatedThermistorPin
+	{
+		SimulatedThermistorPin {
+			state: self.state.clone(),
+		}
+	}
+
+	/// Advances the virtual time by `duration`, updating the temperatures.
+	///
+	/// The model is updated every [`Self::SIMULATION_STEP`], so the time that doesn't make a full step is kept for
+	/// the next call.
+	pub fn advance(&mut self, duration: Duration)
+	{
+		let mut state = self.state.borrow_mut();
+		state.pending_time += duration;
+		while state.pending_time >= Self::SIMULATION_STEP
+		{
+			state.pending_time -= Self::SIMULATION_STEP;
+			state.step(Self::SIMULATION_STEP.as_secs_f32());
+		}
+	}
+
+	/// Returns how much virtual time has passed since the creation of the plant.
+	pub fn get_elapsed_time(&self) -> Duration
+	{
+		self.state.borrow().elapsed_time
+	}
+
+	/// Returns the real temperature of the heated block.
+	pub fn get_block_temperature(&self) -> Temperature
+	{
+		Temperature::from_kelvin(self.state.borrow().block_temperature)
+	}
+
+	/// Returns the temperature of the sensor, which is what the thermistor measures.
+	pub fn get_sensor_temperature(&self) -> Temperature
+	{
+		Temperature::from_kelvin(self.state.borrow().sensor_temperature)
+	}
+
+	/// Simulates a thermistor whose wires are disconnected, so the ADC reads the reference voltage.
+	pub fn set_sensor_disconnected(&mut self, is_disconnected: bool)
+	{
+		self.state.borrow_mut().is_sensor_disconnected = is_disconnected;
+	}
+
+	/// Simulates a thermistor stuck at its current temperature (for example because it has fallen out of the block),
+	/// so the ADC keeps reading the same voltage.
+	pub fn set_sensor_stuck(&mut self, is_stuck: bool)
+	{
+		let mut state = self.state.borrow_mut();
+		state.stuck_sensor_temperature = is_stuck.then_some(state.sensor_temperature);
+	}
+
+	/// Simulates a broken heater, which doesn't heat regardless of its duty cycle.
+	pub fn set_heater_broken(&mut self, is_broken: bool)
+	{
+		self.state.borrow_mut().is_heater_broken = is_broken;
+	}
+
+	/// Simulates a heater whose switch is stuck on, which heats at full power regardless of its duty cycle.
+	pub fn set_heater_stuck_on(&mut self, is_stuck_on: bool)
+	{
+		self.state.borrow_mut().is_heater_stuck_on = is_stuck_on;
+	}
+
+	/// Simulates a draft (for example the part cooling fan blowing on the block), which adds `extra_ambient_loss`
+	/// `W/K` to [`ThermalPlantConfig::ambient_loss`].
+	pub fn set_fan_draft(&mut self, extra_ambient_loss: f32)
+	{
+		self.state.borrow_mut().fan_draft = extra_ambient_loss;
+	}
+}
+
+struct ThermalPlantState
+{
+	config: ThermalPlantConfig,
+	block_temperature: f32,
+	sensor_temperature: f32,
+	duty_cycle: Percentage,
+	frequency: Frequency,
+	/// The powers given by the heater that haven't reached the block yet, because of the dead time.
+	delayed_powers: VecDeque<f32>,
+	elapsed_time: Duration,
+	pending_time: Duration,
+
+	is_sensor_disconnected: bool,
+	stuck_sensor_temperature: Option<f32>,
+	is_heater_broken: bool,
+	is_heater_stuck_on: bool,
+	fan_draft: f32,
+}
+
+impl ThermalPlantState
+{
+	fn step(&mut self, delta_time: f32)
+	{
+		let config = &self.config;
+		let heater_power = match (self.is_heater_broken, self.is_heater_stuck_on)
+		{
+			(true, _) => 0.,
+			(false, true) => config.heater_power,
+			(false, false) => self.duty_cycle.into_0_to_1() * config.heater_power,
+		};
+		self.delayed_powers.push_back(heater_power);
+		let heater_power = self.delayed_powers.pop_front().unwrap_or(heater_power);
+
+		let lost_power =
+			(config.ambient_loss + self.fan_draft) * (self.block_temperature - config.ambient_temperature.as_kelvin());
+		self.block_temperature += (heater_power - lost_power) / config.heat_capacity * delta_time;
+
+		self.sensor_temperature += match config.sensor_lag_in_seconds > 0.
+		{
+			true =>
+			{
+				(self.block_temperature - self.sensor_temperature) * (delta_time / config.sensor_lag_in_seconds).min(1.)
+			},
+			false => self.block_temperature - self.sensor_temperature,
+		};
+		self.elapsed_time += MockThermalPlant::SIMULATION_STEP;
+	}
+}
+
+/// The [`PwmPin`] of the heater of a [`MockThermalPlant`].
+pub struct SimulatedHeaterPin
+{
+	state: Rc<RefCell<ThermalPlantState>>,
+}
+
+impl PwmPin for SimulatedHeaterPin
+{
+	type Error = MockEmptyError;
+
+	fn get_duty_cycle(&self) -> Percentage
+	{
+		self.state.borrow().duty_cycle
+	}
+
+	fn set_duty_cycle(&mut self, percentage: Percentage) -> Result<(), Self::Error>
+	{
+		self.state.borrow_mut().duty_cycle = percentage;
+		Ok(())
+	}
+
+	fn set_frequency(&mut self, frequency: Frequency) -> Result<(), Self::Error>
+	{
+		self.state.borrow_mut().frequency = frequency;
+		Ok(())
+	}
+}
+
+/// The [`AdcPin`] connected to the thermistor of a [`MockThermalPlant`], in a voltage divider where the thermistor is
+/// the resistor connected to the ground.
+pub struct SimulatedThermistorPin
+{
+	state: Rc<RefCell<ThermalPlantState>>,
+}
+
+impl AdcPin<MockAdc> for SimulatedThermistorPin
+{
+	type Error = MockEmptyError;
+
+	fn read(&mut self, _: &mut MockAdc) -> Result<MockAdcValue, Self::Error>
+	{
+		let state = self.state.borrow();
+		if state.is_sensor_disconnected
+		{
+			return Ok(MockAdcValue(MockAdc::MAX_READABLE_VALUE));
+		}
+
+		// The inverse of the beta equation used by the `Thermistor`
+		let thermistor = state.config.thermistor;
+		let sensor_temperature = state.stuck_sensor_temperature.unwrap_or(state.sensor_temperature);
+		let resistance = thermistor.resistance_at_t0 as f32
+			* f32::exp(thermistor.beta as f32 * (1. / sensor_temperature - 1. / T0.as_kelvin()));
+		let voltage_ratio = resistance / (resistance + thermistor.other_resistance as f32);
+
+		Ok(MockAdcValue(
+			(voltage_ratio * MockAdc::MAX_READABLE_VALUE as f32).round() as u16,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::{
+		drivers::{heater::PwmHeater, thermistor::Thermistor},
+		features::temperature::{
+			safety::{
+				sensor_fault::SensorFaultConfig, temperature_change::TemperatureChangeConfig, TemperatureError,
+				TemperatureSafety,
+			},
+			AutotuneConfig, AutotuneStatus, PidAutotune, PidUpdateError, TemperaturePidController, TemperaturePidGains,
+			TuningRule,
+		},
+	};
+
+	type PidController = TemperaturePidController<SimulatedHeaterPin, Thermistor<MockAdc, SimulatedThermistorPin>>;
+
+	const TICK_PERIOD: Duration = Duration::from_millis(100);
+
+	fn new_pid_controller(plant: &MockThermalPlant) -> PidController
+	{
+		new_pid_controller_with_safety(plant, new_safety())
+	}
+
+	fn new_safety() -> TemperatureSafety
+	{
+		TemperatureSafety::new(
+			Temperature::from_celsius(0.)..=Temperature::from_celsius(300.),
+			TemperatureChangeConfig {
+				period: Duration::from_secs(40),
+				hysteresis: 10.,
+			},
+			TemperatureChangeConfig {
+				period: Duration::from_secs(20),
+				hysteresis: 2.,
+			},
+		)
+	}
+
+	fn new_pid_controller_with_safety(plant: &MockThermalPlant, safety: TemperatureSafety) -> PidController
+	{
+		let thermistor_config = plant.state.borrow().config.thermistor;
+		let thermistor = Thermistor::new(
+			plant.get_thermistor_pin(),
+			thermistor_config.beta,
+			thermistor_config.resistance_at_t0,
+			thermistor_config.other_resistance,
+		);
+		let pid_gains = TemperaturePidGains { p: 0., i: 0., d: 0. };
+
+		PidController::new(thermistor, PwmHeater::new(plant.get_heater_pin()), pid_gains, safety)
+	}
+
+	/// Ticks the `pid_controller` for `duration` of virtual time, stopping at the first error.
+	fn run(
+		plant: &mut MockThermalPlant, pid_controller: &mut PidController, duration: Duration,
+	) -> Result<(), PidUpdateError>
+	{
+		let end_time = plant.get_elapsed_time() + duration;
+		while plant.get_elapsed_time() < end_time
+		{
+			pid_controller.tick(TICK_PERIOD.as_secs_f32(), &mut MockAdc)?;
+			plant.advance(TICK_PERIOD);
+		}
+
+		Ok(())
+	}
+
+	/// Returns the average heat percentage (in the range `0..=1`) given by the `pid_controller` in `10s`.
+	fn get_average_heat(plant: &mut MockThermalPlant, pid_controller: &mut PidController) -> f32
+	{
+		let heater_pin = plant.get_heater_pin();
+		let ticks_count = 100;
+		let mut heat_sum = 0.;
+		for _ in 0..ticks_count
+		{
+			run(plant, pid_controller, TICK_PERIOD).unwrap();
+			heat_sum += heater_pin.get_duty_cycle().into_0_to_1();
+		}
+
+		heat_sum / ticks_count as f32
+	}
+
+	fn new_tuned_pid_controller(plant: &mut MockThermalPlant) -> PidController
+	{
+		let mut pid_controller = new_pid_controller(plant);
+		let mut autotune = PidAutotune::new(AutotuneConfig {
+			target_temperature: Temperature::from_celsius(200.),
+			heater_power: Percentage::FULL,
+			hysteresis: 1.,
+			cycles_count: 3,
+			timeout_in_seconds: 600.,
+		});
+
+		let result = loop
+		{
+			match pid_controller
+				.autotune_tick(&mut autotune, TICK_PERIOD.as_secs_f32(), &mut MockAdc)
+				.unwrap()
+			{
+				AutotuneStatus::Running { .. } => plant.advance(TICK_PERIOD),
+				AutotuneStatus::Finished(result) => break result,
+			}
+		};
+		pid_controller.set_pid_gains(&result.get_pid_gains(TuningRule::NoOvershoot, TICK_PERIOD.as_secs_f32()));
+
+		pid_controller
+	}
+
+	#[test]
+	fn thermistor_reads_sensor_temperature()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut pid_controller = new_pid_controller(&plant);
+
+		let temperature = pid_controller.get_current_temperature(&mut MockAdc).unwrap();
+		assert!((temperature.as_celsius() - 25.).abs() < 0.5);
+
+		plant.get_heater_pin().set_duty_cycle(Percentage::FULL).unwrap();
+		plant.advance(Duration::from_secs(60));
+		let temperature = pid_controller.get_current_temperature(&mut MockAdc).unwrap();
+		assert!((temperature.as_celsius() - plant.get_sensor_temperature().as_celsius()).abs() < 1.);
+		assert!(plant.get_sensor_temperature() < plant.get_block_temperature());
+	}
+
+	#[test]
+	fn dead_time_delays_heating()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		plant.get_heater_pin().set_duty_cycle(Percentage::FULL).unwrap();
+
+		plant.advance(Duration::from_millis(400));
+		assert_eq!(
+			plant.get_block_temperature(),
+			ThermalPlantConfig::HOTEND.ambient_temperature
+		);
+		plant.advance(Duration::from_millis(200));
+		assert!(plant.get_block_temperature() > ThermalPlantConfig::HOTEND.ambient_temperature);
+	}
+
+	#[test]
+	fn autotuned_pid_keeps_target_temperature()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut pid_controller = new_tuned_pid_controller(&mut plant);
+
+		pid_controller.set_target_temperature(Temperature::from_celsius(210.));
+		run(&mut plant, &mut pid_controller, Duration::from_secs(300)).unwrap();
+		assert!((plant.get_sensor_temperature().as_celsius() - 210.).abs() < 1.);
+		assert!(get_average_heat(&mut plant, &mut pid_controller) < 0.5);
+
+		// A draft makes the temperature drop, then the controller compensates it with more heat
+		plant.set_fan_draft(0.05);
+		run(&mut plant, &mut pid_controller, Duration::from_secs(300)).unwrap();
+		assert!((plant.get_sensor_temperature().as_celsius() - 210.).abs() < 1.);
+		assert!(get_average_heat(&mut plant, &mut pid_controller) > 0.65);
+	}
+
+	#[test]
+	fn disconnected_sensor_latches_fault()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let mut pid_controller = new_tuned_pid_controller(&mut plant);
+		pid_controller.set_target_temperature(Temperature::from_celsius(200.));
+		run(&mut plant, &mut pid_controller, Duration::from_secs(60)).unwrap();
+
+		plant.set_sensor_disconnected(true);
+		match run(&mut plant, &mut pid_controller, Duration::from_secs(1))
+		{
+			Err(PidUpdateError::ReadTemperatureIsWrong(errors)) =>
+			{
+				assert_eq!(errors, TemperatureError::SensorOpenCircuit)
+			},
+			result => panic!("{result:?}"),
+		}
+		assert!(pid_controller.get_fault().is_some());
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+	}
+
+	#[test]
+	fn broken_heater_is_detected()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		plant.set_heater_broken(true);
+		let mut pid_controller = new_pid_controller(&plant);
+		pid_controller.set_pid_gains(&TemperaturePidGains { p: 10., i: 0., d: 0. });
+		pid_controller.set_target_temperature(Temperature::from_celsius(200.));
+
+		match run(&mut plant, &mut pid_controller, Duration::from_secs(60))
+		{
+			Err(PidUpdateError::ReadTemperatureIsWrong(errors)) =>
+			{
+				assert!(errors.contains(TemperatureError::CantRiseFastEnoughToTargetTemperature))
+			},
+			result => panic!("{result:?}"),
+		}
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+	}
+
+	#[test]
+	fn stuck_sensor_is_detected()
+	{
+		let mut plant = MockThermalPlant::new(ThermalPlantConfig::HOTEND);
+		let safety = new_safety().with_sensor_fault_detection(SensorFaultConfig {
+			max_jump: 10.,
+			stuck_min_heater_percentage: Percentage::from_0_to_100(80.).unwrap(),
+			stuck_hysteresis: 2.,
+			stuck_period: Duration::from_secs(10),
+		});
+		let mut pid_controller = new_pid_controller_with_safety(&plant, safety);
+		pid_controller.set_pid_gains(&TemperaturePidGains { p: 10., i: 0., d: 0. });
+		pid_controller.set_target_temperature(Temperature::from_celsius(200.));
+		run(&mut plant, &mut pid_controller, Duration::from_secs(10)).unwrap();
+
+		plant.set_sensor_stuck(true);
+		match run(&mut plant, &mut pid_controller, Duration::from_secs(60))
+		{
+			Err(PidUpdateError::ReadTemperatureIsWrong(errors)) =>
+			{
+				assert_eq!(errors, TemperatureError::SensorStuckReading)
+			},
+			result => panic!("{result:?}"),
+		}
+		assert!(plant.get_elapsed_time() < Duration::from_secs(22));
+		assert_eq!(plant.get_heater_pin().get_duty_cycle(), Percentage::ZERO);
+	}
+}
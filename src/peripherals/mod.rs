@@ -7,6 +7,7 @@
 pub mod adc;
 pub mod interrupt;
 pub mod pwm;
+pub mod spi;
 pub mod time;
 pub mod uart;
 pub mod watchdog;
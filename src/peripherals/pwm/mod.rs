@@ -60,4 +60,14 @@ pub trait PwmPin
 
 	/// Set the frequency of the PWM signal.
 	fn set_frequency(&mut self, frequency: Frequency) -> Result<(), Self::Error>;
+
+	/// Shift the start of each period of the PWM signal by `phase` of the period, so that pins with different phases
+	/// aren't switched on at the same time.
+	///
+	/// Pins that can't shift their phase ignore it, which is what the default implementation does.
+	fn set_phase(&mut self, phase: Percentage) -> Result<(), Self::Error>
+	{
+		let _ = phase;
+		Ok(())
+	}
 }
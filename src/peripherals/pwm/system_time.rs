@@ -14,6 +14,7 @@ pub struct SystemTimePwm<P: OutputPin>
 {
 	pin: P,
 	duty_cycle: Percentage,
+	phase: Percentage,
 	single_cycle_duration: SmallDuration,
 	current_time: SmallDuration,
 }
@@ -27,6 +28,7 @@ impl<P: OutputPin> SystemTimePwm<P>
 		Ok(Self {
 			pin,
 			duty_cycle: Percentage::ZERO,
+			phase: Percentage::ZERO,
 			single_cycle_duration,
 			current_time: SmallDuration::ZERO,
 		})
@@ -40,7 +42,13 @@ impl<P: OutputPin> SystemTimePwm<P>
 			self.current_time -= self.single_cycle_duration;
 		}
 
-		match self.current_time > self.single_cycle_duration * self.duty_cycle.into_0_to_1()
+		let mut time_in_cycle = self.current_time + self.single_cycle_duration * self.phase.into_0_to_1();
+		if time_in_cycle >= self.single_cycle_duration
+		{
+			time_in_cycle -= self.single_cycle_duration;
+		}
+
+		match time_in_cycle > self.single_cycle_duration * self.duty_cycle.into_0_to_1()
 		{
 			true => self.pin.set_high(),
 			false => self.pin.set_low(),
@@ -68,4 +76,52 @@ impl<P: OutputPin> PwmPin for SystemTimePwm<P>
 		self.single_cycle_duration = frequency.into();
 		Ok(())
 	}
+
+	fn set_phase(&mut self, phase: Percentage) -> Result<(), Self::Error>
+	{
+		self.phase = phase;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::hardware::mock::MockOutputPin;
+
+	/// Returns the levels of the pin in the middle of each tenth of a period.
+	fn get_levels(phase: f32) -> [bool; 10]
+	{
+		let mut pwm =
+			SystemTimePwm::new(MockOutputPin::Ok { is_high: false }, SmallDuration::from_millis(100)).unwrap();
+		pwm.set_duty_cycle(Percentage::from_0_to_1(0.3).unwrap()).unwrap();
+		pwm.set_phase(Percentage::from_0_to_1(phase).unwrap()).unwrap();
+
+		pwm.tick(SmallDuration::from_millis(5)).unwrap();
+		core::array::from_fn(|index| {
+			if index > 0
+			{
+				pwm.tick(SmallDuration::from_millis(10)).unwrap();
+			}
+			matches!(pwm.pin, MockOutputPin::Ok { is_high: true })
+		})
+	}
+
+	#[test]
+	fn phase_shifts_the_period()
+	{
+		let levels = get_levels(0.);
+		assert_eq!(levels, [false, false, false, true, true, true, true, true, true, true]);
+
+		// The signal is shifted by `phase * period`, wrapping around at the end of the period
+		for (phase, shift) in [(0.5, 5), (0.8, 8)]
+		{
+			let shifted_levels = get_levels(phase);
+			for index in 0..10
+			{
+				assert_eq!(shifted_levels[index], levels[(index + shift) % 10], "{phase} {index}");
+			}
+		}
+	}
 }
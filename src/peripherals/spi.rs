@@ -0,0 +1,52 @@
+use embedded_hal::spi::{Operation, SpiDevice};
+
+/// The number of data lines used to exchange the data of a SPI transaction.
+///
+/// The variants are ordered from the slowest to the fastest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum BusWidth
+{
+	/// The standard SPI, where the data is received on the `MISO` line.
+	Single,
+	/// The data is received on 2 lines (`IO0` and `IO1`).
+	Dual,
+	/// The data is received on 4 lines (`IO0`-`IO3`).
+	Quad,
+}
+
+impl BusWidth
+{
+	/// Returns the number of data lines.
+	pub fn get_lines_count(&self) -> u8
+	{
+		match self
+		{
+			Self::Single => 1,
+			Self::Dual => 2,
+			Self::Quad => 4,
+		}
+	}
+}
+
+/// A [`SpiDevice`] that can receive data on more than one line, like the dual and quad SPI peripherals of many
+/// microcontrollers.
+///
+/// The [`SpiDevice::transaction`] must still use a single line for all the operations, so that the device can be used
+/// by the drivers that don't know about this trait.
+pub trait MultiIoSpiDevice: SpiDevice<u8>
+{
+	/// Returns the widest bus that can be used to receive data.
+	fn get_max_read_bus_width(&self) -> BusWidth;
+
+	/// Executes the provided `operations` like [`SpiDevice::transaction`], except the [`Operation::Read`]s that receive
+	/// the data on `bus_width` lines. All the writes (for example the op code and the address of a command) still use a
+	/// single line.
+	///
+	/// Returns `Ok(())` if the transaction has been executed successfully, otherwise returns `Err(Self::Error)`.
+	///
+	/// # Note
+	/// `bus_width` is never wider than [`Self::get_max_read_bus_width`].
+	fn transaction_with_read_bus_width(
+		&mut self, operations: &mut [Operation<'_, u8>], bus_width: BusWidth,
+	) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,99 @@
+//! A [`cyclic redundancy check`] is an error-detecting code commonly used to detect accidental changes to data
+//! (for example data stored in a flash memory that could have been only partially written due to a power loss).
+//!
+//! [`cyclic redundancy check`]: <https://en.wikipedia.org/wiki/Cyclic_redundancy_check>
+
+/// The reversed polynomial of the `CRC-32/ISO-HDLC` algorithm (the one used by Ethernet, zlib, PNG...).
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const CRC32_TABLE: [u32; 256] = {
+	let mut table = [0; 256];
+	let mut i = 0;
+	while i < 256
+	{
+		let mut crc = i as u32;
+		let mut bit = 0;
+		while bit < 8
+		{
+			crc = if crc & 1 != 0
+			{
+				(crc >> 1) ^ CRC32_POLYNOMIAL
+			}
+			else
+			{
+				crc >> 1
+			};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+};
+
+/// Incremental calculation of a `CRC-32/ISO-HDLC` checksum, useful when the data isn't available all at once.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::utils::algorithms::crc::*;
+/// #
+/// let mut crc = Crc32::new();
+/// crc.update(b"1234");
+/// crc.update(b"56789");
+///
+/// assert_eq!(crc.finish(), 0xCBF4_3926);
+/// assert_eq!(crc.finish(), crc32(b"123456789"));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crc32
+{
+	state: u32,
+}
+
+impl Crc32
+{
+	/// Returns a [`Crc32`] that hasn't processed any byte yet.
+	pub const fn new() -> Self
+	{
+		Self { state: u32::MAX }
+	}
+
+	/// Adds the provided `data` to the bytes processed by this checksum.
+	pub fn update(&mut self, data: &[u8])
+	{
+		for byte in data
+		{
+			self.state = CRC32_TABLE[((self.state ^ *byte as u32) & 0xFF) as usize] ^ (self.state >> 8);
+		}
+	}
+
+	/// Returns the checksum of all the bytes processed until now.
+	pub const fn finish(&self) -> u32
+	{
+		!self.state
+	}
+}
+
+impl Default for Crc32
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// Returns the `CRC-32/ISO-HDLC` checksum of the provided `data`.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::utils::algorithms::crc::crc32;
+/// #
+/// assert_eq!(crc32(b""), 0);
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+pub fn crc32(data: &[u8]) -> u32
+{
+	let mut crc = Crc32::new();
+	crc.update(data);
+	crc.finish()
+}
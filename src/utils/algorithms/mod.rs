@@ -1,3 +1,5 @@
 //! Module containing a bunch of algorithms frequently used in embedded development.
 
 pub mod bresenham;
+pub mod crc;
+pub mod sha256;
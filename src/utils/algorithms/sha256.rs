@@ -0,0 +1,243 @@
+//! [`SHA-256`] is a cryptographic hash function, commonly used to check that some data (for example a firmware image)
+//! hasn't been changed, neither accidentally nor on purpose.
+//!
+//! [`SHA-256`]: <https://en.wikipedia.org/wiki/SHA-2>
+
+/// Size in bytes of a SHA-256 hash.
+pub const SHA256_SIZE: usize = 32;
+const BLOCK_SIZE: usize = 64;
+
+const INITIAL_STATE: [u32; 8] = [
+	0x6A09_E667,
+	0xBB67_AE85,
+	0x3C6E_F372,
+	0xA54F_F53A,
+	0x510E_527F,
+	0x9B05_688C,
+	0x1F83_D9AB,
+	0x5BE0_CD19,
+];
+
+const ROUND_CONSTANTS: [u32; 64] = [
+	0x428A_2F98,
+	0x7137_4491,
+	0xB5C0_FBCF,
+	0xE9B5_DBA5,
+	0x3956_C25B,
+	0x59F1_11F1,
+	0x923F_82A4,
+	0xAB1C_5ED5,
+	0xD807_AA98,
+	0x1283_5B01,
+	0x2431_85BE,
+	0x550C_7DC3,
+	0x72BE_5D74,
+	0x80DE_B1FE,
+	0x9BDC_06A7,
+	0xC19B_F174,
+	0xE49B_69C1,
+	0xEFBE_4786,
+	0x0FC1_9DC6,
+	0x240C_A1CC,
+	0x2DE9_2C6F,
+	0x4A74_84AA,
+	0x5CB0_A9DC,
+	0x76F9_88DA,
+	0x983E_5152,
+	0xA831_C66D,
+	0xB003_27C8,
+	0xBF59_7FC7,
+	0xC6E0_0BF3,
+	0xD5A7_9147,
+	0x06CA_6351,
+	0x1429_2967,
+	0x27B7_0A85,
+	0x2E1B_2138,
+	0x4D2C_6DFC,
+	0x5338_0D13,
+	0x650A_7354,
+	0x766A_0ABB,
+	0x81C2_C92E,
+	0x9272_2C85,
+	0xA2BF_E8A1,
+	0xA81A_664B,
+	0xC24B_8B70,
+	0xC76C_51A3,
+	0xD192_E819,
+	0xD699_0624,
+	0xF40E_3585,
+	0x106A_A070,
+	0x19A4_C116,
+	0x1E37_6C08,
+	0x2748_774C,
+	0x34B0_BCB5,
+	0x391C_0CB3,
+	0x4ED8_AA4A,
+	0x5B9C_CA4F,
+	0x682E_6FF3,
+	0x748F_82EE,
+	0x78A5_636F,
+	0x84C8_7814,
+	0x8CC7_0208,
+	0x90BE_FFFA,
+	0xA450_6CEB,
+	0xBEF9_A3F7,
+	0xC671_78F2,
+];
+
+/// Incremental calculation of a SHA-256 hash, useful when the data isn't available all at once.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::utils::algorithms::sha256::*;
+/// #
+/// let mut hasher = Sha256::new();
+/// hasher.update(b"a");
+/// hasher.update(b"bc");
+///
+/// let hash = hasher.finish();
+/// assert_eq!(hash[..4], [0xBA, 0x78, 0x16, 0xBF]);
+/// assert_eq!(hash[28..], [0xF2, 0x00, 0x15, 0xAD]);
+/// assert_eq!(hash, sha256(b"abc"));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sha256
+{
+	state: [u32; 8],
+	block: [u8; BLOCK_SIZE],
+	block_len: usize,
+	total_len: u64,
+}
+
+impl Sha256
+{
+	/// Returns a [`Sha256`] that hasn't processed any byte yet.
+	pub const fn new() -> Self
+	{
+		Self {
+			state: INITIAL_STATE,
+			block: [0; BLOCK_SIZE],
+			block_len: 0,
+			total_len: 0,
+		}
+	}
+
+	/// Adds the provided `data` to the bytes processed by this hash.
+	pub fn update(&mut self, mut data: &[u8])
+	{
+		self.total_len += data.len() as u64;
+
+		while !data.is_empty()
+		{
+			let length = data.len().min(BLOCK_SIZE - self.block_len);
+			self.block[self.block_len..self.block_len + length].copy_from_slice(&data[..length]);
+			self.block_len += length;
+			data = &data[length..];
+
+			if self.block_len == BLOCK_SIZE
+			{
+				self.process_block();
+			}
+		}
+	}
+
+	/// Returns the hash of all the bytes processed until now.
+	pub fn finish(&self) -> [u8; SHA256_SIZE]
+	{
+		let mut sha256 = *self;
+		let bits_len = self.total_len.wrapping_mul(8);
+
+		// The padding is a 1 bit, followed by 0 bits until there are only 8 bytes left in the block for the length
+		sha256.block[sha256.block_len] = 0x80;
+		sha256.block[sha256.block_len + 1..].fill(0);
+		if sha256.block_len + 1 > BLOCK_SIZE - 8
+		{
+			sha256.process_block();
+			sha256.block.fill(0);
+		}
+		sha256.block[BLOCK_SIZE - 8..].copy_from_slice(&bits_len.to_be_bytes());
+		sha256.process_block();
+
+		let mut hash = [0; SHA256_SIZE];
+		for (bytes, word) in hash.chunks_exact_mut(4).zip(sha256.state)
+		{
+			bytes.copy_from_slice(&word.to_be_bytes());
+		}
+		hash
+	}
+
+	fn process_block(&mut self)
+	{
+		let mut words = [0; 64];
+		for (word, bytes) in words.iter_mut().zip(self.block.chunks_exact(4))
+		{
+			*word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		for i in 16..64
+		{
+			let s0 = words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+			let s1 = words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+			words[i] = words[i - 16]
+				.wrapping_add(s0)
+				.wrapping_add(words[i - 7])
+				.wrapping_add(s1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+		for i in 0..64
+		{
+			let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+			let choice = (e & f) ^ (!e & g);
+			let temp1 = h
+				.wrapping_add(s1)
+				.wrapping_add(choice)
+				.wrapping_add(ROUND_CONSTANTS[i])
+				.wrapping_add(words[i]);
+			let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+			let majority = (a & b) ^ (a & c) ^ (b & c);
+			let temp2 = s0.wrapping_add(majority);
+
+			h = g;
+			g = f;
+			f = e;
+			e = d.wrapping_add(temp1);
+			d = c;
+			c = b;
+			b = a;
+			a = temp1.wrapping_add(temp2);
+		}
+
+		for (word, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h])
+		{
+			*word = word.wrapping_add(value);
+		}
+		self.block_len = 0;
+	}
+}
+
+impl Default for Sha256
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+/// Returns the SHA-256 hash of the provided `data`.
+///
+/// # Examples
+/// ```
+/// # use a13c_embedded::utils::algorithms::sha256::sha256;
+/// #
+/// assert_eq!(sha256(b"")[..4], [0xE3, 0xB0, 0xC4, 0x42]);
+///
+/// // The padding of the data longer than 55 bytes needs an additional block
+/// let hash = sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq");
+/// assert_eq!(hash[..4], [0x24, 0x8D, 0x6A, 0x61]);
+/// ```
+pub fn sha256(data: &[u8]) -> [u8; SHA256_SIZE]
+{
+	let mut sha256 = Sha256::new();
+	sha256.update(data);
+	sha256.finish()
+}